@@ -0,0 +1,155 @@
+use crate::{hittable::*, vectors::*, rays::*, materials::*};
+use std::sync::Arc;
+
+
+// axis aligned rectangles, one struct per plane, each sitting at k along the remaining axis
+
+
+pub struct XyRect {
+    pub x0: f32,
+    pub x1: f32,
+    pub y0: f32,
+    pub y1: f32,
+    pub k: f32,
+    pub mat: Arc<dyn Material>,
+}
+
+
+impl XyRect {
+    pub fn new(x0: f32, x1: f32, y0: f32, y1: f32, k: f32, mat: Arc<dyn Material>) -> XyRect {
+        XyRect { x0, x1, y0, y1, k, mat }
+    }
+}
+
+
+impl Hittable for XyRect {
+
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32, rec: &mut HitRecord) -> bool {
+        let t = (self.k - r.origin.z) / r.direction.z;
+        if !(t_min..=t_max).contains(&t) {
+            return false;
+        }
+
+        let x = r.origin.x + t * r.direction.x;
+        let y = r.origin.y + t * r.direction.y;
+        if x < self.x0 || x > self.x1 || y < self.y0 || y > self.y1 {
+            return false;
+        }
+
+        rec.u = (x - self.x0) / (self.x1 - self.x0);
+        rec.v = (y - self.y0) / (self.y1 - self.y0);
+        rec.dpdu = Vector3::new(self.x1 - self.x0, 0.0, 0.0);
+        rec.dpdv = Vector3::new(0.0, self.y1 - self.y0, 0.0);
+        rec.t = t;
+        rec.set_face_normal(r, Vector3::newi(0, 0, 1));
+        rec.mat = self.mat.clone();
+        rec.p = r.at(t);
+
+        true
+    }
+
+    fn materials(&self) -> Vec<Arc<dyn Material>> {
+        vec![self.mat.clone()]
+    }
+
+}
+
+
+pub struct XzRect {
+    pub x0: f32,
+    pub x1: f32,
+    pub z0: f32,
+    pub z1: f32,
+    pub k: f32,
+    pub mat: Arc<dyn Material>,
+}
+
+
+impl XzRect {
+    pub fn new(x0: f32, x1: f32, z0: f32, z1: f32, k: f32, mat: Arc<dyn Material>) -> XzRect {
+        XzRect { x0, x1, z0, z1, k, mat }
+    }
+}
+
+
+impl Hittable for XzRect {
+
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32, rec: &mut HitRecord) -> bool {
+        let t = (self.k - r.origin.y) / r.direction.y;
+        if !(t_min..=t_max).contains(&t) {
+            return false;
+        }
+
+        let x = r.origin.x + t * r.direction.x;
+        let z = r.origin.z + t * r.direction.z;
+        if x < self.x0 || x > self.x1 || z < self.z0 || z > self.z1 {
+            return false;
+        }
+
+        rec.u = (x - self.x0) / (self.x1 - self.x0);
+        rec.v = (z - self.z0) / (self.z1 - self.z0);
+        rec.dpdu = Vector3::new(self.x1 - self.x0, 0.0, 0.0);
+        rec.dpdv = Vector3::new(0.0, 0.0, self.z1 - self.z0);
+        rec.t = t;
+        rec.set_face_normal(r, Vector3::newi(0, 1, 0));
+        rec.mat = self.mat.clone();
+        rec.p = r.at(t);
+
+        true
+    }
+
+    fn materials(&self) -> Vec<Arc<dyn Material>> {
+        vec![self.mat.clone()]
+    }
+
+}
+
+
+pub struct YzRect {
+    pub y0: f32,
+    pub y1: f32,
+    pub z0: f32,
+    pub z1: f32,
+    pub k: f32,
+    pub mat: Arc<dyn Material>,
+}
+
+
+impl YzRect {
+    pub fn new(y0: f32, y1: f32, z0: f32, z1: f32, k: f32, mat: Arc<dyn Material>) -> YzRect {
+        YzRect { y0, y1, z0, z1, k, mat }
+    }
+}
+
+
+impl Hittable for YzRect {
+
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32, rec: &mut HitRecord) -> bool {
+        let t = (self.k - r.origin.x) / r.direction.x;
+        if !(t_min..=t_max).contains(&t) {
+            return false;
+        }
+
+        let y = r.origin.y + t * r.direction.y;
+        let z = r.origin.z + t * r.direction.z;
+        if y < self.y0 || y > self.y1 || z < self.z0 || z > self.z1 {
+            return false;
+        }
+
+        rec.u = (y - self.y0) / (self.y1 - self.y0);
+        rec.v = (z - self.z0) / (self.z1 - self.z0);
+        rec.dpdu = Vector3::new(0.0, self.y1 - self.y0, 0.0);
+        rec.dpdv = Vector3::new(0.0, 0.0, self.z1 - self.z0);
+        rec.t = t;
+        rec.set_face_normal(r, Vector3::newi(1, 0, 0));
+        rec.mat = self.mat.clone();
+        rec.p = r.at(t);
+
+        true
+    }
+
+    fn materials(&self) -> Vec<Arc<dyn Material>> {
+        vec![self.mat.clone()]
+    }
+
+}
@@ -0,0 +1,260 @@
+use crate::{vectors::*, rays::Ray, hittable::*, scene::Scene, spectrum, denoise::Features};
+use std::cell::RefCell;
+
+
+// arbitrary output variables, the passes a compositor takes apart alongside the beauty image
+#[derive(Copy, Clone, PartialEq)]
+pub enum Aov {
+    Depth,
+    Position,
+    Normal,
+    Albedo,
+    ObjectId,
+    MaterialId,
+    DirectDiffuse,
+    IndirectDiffuse,
+    DirectSpecular,
+    IndirectSpecular,
+    Emission,
+    Motion,
+}
+
+
+pub const ALL_AOVS: [Aov; 12] = [
+    Aov::Depth, Aov::Position, Aov::Normal, Aov::Albedo, Aov::ObjectId, Aov::MaterialId,
+    Aov::DirectDiffuse, Aov::IndirectDiffuse, Aov::DirectSpecular, Aov::IndirectSpecular, Aov::Emission, Aov::Motion,
+];
+
+
+// how far away the sky is in the depth pass
+const BACKGROUND_DEPTH: f32 = 1e10;
+
+
+impl Aov {
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Aov::Depth => "depth",
+            Aov::Position => "position",
+            Aov::Normal => "normal",
+            Aov::Albedo => "albedo",
+            Aov::ObjectId => "object_id",
+            Aov::MaterialId => "material_id",
+            Aov::DirectDiffuse => "direct_diffuse",
+            Aov::IndirectDiffuse => "indirect_diffuse",
+            Aov::DirectSpecular => "direct_specular",
+            Aov::IndirectSpecular => "indirect_specular",
+            Aov::Emission => "emission",
+            Aov::Motion => "motion",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Aov> {
+        ALL_AOVS.iter().find(|aov| aov.name() == name).copied()
+    }
+
+    // ids mean nothing averaged, so a pixel keeps the first one its samples saw
+    pub fn is_id(&self) -> bool {
+        matches!(self, Aov::ObjectId | Aov::MaterialId)
+    }
+
+}
+
+
+
+// the radiance of one camera sample split by the way the light took, as far as the integrator
+// tells. direct light reached the first hit straight from an emitter or the sky, indirect
+// bounced at least once more, & either is diffuse or specular by the first hit's lobe. emission
+// is what the camera saw glow, the sky included
+#[derive(Copy, Clone)]
+pub struct LightPaths {
+    pub emission: Color,
+    pub direct_diffuse: Color,
+    pub indirect_diffuse: Color,
+    pub direct_specular: Color,
+    pub indirect_specular: Color,
+}
+
+
+impl LightPaths {
+    pub fn zeros() -> LightPaths {
+        LightPaths {
+            emission: Color::zeros(),
+            direct_diffuse: Color::zeros(),
+            indirect_diffuse: Color::zeros(),
+            direct_specular: Color::zeros(),
+            indirect_specular: Color::zeros(),
+        }
+    }
+}
+
+
+thread_local! {
+    static LIGHT_PATHS: RefCell<Option<LightPaths>> = const { RefCell::new(None) };
+}
+
+
+// runs f, splitting what it records with record_light into the light passes
+pub fn with_light_paths<R, F: FnOnce() -> R>(f: F) -> (R, LightPaths) {
+    let previous = LIGHT_PATHS.with(|l| l.replace(Some(LightPaths::zeros())));
+    let result = f();
+    let light_paths = LIGHT_PATHS.with(|l| l.replace(previous)).unwrap();
+
+    (result, light_paths)
+}
+
+
+// so integrators needn't work out the first hit's lobes for nothing
+pub fn recording_light() -> bool {
+    LIGHT_PATHS.with(|l| l.borrow().is_some())
+}
+
+
+// radiance the path picked up after scattering bounces times, diffuse_fraction of it by the first
+// hit's diffuse lobe. does nothing unless the light passes are wanted
+pub fn record_light(bounces: u32, diffuse_fraction: f32, radiance: Color) {
+    LIGHT_PATHS.with(|l| if let Some(paths) = l.borrow_mut().as_mut() {
+        let radiance = match spectrum::current_wavelengths() {
+            Some(wavelengths) => wavelengths.to_rgb(&radiance),
+            None => radiance,
+        };
+        let (diffuse, specular) = (diffuse_fraction * radiance, (1.0 - diffuse_fraction) * radiance);
+
+        match bounces {
+            0 => paths.emission += radiance,
+            1 => {
+                paths.direct_diffuse += diffuse;
+                paths.direct_specular += specular;
+            }
+            _ => {
+                paths.indirect_diffuse += diffuse;
+                paths.indirect_specular += specular;
+            }
+        }
+    })
+}
+
+
+
+// the value of each of aovs for one camera sample along r, on a width by height film
+pub fn sample_aovs(aovs: &[Aov], scene: &Scene, r: &Ray, light_paths: &LightPaths, width: u32, height: u32) -> Vec<Color> {
+    let mut rec = HitRecord::new();
+    let hit = scene.world.hit(r, 0.001, f32::INFINITY, &mut rec);
+
+    aovs.iter().map(|aov| match (aov, hit) {
+        (Aov::Depth, true) => Color::fromv(rec.t * r.direction.magnitude()),
+        (Aov::Depth, false) => Color::fromv(BACKGROUND_DEPTH),
+        (Aov::Position, true) => rec.p,
+        (Aov::Normal, true) => rec.normal,
+        (Aov::Albedo, _) => Features::of_first_hit(scene, r).albedo,
+        // 0 for the sky
+        (Aov::ObjectId, true) => Color::fromv((rec.object + 1) as f32),
+        (Aov::MaterialId, true) => Color::fromv(scene.material_id(&rec.mat) as f32),
+        (Aov::DirectDiffuse, _) => light_paths.direct_diffuse,
+        (Aov::IndirectDiffuse, _) => light_paths.indirect_diffuse,
+        (Aov::DirectSpecular, _) => light_paths.direct_specular,
+        (Aov::IndirectSpecular, _) => light_paths.indirect_specular,
+        (Aov::Emission, _) => light_paths.emission,
+        // pixels moved over the shutter, right & up
+        (Aov::Motion, true) => match scene.camera.image_motion(&rec.p, &rec.velocity, r.time) {
+            Some((ds, dt)) => Color::new(ds * (width - 1) as f32, dt * (height - 1) as f32, 0.0),
+            None => Color::zeros(),
+        },
+        _ => Color::zeros(),
+    }).collect()
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{hittable_list::HittableList, sphere::Sphere, moving_sphere::MovingSphere, materials::*, principled::Principled,
+                texture::solid, sky::GradientSky, camera::Camera, film::Film, integrator::*};
+    use std::sync::Arc;
+
+    fn scene(camera: Camera) -> Scene {
+        let mut world = HittableList::new();
+        world.add(Box::new(Sphere::new(Point3::new(0.0, -100.5, -1.0), 100.0, Arc::new(Lambertian::new(Color::fromv(0.5))))));
+        world.add(Box::new(Sphere::new(Point3::newi(0, 0, -1), 0.5, Arc::new(Principled::new(solid(Color::new(0.7, 0.3, 0.3)))))));
+        world.add(Box::new(MovingSphere::new(Point3::newi(1, 0, -1), Point3::new(1.2, 0.0, -1.0), 0.0, 1.0, 0.4, Arc::new(Metal::new(Color::fromv(0.8), 0.3)))));
+
+        Scene::new(world, HittableList::new(), Some(Box::new(GradientSky::new(Color::fromv(1.0), Color::new(0.5, 0.7, 1.0)))), camera)
+    }
+
+    fn camera() -> Camera {
+        Camera::new(Point3::zeros(), Point3::newi(0, 0, -1), Vector3::newi(0, 1, 0), 90.0, 1.0, 0.0, 1.0, 0.0, 1.0)
+    }
+
+    #[test]
+    fn light_passes_add_up_to_the_beauty() {
+        let scene = scene(camera());
+        let mut film = Film::new(4, 4);
+        let integrators: [Box<dyn Integrator>; 2] = [Box::new(PathTracer::new(10, 3)), Box::new(MisPathTracer::new(10, 3))];
+
+        for integrator in integrators.iter() {
+            let mut totals = LightPaths::zeros();
+            for k in 0..2000 {
+                let r = scene.camera.get_ray((k % 50) as f32 / 50.0, (k / 50) as f32 / 40.0);
+                let (radiance, paths) = with_light_paths(|| integrator.li(&r, &scene, &mut film));
+
+                let sum = paths.emission + paths.direct_diffuse + paths.indirect_diffuse + paths.direct_specular + paths.indirect_specular;
+                assert!((sum - radiance).magnitude() <= 1e-4 * radiance.magnitude().max(1.0));
+
+                totals.emission += paths.emission;
+                totals.direct_diffuse += paths.direct_diffuse;
+                totals.direct_specular += paths.direct_specular;
+                totals.indirect_diffuse += paths.indirect_diffuse;
+            }
+
+            // the sky's in view, & lights the diffuse floor & the glossy metal & principled spheres
+            assert!(totals.emission.x > 0.0 && totals.direct_diffuse.x > 0.0 && totals.direct_specular.x > 0.0 && totals.indirect_diffuse.x > 0.0);
+        }
+    }
+
+    #[test]
+    fn moving_objects_have_motion_vectors() {
+        let scene = scene(camera());
+        let aovs = [Aov::Motion, Aov::ObjectId, Aov::Depth];
+        let through = |x: f32| Ray::new(Point3::zeros(), Vector3::new(x, 0.0, -1.0), 0.5);
+
+        // the moving sphere slides right by 0.2 over the shutter, seen where the ray through its center
+        // at mid shutter meets it. the image is 2 wide at a depth of 1, across 100 pixels
+        let moving = sample_aovs(&aovs, &scene, &through(1.1), &LightPaths::zeros(), 101, 101);
+        let still = sample_aovs(&aovs, &scene, &through(0.0), &LightPaths::zeros(), 101, 101);
+        let sky = sample_aovs(&aovs, &scene, &Ray::new(Point3::zeros(), Vector3::newi(0, 1, 0), 0.5), &LightPaths::zeros(), 101, 101);
+
+        let depth = 1.0 - 0.4 / (1.1f32 * 1.1 + 1.0).sqrt();
+        let expected = 100.0 * 0.2 / depth / 2.0;
+        assert!((moving[0].x - expected).abs() < 0.1 && moving[0].y.abs() < 1e-3, "{}", moving[0].x);
+        assert_eq!(still[0].x, 0.0);
+        assert_eq!((still[1].x, moving[1].x, sky[1].x), (2.0, 3.0, 0.0));
+        assert!((still[2].x - 0.5).abs() < 1e-4 && sky[2].x == BACKGROUND_DEPTH);
+    }
+
+    #[test]
+    fn material_ids_are_numbered_as_the_scene_adds_them() {
+        let ids = |scene: &Scene, directions: &[Vector3]| -> Vec<f32> {
+            directions.iter().map(|d| {
+                sample_aovs(&[Aov::MaterialId], scene, &Ray::new(Point3::zeros(), *d, 0.5), &LightPaths::zeros(), 2, 2)[0].x
+            }).collect()
+        };
+
+        // the floor, the spheres in the order they were added & the sky, the same every time it's built
+        let directions = [Vector3::new(0.0, -1.0, -1.0), Vector3::newi(0, 0, -1), Vector3::new(1.1, 0.0, -1.0), Vector3::newi(0, 1, 0)];
+        let first = scene(camera());
+        assert_eq!(ids(&first, &directions), vec![1.0, 2.0, 3.0, 0.0]);
+        assert_eq!(ids(&scene(camera()), &directions), ids(&first, &directions));
+
+        // objects sharing a material share its number
+        let shared: Arc<dyn Material> = Arc::new(Lambertian::new(Color::fromv(0.5)));
+        let mut world = HittableList::new();
+        world.add(Box::new(Sphere::new(Point3::new(-1.0, 0.0, -2.0), 0.5, shared.clone())));
+        world.add(Box::new(Sphere::new(Point3::new(0.0, 0.0, -2.0), 0.5, Arc::new(Metal::new(Color::fromv(0.8), 0.0)))));
+        world.add(Box::new(Sphere::new(Point3::new(1.0, 0.0, -2.0), 0.5, shared)));
+        let shared_scene = Scene::new(world, HittableList::new(), None, camera());
+
+        let directions = [Vector3::new(-0.5, 0.0, -1.0), Vector3::newi(0, 0, -1), Vector3::new(0.5, 0.0, -1.0)];
+        assert_eq!(ids(&shared_scene, &directions), vec![1.0, 2.0, 1.0]);
+    }
+}
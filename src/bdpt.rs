@@ -0,0 +1,575 @@
+use crate::{
+            vectors::*,
+            rays::Ray,
+            hittable::*,
+            scene::Scene,
+            film::Film,
+            onb::*,
+            integrator::{Integrator, power_heuristic},
+            random::start_bounce,
+};
+use std::{f32::consts::PI, ops::Range};
+
+
+// bidirectional path tracing (Veach 1997, laid out like pbrt's BDPTIntegrator). a camera and a
+// light subpath are traced per sample and every pair of their vertices is connected, each
+// strategy weighted against all the others that could have made the same path with the power
+// heuristic. the area lights in scene.lights take part in every strategy, the sky only in
+// hitting it & sampling it from the camera subpath.
+pub struct Bdpt {
+    pub max_depth: u32, // path segments, camera ray included, as in the path tracers
+}
+
+
+impl Bdpt {
+    pub fn new(max_depth: u32) -> Bdpt {
+        Bdpt { max_depth }
+    }
+}
+
+
+#[derive(Copy, Clone, PartialEq)]
+enum VertexKind {
+    Camera,
+    Light,
+    Surface,
+}
+
+
+#[derive(Clone)]
+struct Vertex {
+    kind: VertexKind,
+    rec: HitRecord, // p & normal for every kind, material for lights & surfaces
+    r_in: Ray, // what arrived at a surface vertex
+    beta: Color,
+    delta: bool, // can't be connected to, only sampled through
+    pdf_fwd: f32, // area density of reaching this vertex from the one before it
+    pdf_rev: f32, // same, coming from the other end of the path
+}
+
+
+// the edge escaping the camera subpath, kept for the sky
+struct Escape {
+    ray: Ray,
+    beta: Color,
+    pdf: f32, // solid angle pdf of its direction, 0 if only a specular bounce could make it
+}
+
+
+fn remap0(pdf: f32) -> f32 {
+    if pdf != 0.0 {pdf} else {1.0}
+}
+
+
+fn is_black(c: &Color) -> bool {
+    c.x <= 0.0 && c.y <= 0.0 && c.z <= 0.0
+}
+
+
+fn unoccluded(scene: &Scene, from: &Point3, to: &Point3, time: f32) -> bool {
+    let direction = *to - *from;
+    let epsilon = 0.001 / direction.magnitude();
+    let mut rec = HitRecord::new();
+
+    !scene.world.hit(&Ray::new(*from, direction, time), epsilon, 1.0 - epsilon, &mut rec)
+}
+
+
+impl Vertex {
+
+    fn new(kind: VertexKind, rec: HitRecord, r_in: Ray, beta: Color) -> Vertex {
+        Vertex { kind, rec, r_in, beta, delta: false, pdf_fwd: 0.0, pdf_rev: 0.0 }
+    }
+
+    fn camera(point: Point3, forward: Vector3, time: f32, beta: Color) -> Vertex {
+        let mut rec = HitRecord::new();
+        rec.p = point;
+        rec.normal = forward;
+
+        Vertex::new(VertexKind::Camera, rec, Ray::new(point, forward, time), beta)
+    }
+
+    fn p(&self) -> Point3 {
+        self.rec.p
+    }
+
+    fn is_on_surface(&self) -> bool {
+        self.kind != VertexKind::Camera
+    }
+
+    fn is_connectible(&self) -> bool {
+        !self.delta && (self.kind != VertexKind::Surface || !self.rec.mat.is_specular())
+    }
+
+    // solid angle density at this vertex to area density at next
+    fn convert_density(&self, pdf: f32, next: &Vertex) -> f32 {
+        let w = next.p() - self.p();
+        let distance_squared = w.magnitude_squared();
+        if distance_squared == 0.0 {
+            return 0.0;
+        }
+
+        let mut pdf = pdf / distance_squared;
+        if next.is_on_surface() {
+            pdf *= Vector3::dot(&next.rec.normal, &w.normalized()).abs();
+        }
+
+        pdf
+    }
+
+    // bsdf times cosine towards next
+    fn f(&self, next: &Vertex) -> Color {
+        self.rec.mat.eval(&self.r_in, &self.rec, &(next.p() - self.p()))
+    }
+
+    // emitted radiance towards v
+    fn le(&self, v: &Vertex) -> Color {
+        if Vector3::dot(&self.rec.normal, &(v.p() - self.p())) <= 0.0 {
+            return Color::zeros();
+        }
+
+        self.rec.mat.emitted(&self.rec)
+    }
+
+    // area density of next being sampled from this vertex, having arrived here from prev
+    fn pdf(&self, scene: &Scene, prev: Option<&Vertex>, next: &Vertex) -> f32 {
+        let direction = next.p() - self.p();
+
+        let pdf = match (self.kind, prev) {
+            (VertexKind::Light, _) => return self.pdf_light(next),
+            (VertexKind::Camera, _) => scene.camera.pdf_importance(&Ray::new(self.p(), direction, self.r_in.time)).1,
+            (VertexKind::Surface, Some(prev)) => {
+                let r_in = Ray::new(prev.p(), self.p() - prev.p(), self.r_in.time);
+                self.rec.mat.scattering_pdf(&r_in, &self.rec, &direction)
+            }
+            (VertexKind::Surface, None) => 0.0,
+        };
+
+        self.convert_density(pdf, next)
+    }
+
+    // area density at next of this emitter sending a cosine distributed ray its way
+    fn pdf_light(&self, next: &Vertex) -> f32 {
+        let cos_theta = Vector3::dot(&self.rec.normal, &(next.p() - self.p()).normalized());
+        if cos_theta <= 0.0 {
+            return 0.0;
+        }
+
+        self.convert_density(cos_theta / PI, next)
+    }
+
+    // area density of this point being the start of a light subpath
+    fn pdf_light_origin(&self, scene: &Scene) -> f32 {
+        scene.lights.surface_pdf(&self.p())
+    }
+
+}
+
+
+impl Bdpt {
+
+    // extends path from its last vertex along ray, whose direction was picked with solid angle pdf_dir,
+    // by a vertex for each of the sampler's bounces. returns the edge that left the scene, if any
+    fn random_walk(&self, scene: &Scene, mut ray: Ray, mut beta: Color, mut pdf_dir: f32, bounces: Range<u32>, path: &mut Vec<Vertex>) -> Option<Escape> {
+        let last = bounces.end.saturating_sub(1);
+
+        for bounce in bounces {
+            start_bounce(bounce);
+            let mut rec = HitRecord::new();
+
+            if !scene.world.hit(&ray, 0.001, f32::INFINITY, &mut rec) {
+                return Some(Escape { ray, beta, pdf: pdf_dir });
+            }
+
+            let prev_index = path.len() - 1;
+            let mut vertex = Vertex::new(VertexKind::Surface, rec.clone(), ray, beta);
+            vertex.pdf_fwd = path[prev_index].convert_density(pdf_dir, &vertex);
+            path.push(vertex);
+
+            if bounce == last {
+                break;
+            }
+
+            let (scatter_ray, attenuation) = match rec.mat.scatter(&ray, &rec) {
+                Some(scattered) => scattered,
+                None => break,
+            };
+
+            let pdf_rev_dir = if rec.mat.is_specular() {
+                path.last_mut().unwrap().delta = true;
+                pdf_dir = 0.0;
+                0.0
+            } else {
+                pdf_dir = rec.mat.scattering_pdf(&ray, &rec, &scatter_ray.direction);
+                // as if the path had come in along scatter_ray & left back towards prev
+                let reverse = Ray::new(rec.p + scatter_ray.direction, -scatter_ray.direction, ray.time);
+                rec.mat.scattering_pdf(&reverse, &rec, &-ray.direction)
+            };
+
+            beta *= attenuation;
+
+            let vertex = path.last().unwrap();
+            path[prev_index].pdf_rev = vertex.convert_density(pdf_rev_dir, &path[prev_index]);
+
+            ray = scatter_ray;
+        }
+
+        None
+    }
+
+    fn camera_subpath(&self, scene: &Scene, r: &Ray, path: &mut Vec<Vertex>) -> Option<Escape> {
+        let (pdf_pos, pdf_dir) = scene.camera.pdf_importance(r);
+
+        let mut camera = Vertex::camera(r.origin, scene.camera.forward(), r.time, Color::fromv(1.0));
+        camera.pdf_fwd = pdf_pos;
+        path.push(camera);
+
+        self.random_walk(scene, *r, Color::fromv(1.0), pdf_dir, 0..self.max_depth, path)
+    }
+
+    // on the bounce dimensions after the camera subpath's
+    fn light_subpath(&self, scene: &Scene, time: f32, path: &mut Vec<Vertex>) {
+        start_bounce(self.max_depth);
+        let (rec, pdf_pos) = match scene.lights.sample_surface() {
+            Some(sample) => sample,
+            None => return,
+        };
+        let le = rec.mat.emitted(&rec);
+        if pdf_pos <= 0.0 || is_black(&le) {
+            return;
+        }
+
+        // cosine weighted away from the surface
+        let d = random_cosine_direction();
+        let cos_theta = d.z;
+        let direction = Onb::build_from_w(&rec.normal).local(d.x, d.y, d.z);
+        let pdf_dir = cos_theta / PI;
+        if pdf_dir <= 0.0 {
+            return;
+        }
+
+        let ray = Ray::new(rec.p, direction, time);
+        let mut light = Vertex::new(VertexKind::Light, rec, ray, le);
+        light.pdf_fwd = pdf_pos;
+        path.push(light);
+
+        let beta = cos_theta / (pdf_pos * pdf_dir) * le;
+        self.random_walk(scene, ray, beta, pdf_dir, self.max_depth + 1..2 * self.max_depth, path);
+    }
+
+    // joins the first s light & first t camera vertices
+    fn connect(&self, scene: &Scene, light: &[Vertex], camera: &[Vertex], s: usize, t: usize, film: &mut Film) -> Color {
+        let time = camera[0].r_in.time;
+        let mut sampled: Option<Vertex> = None;
+        let mut image_position = None;
+        let mut l = Color::zeros();
+
+        if s == 0 {
+            // the camera subpath ran into a light by itself
+            let pt = &camera[t - 1];
+            if pt.kind == VertexKind::Surface {
+                l = pt.beta * pt.le(&camera[t - 2]);
+            }
+        } else if t == 1 {
+            // light tracing: the light subpath connects straight to the lens
+            let qs = &light[s - 1];
+            if qs.is_connectible() {
+                if let Some(lens) = scene.camera.sample_lens(&qs.p()) {
+                    let camera_vertex = Vertex::camera(lens.point, scene.camera.forward(), time, Color::fromv(lens.importance / lens.pdf));
+                    l = qs.beta * qs.f(&camera_vertex) * camera_vertex.beta;
+
+                    if !is_black(&l) && !unoccluded(scene, &qs.p(), &lens.point, time) {
+                        l = Color::zeros();
+                    }
+
+                    image_position = Some((lens.s, lens.t));
+                    sampled = Some(camera_vertex);
+                }
+            }
+        } else if s == 1 {
+            // next event estimation with a freshly picked point on a light
+            let pt = &camera[t - 1];
+            if pt.is_connectible() {
+                if let Some((rec, pdf_pos)) = scene.lights.sample_surface() {
+                    let mut light_vertex = Vertex::new(VertexKind::Light, rec, Ray::new(pt.p(), Vector3::zeros(), time), Color::zeros());
+                    light_vertex.pdf_fwd = pdf_pos;
+
+                    let to_light = light_vertex.p() - pt.p();
+                    let cos_light = Vector3::dot(&light_vertex.rec.normal, &-to_light.normalized());
+
+                    if pdf_pos > 0.0 && cos_light > 0.0 {
+                        light_vertex.beta = light_vertex.le(pt) / pdf_pos;
+                        l = pt.beta * pt.f(&light_vertex) * light_vertex.beta * (cos_light / to_light.magnitude_squared());
+
+                        if !is_black(&l) && !unoccluded(scene, &pt.p(), &light_vertex.p(), time) {
+                            l = Color::zeros();
+                        }
+                    }
+                    sampled = Some(light_vertex);
+                }
+            }
+        } else {
+            let qs = &light[s - 1];
+            let pt = &camera[t - 1];
+            if qs.is_connectible() && pt.is_connectible() {
+                let distance_squared = (pt.p() - qs.p()).magnitude_squared();
+                l = qs.beta * qs.f(pt) * pt.f(qs) * pt.beta / distance_squared;
+
+                if !is_black(&l) && !unoccluded(scene, &qs.p(), &pt.p(), time) {
+                    l = Color::zeros();
+                }
+            }
+        }
+
+        if is_black(&l) {
+            return Color::zeros();
+        }
+
+        let weighted = self.mis_weight(scene, light, camera, &sampled, s, t) * l;
+
+        match image_position {
+            Some((u, v)) => {
+                film.add_splat(u, v, weighted);
+                Color::zeros()
+            }
+            None => weighted,
+        }
+    }
+
+    // power heuristic over every other (s, t) split of the same path, from the ratios of the
+    // pdfs its vertices would have had if sampled from the other side
+    fn mis_weight(&self, scene: &Scene, light: &[Vertex], camera: &[Vertex], sampled: &Option<Vertex>, s: usize, t: usize) -> f32 {
+        if s + t == 2 {
+            return 1.0;
+        }
+
+        let mut camera_fwd: Vec<f32> = camera[..t].iter().map(|v| v.pdf_fwd).collect();
+        let mut camera_rev: Vec<f32> = camera[..t].iter().map(|v| v.pdf_rev).collect();
+        let mut camera_delta: Vec<bool> = camera[..t].iter().map(|v| v.delta).collect();
+        let mut light_fwd: Vec<f32> = light[..s].iter().map(|v| v.pdf_fwd).collect();
+        let mut light_rev: Vec<f32> = light[..s].iter().map(|v| v.pdf_rev).collect();
+        let mut light_delta: Vec<bool> = light[..s].iter().map(|v| v.delta).collect();
+
+        let pt = if t == 1 {sampled.as_ref().unwrap()} else {&camera[t - 1]};
+        let qs = match s {
+            0 => None,
+            1 => sampled.as_ref(),
+            _ => Some(&light[s - 1]),
+        };
+        let pt_minus = if t >= 2 {Some(&camera[t - 2])} else {None};
+        let qs_minus = if s >= 2 {Some(&light[s - 2])} else {None};
+
+        if t == 1 {
+            camera_fwd[0] = pt.pdf_fwd;
+        }
+        if s == 1 {
+            light_fwd[0] = qs.unwrap().pdf_fwd;
+        }
+
+        // the two vertices being connected are never delta
+        camera_delta[t - 1] = false;
+        if s > 0 {
+            light_delta[s - 1] = false;
+        }
+
+        match qs {
+            Some(qs) => {
+                camera_rev[t - 1] = qs.pdf(scene, qs_minus, pt);
+                if let Some(pt_minus) = pt_minus {
+                    camera_rev[t - 2] = pt.pdf(scene, Some(qs), pt_minus);
+                }
+                light_rev[s - 1] = pt.pdf(scene, pt_minus, qs);
+                if let Some(qs_minus) = qs_minus {
+                    light_rev[s - 2] = qs.pdf(scene, Some(pt), qs_minus);
+                }
+            }
+            None => {
+                camera_rev[t - 1] = pt.pdf_light_origin(scene);
+                // an emitter missing from scene.lights can't be reached any other way
+                if camera_rev[t - 1] == 0.0 {
+                    return 1.0;
+                }
+                if let Some(pt_minus) = pt_minus {
+                    camera_rev[t - 2] = pt.pdf_light(pt_minus);
+                }
+            }
+        }
+
+        let mut sum = 0.0;
+
+        let mut ri = 1.0;
+        for i in (1..t).rev() {
+            ri *= remap0(camera_rev[i]) / remap0(camera_fwd[i]);
+            if !camera_delta[i] && !camera_delta[i - 1] {
+                sum += ri * ri;
+            }
+        }
+
+        let mut ri = 1.0;
+        for i in (0..s).rev() {
+            ri *= remap0(light_rev[i]) / remap0(light_fwd[i]);
+            let delta_before = if i > 0 {light_delta[i - 1]} else {false};
+            if !light_delta[i] && !delta_before {
+                sum += ri * ri;
+            }
+        }
+
+        1.0 / (1.0 + sum)
+    }
+
+    // the sky, from escaping camera subpaths & light samples towards it at each of their vertices
+    fn sky_radiance(&self, scene: &Scene, camera: &[Vertex], escape: &Option<Escape>) -> Color {
+        let sky = match &scene.sky {
+            Some(sky) => sky,
+            None => return Color::zeros(),
+        };
+        let mut radiance = Color::zeros();
+
+        // the camera vertex doesn't sample the sky, so what it sees directly is all the camera ray's
+        if let Some(escape) = escape {
+            let weight = if escape.pdf > 0.0 && camera.len() > 1 {power_heuristic(escape.pdf, sky.pdf(&escape.ray.direction))} else {1.0};
+            radiance += weight * escape.beta * scene.background(&escape.ray.direction);
+        }
+
+        // the sky sample adds a segment, so not from a vertex that is max_depth segments in already
+        for vertex in camera.iter().skip(1).take(self.max_depth.saturating_sub(1) as usize) {
+            if !vertex.is_connectible() {
+                continue;
+            }
+
+            let (direction, sky_pdf) = sky.sample_direction();
+            let f = vertex.rec.mat.eval(&vertex.r_in, &vertex.rec, &direction);
+            if sky_pdf <= 0.0 || is_black(&f) {
+                continue;
+            }
+
+            let mut rec = HitRecord::new();
+            if scene.world.hit(&Ray::new(vertex.p(), direction, vertex.r_in.time), 0.001, f32::INFINITY, &mut rec) {
+                continue;
+            }
+
+            let bsdf_pdf = vertex.rec.mat.scattering_pdf(&vertex.r_in, &vertex.rec, &direction);
+            radiance += power_heuristic(sky_pdf, bsdf_pdf) / sky_pdf * vertex.beta * f * scene.background(&direction);
+        }
+
+        radiance
+    }
+
+}
+
+
+impl Integrator for Bdpt {
+    fn li(&self, r: &Ray, scene: &Scene, film: &mut Film) -> Color {
+        let mut camera = Vec::new();
+        let mut light = Vec::new();
+
+        let escape = self.camera_subpath(scene, r, &mut camera);
+        self.light_subpath(scene, r.time, &mut light);
+
+        // the sky & light samples the connections take come after both subpaths
+        start_bounce(2 * self.max_depth);
+        let mut radiance = self.sky_radiance(scene, &camera, &escape);
+
+        for t in 1..=camera.len() {
+            for s in 0..=light.len() {
+                let segments = s + t - 1;
+                if (s == 1 && t == 1) || segments == 0 || segments > self.max_depth as usize {
+                    continue;
+                }
+
+                radiance += self.connect(scene, &light, &camera, s, t, film);
+            }
+        }
+
+        radiance
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{hittable_list::HittableList, sphere::Sphere, materials::*, camera::Camera, integrator::{PathTracer, MisPathTracer}, render::render, sampler::*, sky::*};
+    use std::sync::Arc;
+
+    // lambertian surfaces only, lit by one lamp that is out of view
+    fn diffuse_scene() -> Scene {
+        let mut world = HittableList::new();
+        let mut lights = HittableList::new();
+
+        world.add(Box::new(Sphere::new(Point3::new(0.0, -1000.0, 0.0), 1000.0, Arc::new(Lambertian::new(Color::fromv(0.7))))));
+        world.add(Box::new(Sphere::new(Point3::newi(0, 1, 0), 1.0, Arc::new(Lambertian::new(Color::new(0.8, 0.3, 0.3))))));
+        world.add(Box::new(Sphere::new(Point3::new(2.0, 0.5, 1.0), 0.5, Arc::new(Lambertian::new(Color::new(0.3, 0.8, 0.3))))));
+
+        let lamp = Arc::new(DiffuseLight::new(Color::fromv(60.0)));
+        world.add(Box::new(Sphere::new(Point3::new(-1.0, 5.0, 2.0), 0.5, lamp.clone())));
+        lights.add(Box::new(Sphere::new(Point3::new(-1.0, 5.0, 2.0), 0.5, lamp)));
+
+        let cam = Camera::new(Point3::newi(0, 2, 8), Point3::new(0.0, 0.8, 0.0), Vector3::newi(0, 1, 0), 40.0, 1.5, 0.0, 8.0, 0.0, 0.0);
+        Scene::new(world, lights, None, cam)
+    }
+
+    fn assert_matches_path_tracing(scene: &Scene, max_depth: u32) {
+        const SAMPLES: u32 = 256;
+
+        let mut reference = Film::new(12, 8);
+        let sampler: Arc<dyn Sampler> = Arc::new(Independent);
+        render(scene, &MisPathTracer::new(max_depth, max_depth), &mut reference, &sampler, SAMPLES);
+        let mut bidirectional = Film::new(12, 8);
+        render(scene, &Bdpt::new(max_depth), &mut bidirectional, &sampler, SAMPLES);
+
+        let expected = reference.quadrant_means();
+        let actual = bidirectional.quadrant_means();
+        let image_mean = expected.iter().fold(Color::zeros(), |sum, q| sum + *q) / 4;
+
+        for (e, a) in expected.iter().zip(actual.iter()) {
+            let difference = *e - *a;
+            assert!(difference.magnitude() < 0.05 * (e.magnitude() + image_mean.magnitude()),
+                    "quadrant means differ: {} {} {} vs {} {} {}", e.x, e.y, e.z, a.x, a.y, a.z);
+        }
+    }
+
+    #[test]
+    fn matches_unidirectional_path_tracing_on_diffuse_scene() {
+        assert_matches_path_tracing(&diffuse_scene(), 5);
+    }
+
+    #[test]
+    fn paths_are_as_long_as_the_path_tracers() {
+        // mostly sky lit, so one segment too many from sampling it shows
+        let mut scene = diffuse_scene();
+        scene.sky = Some(Box::new(GradientSky::new(Color::fromv(1.0), Color::new(0.5, 0.7, 1.0))));
+
+        for max_depth in 1..=3 {
+            assert_matches_path_tracing(&scene, max_depth);
+        }
+    }
+
+    #[test]
+    fn sky_seen_directly_is_as_bright_as_the_path_tracers() {
+        let sampler: Arc<dyn Sampler> = Arc::new(Halton);
+        let sun = Vector3::new(0.0, 1.0, 1.0).normalized();
+        let skies: [(Box<dyn Sky>, f32); 3] = [
+            (Box::new(GradientSky::new(Color::fromv(1.0), Color::new(0.5, 0.7, 1.0))), 90.0),
+            (Box::new(PhysicalSky::new(sun, 3.0)), 20.0),
+            (Box::new(PhysicalSky::new(sun, 3.0)), 90.0),
+        ];
+
+        // nothing in the way, so every camera ray sees the sky & both come out the same
+        for (sky, vfov) in skies {
+            let camera = Camera::new(Point3::zeros(), sun, Vector3::newi(0, 1, 0), vfov, 1.0, 0.0, 1.0, 0.0, 0.0);
+            let scene = Scene::new(HittableList::new(), HittableList::new(), Some(sky), camera);
+            let mut reference = Film::new(8, 8);
+            render(&scene, &PathTracer::new(3, 3), &mut reference, &sampler, 16);
+            let mut bidirectional = Film::new(8, 8);
+            render(&scene, &Bdpt::new(3), &mut bidirectional, &sampler, 16);
+
+            for j in 0..8 {
+                for i in 0..8 {
+                    let (e, a) = (reference.pixel(i, j), bidirectional.pixel(i, j));
+                    assert!((e - a).magnitude() <= 1e-4 * e.magnitude(), "pixel {} {}: {} vs {}", i, j, e.y, a.y);
+                }
+            }
+        }
+    }
+}
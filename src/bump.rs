@@ -0,0 +1,219 @@
+use crate::{
+            vectors::*,
+            rays::Ray,
+            hittable::*,
+            texture::Texture,
+            onb::Onb,
+            materials::Material,
+};
+use std::sync::Arc;
+
+
+// surface detail without the geometry: wrappers that tilt the normal of whatever their object
+// hit, before any material sees it. the geometry stays as it was, so shadows & silhouettes
+// don't change. both need the shape to fill in rec.dpdu & rec.dpdv (spheres & rects do)
+
+
+// the normal facing out of the object, whichever side the ray came from
+fn outward_normal(rec: &HitRecord) -> Vector3 {
+    if rec.front_face {rec.normal} else {-rec.normal}
+}
+
+
+// sets the tilted outward normal, flipped to face the ray the same way the geometric one did
+fn set_outward_normal(rec: &mut HitRecord, outward: Vector3) {
+    let outward = outward.normalized();
+    rec.normal = if rec.front_face {outward} else {-outward};
+}
+
+
+
+// tangent space normal map: the texture's rgb in [0, 1] is a normal in the frame of the surface's
+// tangent (dpdu), bitangent & normal, so (0.5, 0.5, 1) leaves it as it is
+pub struct NormalMap {
+    pub object: Box<dyn Hittable>,
+    pub map: Arc<dyn Texture>,
+}
+
+
+impl NormalMap {
+    pub fn new(object: Box<dyn Hittable>, map: Arc<dyn Texture>) -> NormalMap {
+        NormalMap { object, map }
+    }
+}
+
+
+impl Hittable for NormalMap {
+
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32, rec: &mut HitRecord) -> bool {
+        if !self.object.hit(r, t_min, t_max, rec) {
+            return false;
+        }
+
+        let n = outward_normal(rec);
+        let frame = Onb::build_from_w_along(&n, &rec.dpdu);
+
+        let encoded = self.map.value(rec.u, rec.v, &rec.p);
+        let local = 2.0 * encoded - Color::fromv(1.0);
+        set_outward_normal(rec, frame.local(local.x, local.y, local.z));
+
+        true
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vector3) -> f32 {
+        self.object.pdf_value(origin, direction)
+    }
+
+    fn random(&self, origin: &Point3) -> Vector3 {
+        self.object.random(origin)
+    }
+
+    fn sample_surface(&self) -> Option<(HitRecord, f32)> {
+        self.object.sample_surface()
+    }
+
+    fn surface_pdf(&self, p: &Point3) -> f32 {
+        self.object.surface_pdf(p)
+    }
+
+    fn materials(&self) -> Vec<Arc<dyn Material>> {
+        self.object.materials()
+    }
+
+}
+
+
+
+// bump map: the texture is a height along the normal, scaled by scale, & the normal is that of
+// the surface displaced by it (blinn), from finite differences in u & v
+pub struct BumpMap {
+    pub object: Box<dyn Hittable>,
+    pub height: Arc<dyn Texture>,
+    pub scale: f32,
+}
+
+
+// step in u & v for the finite differences
+const BUMP_DELTA: f32 = 1e-3;
+
+
+impl BumpMap {
+    pub fn new(object: Box<dyn Hittable>, height: Arc<dyn Texture>, scale: f32) -> BumpMap {
+        BumpMap { object, height, scale }
+    }
+}
+
+
+impl Hittable for BumpMap {
+
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32, rec: &mut HitRecord) -> bool {
+        if !self.object.hit(r, t_min, t_max, rec) {
+            return false;
+        }
+        if rec.dpdu.near_zero() || rec.dpdv.near_zero() {
+            return true;
+        }
+
+        let (u, v, p) = (rec.u, rec.v, rec.p);
+        let height = |u: f32, v: f32, p: &Point3| self.scale * self.height.scalar(u, v, p);
+        let displace = height(u, v, &p);
+        let u_displace = height(u + BUMP_DELTA, v, &(p + BUMP_DELTA * rec.dpdu));
+        let v_displace = height(u, v + BUMP_DELTA, &(p + BUMP_DELTA * rec.dpdv));
+
+        // how the displaced surface moves with u & v, ignoring how the normal itself bends
+        let n = outward_normal(rec);
+        let dpdu = rec.dpdu + (u_displace - displace) / BUMP_DELTA * n;
+        let dpdv = rec.dpdv + (v_displace - displace) / BUMP_DELTA * n;
+
+        // the parameterization may run either way round, keep to the side the geometric normal is on
+        let bumped = Vector3::cross(&dpdu, &dpdv);
+        let bumped = if Vector3::dot(&bumped, &n) < 0.0 {-bumped} else {bumped};
+        set_outward_normal(rec, bumped);
+
+        true
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vector3) -> f32 {
+        self.object.pdf_value(origin, direction)
+    }
+
+    fn random(&self, origin: &Point3) -> Vector3 {
+        self.object.random(origin)
+    }
+
+    fn sample_surface(&self) -> Option<(HitRecord, f32)> {
+        self.object.sample_surface()
+    }
+
+    fn surface_pdf(&self, p: &Point3) -> f32 {
+        self.object.surface_pdf(p)
+    }
+
+    fn materials(&self) -> Vec<Arc<dyn Material>> {
+        self.object.materials()
+    }
+
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{aarect::XyRect, sphere::*, materials::Lambertian, texture::*};
+
+    fn plate() -> Box<dyn Hittable> {
+        Box::new(XyRect::new(0.0, 2.0, 0.0, 1.0, 0.0, Arc::new(Lambertian::new(Color::fromv(0.5)))))
+    }
+
+    fn normal_at(object: &dyn Hittable, r: &Ray) -> Vector3 {
+        let mut rec = HitRecord::new();
+        assert!(object.hit(r, 0.001, f32::INFINITY, &mut rec));
+        rec.normal
+    }
+
+    #[test]
+    fn sphere_partials_follow_the_uv_mapping() {
+        let direction = Vector3::new(0.3, -0.4, 0.5).normalized();
+        let (u, v) = get_sphere_uv(&direction);
+        let (dpdu, dpdv) = get_sphere_partials(&direction, 2.0);
+
+        // step along u & v, & find the point on the sphere they map to
+        let at = |u: f32, v: f32| {
+            let (theta, phi) = (std::f32::consts::PI * v, 2.0 * std::f32::consts::PI * u);
+            2.0 * Vector3::new(-theta.sin() * phi.cos(), -theta.cos(), theta.sin() * phi.sin())
+        };
+        assert!((at(u, v) - 2.0 * direction).magnitude() < 1e-4);
+        assert!(((at(u + 1e-3, v) - at(u - 1e-3, v)) / 2e-3 - dpdu).magnitude() < 1e-2);
+        assert!(((at(u, v + 1e-3) - at(u, v - 1e-3)) / 2e-3 - dpdv).magnitude() < 1e-2);
+    }
+
+    #[test]
+    fn flat_maps_leave_the_normal_alone() {
+        let r = Ray::new(Point3::new(0.5, 0.5, 1.0), Vector3::newi(0, 0, -1), 0.0);
+        let from_behind = Ray::new(Point3::new(0.5, 0.5, -1.0), Vector3::newi(0, 0, 1), 0.0);
+
+        let mapped = NormalMap::new(plate(), solid(Color::new(0.5, 0.5, 1.0)));
+        assert!((normal_at(&mapped, &r) - Vector3::newi(0, 0, 1)).magnitude() < 1e-5);
+        assert!((normal_at(&mapped, &from_behind) - Vector3::newi(0, 0, -1)).magnitude() < 1e-5);
+
+        let bumped = BumpMap::new(plate(), constant(0.7), 0.1);
+        assert!((normal_at(&bumped, &r) - Vector3::newi(0, 0, 1)).magnitude() < 1e-5);
+        assert!((normal_at(&bumped, &from_behind) - Vector3::newi(0, 0, -1)).magnitude() < 1e-5);
+    }
+
+    #[test]
+    fn slopes_tilt_the_normal() {
+        let r = Ray::new(Point3::new(0.5, 0.5, 1.0), Vector3::newi(0, 0, -1), 0.0);
+
+        // height rising by 0.5 across the plate's 2 units of x, a slope of 1 in 4
+        let ramp = BumpMap::new(plate(), Arc::new(Procedural::new(|u, _, _| Color::fromv(u))), 0.5);
+        let expected = Vector3::new(-0.25, 0.0, 1.0).normalized();
+        assert!((normal_at(&ramp, &r) - expected).magnitude() < 1e-3);
+
+        // a normal map pointing half way towards the tangent, which runs along x
+        let tilted = NormalMap::new(plate(), solid(Color::new(1.0, 0.5, 1.0)));
+        let expected = Vector3::new(1.0, 0.0, 1.0).normalized();
+        assert!((normal_at(&tilted, &r) - expected).magnitude() < 1e-5);
+    }
+}
@@ -1,5 +1,6 @@
 use crate::{vectors::*, rays::*};
-use rand::prelude::*;
+use crate::random::random_range;
+use std::f32::consts::PI;
 
 pub struct Camera {
 
@@ -10,6 +11,8 @@ pub struct Camera {
     lens_radius: f32,
     u: Vector3,
     v: Vector3,
+    w: Vector3,
+    focus_dist: f32,
     time0: f32, // shutter opens
     time1: f32, // shutter closes
 }
@@ -17,6 +20,7 @@ pub struct Camera {
 
 impl Camera {
 
+    #[allow(clippy::too_many_arguments)]
     pub fn new(look_from: Point3, look_at: Point3, vup: Vector3, vfov: f32, aspect_ratio: f32, aperture: f32, focus_dist: f32, time0: f32, time1: f32) -> Camera {
         let theta = vfov.to_radians();
         let h = (theta / 2.0).tan();
@@ -36,16 +40,115 @@ impl Camera {
         let lower_left_corner = origin - horizontal / 2.0 - vertical / 2.0 - focus_dist * w;
         let lens_radius = aperture / 2.0;
 
-        Camera { origin, lower_left_corner, horizontal, vertical, lens_radius, u, v, time0, time1}
+        Camera { origin, lower_left_corner, horizontal, vertical, lens_radius, u, v, w, focus_dist, time0, time1}
 
     }
 
     pub fn get_ray(&self, s: f32, t: f32) -> Ray {
         let rd: Vector3 = self.lens_radius * Vector3::random_in_unit_disk();
         let offset: Vector3 = self.u * rd.x + self.v * rd.y;
-        let mut rng = thread_rng();
 
-        Ray::new(self.origin + offset, self.lower_left_corner + s * self.horizontal + t * self.vertical -self.origin - offset, rng.gen_range(self.time0..self.time1))
+        Ray::new(self.origin + offset, self.lower_left_corner + s * self.horizontal + t * self.vertical -self.origin - offset, self.shutter_time())
     }
 
-}
\ No newline at end of file
+    // a random moment while the shutter is open. the number is drawn even when it's open for no time
+    // at all, so the numbers drawn after it don't move to other sampler dimensions
+    pub fn shutter_time(&self) -> f32 {
+        let time = random_range(self.time0, self.time1);
+        if self.time1 > self.time0 {time} else {self.time0}
+    }
+
+
+    // how far a point moving at velocity, seen at time, travels across the image while the shutter
+    // is open, in the (s, t) get_ray takes. None for points behind the camera
+    pub fn image_motion(&self, p: &Point3, velocity: &Vector3, time: f32) -> Option<(f32, f32)> {
+        let project = |q: Point3| {
+            let direction = q - self.origin;
+            let depth = Vector3::dot(&direction, &self.forward());
+            if depth <= 0.0 {
+                return None;
+            }
+
+            let offset = self.origin + (self.focus_dist / depth) * direction - self.lower_left_corner;
+            Some((Vector3::dot(&offset, &self.horizontal) / self.horizontal.magnitude_squared(),
+                  Vector3::dot(&offset, &self.vertical) / self.vertical.magnitude_squared()))
+        };
+
+        let (s0, t0) = project(*p + (self.time0 - time) * *velocity)?;
+        let (s1, t1) = project(*p + (self.time1 - time) * *velocity)?;
+        Some((s1 - s0, t1 - t0))
+    }
+
+
+    // the rest treats the camera as something that emits importance, so light paths can reach it
+
+    // direction the camera looks in
+    pub fn forward(&self) -> Vector3 {
+        -self.w
+    }
+
+    fn lens_area(&self) -> f32 {
+        if self.lens_radius > 0.0 {PI * self.lens_radius * self.lens_radius} else {1.0}
+    }
+
+    // area of the image plane when moved to unit distance from the lens
+    fn image_plane_area(&self) -> f32 {
+        self.horizontal.magnitude() * self.vertical.magnitude() / (self.focus_dist * self.focus_dist)
+    }
+
+    // where a ray leaving the lens lands on the image, as the (s, t) get_ray would have made it from
+    fn image_coordinates(&self, r: &Ray) -> Option<(f32, f32, f32)> {
+        let direction = r.direction.normalized();
+        let cos_theta = Vector3::dot(&direction, &self.forward());
+        if cos_theta <= 0.0 {
+            return None;
+        }
+
+        let on_focus_plane = r.origin + (self.focus_dist / cos_theta) * direction;
+        let offset = on_focus_plane - self.lower_left_corner;
+        let s = Vector3::dot(&offset, &self.horizontal) / self.horizontal.magnitude_squared();
+        let t = Vector3::dot(&offset, &self.vertical) / self.vertical.magnitude_squared();
+
+        if (0.0..=1.0).contains(&s) && (0.0..=1.0).contains(&t) {Some((s, t, cos_theta))} else {None}
+    }
+
+    // importance carried along r, with the image coordinates it belongs to
+    pub fn importance(&self, r: &Ray) -> Option<(f32, f32, f32)> {
+        let (s, t, cos_theta) = self.image_coordinates(r)?;
+        let importance = 1.0 / (self.image_plane_area() * self.lens_area() * cos_theta.powi(4));
+
+        Some((importance, s, t))
+    }
+
+    // (area pdf of r's origin on the lens, solid angle pdf of its direction) had get_ray made r
+    pub fn pdf_importance(&self, r: &Ray) -> (f32, f32) {
+        match self.image_coordinates(r) {
+            Some((_, _, cos_theta)) => (1.0 / self.lens_area(), 1.0 / (self.image_plane_area() * cos_theta.powi(3))),
+            None => (0.0, 0.0),
+        }
+    }
+
+    // picks a point on the lens to connect p to. the pdf is a solid angle as seen from p
+    pub fn sample_lens(&self, p: &Point3) -> Option<LensSample> {
+        let rd: Vector3 = self.lens_radius * Vector3::random_in_unit_disk();
+        let point = self.origin + self.u * rd.x + self.v * rd.y;
+
+        let to_lens = point - *p;
+        let distance_squared = to_lens.magnitude_squared();
+        let cos_lens = Vector3::dot(&to_lens.normalized(), &self.w).abs();
+        let (importance, s, t) = self.importance(&Ray::new(point, -to_lens, 0.0))?;
+        let pdf = distance_squared / (cos_lens * self.lens_area());
+
+        Some(LensSample { point, pdf, importance, s, t })
+    }
+
+}
+
+
+pub struct LensSample {
+    pub point: Point3,
+    pub pdf: f32,
+    pub importance: f32,
+    pub s: f32,
+    pub t: f32,
+}
@@ -0,0 +1,235 @@
+use crate::{vectors::Color, colors::clamp};
+use std::cell::Cell;
+
+
+// rgb color spaces, by their primaries & white. every one here is linear, how values are encoded
+// on top of that is a Transfer. the scene's colors are declared in linear srgb unless they say
+// otherwise, & rendering happens in the working space, which is srgb or acescg
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ColorSpace {
+    Srgb, // rec. 709 primaries, d65
+    AcesCg, // aces ap1 primaries, d60
+    DisplayP3, // dci-p3 primaries, d65
+    Rec2020,
+}
+
+
+// how stored values relate to linear light
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Transfer {
+    Linear,
+    Srgb, // the srgb curve, which display p3 shares
+    Gamma24, // bt.1886, rec. 2020's displays
+}
+
+
+type Matrix = [[f32; 3]; 3];
+
+fn transform(m: &Matrix, c: &Color) -> Color {
+    Color::new(
+        m[0][0] * c.x + m[0][1] * c.y + m[0][2] * c.z,
+        m[1][0] * c.x + m[1][1] * c.y + m[1][2] * c.z,
+        m[2][0] * c.x + m[2][1] * c.y + m[2][2] * c.z,
+    )
+}
+
+
+// to & from linear srgb, bradford adapted between d60 & d65 for acescg so white stays (1, 1, 1)
+const ACESCG_TO_SRGB: Matrix = [
+    [ 1.705_051, -0.621_792, -0.083_259],
+    [-0.130_256,  1.140_805, -0.010_548],
+    [-0.024_003, -0.128_969,  1.152_972],
+];
+const SRGB_TO_ACESCG: Matrix = [
+    [0.613_097, 0.339_523, 0.047_379],
+    [0.070_194, 0.916_354, 0.013_452],
+    [0.020_616, 0.109_570, 0.869_815],
+];
+const DISPLAY_P3_TO_SRGB: Matrix = [
+    [ 1.224_94,  -0.224_94,  0.0],
+    [-0.042_057,  1.042_057, 0.0],
+    [-0.019_638, -0.078_636, 1.098_274],
+];
+const SRGB_TO_DISPLAY_P3: Matrix = [
+    [0.822_462, 0.177_538, 0.0],
+    [0.033_194, 0.966_806, 0.0],
+    [0.017_083, 0.072_397, 0.910_520],
+];
+const REC2020_TO_SRGB: Matrix = [
+    [ 1.660_491, -0.587_641, -0.072_850],
+    [-0.124_550,  1.132_9,   -0.008_349],
+    [-0.018_151, -0.100_579,  1.118_73 ],
+];
+const SRGB_TO_REC2020: Matrix = [
+    [0.627_404, 0.329_283, 0.043_313],
+    [0.069_097, 0.919_540, 0.011_362],
+    [0.016_391, 0.088_013, 0.895_595],
+];
+const SRGB_TO_XYZ: Matrix = [
+    [0.4124, 0.3576, 0.1805],
+    [0.2126, 0.7152, 0.0722],
+    [0.0193, 0.1192, 0.9505],
+];
+const XYZ_TO_SRGB: Matrix = [
+    [ 3.2406, -1.5372, -0.4986],
+    [-0.9689,  1.8758,  0.0415],
+    [ 0.0557, -0.2040,  1.0570],
+];
+
+
+impl ColorSpace {
+
+    pub fn from_name(name: &str) -> Option<ColorSpace> {
+        match name {
+            "srgb" | "rec709" => Some(ColorSpace::Srgb),
+            "acescg" => Some(ColorSpace::AcesCg),
+            "display-p3" => Some(ColorSpace::DisplayP3),
+            "rec2020" => Some(ColorSpace::Rec2020),
+            _ => None,
+        }
+    }
+
+    // c in this space as linear srgb
+    pub fn to_srgb(self, c: &Color) -> Color {
+        match self {
+            ColorSpace::Srgb => *c,
+            ColorSpace::AcesCg => transform(&ACESCG_TO_SRGB, c),
+            ColorSpace::DisplayP3 => transform(&DISPLAY_P3_TO_SRGB, c),
+            ColorSpace::Rec2020 => transform(&REC2020_TO_SRGB, c),
+        }
+    }
+
+    // linear srgb c in this space
+    pub fn srgb_in(self, c: &Color) -> Color {
+        match self {
+            ColorSpace::Srgb => *c,
+            ColorSpace::AcesCg => transform(&SRGB_TO_ACESCG, c),
+            ColorSpace::DisplayP3 => transform(&SRGB_TO_DISPLAY_P3, c),
+            ColorSpace::Rec2020 => transform(&SRGB_TO_REC2020, c),
+        }
+    }
+
+    pub fn convert(self, c: &Color, to: ColorSpace) -> Color {
+        if self == to {*c} else {to.srgb_in(&self.to_srgb(c))}
+    }
+
+    // d65 xyz, whites adapted to it
+    pub fn to_xyz(self, c: &Color) -> Color {
+        transform(&SRGB_TO_XYZ, &self.to_srgb(c))
+    }
+
+    pub fn xyz_in(self, xyz: &Color) -> Color {
+        self.srgb_in(&transform(&XYZ_TO_SRGB, xyz))
+    }
+
+    // how a display with these primaries encodes its signal
+    pub fn display_transfer(self) -> Transfer {
+        match self {
+            ColorSpace::Rec2020 => Transfer::Gamma24,
+            _ => Transfer::Srgb,
+        }
+    }
+
+}
+
+
+impl Transfer {
+
+    // a stored value in [0, 1] to linear light
+    pub fn decode(&self, x: f32) -> f32 {
+        match self {
+            Transfer::Linear => x,
+            Transfer::Srgb => if x <= 0.040_45 {x / 12.92} else {((x + 0.055) / 1.055).powf(2.4)},
+            Transfer::Gamma24 => x.max(0.0).powf(2.4),
+        }
+    }
+
+    // linear light to the stored value, clamped to the display's range
+    pub fn encode(&self, x: f32) -> f32 {
+        let x = clamp(x, 0.0, 1.0);
+        match self {
+            Transfer::Linear => x,
+            Transfer::Srgb => srgb_oetf(x),
+            Transfer::Gamma24 => x.powf(1.0 / 2.4),
+        }
+    }
+
+}
+
+
+// the exact srgb transfer function, linear near black, clamped to the display's range
+pub fn srgb_oetf(x: f32) -> f32 {
+    let x = clamp(x, 0.0, 1.0);
+    if x <= 0.003_130_8 {12.92 * x} else {1.055 * x.powf(1.0 / 2.4) - 0.055}
+}
+
+
+
+thread_local! {
+    static WORKING_SPACE: Cell<ColorSpace> = const { Cell::new(ColorSpace::Srgb) };
+}
+
+
+// runs f rendering in space, every color the scene declares is converted into it as it's used
+pub fn with_working_space<R, F: FnOnce() -> R>(space: ColorSpace, f: F) -> R {
+    let previous = WORKING_SPACE.with(|w| w.replace(space));
+    let result = f();
+    WORKING_SPACE.with(|w| w.set(previous));
+
+    result
+}
+
+
+pub fn working_space() -> ColorSpace {
+    WORKING_SPACE.with(|w| w.get())
+}
+
+
+// a scene color, linear srgb, in the working space
+pub fn to_working(c: &Color) -> Color {
+    working_space().srgb_in(c)
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conversions_round_trip_and_keep_white() {
+        let spaces = [ColorSpace::Srgb, ColorSpace::AcesCg, ColorSpace::DisplayP3, ColorSpace::Rec2020];
+        let c = Color::new(0.9, 0.2, 0.05);
+
+        for from in spaces.iter() {
+            for to in spaces.iter() {
+                let white = from.convert(&Color::fromv(1.0), *to);
+                assert!((white - Color::fromv(1.0)).magnitude() < 1e-3);
+
+                let back = to.convert(&from.convert(&c, *to), *from);
+                assert!((back - c).magnitude() < 1e-4);
+            }
+        }
+
+        // srgb's red is inside the wider gamuts, & theirs outside srgb
+        assert!([ColorSpace::AcesCg, ColorSpace::DisplayP3, ColorSpace::Rec2020].iter().all(|space| {
+            let red = space.srgb_in(&Color::newi(1, 0, 0));
+            red.x < 1.0 && red.y > 0.0 && red.z >= 0.0 && space.to_srgb(&Color::newi(1, 0, 0)).y < 0.0
+        }));
+    }
+
+    #[test]
+    fn srgb_decoding_inverts_encoding() {
+        assert_eq!(Transfer::Srgb.decode(0.0), 0.0);
+        assert!((Transfer::Srgb.decode(1.0) - 1.0).abs() < 1e-6);
+        // 8 bit middle gray isn't half the light
+        assert!((Transfer::Srgb.decode(128.0 / 255.0) - 0.215_861).abs() < 1e-5);
+
+        for transfer in [Transfer::Linear, Transfer::Srgb, Transfer::Gamma24].iter() {
+            for k in 0..=20 {
+                let x = k as f32 / 20.0;
+                assert!((transfer.encode(transfer.decode(x)) - x).abs() < 1e-5);
+            }
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use crate::{
+            vectors::*,
+            rays::Ray,
+            hittable::HitRecord,
+            materials::Material,
+            texture::Texture,
+            layered::hash_point,
+};
+use std::sync::Arc;
+
+
+// an alpha mask over any material, for leaves & fences cut out of flat cards. where the mask says
+// the surface isn't there, hittable lists skip the hit & look further along the ray, so the hole
+// lets through camera rays, shadow rays & light paths alike. with a threshold the mask is cut
+// hard at it, without one alpha is the chance of a hit, picked by hashing the hit point so every
+// ray through the same point agrees (as Mix does)
+pub struct Cutout {
+    pub material: Arc<dyn Material>,
+    pub alpha: Arc<dyn Texture>,
+    pub threshold: Option<f32>,
+}
+
+
+impl Cutout {
+
+    pub fn new(material: Arc<dyn Material>, alpha: Arc<dyn Texture>) -> Cutout {
+        Cutout { material, alpha, threshold: None }
+    }
+
+    pub fn with_threshold(self, threshold: f32) -> Cutout {
+        Cutout { threshold: Some(threshold), ..self }
+    }
+
+}
+
+
+impl Material for Cutout {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Ray, Color)> {
+        self.material.scatter(r_in, rec)
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Color {
+        self.material.emitted(rec)
+    }
+
+    fn is_specular(&self) -> bool {
+        self.material.is_specular()
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: &Vector3) -> Color {
+        self.material.eval(r_in, rec, direction)
+    }
+
+    fn scattering_pdf(&self, r_in: &Ray, rec: &HitRecord, direction: &Vector3) -> f32 {
+        self.material.scattering_pdf(r_in, rec, direction)
+    }
+
+    fn is_cut_out(&self, rec: &HitRecord) -> bool {
+        let alpha = self.alpha.scalar(rec.u, rec.v, &rec.p);
+
+        match self.threshold {
+            Some(threshold) => alpha < threshold,
+            None => hash_point(&rec.p) >= alpha,
+        }
+    }
+
+    fn diffuse_fraction(&self, r_in: &Ray, rec: &HitRecord, direction: &Vector3) -> f32 {
+        self.material.diffuse_fraction(r_in, rec, direction)
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{aarect::XyRect, hittable::Hittable, hittable_list::HittableList, materials::Lambertian, texture::*, random::random_f32};
+
+    // a card at z = 0 in front of a wall at z = -1, which hits land on
+    fn card_and_wall(card: Cutout) -> HittableList {
+        let mut world = HittableList::new();
+        world.add(Box::new(XyRect::new(0.0, 1.0, 0.0, 1.0, 0.0, Arc::new(card))));
+        world.add(Box::new(XyRect::new(-5.0, 5.0, -5.0, 5.0, -1.0, Arc::new(Lambertian::new(Color::zeros())))));
+        world
+    }
+
+    fn hit_depth(world: &HittableList, x: f32, y: f32) -> f32 {
+        let mut rec = HitRecord::new();
+        assert!(world.hit(&Ray::new(Point3::new(x, y, 1.0), Vector3::newi(0, 0, -1), 0.0), 0.001, f32::INFINITY, &mut rec));
+        rec.p.z
+    }
+
+    #[test]
+    fn thresholded_masks_cut_holes() {
+        // solid on the left half of the card, cut out on the right
+        let mask = Arc::new(Procedural::new(|u, _, _| Color::fromv(if u < 0.5 {0.9} else {0.1})));
+        let world = card_and_wall(Cutout::new(Arc::new(Lambertian::new(Color::fromv(0.5))), mask).with_threshold(0.5));
+
+        assert!(hit_depth(&world, 0.25, 0.5).abs() < 1e-5);
+        assert!((hit_depth(&world, 0.75, 0.5) + 1.0).abs() < 1e-5);
+
+        // & several cut out cards in a row don't stop the search either
+        let mut stack = HittableList::new();
+        for i in 0..4 {
+            let card = Cutout::new(Arc::new(Lambertian::new(Color::fromv(0.5))), constant(0.0)).with_threshold(0.5);
+            stack.add(Box::new(XyRect::new(0.0, 1.0, 0.0, 1.0, -0.1 * i as f32, Arc::new(card))));
+        }
+        stack.add(Box::new(XyRect::new(-5.0, 5.0, -5.0, 5.0, -1.0, Arc::new(Lambertian::new(Color::zeros())))));
+        assert!((hit_depth(&stack, 0.5, 0.5) + 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn stochastic_masks_cover_alpha_of_the_surface() {
+        let world = card_and_wall(Cutout::new(Arc::new(Lambertian::new(Color::fromv(0.5))), constant(0.3)));
+        let samples = 100_000;
+
+        let covered = (0..samples).filter(|_| hit_depth(&world, random_f32(), random_f32()) > -0.5).count();
+        assert!((covered as f32 / samples as f32 - 0.3).abs() < 0.01);
+
+        // the same point is always either there or not
+        let (x, y) = (0.37, 0.61);
+        let first = hit_depth(&world, x, y);
+        assert!((0..10).all(|_| hit_depth(&world, x, y) == first));
+    }
+}
@@ -0,0 +1,225 @@
+use crate::{vectors::*, rays::Ray, hittable::*, scene::Scene, film::Film, colors::{luminance, clamp}};
+use std::ops::AddAssign;
+
+
+// what a camera ray saw first, gathered alongside the beauty pass so the denoiser can tell the
+// edges of the scene from noise
+#[derive(Copy, Clone)]
+pub struct Features {
+    pub albedo: Color, // through perfectly specular surfaces, to the first one that isn't
+    pub normal: Vector3, // zero where the ray missed
+    pub depth: f32, // distance along the ray
+}
+
+
+// depth of the sky, far enough that anything hit stands apart from it
+const MISS_DEPTH: f32 = 1e6;
+
+// specular bounces followed looking for an albedo, past glass & mirrors
+const FEATURE_SPECULAR_BOUNCES: u32 = 4;
+
+
+impl Features {
+
+    pub fn zeros() -> Features {
+        Features { albedo: Color::zeros(), normal: Vector3::zeros(), depth: 0.0 }
+    }
+
+    pub fn of_first_hit(scene: &Scene, r: &Ray) -> Features {
+        let clamped = |c: Color| Color::new(clamp(c.x, 0.0, 1.0), clamp(c.y, 0.0, 1.0), clamp(c.z, 0.0, 1.0));
+        let mut features = Features { albedo: Color::fromv(1.0), normal: Vector3::zeros(), depth: MISS_DEPTH };
+        let mut rec = HitRecord::new();
+        let mut ray = *r;
+
+        for bounce in 0..FEATURE_SPECULAR_BOUNCES {
+            if !scene.world.hit(&ray, 0.001, f32::INFINITY, &mut rec) {
+                features.albedo *= clamped(scene.background(&ray.direction));
+                break;
+            }
+            if bounce == 0 {
+                features.normal = rec.normal;
+                features.depth = rec.t * ray.direction.magnitude();
+            }
+
+            // one sample of the directional albedo, averaged over the pixel's samples
+            let (scattered, attenuation) = match rec.mat.scatter(&ray, &rec) {
+                Some(scattered) => scattered,
+                None => {
+                    features.albedo *= clamped(rec.mat.emitted(&rec));
+                    break;
+                }
+            };
+            features.albedo *= attenuation;
+            if !rec.mat.is_specular() {
+                break;
+            }
+            ray = scattered;
+        }
+
+        features
+    }
+
+}
+
+
+impl AddAssign for Features {
+    fn add_assign(&mut self, other: Features) {
+        self.albedo += other.albedo;
+        self.normal += other.normal;
+        self.depth += other.depth;
+    }
+}
+
+
+
+// edge avoiding à-trous wavelet filter, after dammertz et al., "edge-avoiding à-trous wavelet
+// transform for fast global illumination filtering", with the variance guided edge stopping of
+// schied et al.'s svgf. the image is divided by its albedo so textures aren't blurred, filtered
+// with a b3 spline kernel whose taps spread twice as far each iteration, & multiplied back
+pub struct Denoiser {
+    pub iterations: u32,
+    pub color_sigma: f32, // standard deviations of luminance difference that still count as noise
+    pub normal_power: f32, // how sharply differences in normal stop the filter
+    pub depth_sigma: f32, // relative difference in depth, per pixel of tap spacing
+}
+
+
+// never divide by less albedo than this
+const MIN_ALBEDO: f32 = 0.01;
+
+const B3_SPLINE: [f32; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+
+// one pixel while it's being filtered
+#[derive(Copy, Clone)]
+struct Texel {
+    color: Color, // albedo divided out
+    variance: f32, // of its luminance
+    albedo: Color,
+    normal: Option<Vector3>,
+    depth: f32,
+}
+
+
+impl Denoiser {
+
+    pub fn new(iterations: u32) -> Denoiser {
+        Denoiser { iterations, color_sigma: 4.0, normal_power: 128.0, depth_sigma: 0.1 }
+    }
+
+    // the denoised image, rows from the bottom like the film's
+    pub fn denoise(&self, film: &Film) -> Vec<Color> {
+        let (width, height) = (film.width as i32, film.height as i32);
+
+        let mut texels: Vec<Texel> = (0..height).flat_map(|j| (0..width).map(move |i| (i as u32, j as u32))).map(|(i, j)| {
+            let features = film.features(i, j);
+            let albedo = match features {
+                Some(f) => Color::new(f.albedo.x.max(MIN_ALBEDO), f.albedo.y.max(MIN_ALBEDO), f.albedo.z.max(MIN_ALBEDO)),
+                None => Color::fromv(1.0),
+            };
+            let normal = features.map(|f| f.normal).filter(|n| n.magnitude() > 1e-3).map(|n| n.normalized());
+            let variance = film.mean_variance(i, j).unwrap_or(f32::NAN) / luminance(&albedo).powi(2);
+
+            Texel { color: film.beauty(i, j) / albedo, variance, albedo, normal, depth: features.map_or(MISS_DEPTH, |f| f.depth) }
+        }).collect();
+
+        // pixels with too few samples for a variance of their own borrow their neighbourhood's
+        let spatial: Vec<f32> = (0..height * width).map(|k| {
+            let (i, j) = (k % width, k / width);
+            let neighbours: Vec<f32> = (-1..=1).flat_map(|dj| (-1..=1).map(move |di| (i + di, j + dj)))
+                .filter(|&(x, y)| x >= 0 && y >= 0 && x < width && y < height)
+                .map(|(x, y)| luminance(&texels[(y * width + x) as usize].color))
+                .collect();
+            let mean = neighbours.iter().sum::<f32>() / neighbours.len() as f32;
+            neighbours.iter().map(|l| (l - mean) * (l - mean)).sum::<f32>() / neighbours.len() as f32
+        }).collect();
+        for (texel, variance) in texels.iter_mut().zip(spatial) {
+            if texel.variance.is_nan() {
+                texel.variance = variance;
+            }
+        }
+
+        for iteration in 0..self.iterations {
+            let step = 1 << iteration;
+            let filtered: Vec<Texel> = (0..height * width).map(|k| {
+                let (i, j) = (k % width, k / width);
+                let p = &texels[k as usize];
+                let sigma = self.color_sigma * p.variance.max(0.0).sqrt() + 1e-6;
+
+                let (mut color, mut variance, mut weights) = (Color::zeros(), 0.0, 0.0);
+                for (b, hy) in B3_SPLINE.iter().enumerate() {
+                    for (a, hx) in B3_SPLINE.iter().enumerate() {
+                        let (x, y) = (i + (a as i32 - 2) * step, j + (b as i32 - 2) * step);
+                        if x < 0 || y < 0 || x >= width || y >= height {
+                            continue;
+                        }
+                        let q = &texels[(y * width + x) as usize];
+
+                        let w_color = (-(luminance(&p.color) - luminance(&q.color)).abs() / sigma).exp();
+                        let w_normal = match (p.normal, q.normal) {
+                            (Some(np), Some(nq)) => Vector3::dot(&np, &nq).max(0.0).powf(self.normal_power),
+                            (None, None) => 1.0,
+                            _ => 0.0,
+                        };
+                        let w_depth = (-(p.depth - q.depth).abs() / (self.depth_sigma * step as f32 * p.depth.max(1e-3))).exp();
+
+                        let w = hx * hy * w_color * w_normal * w_depth;
+                        color += w * q.color;
+                        variance += w * w * q.variance;
+                        weights += w;
+                    }
+                }
+
+                // the pixel itself always has weight, so weights is never zero
+                Texel { color: color / weights, variance: variance / (weights * weights), ..*p }
+            }).collect();
+            texels = filtered;
+        }
+
+        texels.iter().map(|t| t.color * t.albedo).collect()
+    }
+
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::random::random_f32;
+
+    #[test]
+    fn noise_is_smoothed_but_not_across_edges() {
+        // two walls meeting down the middle of the image, at right angles, lit differently
+        let (width, height) = (16, 8);
+        let mut film = Film::new(width, height);
+        let side = |i: u32| if i < width / 2 {(0.5, Vector3::newi(0, 0, 1))} else {(0.1, Vector3::newi(1, 0, 0))};
+
+        for j in 0..height {
+            for i in 0..width {
+                let (mean, normal) = side(i);
+                for _ in 0..16 {
+                    film.add_sample(i, j, Color::fromv(mean * 2.0 * random_f32()));
+                    film.add_features(i, j, Features { albedo: Color::fromv(0.8), normal, depth: 2.0 });
+                }
+            }
+        }
+
+        let error = |image: &dyn Fn(u32, u32) -> Color| -> f32 {
+            (0..height).flat_map(|j| (0..width).map(move |i| (i, j)))
+                .map(|(i, j)| (image(i, j).x - side(i).0).powi(2))
+                .sum::<f32>() / (width * height) as f32
+        };
+
+        let denoised = Denoiser::new(4).denoise(&film);
+        let noisy_error = error(&|i, j| film.beauty(i, j));
+        let denoised_error = error(&|i, j| denoised[(j * width + i) as usize]);
+        assert!(denoised_error < 0.2 * noisy_error, "{} vs {}", denoised_error, noisy_error);
+
+        // the pixels either side of the edge keep to their own wall's brightness
+        for j in 0..height {
+            assert!((denoised[(j * width + width / 2 - 1) as usize].x - 0.5).abs() < 0.15);
+            assert!((denoised[(j * width + width / 2) as usize].x - 0.1).abs() < 0.05);
+        }
+    }
+}
@@ -0,0 +1,727 @@
+use crate::{vectors::Color, colors::{luminance, clamp}, spectrum, filter::*, denoise::*, aov::Aov, tonemap::OutputTransform};
+use std::{convert::TryInto, fs::{self, File}, io::{self, Write, BufWriter}, ops::AddAssign, sync::Arc, time::Duration};
+
+
+// what a pixel has been sent so far
+#[derive(Copy, Clone)]
+struct PixelSums {
+    weighted: Color, // filter weighted sum of the samples
+    weight: f32, // sum of the filter weights
+    luminance: f32, // the pixel's own samples, unweighted, for the error estimates
+    square: f32, // of their luminances
+    count: u32,
+    features: Features, // summed over the samples that gathered them
+    feature_count: u32,
+    aov_count: u32, // samples that gave the aovs
+}
+
+
+impl PixelSums {
+    fn zeros() -> PixelSums {
+        PixelSums { weighted: Color::zeros(), weight: 0.0, luminance: 0.0, square: 0.0, count: 0, features: Features::zeros(), feature_count: 0, aov_count: 0 }
+    }
+
+    // one sample taken for this pixel, whichever pixels it ends up weighted into
+    fn count_sample(&mut self, color: &Color) {
+        let y = luminance(color);
+        self.luminance += y;
+        self.square += y * y;
+        self.count += 1;
+    }
+}
+
+
+impl AddAssign for PixelSums {
+    fn add_assign(&mut self, other: PixelSums) {
+        self.weighted += other.weighted;
+        self.weight += other.weight;
+        self.luminance += other.luminance;
+        self.square += other.square;
+        self.count += other.count;
+        self.features += other.features;
+        self.feature_count += other.feature_count;
+        self.aov_count += other.aov_count;
+    }
+}
+
+
+
+// where a progressive render keeps the image it has so far, & how often it rewrites it
+#[derive(Clone)]
+pub struct Snapshots {
+    pub path: String,
+    pub transform: OutputTransform,
+    pub every_passes: Option<u32>,
+    pub every_seconds: Option<f32>,
+}
+
+
+
+// where a render's accumulated state is saved every so often, to resume it from if it's stopped
+#[derive(Clone)]
+pub struct Checkpoints {
+    pub path: String,
+    pub every_seconds: f32,
+    pub seed: u64, // the scene was built with, so a resumed render can build the same one
+    pub settings: String, // what else decides how the samples come out, a line each
+}
+
+
+// what a checkpoint file starts with, & the version of its layout
+const CHECKPOINT_MAGIC: &[u8; 4] = b"RTCK";
+const CHECKPOINT_VERSION: u32 = 4;
+
+
+
+// accumulates the samples of every pixel, plus light splatted straight onto the image
+// by integrators that trace paths from the lights. samples are weighted into the pixels around
+// them by the reconstruction filter, or with filter importance sampling, placed around their own
+// pixel's center by it & kept there. the spread of each pixel's samples says how far its mean may
+// still be off, which adaptive sampling goes by
+pub struct Film {
+    pub width: u32,
+    pub height: u32,
+    pub target_error: Option<f32>, // relative error pixels are sampled down to, None samples them all alike
+    pub filter: Arc<dyn Filter>,
+    pub filter_sampler: Option<Arc<FilterSampler>>, // Some with filter importance sampling
+    pub gather_features: bool, // whether samples also look up what they hit first, for the denoiser
+    pub aovs: Vec<Aov>, // passes kept besides the beauty image
+    pub snapshots: Option<Snapshots>, // Some renders progressively, a sample per pixel at a time
+    pub checkpoints: Option<Checkpoints>,
+    pub time_budget: Option<Duration>, // Some renders passes for this long, whatever the samples per pixel
+    pub render_time: Duration, // spent on those passes so far, counting any the film was resumed from
+    pixels: Vec<PixelSums>,
+    aov_values: Vec<Color>, // summed, all of a pixel's aovs side by side
+    total_samples: u64,
+    splats: Vec<Color>,
+    denoised: Option<Vec<Color>>, // what pixel gives once the film's been denoised
+}
+
+
+// means darker than this count as this bright when their error is made relative, so black pixels
+// with a little noise in them don't take the whole budget
+const DARK_LUMINANCE: f32 = 0.01;
+
+
+impl Film {
+
+    // box filtered, each sample counting towards its own pixel only
+    pub fn new(width: u32, height: u32) -> Film {
+        let size = (width * height) as usize;
+        Film {
+            width,
+            height,
+            target_error: None,
+            filter: Arc::new(BoxFilter::new(0.5)),
+            filter_sampler: None,
+            gather_features: false,
+            aovs: Vec::new(),
+            snapshots: None,
+            checkpoints: None,
+            time_budget: None,
+            render_time: Duration::ZERO,
+            pixels: vec![PixelSums::zeros(); size],
+            aov_values: Vec::new(),
+            total_samples: 0,
+            splats: vec![Color::zeros(); size],
+            denoised: None,
+        }
+    }
+
+    pub fn with_target_error(mut self, target_error: f32) -> Film {
+        self.target_error = Some(target_error);
+        self
+    }
+
+    pub fn with_filter(mut self, filter: Arc<dyn Filter>) -> Film {
+        if self.filter_sampler.is_some() {
+            self.filter_sampler = Some(Arc::new(FilterSampler::new(filter.as_ref())));
+        }
+        self.filter = filter;
+        self
+    }
+
+    pub fn with_filter_importance_sampling(mut self) -> Film {
+        self.filter_sampler = Some(Arc::new(FilterSampler::new(self.filter.as_ref())));
+        self
+    }
+
+    pub fn with_features(mut self) -> Film {
+        self.gather_features = true;
+        self
+    }
+
+    pub fn with_aovs(mut self, aovs: Vec<Aov>) -> Film {
+        self.aov_values = vec![Color::zeros(); (self.width * self.height) as usize * aovs.len()];
+        self.aovs = aovs;
+        self
+    }
+
+    pub fn with_snapshots(mut self, snapshots: Snapshots) -> Film {
+        self.snapshots = Some(snapshots);
+        self
+    }
+
+    pub fn with_checkpoints(mut self, checkpoints: Checkpoints) -> Film {
+        self.checkpoints = Some(checkpoints);
+        self
+    }
+
+    pub fn with_time_budget(mut self, time_budget: Duration) -> Film {
+        self.time_budget = Some(time_budget);
+        self
+    }
+
+    fn index(&self, i: u32, j: u32) -> usize {
+        (j * self.width + i) as usize
+    }
+
+    // a sample counted for pixel (i, j) alone, unfiltered. for integrators that work out their
+    // pixels themselves
+    pub fn add_sample(&mut self, i: u32, j: u32, color: Color) {
+        let index = self.index(i, j);
+        let pixel = &mut self.pixels[index];
+        pixel.weighted += color;
+        pixel.weight += 1.0;
+        pixel.count_sample(&color);
+        self.total_samples += 1;
+    }
+
+    pub fn add_features(&mut self, i: u32, j: u32, features: Features) {
+        let index = self.index(i, j);
+        self.pixels[index].features += features;
+        self.pixels[index].feature_count += 1;
+    }
+
+    // one sample's value of each of the film's aovs, in the same order
+    pub fn add_aovs(&mut self, i: u32, j: u32, values: &[Color]) {
+        let index = self.index(i, j);
+        add_aovs(&self.aovs, &mut self.aov_values[index * self.aovs.len()..], self.pixels[index].aov_count, values);
+        self.pixels[index].aov_count += 1;
+    }
+
+    // somewhere to put the samples of pixels x0..x1 by y0..y1 while they're being taken, padded
+    // by the filter's radius so it covers every pixel they can reach
+    pub fn tile(&self, x0: u32, x1: u32, y0: u32, y1: u32) -> FilmTile {
+        let pad = if self.filter_sampler.is_some() {0} else {(self.filter.radius() - 0.5).max(0.0).ceil() as u32};
+        let (x0, x1) = (x0.saturating_sub(pad), (x1 + pad).min(self.width));
+        let (y0, y1) = (y0.saturating_sub(pad), (y1 + pad).min(self.height));
+
+        FilmTile {
+            x0, x1, y0, y1,
+            filter: self.filter.clone(),
+            importance_sampled: self.filter_sampler.is_some(),
+            aovs: self.aovs.clone(),
+            pixels: vec![PixelSums::zeros(); ((x1 - x0) * (y1 - y0)) as usize],
+            aov_values: vec![Color::zeros(); ((x1 - x0) * (y1 - y0)) as usize * self.aovs.len()],
+            samples: 0,
+        }
+    }
+
+    pub fn merge_tile(&mut self, tile: &FilmTile) {
+        for j in tile.y0..tile.y1 {
+            for i in tile.x0..tile.x1 {
+                let (index, tile_index) = (self.index(i, j), tile.index(i, j));
+                let n = self.aovs.len();
+                for (k, aov) in self.aovs.iter().enumerate() {
+                    // a pixel's id comes from its first tile with any samples
+                    let value = tile.aov_values[tile_index * n + k];
+                    if !aov.is_id() || self.pixels[index].aov_count == 0 {
+                        self.aov_values[index * n + k] += value;
+                    }
+                }
+                self.pixels[index] += tile.pixels[tile_index];
+            }
+        }
+        self.total_samples += tile.samples;
+    }
+
+    // s & t are the camera's image plane coordinates, the same ones get_ray takes. splats made
+    // while a spectral path is traced are converted to rgb here
+    pub fn add_splat(&mut self, s: f32, t: f32, color: Color) {
+        let color = spectrum::film_rgb(&color);
+        let i = (s * (self.width - 1) as f32).floor();
+        let j = (t * (self.height - 1) as f32).floor();
+
+        if i >= 0.0 && j >= 0.0 && (i as u32) < self.width && (j as u32) < self.height {
+            let index = self.index(i as u32, j as u32);
+            self.splats[index] += color;
+        }
+    }
+
+    pub fn pixel(&self, i: u32, j: u32) -> Color {
+        match &self.denoised {
+            Some(denoised) => denoised[self.index(i, j)],
+            None => self.beauty(i, j),
+        }
+    }
+
+    // average of each quarter of the image, for comparing renders in tests
+    #[cfg(test)]
+    pub fn quadrant_means(&self) -> [Color; 4] {
+        let mut means = [Color::zeros(); 4];
+        for j in 0..self.height {
+            for i in 0..self.width {
+                let quadrant = 2 * (2 * j / self.height) + 2 * i / self.width;
+                means[quadrant as usize] += self.pixel(i, j) / (self.width * self.height / 4) as f32;
+            }
+        }
+        means
+    }
+
+    // the pixel as rendered, before any denoising
+    pub fn beauty(&self, i: u32, j: u32) -> Color {
+        let index = self.index(i, j);
+
+        // every camera sample traces one light path, and the image plane spans (width-1) x (height-1) pixels
+        let splat_scale = if self.total_samples > 0 {
+            ((self.width - 1) * (self.height - 1)) as f32 / self.total_samples as f32
+        } else {
+            0.0
+        };
+
+        // negative lobes can leave a pixel with no weight at all, or even less
+        let pixel = &self.pixels[index];
+        let mean = if pixel.weight != 0.0 {pixel.weighted / pixel.weight} else {Color::zeros()};
+
+        mean + splat_scale * self.splats[index]
+    }
+
+    pub fn samples(&self, i: u32, j: u32) -> u32 {
+        self.pixels[self.index(i, j)].count
+    }
+
+    // taken for all the pixels together
+    pub fn sample_count(&self) -> u64 {
+        self.pixels.iter().map(|p| p.count as u64).sum()
+    }
+
+    // the average over the image
+    pub fn samples_per_pixel(&self) -> f32 {
+        self.sample_count() as f32 / self.pixels.len() as f32
+    }
+
+    // the averages of the features the pixel's samples gathered, None if they didn't
+    pub fn features(&self, i: u32, j: u32) -> Option<Features> {
+        let pixel = &self.pixels[self.index(i, j)];
+        let n = pixel.feature_count as f32;
+        let f = &pixel.features;
+
+        if pixel.feature_count > 0 {Some(Features { albedo: f.albedo / n, normal: f.normal / n, depth: f.depth / n })} else {None}
+    }
+
+    // variance of the mean luminance of the pixel's own samples, from their spread. None until
+    // there are two samples to go by
+    pub fn mean_variance(&self, i: u32, j: u32) -> Option<f32> {
+        let pixel = &self.pixels[self.index(i, j)];
+        let n = pixel.count;
+        if n < 2 {
+            return None;
+        }
+
+        let mean = pixel.luminance / n as f32;
+        let variance = ((pixel.square / n as f32 - mean * mean) * n as f32 / (n - 1) as f32).max(0.0);
+        Some(variance / n as f32)
+    }
+
+    // standard error of the mean luminance over that mean. infinite until there are two samples
+    pub fn relative_error(&self, i: u32, j: u32) -> f32 {
+        let pixel = &self.pixels[self.index(i, j)];
+
+        match self.mean_variance(i, j) {
+            Some(variance) => variance.sqrt() / (pixel.luminance / pixel.count as f32).abs().max(DARK_LUMINANCE),
+            None => f32::INFINITY,
+        }
+    }
+
+    // the average of the pixel's samples of the film's k-th aov, the first one for ids
+    pub fn aov(&self, k: usize, i: u32, j: u32) -> Color {
+        let index = self.index(i, j);
+        let (value, count) = (self.aov_values[index * self.aovs.len() + k], self.pixels[index].aov_count);
+
+        if self.aovs[k].is_id() || count == 0 {value} else {value / count as f32}
+    }
+
+    // every aov in a file of its own, prefix.name.pfm
+    pub fn write_aovs(&self, prefix: &str) -> io::Result<()> {
+        for (k, aov) in self.aovs.iter().enumerate() {
+            self.write_pfm(&format!("{}.{}.pfm", prefix, aov.name()), |i, j| self.aov(k, i, j))?;
+        }
+
+        Ok(())
+    }
+
+    // the image as it is, linear & unbounded, no output transform
+    pub fn write_hdr(&self, path: &str) -> io::Result<()> {
+        self.write_pfm(path, |i, j| self.pixel(i, j))
+    }
+
+    // three channel floats, rows from the bottom
+    fn write_pfm<F: Fn(u32, u32) -> Color>(&self, path: &str, value: F) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        write!(out, "PF\n{} {}\n-1.0\n", self.width, self.height)?;
+
+        for j in 0..self.height {
+            for i in 0..self.width {
+                let value = value(i, j);
+                for c in [value.x, value.y, value.z].iter() {
+                    out.write_all(&c.to_le_bytes())?;
+                }
+            }
+        }
+
+        out.flush()
+    }
+
+    // everything the film's accumulated, bit for bit, so a render carried on from it comes out the
+    // same as if it had never stopped. the samplers hash their numbers from the pixel & the sample's
+    // index, so the pixels' sample counts are all of their state there is. written beside path &
+    // moved over it, so a render stopped while writing leaves the last checkpoint whole
+    pub fn save_checkpoint(&self, path: &str) -> io::Result<()> {
+        let partial = format!("{}.partial", path);
+        let mut out = BufWriter::new(File::create(&partial)?);
+        let floats = |out: &mut BufWriter<File>, values: &[f32]| -> io::Result<()> {
+            values.iter().try_for_each(|v| out.write_all(&v.to_le_bytes()))
+        };
+
+        out.write_all(CHECKPOINT_MAGIC)?;
+        for v in [CHECKPOINT_VERSION, self.width, self.height].iter() {
+            out.write_all(&v.to_le_bytes())?;
+        }
+        out.write_all(&self.checkpoints.as_ref().map_or(0, |c| c.seed).to_le_bytes())?;
+        for text in [self.settings(), self.checkpoints.as_ref().map_or(String::new(), |c| c.settings.clone())].iter() {
+            out.write_all(&(text.len() as u32).to_le_bytes())?;
+            out.write_all(text.as_bytes())?;
+        }
+        for p in self.pixels.iter() {
+            let f = &p.features;
+            floats(&mut out, &[p.weighted.x, p.weighted.y, p.weighted.z, p.weight, p.luminance, p.square])?;
+            floats(&mut out, &[f.albedo.x, f.albedo.y, f.albedo.z, f.normal.x, f.normal.y, f.normal.z, f.depth])?;
+            for v in [p.count, p.feature_count, p.aov_count].iter() {
+                out.write_all(&v.to_le_bytes())?;
+            }
+        }
+        for c in self.aov_values.iter().chain(self.splats.iter()) {
+            floats(&mut out, &[c.x, c.y, c.z])?;
+        }
+        out.write_all(&self.total_samples.to_le_bytes())?;
+        out.write_all(&(self.render_time.as_nanos() as u64).to_le_bytes())?;
+
+        out.flush()?;
+        drop(out);
+        fs::rename(&partial, path)
+    }
+
+    // picks up a checkpoint of a film made like this one, the same size, with the same aovs & the
+    // same way of taking samples. if the film has checkpoints of its own, the scene has to have
+    // been built from the same seed & everything in their settings has to match too
+    pub fn load_checkpoint(&mut self, path: &str) -> io::Result<()> {
+        let mut input = CheckpointReader::open(path)?;
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path, message));
+
+        if (input.u32()?, input.u32()?) != (self.width, self.height) {
+            return Err(invalid("made for a different image size"));
+        }
+        let (seed, film_settings, settings) = (input.u64()?, input.string()?, input.string()?);
+        if let Some(difference) = first_difference(&film_settings, &self.settings()) {
+            return Err(invalid(&difference));
+        }
+        if let Some(checkpoints) = &self.checkpoints {
+            if checkpoints.seed != seed {
+                return Err(invalid(&format!("made of a scene built with --seed {}", seed)));
+            }
+            if let Some(difference) = first_difference(&settings, &checkpoints.settings) {
+                return Err(invalid(&difference));
+            }
+        }
+
+        for p in self.pixels.iter_mut() {
+            p.weighted = input.color()?;
+            p.weight = input.f32()?;
+            p.luminance = input.f32()?;
+            p.square = input.f32()?;
+            p.features = Features { albedo: input.color()?, normal: input.color()?, depth: input.f32()? };
+            p.count = input.u32()?;
+            p.feature_count = input.u32()?;
+            p.aov_count = input.u32()?;
+        }
+        for c in self.aov_values.iter_mut().chain(self.splats.iter_mut()) {
+            *c = input.color()?;
+        }
+        self.total_samples = input.u64()?;
+        self.render_time = Duration::from_nanos(input.u64()?);
+
+        if input.at != input.bytes.len() {
+            return Err(invalid("trailing bytes"));
+        }
+        Ok(())
+    }
+
+    // the seed the scene a checkpoint was made of was built with
+    pub fn checkpoint_seed(path: &str) -> io::Result<u64> {
+        let mut input = CheckpointReader::open(path)?;
+        input.take(8)?;
+        input.u64()
+    }
+
+    // how the film takes its samples, which a checkpoint has to have been made with to be resumed.
+    // tiles are sampled whole & passes over the image a sample at a time, so neither can carry on
+    // from what the other left
+    fn settings(&self) -> String {
+        let aovs: Vec<&str> = self.aovs.iter().map(|aov| aov.name()).collect();
+        let rendered_in = if self.snapshots.is_some() || self.time_budget.is_some() {"passes"} else {"tiles"};
+        format!("aovs: {}\nfeatures: {}\nfilter importance: {}\nrendered in: {}\n", aovs.join(", "), self.gather_features, self.filter_sampler.is_some(), rendered_in)
+    }
+
+    // replaces what pixel gives with the denoiser's take on the image
+    pub fn denoise(&mut self, denoiser: &Denoiser) {
+        self.denoised = Some(denoiser.denoise(self));
+    }
+
+    // how many samples each pixel took, black for the fewest to white for the most through red & yellow
+    pub fn write_sample_heat_map(&self, path: &str) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        writeln!(out, "P3\n{} {}\n255", self.width, self.height)?;
+
+        let counts = self.pixels.iter().map(|p| p.count);
+        let (min, max) = (counts.clone().min().unwrap_or(0), counts.max().unwrap_or(0));
+        for j in (0..self.height).rev() {
+            for i in 0..self.width {
+                let x = if max > min {(self.samples(i, j) - min) as f32 / (max - min) as f32} else {0.0};
+                let heat = [3.0 * x, 3.0 * x - 1.0, 3.0 * x - 2.0].map(|c| (255.0 * clamp(c, 0.0, 1.0)) as u8);
+                writeln!(out, "{} {} {}", heat[0], heat[1], heat[2])?;
+            }
+        }
+
+        out.flush()
+    }
+
+    // the display image, with the samples it's made of noted in the header
+    pub fn write_ppm(&self, out: &mut dyn Write, transform: &OutputTransform) -> io::Result<()> {
+        writeln!(out, "P3\n# samples per pixel: {}\n{} {}\n255", self.samples_per_pixel(), self.width, self.height)?;
+
+        for j in (0..self.height).rev() {
+            for i in 0..self.width {
+                let [r, g, b] = transform.to_bytes(&self.pixel(i, j));
+                writeln!(out, "{} {} {}", r, g, b)?;
+            }
+        }
+
+        out.flush()
+    }
+
+    // written beside path & moved over it, so whoever's watching the file never sees half an image
+    pub fn save_ppm(&self, path: &str, transform: &OutputTransform) -> io::Result<()> {
+        let partial = format!("{}.partial", path);
+        self.write_ppm(&mut BufWriter::new(File::create(&partial)?), transform)?;
+        fs::rename(&partial, path)
+    }
+
+}
+
+
+
+// sums one sample's aovs into a pixel's, which has count samples so far. ids aren't summed,
+// the first one stays
+fn add_aovs(aovs: &[Aov], sums: &mut [Color], count: u32, values: &[Color]) {
+    for ((aov, sum), value) in aovs.iter().zip(sums.iter_mut()).zip(values) {
+        if !aov.is_id() || count == 0 {
+            *sum += *value;
+        }
+    }
+}
+
+
+
+// the first line a checkpoint was made with that's different now, if there is one
+fn first_difference(saved: &str, current: &str) -> Option<String> {
+    let (mut saved, mut current) = (saved.lines(), current.lines());
+    loop {
+        match (saved.next(), current.next()) {
+            (None, None) => return None,
+            (a, b) if a == b => continue,
+            (a, b) => return Some(format!("made with '{}' where this render has '{}'", a.unwrap_or(""), b.unwrap_or(""))),
+        }
+    }
+}
+
+
+
+// reads a checkpoint's little endian values in order
+struct CheckpointReader {
+    bytes: Vec<u8>,
+    at: usize,
+}
+
+
+impl CheckpointReader {
+
+    // the file at path, read up to past its magic & version
+    fn open(path: &str) -> io::Result<CheckpointReader> {
+        let mut input = CheckpointReader { bytes: fs::read(path)?, at: 0 };
+        if input.take(4)? != CHECKPOINT_MAGIC || input.u32()? != CHECKPOINT_VERSION {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{}: not a checkpoint this version can read", path)));
+        }
+        Ok(input)
+    }
+
+    fn take(&mut self, n: usize) -> io::Result<&[u8]> {
+        let bytes = self.bytes.get(self.at..self.at + n).ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "truncated checkpoint"))?;
+        self.at += n;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn f32(&mut self) -> io::Result<f32> {
+        Ok(f32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    // utf-8, after its length in bytes
+    fn string(&mut self) -> io::Result<String> {
+        let length = self.u32()? as usize;
+        String::from_utf8(self.take(length)?.to_vec()).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+    }
+
+    fn color(&mut self) -> io::Result<Color> {
+        Ok(Color::new(self.f32()?, self.f32()?, self.f32()?))
+    }
+
+}
+
+
+
+// the pixels one tile of the image can reach, the tile's own & the filter's width around them.
+// samples only ever go into the tile being worked on, & tiles are merged into the film once done,
+// so tiles never write to each other's pixels, however wide the filter
+pub struct FilmTile {
+    pub x0: u32,
+    pub x1: u32,
+    pub y0: u32,
+    pub y1: u32,
+    filter: Arc<dyn Filter>,
+    importance_sampled: bool,
+    aovs: Vec<Aov>,
+    pixels: Vec<PixelSums>,
+    aov_values: Vec<Color>,
+    samples: u64,
+}
+
+
+impl FilmTile {
+
+    fn index(&self, i: u32, j: u32) -> usize {
+        ((j - self.y0) * (self.x1 - self.x0) + i - self.x0) as usize
+    }
+
+    pub fn add_features(&mut self, i: u32, j: u32, features: Features) {
+        let index = self.index(i, j);
+        self.pixels[index].features += features;
+        self.pixels[index].feature_count += 1;
+    }
+
+    pub fn add_aovs(&mut self, i: u32, j: u32, values: &[Color]) {
+        let index = self.index(i, j);
+        add_aovs(&self.aovs, &mut self.aov_values[index * self.aovs.len()..], self.pixels[index].aov_count, values);
+        self.pixels[index].aov_count += 1;
+    }
+
+    // a sample taken for pixel (i, j), dx & dy from its center, with weight from filter importance
+    // sampling (1 without)
+    pub fn add_sample(&mut self, i: u32, j: u32, dx: f32, dy: f32, color: Color, weight: f32) {
+        let index = self.index(i, j);
+        self.pixels[index].count_sample(&color);
+        self.samples += 1;
+
+        if self.importance_sampled {
+            self.pixels[index].weighted += weight * color;
+            self.pixels[index].weight += weight;
+            return;
+        }
+
+        // every pixel whose center is within the filter's radius of the sample
+        let (x, y) = (i as f32 + 0.5 + dx, j as f32 + 0.5 + dy);
+        let r = self.filter.radius();
+        let reach = |p: f32, min: u32, max: u32| ((p - 0.5 - r).ceil().max(min as f32) as u32, ((p - 0.5 + r).floor() + 1.0).clamp(min as f32, max as f32) as u32);
+        let ((i0, i1), (j0, j1)) = (reach(x, self.x0, self.x1), reach(y, self.y0, self.y1));
+
+        for pj in j0..j1 {
+            for pi in i0..i1 {
+                let w = weight * self.filter.evaluate(pi as f32 + 0.5 - x, pj as f32 + 0.5 - y);
+                if w != 0.0 {
+                    let index = self.index(pi, pj);
+                    self.pixels[index].weighted += w * color;
+                    self.pixels[index].weight += w;
+                }
+            }
+        }
+    }
+
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tiles_splat_into_neighbours_only_through_the_filter() {
+        let mut film = Film::new(6, 6).with_filter(Arc::new(TentFilter::new(1.5)));
+
+        // two tiles side by side, their padding overlapping, a sample on either side of the seam
+        let mut left = film.tile(0, 3, 0, 6);
+        let mut right = film.tile(3, 6, 0, 6);
+        assert!(left.x1 > 3 && right.x0 < 3);
+        left.add_sample(2, 2, 0.4, 0.0, Color::fromv(1.0), 1.0);
+        right.add_sample(3, 2, -0.4, 0.0, Color::fromv(3.0), 1.0);
+        film.merge_tile(&left);
+        film.merge_tile(&right);
+
+        // each pixel by the seam weighs the nearer sample 1.1 to the other's 0.9
+        assert!((film.pixel(2, 2).x - 1.9).abs() < 1e-5 && (film.pixel(3, 2).x - 2.1).abs() < 1e-5);
+        // further out only the nearer sample reaches
+        assert!((film.pixel(1, 2).x - 1.0).abs() < 1e-5 && (film.pixel(4, 2).x - 3.0).abs() < 1e-5);
+        // rows beyond the radius get nothing, but samples are counted for their own pixel only
+        assert_eq!(film.pixel(2, 4).x, 0.0);
+        assert_eq!((film.samples(2, 2), film.samples(3, 2), film.samples(2, 3)), (1, 1, 0));
+    }
+
+    #[test]
+    fn box_filtered_tiles_keep_to_their_own_pixels() {
+        let mut film = Film::new(4, 4);
+        let mut tile = film.tile(0, 2, 0, 2);
+        assert_eq!((tile.x0, tile.x1, tile.y0, tile.y1), (0, 2, 0, 2));
+
+        tile.add_sample(1, 1, 0.49, -0.49, Color::fromv(2.0), 1.0);
+        film.merge_tile(&tile);
+        assert_eq!(film.pixel(1, 1).x, 2.0);
+        assert_eq!(film.pixel(2, 1).x, 0.0);
+    }
+
+    #[test]
+    fn checkpoints_only_resume_renders_made_the_same_way() {
+        let path = std::env::temp_dir().join(format!("film_checkpoint_{}", std::process::id())).to_string_lossy().into_owned();
+        let checkpoints = |seed: u64, sampler: &str| Checkpoints { path: path.clone(), every_seconds: 0.0, seed, settings: format!("sampler: {}\nspectral: false\n", sampler) };
+        let film = |aovs: Vec<Aov>, seed: u64, sampler: &str| Film::new(4, 4).with_aovs(aovs).with_checkpoints(checkpoints(seed, sampler));
+        let resumed = |mut film: Film| film.load_checkpoint(&path).map_err(|error| error.to_string());
+
+        film(vec![Aov::Depth, Aov::Albedo], 7, "Sobol").save_checkpoint(&path).unwrap();
+
+        assert!(resumed(film(vec![Aov::Depth, Aov::Albedo], 7, "Sobol")).is_ok());
+        // the same number of aovs, but not the same ones
+        assert!(resumed(film(vec![Aov::Depth, Aov::Normal], 7, "Sobol")).unwrap_err().ends_with("made with 'aovs: depth, albedo' where this render has 'aovs: depth, normal'"));
+        assert!(resumed(film(vec![Aov::Depth, Aov::Albedo], 7, "Halton")).unwrap_err().ends_with("made with 'sampler: Sobol' where this render has 'sampler: Halton'"));
+        assert!(resumed(film(vec![Aov::Depth, Aov::Albedo], 8, "Sobol")).is_err());
+        assert!(resumed(film(vec![Aov::Depth, Aov::Albedo], 7, "Sobol").with_features()).is_err());
+
+        assert_eq!(Film::checkpoint_seed(&path).unwrap(), 7);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,279 @@
+use crate::random::random_f32;
+use std::f32::consts::PI;
+
+
+// how much a sample counts towards a pixel, by its offset from the pixel's center in pixels.
+// all of these are separable, the product of one profile along x & the same along y
+pub trait Filter: Send + Sync {
+    // the filter is zero past this far from the center, along either axis
+    fn radius(&self) -> f32;
+
+    fn evaluate_1d(&self, x: f32) -> f32;
+
+    fn evaluate(&self, x: f32, y: f32) -> f32 {
+        self.evaluate_1d(x) * self.evaluate_1d(y)
+    }
+}
+
+
+
+pub struct BoxFilter {
+    pub radius: f32,
+}
+
+
+impl BoxFilter {
+    pub fn new(radius: f32) -> BoxFilter {
+        BoxFilter { radius }
+    }
+}
+
+
+impl Filter for BoxFilter {
+    fn radius(&self) -> f32 {
+        self.radius
+    }
+
+    fn evaluate_1d(&self, x: f32) -> f32 {
+        if x.abs() <= self.radius {1.0} else {0.0}
+    }
+}
+
+
+
+pub struct TentFilter {
+    pub radius: f32,
+}
+
+
+impl TentFilter {
+    pub fn new(radius: f32) -> TentFilter {
+        TentFilter { radius }
+    }
+}
+
+
+impl Filter for TentFilter {
+    fn radius(&self) -> f32 {
+        self.radius
+    }
+
+    fn evaluate_1d(&self, x: f32) -> f32 {
+        (self.radius - x.abs()).max(0.0)
+    }
+}
+
+
+
+// a gaussian, less its value at the radius so it falls to zero there
+pub struct GaussianFilter {
+    pub radius: f32,
+    pub sigma: f32,
+}
+
+
+impl GaussianFilter {
+    pub fn new(radius: f32, sigma: f32) -> GaussianFilter {
+        GaussianFilter { radius, sigma }
+    }
+
+    fn gaussian(&self, x: f32) -> f32 {
+        (-x * x / (2.0 * self.sigma * self.sigma)).exp()
+    }
+}
+
+
+impl Filter for GaussianFilter {
+    fn radius(&self) -> f32 {
+        self.radius
+    }
+
+    fn evaluate_1d(&self, x: f32) -> f32 {
+        (self.gaussian(x) - self.gaussian(self.radius)).max(0.0)
+    }
+}
+
+
+
+// mitchell & netravali's cubic, "reconstruction filters in computer graphics". its negative lobes
+// sharpen edges, b = c = 1/3 is what they recommend
+pub struct MitchellFilter {
+    pub radius: f32,
+    pub b: f32,
+    pub c: f32,
+}
+
+
+impl MitchellFilter {
+    pub fn new(radius: f32, b: f32, c: f32) -> MitchellFilter {
+        MitchellFilter { radius, b, c }
+    }
+}
+
+
+impl Filter for MitchellFilter {
+    fn radius(&self) -> f32 {
+        self.radius
+    }
+
+    // the cubic is defined over [-2, 2], stretched to the radius
+    fn evaluate_1d(&self, x: f32) -> f32 {
+        let (b, c) = (self.b, self.c);
+        let x = (2.0 * x / self.radius).abs();
+
+        let value = if x <= 1.0 {
+            (12.0 - 9.0 * b - 6.0 * c) * x * x * x + (-18.0 + 12.0 * b + 6.0 * c) * x * x + (6.0 - 2.0 * b)
+        } else if x <= 2.0 {
+            (-b - 6.0 * c) * x * x * x + (6.0 * b + 30.0 * c) * x * x + (-12.0 * b - 48.0 * c) * x + (8.0 * b + 24.0 * c)
+        } else {
+            0.0
+        };
+        value / 6.0
+    }
+}
+
+
+
+// sinc windowed by a wider sinc, tau lobes of it. tau = radius is lanczos' own window
+pub struct LanczosFilter {
+    pub radius: f32,
+    pub tau: f32,
+}
+
+
+impl LanczosFilter {
+    pub fn new(radius: f32, tau: f32) -> LanczosFilter {
+        LanczosFilter { radius, tau }
+    }
+}
+
+
+fn sinc(x: f32) -> f32 {
+    if x.abs() < 1e-5 {1.0} else {(PI * x).sin() / (PI * x)}
+}
+
+
+impl Filter for LanczosFilter {
+    fn radius(&self) -> f32 {
+        self.radius
+    }
+
+    fn evaluate_1d(&self, x: f32) -> f32 {
+        if x.abs() > self.radius {0.0} else {sinc(x) * sinc(x / self.tau)}
+    }
+}
+
+
+
+// filter importance sampling: offsets picked in proportion to the filter's magnitude, from a table
+// of it, & weighted by its sign. every sample then lands in one pixel only, instead of being
+// spread over the pixels around it, which keeps the noise of neighbouring pixels apart
+pub struct FilterSampler {
+    pub radius: f32,
+    values: Vec<f32>, // the filter at the middle of each bin
+    cdf: Vec<f32>,
+    integral: f32, // of the filter's magnitude along one axis
+}
+
+
+// table bins per pixel of radius
+const FILTER_BINS_PER_PIXEL: f32 = 64.0;
+
+
+impl FilterSampler {
+
+    pub fn new(filter: &dyn Filter) -> FilterSampler {
+        let radius = filter.radius();
+        let bins = (FILTER_BINS_PER_PIXEL * radius).ceil().max(8.0) as usize;
+        let width = 2.0 * radius / bins as f32;
+
+        let values: Vec<f32> = (0..bins).map(|k| filter.evaluate_1d(-radius + (k as f32 + 0.5) * width)).collect();
+        let cdf: Vec<f32> = values.iter().scan(0.0, |sum, v| { *sum += v.abs() * width; Some(*sum) }).collect();
+        let integral = cdf[bins - 1];
+
+        FilterSampler { radius, values, cdf, integral }
+    }
+
+    // an offset along one axis & its weight, the filter there over the pdf of picking it
+    fn sample_1d(&self, u: f32) -> (f32, f32) {
+        let target = u * self.integral;
+        let k = self.cdf.partition_point(|c| *c <= target).min(self.cdf.len() - 1);
+
+        let width = 2.0 * self.radius / self.values.len() as f32;
+        let before = if k == 0 {0.0} else {self.cdf[k - 1]};
+        let within = if self.cdf[k] > before {(target - before) / (self.cdf[k] - before)} else {0.5};
+
+        let weight = if self.values[k] < 0.0 {-self.integral} else {self.integral};
+        (-self.radius + (k as f32 + within) * width, weight)
+    }
+
+    // an offset from the pixel's center & its weight
+    pub fn sample(&self) -> (f32, f32, f32) {
+        let (x, wx) = self.sample_1d(random_f32());
+        let (y, wy) = self.sample_1d(random_f32());
+        (x, y, wx * wy)
+    }
+
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filters() -> Vec<Box<dyn Filter>> {
+        vec![
+            Box::new(BoxFilter::new(0.5)),
+            Box::new(TentFilter::new(1.0)),
+            Box::new(GaussianFilter::new(1.5, 0.5)),
+            Box::new(MitchellFilter::new(2.0, 1.0 / 3.0, 1.0 / 3.0)),
+            Box::new(LanczosFilter::new(3.0, 3.0)),
+        ]
+    }
+
+    #[test]
+    fn filters_peak_in_the_middle_and_end_at_their_radius() {
+        for filter in filters().iter() {
+            let r = filter.radius();
+            assert!(filter.evaluate(0.0, 0.0) > 0.0);
+            assert!(filter.evaluate(0.0, 0.0) >= filter.evaluate(0.3 * r, 0.2 * r));
+            assert_eq!(filter.evaluate(1.01 * r, 0.0), 0.0);
+            assert_eq!(filter.evaluate(0.0, -1.01 * r), 0.0);
+        }
+
+        // mitchell & lanczos have negative lobes
+        assert!(MitchellFilter::new(2.0, 1.0 / 3.0, 1.0 / 3.0).evaluate_1d(1.5) < 0.0);
+        assert!(LanczosFilter::new(3.0, 3.0).evaluate_1d(1.5) < 0.0);
+    }
+
+    #[test]
+    fn importance_sampling_integrates_the_filter() {
+        // the weighted samples average to the filter's integral over any region
+        for filter in filters().iter() {
+            let sampler = FilterSampler::new(filter.as_ref());
+            let r = filter.radius();
+            let samples = 200_000;
+
+            let (mut all, mut quarter) = (0.0, 0.0);
+            for _ in 0..samples {
+                let (x, y, weight) = sampler.sample();
+                assert!(x.abs() <= r && y.abs() <= r);
+                all += weight;
+                if x > 0.0 && y > 0.0 {
+                    quarter += weight;
+                }
+            }
+
+            // the integrals by the midpoint rule
+            let n = 400;
+            let d = 2.0 * r / n as f32;
+            let line: f32 = (0..n).map(|k| filter.evaluate_1d(-r + (k as f32 + 0.5) * d) * d).sum();
+            let half: f32 = (n / 2..n).map(|k| filter.evaluate_1d(-r + (k as f32 + 0.5) * d) * d).sum();
+
+            let (all, quarter) = (all / samples as f32, quarter / samples as f32);
+            assert!((all - line * line).abs() < 0.01 * line * line, "{} vs {}", all, line * line);
+            assert!((quarter - half * half).abs() < 0.02 * line * line, "{} vs {}", quarter, half * half);
+        }
+    }
+}
@@ -2,13 +2,19 @@ use crate::{vectors::*, materials::*};
 use crate::rays::Ray;
 use std::sync::Arc;
 
-// #[derive(Clone)]
+#[derive(Clone)]
 pub struct HitRecord {
     pub p: Point3,
     pub normal: Vector3,
     pub t: f32,
+    pub u: f32, // surface coordinates
+    pub v: f32,
+    pub dpdu: Vector3, // how p moves with u & v, for tangent frames & bump mapping. zero if the shape doesn't say
+    pub dpdv: Vector3,
     pub front_face: bool,   
     pub mat: Arc<dyn Material>,
+    pub velocity: Vector3, // how fast p moves over the shutter, for motion vectors. zero for shapes that keep still
+    pub object: usize, // which object of the outermost list was hit, for object ids
 }
 
 
@@ -19,8 +25,14 @@ impl HitRecord {
             p: Point3::zeros(),
             normal: Vector3::zeros(),
             t: 0.0,
+            u: 0.0,
+            v: 0.0,
+            dpdu: Vector3::zeros(),
+            dpdv: Vector3::zeros(),
             front_face: false,
             mat: Arc::new(Lambertian::new(Color::zeros())),
+            velocity: Vector3::zeros(),
+            object: 0,
         }
     }
 
@@ -31,6 +43,30 @@ impl HitRecord {
 }
 
 
-pub trait Hittable{
+pub trait Hittable: Send + Sync {
    fn hit(&self, r: &Ray, t_min: f32, t_max: f32, rec: &mut HitRecord) -> bool; 
+
+   // solid angle pdf of random() picking direction from origin, for objects used as lights
+   fn pdf_value(&self, _origin: &Point3, _direction: &Vector3) -> f32 {
+       0.0
+   }
+
+   fn random(&self, _origin: &Point3) -> Vector3 {
+       Vector3::newi(1, 0, 0)
+   }
+
+   // a uniformly picked point on the surface, facing out, with its area pdf. lets lights emit paths
+   fn sample_surface(&self) -> Option<(HitRecord, f32)> {
+       None
+   }
+
+   // area pdf of sample_surface picking p
+   fn surface_pdf(&self, _p: &Point3) -> f32 {
+       0.0
+   }
+
+   // the materials hits on the object can have, so the scene can number them
+   fn materials(&self) -> Vec<Arc<dyn Material>> {
+       Vec::new()
+   }
 }
\ No newline at end of file
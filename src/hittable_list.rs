@@ -1,4 +1,6 @@
-use crate::{hittable::{Hittable, HitRecord}, rays::Ray};
+use crate::{hittable::{Hittable, HitRecord}, rays::Ray, vectors::*, materials::Material};
+use crate::random::random_index;
+use std::sync::Arc;
 
 
 pub struct HittableList {
@@ -17,6 +19,25 @@ impl HittableList {
     pub fn add(&mut self, object: Box<dyn Hittable>) {
         self.objects.push(object);
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+}
+
+
+// the nearest hit on object that its material doesn't cut out, searching on past the ones it does
+fn hit_opaque(object: &dyn Hittable, r: &Ray, t_min: f32, t_max: f32, rec: &mut HitRecord) -> bool {
+    let mut t_min = t_min;
+
+    while object.hit(r, t_min, t_max, rec) {
+        if !rec.mat.is_cut_out(rec) {
+            return true;
+        }
+        t_min = rec.t.next_up();
+    }
+
+    false
 }
 
 
@@ -25,17 +46,60 @@ impl Hittable for HittableList {
         let mut hit_anything = false;
         let mut closest_so_far = t_max;
 
-        for object in self.objects.iter() {
+        for (index, object) in self.objects.iter().enumerate() {
             let mut tmp_rec = HitRecord::new();
 
-            if object.hit(r, t_min, closest_so_far, &mut tmp_rec) {
+            if hit_opaque(object.as_ref(), r, t_min, closest_so_far, &mut tmp_rec) {
                 hit_anything = true;
                 closest_so_far = tmp_rec.t;
                 *rec = tmp_rec;
+                rec.object = index;
             }
         }
 
         hit_anything
     }
-}
 
+
+    // picks one of the objects uniformly, so the pdf is the average of theirs
+    fn pdf_value(&self, origin: &Point3, direction: &Vector3) -> f32 {
+        if self.objects.is_empty() {
+            return 0.0;
+        }
+
+        let sum: f32 = self.objects.iter().map(|object| object.pdf_value(origin, direction)).sum();
+        sum / self.objects.len() as f32
+    }
+
+
+    fn random(&self, origin: &Point3) -> Vector3 {
+        self.objects[random_index(self.objects.len())].random(origin)
+    }
+
+
+    fn sample_surface(&self) -> Option<(HitRecord, f32)> {
+        if self.objects.is_empty() {
+            return None;
+        }
+
+        let (rec, _) = self.objects[random_index(self.objects.len())].sample_surface()?;
+
+        // the point could lie on more than one of the objects, so ask all of them for its density
+        let pdf = self.surface_pdf(&rec.p);
+        Some((rec, pdf))
+    }
+
+
+    fn surface_pdf(&self, p: &Point3) -> f32 {
+        if self.objects.is_empty() {
+            return 0.0;
+        }
+
+        let sum: f32 = self.objects.iter().map(|object| object.surface_pdf(p)).sum();
+        sum / self.objects.len() as f32
+    }
+
+    fn materials(&self) -> Vec<Arc<dyn Material>> {
+        self.objects.iter().flat_map(|object| object.materials()).collect()
+    }
+}
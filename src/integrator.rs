@@ -0,0 +1,492 @@
+use crate::{vectors::*, rays::Ray, hittable::*, scene::Scene, onb::*, film::Film, render, sampler::Sampler};
+use crate::{random::{random_f32, start_bounce}, aov::{record_light, recording_light}};
+use std::{sync::Arc, collections::hash_map::DefaultHasher, hash::{Hash, Hasher}};
+
+
+// estimates the radiance arriving at the camera along r. light reaching the camera some
+// other way than through r can be splatted onto the film
+pub trait Integrator {
+    fn li(&self, r: &Ray, scene: &Scene, film: &mut Film) -> Color;
+
+    // integrators that work on the whole image at once (photon mapping) replace the pixel loop
+    fn render(&self, scene: &Scene, film: &mut Film, sampler: &Arc<dyn Sampler>, samples_per_pixel: u32) {
+        render::render(scene, self, film, sampler, samples_per_pixel);
+    }
+}
+
+
+pub fn power_heuristic(pdf: f32, other_pdf: f32) -> f32 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b > 0.0 {a / (a + b)} else {0.0}
+}
+
+
+fn max_component(c: &Color) -> f32 {
+    c.x.max(c.y).max(c.z)
+}
+
+
+// returns false when the path got killed, otherwise reweights throughput to stay unbiased
+pub fn russian_roulette(throughput: &mut Color) -> bool {
+    let survival = max_component(throughput).min(1.0);
+    if random_f32() >= survival {
+        return false;
+    }
+
+    *throughput = *throughput / survival;
+    true
+}
+
+
+// next event estimation: one direction towards the lights, weighted against the bsdf picking it
+pub fn sample_direct(r: &Ray, rec: &HitRecord, scene: &Scene) -> Color {
+    sample_direct_along(r, rec, scene).map_or(Color::zeros(), |(direct, _)| direct)
+}
+
+
+// the same, with the direction it was sampled along
+pub fn sample_direct_along(r: &Ray, rec: &HitRecord, scene: &Scene) -> Option<(Color, Vector3)> {
+    let direction = scene.sample_light_direction(&rec.p)?;
+
+    let light_pdf = scene.light_pdf(&rec.p, &direction);
+    let f = rec.mat.eval(r, rec, &direction);
+    if light_pdf <= 0.0 || max_component(&f) <= 0.0 {
+        return None;
+    }
+
+    let li = scene.incoming_radiance(&Ray::new(rec.p, direction, r.time));
+    let bsdf_pdf = rec.mat.scattering_pdf(r, rec, &direction);
+
+    Some((power_heuristic(light_pdf, bsdf_pdf) / light_pdf * f * li, direction))
+}
+
+
+// light arriving at a diffuse hit straight from the lights or the sky, sampling both the
+// lights and the bsdf
+pub fn estimate_direct(r: &Ray, rec: &HitRecord, scene: &Scene) -> Color {
+    let mut direct = sample_direct(r, rec, scene);
+    if let Some((scatter_ray, attenuation)) = rec.mat.scatter(r, rec) {
+        let prev = Some((rec.p, rec.mat.scattering_pdf(r, rec, &scatter_ray.direction)));
+        let weight = bsdf_hit_weight(scene, &prev, &scatter_ray.direction);
+        direct += weight * attenuation * scene.incoming_radiance(&scatter_ray);
+    }
+
+    direct
+}
+
+
+// weight for light found by following the bsdf. prev is the last diffuse vertex & the pdf it
+// was scattered with, None after the camera or a specular bounce where lights couldn't be sampled
+fn bsdf_hit_weight(scene: &Scene, prev: &Option<(Point3, f32)>, direction: &Vector3) -> f32 {
+    match prev {
+        Some((origin, bsdf_pdf)) => power_heuristic(*bsdf_pdf, scene.light_pdf(origin, direction)),
+        None => 1.0,
+    }
+}
+
+
+
+// iterative path tracer. carries the path throughput instead of recursing, and past
+// rr_min_bounces kills dim paths with russian roulette (survivors are reweighted so the
+// expected radiance stays the same)
+pub struct PathTracer {
+    pub max_depth: u32,
+    pub rr_min_bounces: u32,
+}
+
+
+impl PathTracer {
+    pub fn new(max_depth: u32, rr_min_bounces: u32) -> PathTracer {
+        PathTracer { max_depth, rr_min_bounces }
+    }
+}
+
+
+impl Integrator for PathTracer {
+    fn li(&self, r: &Ray, scene: &Scene, _film: &mut Film) -> Color {
+        let mut radiance = Color::zeros();
+        let mut throughput = Color::fromv(1.0);
+        let mut ray = *r;
+        let mut diffuse_fraction = 0.0; // of the first hit, for the light passes
+
+        for bounce in 0..self.max_depth {
+            start_bounce(bounce);
+            let mut rec = HitRecord::new();
+
+            if !scene.world.hit(&ray, 0.001, f32::INFINITY, &mut rec) {
+                let background = throughput * scene.background(&ray.direction);
+                radiance += background;
+                record_light(bounce, diffuse_fraction, background);
+                break;
+            }
+
+            let emitted = throughput * rec.mat.emitted(&rec);
+            radiance += emitted;
+            record_light(bounce, diffuse_fraction, emitted);
+
+            let (scatter_ray, attenuation) = match rec.mat.scatter(&ray, &rec) {
+                Some(scattered) => scattered,
+                None => break,
+            };
+
+            if bounce == 0 && recording_light() {
+                diffuse_fraction = rec.mat.diffuse_fraction(&ray, &rec, &scatter_ray.direction);
+            }
+            throughput *= attenuation;
+
+            if bounce >= self.rr_min_bounces && !russian_roulette(&mut throughput) {
+                break;
+            }
+
+            ray = scatter_ray;
+        }
+
+        radiance
+    }
+}
+
+
+
+// path tracer with next event estimation at every diffuse vertex, combined with the
+// bsdf sampled paths by multiple importance sampling
+pub struct MisPathTracer {
+    pub max_depth: u32,
+    pub rr_min_bounces: u32,
+}
+
+
+impl MisPathTracer {
+    pub fn new(max_depth: u32, rr_min_bounces: u32) -> MisPathTracer {
+        MisPathTracer { max_depth, rr_min_bounces }
+    }
+}
+
+
+impl Integrator for MisPathTracer {
+    fn li(&self, r: &Ray, scene: &Scene, _film: &mut Film) -> Color {
+        let mut radiance = Color::zeros();
+        let mut throughput = Color::fromv(1.0);
+        let mut ray = *r;
+        let mut prev: Option<(Point3, f32)> = None;
+        let mut diffuse_fraction = 0.0; // of the first hit, for the light passes
+
+        for bounce in 0..self.max_depth {
+            start_bounce(bounce);
+            let mut rec = HitRecord::new();
+
+            if !scene.world.hit(&ray, 0.001, f32::INFINITY, &mut rec) {
+                let weight = bsdf_hit_weight(scene, &prev, &ray.direction);
+                let background = weight * throughput * scene.background(&ray.direction);
+                radiance += background;
+                record_light(bounce, diffuse_fraction, background);
+                break;
+            }
+
+            let emitted = rec.mat.emitted(&rec);
+            if max_component(&emitted) > 0.0 {
+                let emitted = bsdf_hit_weight(scene, &prev, &ray.direction) * throughput * emitted;
+                radiance += emitted;
+                record_light(bounce, diffuse_fraction, emitted);
+            }
+
+            // the light sample lands one vertex further down the path
+            if !rec.mat.is_specular() && bounce + 1 < self.max_depth {
+                if let Some((direct, direction)) = sample_direct_along(&ray, &rec, scene) {
+                    let direct = throughput * direct;
+                    radiance += direct;
+                    if recording_light() {
+                        let fraction = if bounce == 0 {rec.mat.diffuse_fraction(&ray, &rec, &direction)} else {diffuse_fraction};
+                        record_light(bounce + 1, fraction, direct);
+                    }
+                }
+            }
+
+            let (scatter_ray, attenuation) = match rec.mat.scatter(&ray, &rec) {
+                Some(scattered) => scattered,
+                None => break,
+            };
+
+            if bounce == 0 && recording_light() {
+                diffuse_fraction = rec.mat.diffuse_fraction(&ray, &rec, &scatter_ray.direction);
+            }
+
+            prev = if rec.mat.is_specular() {
+                None
+            } else {
+                Some((rec.p, rec.mat.scattering_pdf(&ray, &rec, &scatter_ray.direction)))
+            };
+            throughput *= attenuation;
+
+            if bounce >= self.rr_min_bounces && !russian_roulette(&mut throughput) {
+                break;
+            }
+
+            ray = scatter_ray;
+        }
+
+        radiance
+    }
+}
+
+
+
+// only light reaching the first diffuse surface straight from an emitter or the sky.
+// specular surfaces in front of it are followed up to max_depth
+pub struct DirectLighting {
+    pub max_depth: u32,
+}
+
+
+impl DirectLighting {
+    pub fn new(max_depth: u32) -> DirectLighting {
+        DirectLighting { max_depth }
+    }
+}
+
+
+impl Integrator for DirectLighting {
+    fn li(&self, r: &Ray, scene: &Scene, _film: &mut Film) -> Color {
+        let mut throughput = Color::fromv(1.0);
+        let mut ray = *r;
+
+        for bounce in 0..self.max_depth {
+            start_bounce(bounce);
+            let mut rec = HitRecord::new();
+
+            if !scene.world.hit(&ray, 0.001, f32::INFINITY, &mut rec) {
+                return throughput * scene.background(&ray.direction);
+            }
+
+            let emitted = throughput * rec.mat.emitted(&rec);
+
+            if !rec.mat.is_specular() {
+                return emitted + throughput * estimate_direct(&ray, &rec, scene);
+            }
+
+            let (scatter_ray, attenuation) = match rec.mat.scatter(&ray, &rec) {
+                Some(scattered) => scattered,
+                None => return emitted,
+            };
+
+            throughput *= attenuation;
+            ray = scatter_ray;
+        }
+
+        Color::zeros()
+    }
+}
+
+
+
+// fraction of the cosine weighted hemisphere above the first hit that is open within max_distance
+pub struct AmbientOcclusion {
+    pub samples: u32,
+    pub max_distance: f32,
+}
+
+
+impl AmbientOcclusion {
+    pub fn new(samples: u32, max_distance: f32) -> AmbientOcclusion {
+        AmbientOcclusion { samples, max_distance }
+    }
+}
+
+
+impl Integrator for AmbientOcclusion {
+    fn li(&self, r: &Ray, scene: &Scene, _film: &mut Film) -> Color {
+        let mut rec = HitRecord::new();
+        if !scene.world.hit(r, 0.001, f32::INFINITY, &mut rec) {
+            return Color::fromv(1.0);
+        }
+
+        let uvw = Onb::build_from_w(&rec.normal);
+        let mut unoccluded = 0;
+
+        // each occlusion ray on dimensions of its own, as if it were a bounce
+        for sample in 0..self.samples {
+            start_bounce(sample);
+            let d = random_cosine_direction();
+            let direction = uvw.local(d.x, d.y, d.z);
+
+            let mut shadow_rec = HitRecord::new();
+            if !scene.world.hit(&Ray::new(rec.p, direction, r.time), 0.001, self.max_distance, &mut shadow_rec) {
+                unoccluded += 1;
+            }
+        }
+
+        Color::fromv(unoccluded as f32 / self.samples.max(1) as f32)
+    }
+}
+
+
+
+#[derive(Copy, Clone, Debug)]
+pub enum DebugMode {
+    Normals,
+    Depth,
+    Uvs,
+    MaterialIds,
+}
+
+
+// how far away a surface is before the depth view fades to 1/e
+const DEBUG_DEPTH_FALLOFF: f32 = 10.0;
+
+
+// false color views of what the camera ray hit first
+pub struct DebugIntegrator {
+    pub mode: DebugMode,
+}
+
+
+impl DebugIntegrator {
+    pub fn new(mode: DebugMode) -> DebugIntegrator {
+        DebugIntegrator { mode }
+    }
+}
+
+
+impl Integrator for DebugIntegrator {
+    fn li(&self, r: &Ray, scene: &Scene, _film: &mut Film) -> Color {
+        let mut rec = HitRecord::new();
+        if !scene.world.hit(r, 0.001, f32::INFINITY, &mut rec) {
+            return Color::zeros();
+        }
+
+        match self.mode {
+            DebugMode::Normals => 0.5 * (rec.normal + 1.0),
+            DebugMode::Depth => Color::fromv((-rec.t * r.direction.magnitude() / DEBUG_DEPTH_FALLOFF).exp()),
+            DebugMode::Uvs => Color::new(rec.u, rec.v, 0.0),
+            DebugMode::MaterialIds => {
+                // every material gets a made up color, the same one every run
+                let mut hasher = DefaultHasher::new();
+                scene.material_id(&rec.mat).hash(&mut hasher);
+                let h = hasher.finish();
+                Color::newi((h & 0xff) as i32, ((h >> 8) & 0xff) as i32, ((h >> 16) & 0xff) as i32) / 255.0
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{hittable_list::HittableList, sphere::Sphere, materials::*, sky::GradientSky, camera::Camera,
+                bdpt::Bdpt, random::{RandomSource, with_source}};
+    use std::{cell::RefCell, rc::Rc};
+
+    // the recursive coloray the path tracer replaced
+    fn coloray(r: &Ray, world: &dyn Hittable, scene: &Scene, depth: u8) -> Color {
+        if depth == 0 { return Color::zeros(); }
+
+        let mut rec = HitRecord::new();
+
+        if world.hit(r, 0.001, f32::INFINITY, &mut rec) {
+            if let Some((scatter_ray, attenuation)) = rec.mat.scatter(r, &rec) {
+                return attenuation * coloray(&scatter_ray, world, scene, depth - 1);
+            }
+            return Color::zeros();
+        }
+
+        scene.background(&r.direction)
+    }
+
+    fn simple_scene() -> Scene {
+        let mut world = HittableList::new();
+        world.add(Box::new(Sphere::new(Point3::new(0.0, -100.5, -1.0), 100.0, Arc::new(Lambertian::new(Color::new(0.8, 0.8, 0.0))))));
+        world.add(Box::new(Sphere::new(Point3::newi(0, 0, -1), 0.5, Arc::new(Lambertian::new(Color::new(0.7, 0.3, 0.3))))));
+        world.add(Box::new(Sphere::new(Point3::newi(-1, 0, -1), 0.5, Arc::new(Dielectric::new(1.5)))));
+        world.add(Box::new(Sphere::new(Point3::newi(1, 0, -1), 0.5, Arc::new(Metal::new(Color::new(0.8, 0.6, 0.2), 0.3)))));
+
+        let sky = GradientSky::new(Color::fromv(1.0), Color::new(0.5, 0.7, 1.0));
+        let cam = Camera::new(Point3::zeros(), Point3::newi(0, 0, -1), Vector3::newi(0, 1, 0), 90.0, 1.0, 0.0, 1.0, 0.0, 0.0);
+        Scene::new(world, HittableList::new(), Some(Box::new(sky)), cam)
+    }
+
+    // simple_scene with a lamp overhead, to exercise sampling emitters as well as the sky
+    fn lit_scene() -> Scene {
+        let mut scene = simple_scene();
+        let lamp = Arc::new(DiffuseLight::new(Color::fromv(4.0)));
+        scene.world.add(Box::new(Sphere::new(Point3::new(0.0, 2.0, -1.0), 0.5, lamp.clone())));
+        scene.lights.add(Box::new(Sphere::new(Point3::new(0.0, 2.0, -1.0), 0.5, lamp)));
+        scene
+    }
+
+    fn targets() -> [Point3; 4] {
+        [Point3::newi(0, 0, -1), Point3::new(-1.0, 0.1, -1.0), Point3::new(1.0, -0.2, -1.0), Point3::new(0.0, -0.45, -1.0)]
+    }
+
+    type Estimator<'a> = &'a dyn Fn(&Ray, &mut Film) -> Color;
+
+    fn assert_same_mean(expected: Estimator, actual: Estimator, samples: u32, tolerance: f32) {
+        let mut film = Film::new(2, 2);
+
+        for target in targets().iter() {
+            let r = Ray::new(Point3::zeros(), *target, 0.0);
+            let mut difference = Color::zeros();
+
+            for _ in 0..samples {
+                difference += expected(&r, &mut film) - actual(&r, &mut film);
+            }
+
+            let difference = difference / samples as f32;
+            assert!(difference.magnitude() < tolerance,
+                    "mean radiance differs by {} {} {}", difference.x, difference.y, difference.z);
+        }
+    }
+
+    #[test]
+    fn russian_roulette_matches_recursive_coloray() {
+        let scene = simple_scene();
+        // roulette from the very first bounce, the harshest setting
+        let tracer = PathTracer::new(50, 0);
+
+        assert_same_mean(&|r, _| coloray(r, &scene.world, &scene, 50), &|r, film| tracer.li(r, &scene, film), 20000, 0.02);
+    }
+
+    #[test]
+    fn light_sampling_matches_naive_path_tracing() {
+        let scene = lit_scene();
+        let naive = PathTracer::new(50, 3);
+        let mis = MisPathTracer::new(50, 3);
+
+        assert_same_mean(&|r, film| naive.li(r, &scene, film), &|r, film| mis.li(r, &scene, film), 20000, 0.02);
+    }
+
+    // plain random numbers, noting the bounces the path says it starts
+    struct BounceLog(Rc<RefCell<Vec<u32>>>);
+
+    impl RandomSource for BounceLog {
+        fn next(&mut self) -> f32 {
+            rand::random()
+        }
+
+        fn start_bounce(&mut self, bounce: u32) {
+            self.0.borrow_mut().push(bounce);
+        }
+    }
+
+    #[test]
+    fn every_integrator_lines_its_bounces_up_with_the_sampler() {
+        let scene = lit_scene();
+        let integrators: Vec<(Box<dyn Integrator>, Vec<u32>)> = vec![
+            (Box::new(PathTracer::new(8, 3)), vec![0]),
+            (Box::new(MisPathTracer::new(8, 3)), vec![0]),
+            (Box::new(DirectLighting::new(8)), vec![0]),
+            (Box::new(AmbientOcclusion::new(3, 1.0)), vec![0, 1, 2]),
+            (Box::new(Bdpt::new(8)), vec![0, 8, 9, 16]), // the camera subpath, the light's, the connections
+        ];
+
+        for (integrator, expected) in integrators.iter() {
+            let log = Rc::new(RefCell::new(Vec::new()));
+            with_source(Box::new(BounceLog(log.clone())), || {
+                integrator.li(&Ray::new(Point3::zeros(), Point3::newi(0, 0, -1), 0.0), &scene, &mut Film::new(2, 2))
+            });
+
+            let log = log.borrow();
+            assert!(expected.iter().all(|bounce| log.contains(bounce)), "started bounces {:?}", log);
+            assert!(log.windows(2).all(|pair| pair[0] < pair[1]), "bounces out of order {:?}", log);
+        }
+    }
+}
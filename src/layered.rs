@@ -0,0 +1,473 @@
+use crate::{
+            vectors::*,
+            rays::Ray,
+            hittable::HitRecord,
+            materials::*,
+            microfacet::*,
+            onb::Onb,
+            texture::Texture,
+            random::random_f32,
+            colors::luminance,
+            spectrum,
+};
+use std::sync::Arc;
+
+
+// either a or b at each hit, b with probability amount. the pick has to be the same for every
+// question asked about one hit, & not all of them see the ray, so it hashes the hit point
+// instead of drawing a random number (as pbrt does)
+pub struct Mix {
+    pub a: Arc<dyn Material>,
+    pub b: Arc<dyn Material>,
+    pub amount: Arc<dyn Texture>,
+}
+
+
+impl Mix {
+
+    pub fn new(a: Arc<dyn Material>, b: Arc<dyn Material>, amount: Arc<dyn Texture>) -> Mix {
+        Mix { a, b, amount }
+    }
+
+    fn choose(&self, rec: &HitRecord) -> &dyn Material {
+        if hash_point(&rec.p) < self.amount.scalar(rec.u, rec.v, &rec.p) {&*self.b} else {&*self.a}
+    }
+
+}
+
+
+// a uniform number in [0, 1) from the bits of p, through splitmix64's finalizer
+pub fn hash_point(p: &Point3) -> f32 {
+    let mut h = (p.x.to_bits() as u64) ^ ((p.y.to_bits() as u64) << 21) ^ ((p.z.to_bits() as u64) << 42);
+    h = (h ^ (h >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    h = (h ^ (h >> 27)).wrapping_mul(0x94d049bb133111eb);
+    h ^= h >> 31;
+
+    (h >> 40) as f32 / (1u64 << 24) as f32
+}
+
+
+impl Material for Mix {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Ray, Color)> {
+        self.choose(rec).scatter(r_in, rec)
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Color {
+        self.choose(rec).emitted(rec)
+    }
+
+    // without a hit to look at, light can only be sampled if neither side needs to be specular
+    fn is_specular(&self) -> bool {
+        self.a.is_specular() || self.b.is_specular()
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: &Vector3) -> Color {
+        self.choose(rec).eval(r_in, rec, direction)
+    }
+
+    fn scattering_pdf(&self, r_in: &Ray, rec: &HitRecord, direction: &Vector3) -> f32 {
+        self.choose(rec).scattering_pdf(r_in, rec, direction)
+    }
+
+    fn is_cut_out(&self, rec: &HitRecord) -> bool {
+        self.choose(rec).is_cut_out(rec)
+    }
+
+    fn diffuse_fraction(&self, r_in: &Ray, rec: &HitRecord, direction: &Vector3) -> f32 {
+        self.choose(rec).diffuse_fraction(r_in, rec, direction)
+    }
+}
+
+
+
+// one material on the side the surface's outward normal faces & another on the back, e.g. a leaf
+// with a paler underside, or a card painted on one face only
+pub struct TwoSided {
+    pub front: Arc<dyn Material>,
+    pub back: Arc<dyn Material>,
+}
+
+
+impl TwoSided {
+
+    pub fn new(front: Arc<dyn Material>, back: Arc<dyn Material>) -> TwoSided {
+        TwoSided { front, back }
+    }
+
+    fn side(&self, rec: &HitRecord) -> &dyn Material {
+        if rec.front_face {&*self.front} else {&*self.back}
+    }
+
+}
+
+
+impl Material for TwoSided {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Ray, Color)> {
+        self.side(rec).scatter(r_in, rec)
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Color {
+        self.side(rec).emitted(rec)
+    }
+
+    fn is_specular(&self) -> bool {
+        self.front.is_specular() || self.back.is_specular()
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: &Vector3) -> Color {
+        self.side(rec).eval(r_in, rec, direction)
+    }
+
+    fn scattering_pdf(&self, r_in: &Ray, rec: &HitRecord, direction: &Vector3) -> f32 {
+        self.side(rec).scattering_pdf(r_in, rec, direction)
+    }
+
+    fn is_cut_out(&self, rec: &HitRecord) -> bool {
+        self.side(rec).is_cut_out(rec)
+    }
+
+    fn diffuse_fraction(&self, r_in: &Ray, rec: &HitRecord, direction: &Vector3) -> f32 {
+        self.side(rec).diffuse_fraction(r_in, rec, direction)
+    }
+}
+
+
+
+// a varnish over any base (weidlich & wilkie, "arbitrarily layered micro-facet surfaces"). light
+// either glints off the rough dielectric coat, or refracts through its mean surface, gets tinted
+// on the way, scatters once off the base & refracts back out. light bouncing around inside the
+// coat more than that is lost, so thick coats darken a little too much. a specular base has no
+// bsdf to put into that sum, so over one the coat is sampled instead: a random walk between the
+// base & the underside of the coat, reflecting off it as often as fresnel says, until it gets out
+pub struct Coated {
+    pub base: Arc<dyn Material>,
+    pub ior: f32,
+    pub distribution: TrowbridgeReitz,
+    pub tint: Color, // the coat's transmittance straight down through it
+}
+
+
+// the coat is never perfectly smooth, a delta lobe couldn't share a pdf with the base
+const MIN_COAT_ALPHA: f32 = 0.002;
+
+// reflections off the underside of the coat a walk over a specular base follows before giving up
+const MAX_LAYER_BOUNCES: u32 = 64;
+
+
+// refracts w (above the surface) through a flat boundary into a medium of relative index eta.
+// the result points up too, it's the direction inside that refracts out into w. None on total
+// internal reflection, when eta < 1
+fn refract_flat(w: &Vector3, eta: f32) -> Option<Vector3> {
+    let (x, y) = (w.x / eta, w.y / eta);
+    let cos2 = 1.0 - x * x - y * y;
+
+    if cos2 <= 0.0 {None} else {Some(Vector3::new(x, y, cos2.sqrt()))}
+}
+
+
+impl Coated {
+
+    // a glossy, clear coat. its index can't be below 1, light from anywhere above has to be able to
+    // refract into it
+    pub fn new(base: Arc<dyn Material>, ior: f32) -> Coated {
+        assert!(ior >= 1.0, "a coat's index of refraction can't be below 1, got {}", ior);
+        Coated { base, ior, distribution: TrowbridgeReitz::new(0.02, 0.02), tint: Color::fromv(1.0) }
+    }
+
+    pub fn with_roughness(self, alpha: f32) -> Coated {
+        let alpha = alpha.max(MIN_COAT_ALPHA);
+        Coated { distribution: TrowbridgeReitz::new(alpha, alpha), ..self }
+    }
+
+    pub fn with_tint(self, tint: Color) -> Coated {
+        Coated { tint, ..self }
+    }
+
+    // chance of sampling the coat over the base. by its reflectance, but that's 4% head on, which
+    // would leave the highlight to a few noisy samples, & goes to 1 at grazing angles, where the
+    // base would never be sampled. any probability is unbiased, so it's kept between the two
+    fn coat_probability(&self, wo: &Vector3) -> f32 {
+        fresnel_dielectric(wo.z, self.ior).clamp(0.25, 0.9)
+    }
+
+    // what the base sees: rays coming down along wo_inside & leaving along wi_inside, in world space
+    fn inside(&self, r_in: &Ray, uvw: &Onb, wo_inside: &Vector3) -> Ray {
+        Ray::new(r_in.origin, -uvw.local(wo_inside.x, wo_inside.y, wo_inside.z), r_in.time)
+    }
+
+    // the coat's own reflection, bsdf times cosine like eval
+    fn coat(&self, wo: &Vector3, wi: &Vector3) -> f32 {
+        let wh = (*wo + *wi).normalized();
+        self.distribution.d(&wh) * self.distribution.g(wo, wi) * fresnel_dielectric(Vector3::dot(wo, &wh), self.ior) / (4.0 * wo.z)
+    }
+
+    // turns the base's solid angle inside the coat into the one outside, for wi refracting into wi_inside
+    fn compression(&self, wi: &Vector3, wi_inside: &Vector3) -> f32 {
+        wi.z / (self.ior * self.ior * wi_inside.z)
+    }
+
+    // the coat's tint along a path through it at cos_theta to the normal
+    fn absorption(&self, cos_theta: f32) -> Color {
+        let tint = spectrum::sampled(&self.tint);
+        let path = 1.0 / cos_theta;
+        Color::new(tint.x.powf(path), tint.y.powf(path), tint.z.powf(path))
+    }
+
+    // light that refracted in along wo_inside, bouncing between a specular base & the coat until
+    // it leaves, out of the top or into the base. the direction it leaves in, in world space, & its weight
+    fn walk(&self, r_in: &Ray, rec: &HitRecord, uvw: &Onb, mut wo_inside: Vector3) -> Option<(Vector3, Color)> {
+        let mut weight = Color::fromv(1.0);
+
+        for _ in 0..MAX_LAYER_BOUNCES {
+            weight *= self.absorption(wo_inside.z);
+            let (scattered, base) = self.base.scatter(&self.inside(r_in, uvw, &wo_inside), rec)?;
+            weight *= base;
+
+            // through the base, e.g. into glass
+            let wi_inside = uvw.to_local(&scattered.direction.normalized());
+            if wi_inside.z <= 0.0 {
+                return Some((scattered.direction, weight));
+            }
+            weight *= self.absorption(wi_inside.z);
+
+            // out through the coat, or reflected back down by it, with total internal reflection past the critical angle
+            if random_f32() >= fresnel_dielectric(wi_inside.z, 1.0 / self.ior) {
+                let wi = refract_flat(&wi_inside, 1.0 / self.ior)?;
+                return Some((uvw.local(wi.x, wi.y, wi.z), weight));
+            }
+            wo_inside = Vector3::new(-wi_inside.x, -wi_inside.y, wi_inside.z);
+        }
+
+        None
+    }
+
+    // over a specular base: the coat's glint, or a walk through the coat
+    fn scatter_over_specular(&self, r_in: &Ray, rec: &HitRecord, uvw: &Onb, wo: &Vector3) -> Option<(Ray, Color)> {
+        let p_coat = self.coat_probability(wo);
+
+        if random_f32() < p_coat {
+            let wm = self.distribution.sample_visible_normal(wo, random_f32(), random_f32());
+            let wi = Vector3::reflect(&-*wo, &wm);
+            if wi.z <= 0.0 {
+                return None;
+            }
+
+            // the base's delta lobe can't have picked wi as well, so the weight is the coat's alone
+            let pdf = self.distribution.visible_d(wo, &wm) / (4.0 * Vector3::dot(wo, &wm));
+            let weight = self.coat(wo, &wi) / (p_coat * pdf);
+            return Some((Ray::new(rec.p, uvw.local(wi.x, wi.y, wi.z), r_in.time), Color::fromv(weight)));
+        }
+
+        let wo_inside = refract_flat(wo, self.ior).unwrap();
+        let (direction, weight) = self.walk(r_in, rec, uvw, wo_inside)?;
+        let transmittance = 1.0 - fresnel_dielectric(wo.z, self.ior);
+
+        Some((Ray::new(rec.p, direction, r_in.time), transmittance / (1.0 - p_coat) * weight))
+    }
+
+}
+
+
+impl Material for Coated {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Ray, Color)> {
+        // only the outside is coated
+        if !rec.front_face {
+            return self.base.scatter(r_in, rec);
+        }
+
+        let (uvw, wo, _) = shading_frame(r_in, rec, &rec.normal);
+        if wo.z <= 0.0 {
+            return None;
+        }
+
+        if self.base.is_specular() {
+            return self.scatter_over_specular(r_in, rec, &uvw, &wo);
+        }
+
+        let wi = if random_f32() < self.coat_probability(&wo) {
+            let wm = self.distribution.sample_visible_normal(&wo, random_f32(), random_f32());
+            Vector3::reflect(&-wo, &wm)
+        } else {
+            let wo_inside = refract_flat(&wo, self.ior).unwrap();
+            let (scattered, _) = self.base.scatter(&self.inside(r_in, &uvw, &wo_inside), rec)?;
+
+            // back out, unless it's reflected back in, which isn't followed
+            let wi_inside = uvw.to_local(&scattered.direction.normalized());
+            if wi_inside.z <= 0.0 {
+                return None;
+            }
+            refract_flat(&wi_inside, 1.0 / self.ior)?
+        };
+
+        if wi.z <= 0.0 {
+            return None;
+        }
+
+        sampled(self, r_in, rec, Ray::new(rec.p, uvw.local(wi.x, wi.y, wi.z), r_in.time))
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Color {
+        self.base.emitted(rec)
+    }
+
+    // over a specular base the walk can only be sampled, not evaluated
+    fn is_specular(&self) -> bool {
+        self.base.is_specular()
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: &Vector3) -> Color {
+        if !rec.front_face {
+            return self.base.eval(r_in, rec, direction);
+        }
+
+        let (uvw, wo, wi) = shading_frame(r_in, rec, direction);
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return Color::zeros();
+        }
+
+        let coat = self.coat(&wo, &wi);
+
+        // both directions always refract in, going from the thinner medium
+        let (wo_inside, wi_inside) = (refract_flat(&wo, self.ior).unwrap(), refract_flat(&wi, self.ior).unwrap());
+        let transmittance = (1.0 - fresnel_dielectric(wo.z, self.ior)) * (1.0 - fresnel_dielectric(wi.z, self.ior));
+        let absorption = self.absorption(wo_inside.z) * self.absorption(wi_inside.z);
+
+        let base = self.base.eval(&self.inside(r_in, &uvw, &wo_inside), rec, &uvw.local(wi_inside.x, wi_inside.y, wi_inside.z));
+
+        Color::fromv(coat) + transmittance * self.compression(&wi, &wi_inside) * absorption * base
+    }
+
+    fn scattering_pdf(&self, r_in: &Ray, rec: &HitRecord, direction: &Vector3) -> f32 {
+        if !rec.front_face {
+            return self.base.scattering_pdf(r_in, rec, direction);
+        }
+
+        let (uvw, wo, wi) = shading_frame(r_in, rec, direction);
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return 0.0;
+        }
+
+        let wh = (wo + wi).normalized();
+        let coat = self.distribution.visible_d(&wo, &wh) / (4.0 * Vector3::dot(&wo, &wh));
+
+        let (wo_inside, wi_inside) = (refract_flat(&wo, self.ior).unwrap(), refract_flat(&wi, self.ior).unwrap());
+        let base = self.base.scattering_pdf(&self.inside(r_in, &uvw, &wo_inside), rec, &uvw.local(wi_inside.x, wi_inside.y, wi_inside.z));
+
+        let p_coat = self.coat_probability(&wo);
+        p_coat * coat + (1.0 - p_coat) * self.compression(&wi, &wi_inside) * base
+    }
+
+    // the coat's glossy, the rest is as diffuse as the base is
+    fn diffuse_fraction(&self, r_in: &Ray, rec: &HitRecord, direction: &Vector3) -> f32 {
+        if !rec.front_face {
+            return self.base.diffuse_fraction(r_in, rec, direction);
+        }
+
+        let (uvw, wo, wi) = shading_frame(r_in, rec, direction);
+        let total = luminance(&self.eval(r_in, rec, direction));
+        if total <= 0.0 {
+            return 0.0;
+        }
+
+        let (wo_inside, wi_inside) = (refract_flat(&wo, self.ior).unwrap(), refract_flat(&wi, self.ior).unwrap());
+        let base = self.base.diffuse_fraction(&self.inside(r_in, &uvw, &wo_inside), rec, &uvw.local(wi_inside.x, wi_inside.y, wi_inside.z));
+
+        base * (1.0 - self.coat(&wo, &wi) / total).max(0.0)
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{microfacet::tests::*, texture::*, principled::Principled};
+
+    #[test]
+    fn mix_picks_b_by_amount() {
+        let mix = Mix::new(Arc::new(Lambertian::new(Color::fromv(0.2))), Arc::new(Lambertian::new(Color::fromv(0.8))), constant(0.25));
+        let (r, mut rec) = hit_at(0.0, true);
+        let samples = 100_000;
+
+        let mut albedo = Color::zeros();
+        for _ in 0..samples {
+            rec.p = Point3::new(random_f32(), random_f32(), 0.0);
+            if let Some((_, weight)) = mix.scatter(&r, &rec) {
+                albedo += weight;
+            }
+        }
+
+        assert!((albedo.x / samples as f32 - (0.75 * 0.2 + 0.25 * 0.8)).abs() < 0.01);
+    }
+
+    #[test]
+    fn two_sided_picks_by_face() {
+        let card = TwoSided::new(Arc::new(Lambertian::new(Color::fromv(0.2))), Arc::new(Lambertian::new(Color::fromv(0.8))));
+
+        let (r, rec) = hit_at(30.0, true);
+        assert!((integrated_albedo(&card, &r, &rec).x - 0.2).abs() < 0.01);
+        let (r, rec) = hit_at(30.0, false);
+        assert!((integrated_albedo(&card, &r, &rec).x - 0.8).abs() < 0.01);
+    }
+
+    #[test]
+    fn coats_sample_what_they_evaluate() {
+        let white = Arc::new(Lambertian::new(Color::fromv(1.0)));
+        let coats = [
+            Coated::new(white.clone(), 1.5).with_roughness(0.1),
+            Coated::new(white, 1.5).with_roughness(0.3).with_tint(Color::new(0.9, 0.6, 0.3)),
+            Coated::new(Arc::new(Principled { metallic: constant(1.0), roughness: constant(0.5), ..Principled::new(solid(Color::new(0.9, 0.2, 0.2))) }), 1.6)
+                .with_roughness(0.05),
+        ];
+
+        for coat in coats.iter() {
+            for theta in [0.0, 45.0, 75.0].iter() {
+                let (r, rec) = hit_at(*theta, true);
+                assert_sampling_matches_eval(coat, &r, &rec);
+
+                // a coat over a white base can only lose light
+                let albedo = integrated_albedo(coat, &r, &rec);
+                assert!(albedo.x < 1.0, "albedo {} at {} degrees", albedo.x, theta);
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "can't be below 1")]
+    fn coats_are_denser_than_the_air_above_them() {
+        // light at grazing angles couldn't refract into it
+        Coated::new(Arc::new(Lambertian::new(Color::fromv(1.0))), 0.8);
+    }
+
+    #[test]
+    fn specular_bases_are_walked_through_the_coat() {
+        let mirror: Arc<dyn Material> = Arc::new(Metal::new(Color::fromv(1.0), 0.0));
+        let clear = Coated::new(mirror.clone(), 1.5);
+        let tinted = Coated::new(mirror, 1.5).with_roughness(0.1).with_tint(Color::new(0.9, 0.6, 0.3));
+        assert!(clear.is_specular());
+
+        for theta in [0.0, 45.0, 75.0].iter() {
+            let (r, rec) = hit_at(*theta, true);
+
+            // everything that gets under the coat comes back out eventually, off a mirror
+            let albedo = sampled_albedo(&clear, &r, &rec, 100_000);
+            assert!((albedo - Color::fromv(1.0)).magnitude() < 0.02, "albedo {} at {} degrees", albedo.x, theta);
+
+            // & the coat takes its share out of each pass through it
+            let albedo = sampled_albedo(&tinted, &r, &rec, 100_000);
+            assert!(albedo.x > albedo.y && albedo.y > albedo.z && albedo.x < 0.95, "albedo {} {} {} at {} degrees", albedo.x, albedo.y, albedo.z, theta);
+        }
+
+        // a glass base lets most of it through, into the object
+        let glass = Coated::new(Arc::new(Dielectric::new(1.5)), 1.5).with_roughness(0.1);
+        let (r, rec) = hit_at(0.0, true);
+        let mut through = Color::zeros();
+        for _ in 0..10_000 {
+            match glass.scatter(&r, &rec) {
+                Some((scattered, weight)) if Vector3::dot(&scattered.direction, &rec.normal) < 0.0 => through += weight / 10_000.0,
+                _ => {}
+            }
+        }
+        assert!(through.x > 0.85 && through.x < 0.95, "{} got through", through.x);
+    }
+}
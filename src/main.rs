@@ -7,104 +7,59 @@ mod sphere;
 mod camera;
 mod materials;
 mod moving_sphere;
+mod onb;
+mod sky;
+mod options;
+mod integrator;
+mod scene;
+mod film;
+mod render;
+mod bdpt;
+mod sppm;
+mod random;
+mod aarect;
+mod mlt;
+mod spectrum;
+mod microfacet;
+mod texture;
+mod principled;
+mod layered;
+mod thin_film;
+mod subsurface;
+mod bump;
+mod cutout;
+mod thin;
+mod sampler;
+mod filter;
+mod denoise;
+mod aov;
+mod tonemap;
+mod color_space;
+mod scene_file;
+mod mesh;
 
 use crate::{
-            vectors::{Vector3, Color, Point3},
-            colors::*,
-            rays::Ray,
-            hittable::*,
-            hittable_list::*,
-            sphere::*,
-            camera::*,
-            materials::*,
-            moving_sphere::*,
+            vectors::Color,
+            sky::*,
+            options::*,
+            integrator::*,
+            scene::*,
+            film::*,
+            bdpt::Bdpt,
+            sppm::Sppm,
+            mlt::Mlt,
+            spectrum::Spectral,
+            sampler::*,
+            filter::*,
+            denoise::Denoiser,
+            tonemap::OutputTransform,
+            texture::ImageTexture,
+            color_space::*,
+            scene_file::load_scene,
 };
+use std::{io::{self, BufWriter}, sync::Arc};
 
 extern crate rand;
-use rand::prelude::*;
-use std::sync::Arc;
-
-fn coloray(r: &Ray, world: &dyn Hittable, depth: u8) -> Color {
-    if depth <= 0 { return Color::zeros(); }
-
-    let mut rec = HitRecord::new();
-
-    if world.hit(&r, 0.001, f32::INFINITY, &mut rec) {
-        
-        let attenuation: Color = rec.mat.get_attenuation();
-        let scatter_ray: Ray = rec.mat.get_scatter_ray(r, &rec);
-
-        if rec.mat.scatter(&rec, &scatter_ray) {
-            return attenuation * coloray(&scatter_ray, world, depth -1);
-        }
-        return Color::zeros();
-    }
-
-    let unit_direction: Vector3 = r.direction.normalized();
-    let t: f32 = (unit_direction.y + 1.0) * 0.5;
-
-    return (1.0 - t) * Color::fromv(1.0) + t * Color::new(0.5, 0.7, 1.0);
-}
-
-
-fn random_scene() -> HittableList {
-    let mut world = HittableList::new();
-
-    let ground = Arc::new(Lambertian::new(Color::fromv(0.5)));
-    world.add(Box::new(Sphere::new(Point3::new( 0.0, -1000.0, 0.0), 1000.0, ground)));
-
-    let mut rng = thread_rng();
-
-    // let mut sphere_material: Arc<dyn Material>;
-
-    for i in -11..11 {
-        for j in -11..11 {
-            let choose_mat = rng.gen::<f32>();
-            let center = Point3::new(i as f32 + 0.9 * rng.gen::<f32>(), 0.2, j as f32 + 0.9 * rng.gen::<f32>());
-
-            if (center - Point3::new(4.0, 0.2, 0.0)).magnitude() > 0.9 {
-
-                if choose_mat < 0.8 {
-                    // diffuse
-                    let albedo = Color::random() * Color::random();
-                    let sphere_material = Arc::new(Lambertian::new(albedo));
-                    let center2 = center + Vector3::new(0.0, rng.gen_range(0.0..0.5), 0.0);
-                    world.add(Box::new(MovingSphere::new(center, center2, 0.0, 0.1, 0.2, sphere_material)));
-                    
-                } else if choose_mat < 0.95 {
-                    // metal
-                    let albedo = Color::random_by_range(0.5, 1.0);
-                    let fuzz = rng.gen_range(0.0..0.5);
-
-                    let sphere_material = Arc::new(Metal::new(albedo, fuzz));
-                    world.add(Box::new(Sphere::new(center, 0.2, sphere_material)));
-
-                } else {
-                    // glass
-                    let sphere_material = Arc::new(Dielectric::new(1.5));
-                    world.add(Box::new(Sphere::new(center, 0.2, sphere_material)));
-                }
-
-                // world.add(Box::new(Sphere::new(center, 0.2, sphere_material)));
-            }
-
-        }
-    }
-
-
-    let m1 = Arc::new(Dielectric::new(1.5));
-    world.add(Box::new(Sphere::new(Point3::newi(0, 1, 0), 1.0, m1)));
-
-    let m2 = Arc::new(Lambertian::new(Color::new(0.4, 0.2, 0.1)));
-    world.add(Box::new(Sphere::new(Point3::newi(-4, 1, 0), 1.0, m2)));
-
-    let m3 = Arc::new(Metal::new(Color::new(0.7, 0.6, 0.5), 0.0));
-    world.add(Box::new(Sphere::new(Point3::newi(4, 1, 0), 1.0, m3)));
-    
-
-    world
-}
-
 
 fn main() {
 
@@ -113,42 +68,142 @@ fn main() {
     const WIDTH: u32 = 400;
     const HEIGHT: u32 = (WIDTH as f32 / ASPECT_RATIO) as u32;
     const SAMPLES_PER_PIXEL: u32 = 100;
-    const MAX_DEPTH: u8 = 50;
 
-    // world
-    let world = random_scene();
+    let options = Options::from_args();
 
-    // camera
-    let look_from = Point3::newi(13, 2, 3);
-    let look_at = Point3::zeros();
-    let vup = Vector3::newi(0, 1, 0);
-    let vfov = 20.0;
-    let aperture = 0.1;
-    let focus_dist = 10.0; //(look_from - look_at).magnitude();
+    // the seed the scene's built from, the checkpoint's when resuming one unless another's given
+    let seed = match (options.seed, &options.checkpoint) {
+        (Some(seed), _) => seed,
+        (None, Some(path)) if options.resume => Film::checkpoint_seed(path).unwrap_or_else(|error| {
+            eprintln!("couldn't resume from {}: {}", path, error);
+            std::process::exit(1);
+        }),
+        (None, _) => rand::random(),
+    };
 
-    let cam = Camera::new(look_from, look_at, vup, vfov, ASPECT_RATIO, aperture, focus_dist, 0.0, 0.1);
+    // world
+    let mut scene = match options.scene {
+        SceneChoice::Random => random_scene(ASPECT_RATIO, seed),
+        SceneChoice::Caustics => caustics_scene(ASPECT_RATIO),
+        SceneChoice::Window => window_scene(ASPECT_RATIO),
+        SceneChoice::Dispersion => dispersion_scene(ASPECT_RATIO),
+        SceneChoice::Rough => rough_scene(ASPECT_RATIO),
+        SceneChoice::Principled => principled_scene(ASPECT_RATIO),
+        SceneChoice::Layered => layered_scene(ASPECT_RATIO),
+        SceneChoice::Iridescent => iridescent_scene(ASPECT_RATIO),
+        SceneChoice::Subsurface => subsurface_scene(ASPECT_RATIO),
+        SceneChoice::Bumps => bumps_scene(ASPECT_RATIO),
+        SceneChoice::Cutouts => cutouts_scene(ASPECT_RATIO, seed),
+        SceneChoice::Sheets => sheets_scene(ASPECT_RATIO),
+        SceneChoice::Colors => {
+            let image = options.texture.as_ref().map(|path| ImageTexture::load_ppm(path, options.texture_space, options.texture_transfer).unwrap_or_else(|error| {
+                eprintln!("couldn't read the texture {}: {}", path, error);
+                std::process::exit(1);
+            }));
+            colors_scene(ASPECT_RATIO, image)
+        }
+        SceneChoice::File(ref path) => load_scene(path, ASPECT_RATIO).unwrap_or_else(|error| {
+            eprintln!("couldn't read the scene {}: {}", path, error);
+            std::process::exit(1);
+        }),
+    };
+
+    match options.sky {
+        Some(SkyChoice::Gradient) => scene.sky = Some(Box::new(GradientSky::new(Color::fromv(1.0), Color::new(0.5, 0.7, 1.0)))),
+        Some(SkyChoice::Physical) => {
+            let sun_direction = direction_from_angles(options.sun_elevation, options.sun_azimuth);
+            scene.sky = Some(Box::new(PhysicalSky::new(sun_direction, options.turbidity)));
+        }
+        None => {}
+    }
 
-   // random setup
-    let mut rng = thread_rng();
+    let integrator: Box<dyn Integrator> = match options.integrator {
+        IntegratorChoice::Path => Box::new(PathTracer::new(options.max_depth, options.rr_min_bounces)),
+        IntegratorChoice::Mis => Box::new(MisPathTracer::new(options.max_depth, options.rr_min_bounces)),
+        IntegratorChoice::Direct => Box::new(DirectLighting::new(options.max_depth)),
+        IntegratorChoice::AmbientOcclusion => Box::new(AmbientOcclusion::new(options.ao_samples, options.ao_distance)),
+        IntegratorChoice::Bidirectional => Box::new(Bdpt::new(options.max_depth)),
+        IntegratorChoice::Metropolis => Box::new(Mlt::new(options.max_depth, options.rr_min_bounces, options.bootstrap, options.chains, options.mutation_size, options.large_step)),
+        IntegratorChoice::PhotonMapping => Box::new(Sppm::new(options.passes, options.photons, options.photon_memory << 20, options.photon_radius, options.max_depth)),
+        IntegratorChoice::Debug(mode) => Box::new(DebugIntegrator::new(mode)),
+    };
+
+    let integrator = if options.spectral {Box::new(Spectral::new(integrator))} else {integrator};
+
+    let sampler: Arc<dyn Sampler> = match options.sampler {
+        SamplerChoice::Independent => Arc::new(Independent),
+        SamplerChoice::Stratified => Arc::new(Stratified::new(SAMPLES_PER_PIXEL)),
+        SamplerChoice::Halton => Arc::new(Halton),
+        SamplerChoice::Sobol => Arc::new(Sobol::new()),
+        SamplerChoice::PaddedSobol => Arc::new(PaddedSobol::new()),
+    };
+
+    let radius = |default: f32| options.filter_radius.unwrap_or(default);
+    let filter: Arc<dyn Filter> = match options.filter {
+        FilterChoice::Box => Arc::new(BoxFilter::new(radius(0.5))),
+        FilterChoice::Tent => Arc::new(TentFilter::new(radius(1.0))),
+        FilterChoice::Gaussian => Arc::new(GaussianFilter::new(radius(1.5), radius(1.5) / 3.0)),
+        FilterChoice::Mitchell => Arc::new(MitchellFilter::new(radius(2.0), 1.0 / 3.0, 1.0 / 3.0)),
+        FilterChoice::Lanczos => Arc::new(LanczosFilter::new(radius(3.0), radius(3.0))),
+    };
+
+    let transform = OutputTransform::new(options.exposure, options.white_balance, options.tonemap).with_color_spaces(options.working_space, options.display);
 
     // render
-    println!("P3\n{} {}\n255", WIDTH, HEIGHT);
-
-
-    for j in (0..HEIGHT-1).rev() {
-        eprintln!("\rLines remaining - {}", j);
-        for i in 0..WIDTH {
+    let mut film = Film::new(WIDTH, HEIGHT).with_filter(filter);
+    if options.filter_importance {
+        film = film.with_filter_importance_sampling();
+    }
+    if options.denoise.is_some() {
+        film = film.with_features();
+    }
+    if !options.aovs.is_empty() {
+        film = film.with_aovs(options.aovs.clone());
+    }
+    if let Some(target_error) = options.target_error {
+        film = film.with_target_error(target_error);
+    }
+    if let Some(path) = &options.progressive {
+        let every_passes = if options.snapshot_seconds.is_some() {options.snapshot_passes} else {Some(options.snapshot_passes.unwrap_or(10))};
+        film = film.with_snapshots(Snapshots { path: path.clone(), transform: transform.clone(), every_passes, every_seconds: options.snapshot_seconds });
+    }
+    if let Some(time_budget) = options.time_budget {
+        film = film.with_time_budget(time_budget);
+    }
+    if let Some(path) = &options.checkpoint {
+        film = film.with_checkpoints(Checkpoints { path: path.clone(), every_seconds: options.checkpoint_seconds, seed, settings: options.render_settings(SAMPLES_PER_PIXEL) });
+        if options.resume {
+            if let Err(error) = film.load_checkpoint(path) {
+                eprintln!("couldn't resume from {}: {}", path, error);
+                std::process::exit(1);
+            }
+        }
+    }
+    with_working_space(options.working_space, || integrator.render(&scene, &mut film, &sampler, SAMPLES_PER_PIXEL));
+    if let Some(iterations) = options.denoise {
+        film.denoise(&Denoiser::new(iterations));
+    }
+    let written = match &options.progressive {
+        Some(path) => film.save_ppm(path, &transform),
+        None => film.write_ppm(&mut BufWriter::new(io::stdout().lock()), &transform),
+    };
+    if let Err(error) = written {
+        eprintln!("couldn't write the image: {}", error);
+    }
 
-            let mut pixel_color = Color::zeros();
+    if let Some(path) = &options.hdr {
+        if let Err(error) = film.write_hdr(path) {
+            eprintln!("couldn't write the hdr image to {}: {}", path, error);
+        }
+    }
 
-            for _ in 0..SAMPLES_PER_PIXEL {
-                let u = (i as f32 + rng.gen_range(0.0..1.0)) / ((WIDTH - 1) as f32);
-                let v = (j as f32 + rng.gen_range(0.0..1.0)) / ((HEIGHT - 1) as f32);
+    if let Err(error) = film.write_aovs(&options.aov_prefix) {
+        eprintln!("couldn't write the aovs to {}: {}", options.aov_prefix, error);
+    }
 
-                let r: Ray = cam.get_ray(u, v);
-                pixel_color += coloray(&r, &world, MAX_DEPTH);
-            }
-            write_color(pixel_color, SAMPLES_PER_PIXEL);
+    if let Some(path) = &options.heat_map {
+        if let Err(error) = film.write_sample_heat_map(path) {
+            eprintln!("couldn't write the heat map to {}: {}", path, error);
         }
     }
 
@@ -1,11 +1,80 @@
-use crate::{vectors::*, rays::*, hittable::*};
-use rand::prelude::*;
+use crate::{vectors::*, rays::*, hittable::*, onb::Onb, microfacet::*, thin_film::*, spectrum};
+use crate::{random::random_f32, colors::average};
+use std::f32::consts::PI;
 
 
-pub trait Material {
-    fn scatter(&self, rec: &HitRecord, scattered: &Ray) -> bool;
-    fn get_attenuation(&self) -> Color;
-    fn get_scatter_ray(&self, r_in: &Ray, rec: &HitRecord) -> Ray;
+pub trait Material: Send + Sync {
+    // picks the ray leaving the surface together with its throughput weight, bsdf times cosine
+    // over its pdf, or None if the path ends here. the two come out of one call so materials
+    // that find the weight while sampling, like random walks, don't have to keep it anywhere
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Ray, Color)>;
+
+    fn emitted(&self, _rec: &HitRecord) -> Color {
+        Color::zeros()
+    }
+
+    // specular materials can only be sampled through scatter, not evaluated
+    fn is_specular(&self) -> bool {
+        true
+    }
+
+    // bsdf times cosine, for light arriving from direction
+    fn eval(&self, _r_in: &Ray, _rec: &HitRecord, _direction: &Vector3) -> Color {
+        Color::zeros()
+    }
+
+    // solid angle pdf of scatter picking direction
+    fn scattering_pdf(&self, _r_in: &Ray, _rec: &HitRecord, _direction: &Vector3) -> f32 {
+        0.0
+    }
+
+    // whether rays pass straight through the surface here, as if it wasn't hit, for alpha masks.
+    // hittable lists ask it before they take a hit
+    fn is_cut_out(&self, _rec: &HitRecord) -> bool {
+        false
+    }
+
+    // how much of eval towards direction is diffuse, for splitting the image into diffuse &
+    // specular passes. glossy reflection counts as specular
+    fn diffuse_fraction(&self, _r_in: &Ray, _rec: &HitRecord, _direction: &Vector3) -> f32 {
+        if self.is_specular() {0.0} else {1.0}
+    }
+}
+
+
+// eval over scattering_pdf, the weight of a direction sampled by a glossy material
+pub fn sampled_weight<M: Material>(material: &M, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Color {
+    let pdf = material.scattering_pdf(r_in, rec, &scattered.direction);
+    if pdf > 0.0 {material.eval(r_in, rec, &scattered.direction) / pdf} else {Color::zeros()}
+}
+
+
+// a ray a glossy material sampled, weighted by sampled_weight, unless it was degenerate
+pub fn sampled<M: Material>(material: &M, r_in: &Ray, rec: &HitRecord, scattered: Ray) -> Option<(Ray, Color)> {
+    if scattered.direction.near_zero() {
+        return None;
+    }
+
+    let weight = sampled_weight(material, r_in, rec, &scattered);
+    Some((scattered, weight))
+}
+
+
+// microfacet materials work in a frame around the normal the ray hit, with wo pointing back along it.
+// the tangent follows dpdu, so anisotropic roughness runs along the surface's u the same way on both faces
+pub fn shading_frame(r_in: &Ray, rec: &HitRecord, direction: &Vector3) -> (Onb, Vector3, Vector3) {
+    let uvw = Onb::build_from_w_along(&rec.normal, &rec.dpdu);
+    let wo = uvw.to_local(&-r_in.direction.normalized());
+    let wi = uvw.to_local(&direction.normalized());
+
+    (uvw, wo, wi)
+}
+
+
+// a sample that lands on the wrong side of the surface gets no direction, so scatter ends the path.
+// sampled & the materials' own scatter turn it into None
+pub fn degenerate(r_in: &Ray, rec: &HitRecord) -> Ray {
+    Ray::new(rec.p, Vector3::zeros(), r_in.time)
 }
 
 
@@ -23,17 +92,7 @@ impl Lambertian {
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, _rec: &HitRecord, _scattered: &Ray) -> bool { 
-        true 
-    }
-
-
-    fn get_attenuation(&self) -> Color {
-        self.albedo//.clone()
-    }
-
-
-    fn get_scatter_ray(&self, r_in: &Ray, rec: &HitRecord) -> Ray{
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Ray, Color)> {
         let mut scatter_direction: Vector3 = rec.normal + Vector3::random_unit_vector();
 
         // catch degenerate scatter direction 
@@ -41,8 +100,24 @@ impl Material for Lambertian {
             scatter_direction = rec.normal;
         }
 
-        Ray::new(rec.p, scatter_direction, r_in.time)
+        Some((Ray::new(rec.p, scatter_direction, r_in.time), spectrum::sampled(&self.albedo)))
+    }
+
+
+    fn is_specular(&self) -> bool {
+        false
+    }
+
 
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: &Vector3) -> Color {
+        self.scattering_pdf(r_in, rec, direction) * spectrum::sampled(&self.albedo)
+    }
+
+
+    // normal + random unit vector is cosine distributed
+    fn scattering_pdf(&self, _r_in: &Ray, rec: &HitRecord, direction: &Vector3) -> f32 {
+        let cosine = Vector3::dot(&rec.normal, &direction.normalized());
+        if cosine > 0.0 {cosine / PI} else {0.0}
     }
 }
 
@@ -53,6 +128,7 @@ impl Material for Lambertian {
 pub struct Metal {
     pub albedo: Color,
     pub fuzz: f32,
+    pub film: Option<ThinFilm>,
 }
 
 
@@ -60,66 +136,361 @@ impl Metal {
     pub fn new(albedo: Color, f: f32) -> Metal { 
         Metal {
             albedo, 
-            fuzz: f.min(1.0)
+            fuzz: f.min(1.0),
+            film: None,
         }
     }
+
+    // thickness in nm
+    pub fn with_thin_film(mut self, thickness: f32, ior: f32) -> Metal {
+        self.film = Some(ThinFilm::new(thickness, ior));
+        self
+    }
+}
+
+
+// a complex index for a metal that reflects albedo head on, tinted the same towards grazing angles
+// (gulbrandsen, "artist friendly metallic fresnel"), so a film has something to sit on
+fn metal_index(albedo: f32) -> Complex {
+    let r = albedo.clamp(0.0, 0.99);
+    let n_min = (1.0 - r) / (1.0 + r);
+    let n_max = (1.0 + r.sqrt()) / (1.0 - r.sqrt());
+    let n = r * n_min + (1.0 - r) * n_max;
+    let k2 = (r * (n + 1.0) * (n + 1.0) - (n - 1.0) * (n - 1.0)) / (1.0 - r);
+
+    Complex::new(n, k2.max(0.0).sqrt())
 }
 
 
 impl Material for Metal {
-    fn scatter(&self, rec: &HitRecord, scattered: &Ray) -> bool {
-       Vector3::dot(&scattered.direction, &rec.normal) > 0.0
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Ray, Color)> {
+        let reflected = Vector3::reflect(&r_in.direction.normalized(), &rec.normal);
+        let scattered = Ray::new(rec.p, reflected + self.fuzz * Vector3::random_in_unit_sphere(), r_in.time);
+        if Vector3::dot(&scattered.direction, &rec.normal) <= 0.0 {
+            return None;
+        }
+
+        let attenuation = match self.film {
+            None => spectrum::sampled(&self.albedo),
+            Some(film) => {
+                let cos_theta = Vector3::dot(&-r_in.direction.normalized(), &rec.normal);
+                film.reflectance(cos_theta, 1.0, |lambda| metal_index(spectrum::rgb_to_spectrum(&self.albedo, lambda)))
+            }
+        };
+        Some((scattered, attenuation))
+    }
+}
+
+
+
+// Conductor
+// a metal described by its complex index of refraction, mirror-like when smooth & ggx rough
+// otherwise, optionally more so along one direction than the other
+pub struct Conductor {
+    pub eta: Color,
+    pub k: Color,
+    pub distribution: TrowbridgeReitz,
+    pub film: Option<ThinFilm>,
+}
+
+
+impl Conductor {
+    pub fn new(eta: Color, k: Color) -> Conductor {
+        Conductor { eta, k, distribution: TrowbridgeReitz::new(0.0, 0.0), film: None }
     }
-    
-    
-    fn get_attenuation(&self) -> Color {
-        self.albedo//.clone()
+
+    // measured eta & k at roughly the red, green & blue wavelengths
+    pub fn gold() -> Conductor {
+        Conductor::new(Color::new(0.143, 0.374, 1.442), Color::new(3.983, 2.385, 1.603))
     }
 
+    pub fn copper() -> Conductor {
+        Conductor::new(Color::new(0.200, 0.924, 1.102), Color::new(3.912, 2.452, 2.142))
+    }
 
-    fn get_scatter_ray(&self, r_in: &Ray, rec: &HitRecord) -> Ray{
-        let reflected = Vector3::reflect(&r_in.direction.normalized(), &rec.normal);
-        Ray::new(rec.p, reflected + self.fuzz * Vector3::random_in_unit_sphere(), r_in.time)
+    pub fn aluminium() -> Conductor {
+        Conductor::new(Color::new(1.657, 0.880, 0.521), Color::new(9.224, 6.270, 4.837))
+    }
+
+    // ggx alphas along the tangent & the bitangent
+    pub fn with_roughness(mut self, alpha_x: f32, alpha_y: f32) -> Conductor {
+        self.distribution = TrowbridgeReitz::new(alpha_x, alpha_y);
+        self
+    }
+
+    // thickness in nm
+    pub fn with_thin_film(mut self, thickness: f32, ior: f32) -> Conductor {
+        self.film = Some(ThinFilm::new(thickness, ior));
+        self
+    }
+
+    fn fresnel(&self, cos_theta: f32) -> Color {
+        match self.film {
+            None => {
+                let (eta, k) = (spectrum::sampled_quantity(&self.eta), spectrum::sampled_quantity(&self.k));
+                spectrum::computed_color(&fresnel_conductor(cos_theta, &eta, &k))
+            }
+            Some(film) => film.reflectance(cos_theta, 1.0, |lambda| {
+                Complex::new(spectrum::rgb_to_spectrum(&self.eta, lambda), spectrum::rgb_to_spectrum(&self.k, lambda))
+            }),
+        }
+    }
+}
+
+
+impl Material for Conductor {
+    // mirrors wo about a microfacet normal it can see
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Ray, Color)> {
+        if self.distribution.is_smooth() {
+            let scattered = Ray::new(rec.p, Vector3::reflect(&r_in.direction.normalized(), &rec.normal), r_in.time);
+            return Some((scattered, self.fresnel(Vector3::dot(&-r_in.direction.normalized(), &rec.normal))));
+        }
+
+        let (uvw, wo, _) = shading_frame(r_in, rec, &rec.normal);
+        let wm = self.distribution.sample_visible_normal(&wo, random_f32(), random_f32());
+        let wi = Vector3::reflect(&-wo, &wm);
+        if wi.z <= 0.0 {
+            return None;
+        }
+
+        sampled(self, r_in, rec, Ray::new(rec.p, uvw.local(wi.x, wi.y, wi.z), r_in.time))
+    }
+
+    fn is_specular(&self) -> bool {
+        self.distribution.is_smooth()
+    }
+
+    fn diffuse_fraction(&self, _r_in: &Ray, _rec: &HitRecord, _direction: &Vector3) -> f32 {
+        0.0
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: &Vector3) -> Color {
+        let (_, wo, wi) = shading_frame(r_in, rec, direction);
+        if self.distribution.is_smooth() || wo.z <= 0.0 || wi.z <= 0.0 {
+            return Color::zeros();
+        }
+
+        let wm = (wo + wi).normalized();
+        self.distribution.d(&wm) * self.distribution.g(&wo, &wi) / (4.0 * wo.z) * self.fresnel(Vector3::dot(&wo, &wm))
+    }
+
+    fn scattering_pdf(&self, r_in: &Ray, rec: &HitRecord, direction: &Vector3) -> f32 {
+        let (_, wo, wi) = shading_frame(r_in, rec, direction);
+        if self.distribution.is_smooth() || wo.z <= 0.0 || wi.z <= 0.0 {
+            return 0.0;
+        }
+
+        let wm = (wo + wi).normalized();
+        self.distribution.visible_d(&wo, &wm) / (4.0 * Vector3::dot(&wo, &wm))
     }
 }
 
+
+
 // Dielectric
-// #[derive(Copy, Clone)]
+
+// how the index of refraction changes with wavelength, in micrometers for both formulas
+#[derive(Copy, Clone)]
+pub enum Dispersion {
+    None,
+    Cauchy { a: f32, b: f32 }, // n = a + b / l^2
+    Sellmeier { b: [f32; 3], c: [f32; 3] }, // n^2 = 1 + sum of b l^2 / (l^2 - c)
+}
+
+
+// sellmeier coefficients of two common dispersive materials
+pub const BK7_SELLMEIER_B: [f32; 3] = [1.039_612, 0.231_792_3, 1.010_469_5];
+pub const BK7_SELLMEIER_C: [f32; 3] = [0.006_000_7, 0.020_017_914, 103.560_65];
+pub const DIAMOND_SELLMEIER_B: [f32; 3] = [0.3306, 4.3356, 0.0];
+pub const DIAMOND_SELLMEIER_C: [f32; 3] = [0.030_625, 0.011_236, 0.0];
+
+// the sodium d line glass catalogues quote n_d at, what dispersive glass uses when rendering rgb
+const LAMBDA_D: f32 = 587.6;
+
+
+// smooth by default, ggx rough (walter et al., "microfacet models for refraction") with_roughness
 pub struct Dielectric {
     pub ir: f32,
+    pub dispersion: Dispersion,
+    pub distribution: TrowbridgeReitz,
+    pub film: Option<ThinFilm>, // on the outside
 }
 
 
 impl Dielectric {
     pub fn new(index_of_refraction: f32) -> Dielectric {
-        Dielectric {ir: index_of_refraction}
+        Dielectric {ir: index_of_refraction, dispersion: Dispersion::None, distribution: TrowbridgeReitz::new(0.0, 0.0), film: None}
+    }
+
+    pub fn cauchy(a: f32, b: f32) -> Dielectric {
+        Dielectric::dispersive(Dispersion::Cauchy { a, b })
+    }
+
+    pub fn sellmeier(b: [f32; 3], c: [f32; 3]) -> Dielectric {
+        Dielectric::dispersive(Dispersion::Sellmeier { b, c })
     }
 
-    fn reflectance(cosine: f32, ref_idx: f32) -> f32{
-        // Christophe Schlick's approximation for reflectance.
-        let r0: f32 = ((1.0 - ref_idx) / (1.0 + ref_idx)).powf(2.0);
-        r0 + (1.0 - r0) * ((1.0 - cosine).powf(5.0))
+    fn dispersive(dispersion: Dispersion) -> Dielectric {
+        let mut dielectric = Dielectric {ir: 1.0, dispersion, distribution: TrowbridgeReitz::new(0.0, 0.0), film: None};
+        dielectric.ir = dielectric.index_at(LAMBDA_D);
+        dielectric
+    }
+
+    // ggx alphas along the tangent & the bitangent
+    pub fn with_roughness(mut self, alpha_x: f32, alpha_y: f32) -> Dielectric {
+        self.distribution = TrowbridgeReitz::new(alpha_x, alpha_y);
+        self
+    }
+
+    // thickness in nm. a soap bubble is a film on a dielectric with an index of 1
+    pub fn with_thin_film(mut self, thickness: f32, ior: f32) -> Dielectric {
+        self.film = Some(ThinFilm::new(thickness, ior));
+        self
+    }
+
+    // lambda in nm
+    pub fn index_at(&self, lambda: f32) -> f32 {
+        let l2 = (lambda / 1000.0) * (lambda / 1000.0);
+
+        match self.dispersion {
+            Dispersion::None => self.ir,
+            Dispersion::Cauchy { a, b } => a + b / l2,
+            Dispersion::Sellmeier { b, c } => (1.0 + (0..3).map(|i| b[i] * l2 / (l2 - c[i])).sum::<f32>()).sqrt(),
+        }
+    }
+
+    // the path's hero wavelength picks the index when rendering spectrally
+    fn index(&self) -> f32 {
+        match (&self.dispersion, spectrum::current_wavelengths()) {
+            (Dispersion::None, _) | (_, None) => self.ir,
+            (_, Some(wavelengths)) => self.index_at(wavelengths.hero()),
+        }
+    }
+
+    // index below the surface over the index above it, up being the side rec.normal faces
+    fn relative_index(&self, rec: &HitRecord) -> f32 {
+        if rec.front_face {self.index()} else {1.0 / self.index()}
+    }
+
+    // reflectance off a (micro)surface wo makes cos_theta with, negative from below it.
+    // the film makes it differ per wavelength
+    fn fresnel(&self, cos_theta: f32, rec: &HitRecord) -> Color {
+        let film = match self.film {
+            None => return Color::fromv(fresnel_dielectric(cos_theta, self.relative_index(rec))),
+            Some(film) => film,
+        };
+
+        // the film sits between the outside & the dielectric, whichever side the light comes from
+        let from_outside = rec.front_face == (cos_theta >= 0.0);
+        let (outside, substrate) = if from_outside {(1.0, self.index())} else {(self.index(), 1.0)};
+        film.reflectance(cos_theta.abs(), outside, |_| Complex::real(substrate))
+    }
+
+    // chance of a smooth surface reflecting, a film's reflectance averaged over the wavelengths.
+    // exact fresnel, so it's what the rough surface tends to as its roughness goes to 0
+    fn reflect_probability(&self, cos_theta: f32, rec: &HitRecord) -> f32 {
+        average(&self.fresnel(cos_theta, rec))
+    }
+
+    // a smooth reflection or refraction picked by reflect_probability, weighted back to the film's
+    // reflectance at each wavelength
+    fn film_weight(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Color {
+        if self.film.is_none() {
+            return Color::fromv(1.0);
+        }
+
+        let cos_theta = Vector3::dot(&-r_in.direction.normalized(), &rec.normal).min(1.0);
+        let reflectance = self.fresnel(cos_theta, rec);
+        let p = average(&reflectance);
+
+        if Vector3::dot(&scattered.direction, &rec.normal) > 0.0 {
+            if p > 0.0 {reflectance / p} else {Color::zeros()}
+        } else {
+            if p < 1.0 {(Color::fromv(1.0) - reflectance) / (1.0 - p)} else {Color::zeros()}
+        }
     }
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, _rec: &HitRecord, _scattered: &Ray) -> bool {
-        true
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Ray, Color)> {
+        if !self.distribution.is_smooth() {
+            return sampled(self, r_in, rec, self.sample_rough(r_in, rec));
+        }
+
+        let scattered = self.sample_smooth(r_in, rec);
+
+        // dispersion splits the wavelengths up, only the hero's direction gets followed
+        let weight = match self.dispersion {
+            Dispersion::None => self.film_weight(r_in, rec, &scattered),
+            _ => spectrum::terminate_secondary() * self.film_weight(r_in, rec, &scattered),
+        };
+        Some((scattered, weight))
+    }
+
+    fn is_specular(&self) -> bool {
+        self.distribution.is_smooth()
+    }
+
+    fn diffuse_fraction(&self, _r_in: &Ray, _rec: &HitRecord, _direction: &Vector3) -> f32 {
+        0.0
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: &Vector3) -> Color {
+        let (_, wo, wi) = shading_frame(r_in, rec, direction);
+        let (wm, etap) = match self.half_vector(&wo, &wi, self.relative_index(rec)) {
+            Some(half) => half,
+            None => return Color::zeros(),
+        };
+
+        let d = self.distribution.d(&wm);
+        let g = self.distribution.g(&wo, &wi);
+        let fresnel = self.fresnel(Vector3::dot(&wo, &wm), rec);
+
+        // radiance isn't rescaled by etap^2 on the way through, same as the smooth case
+        let f = if wo.z * wi.z > 0.0 {
+            d * g / (4.0 * wo.z * wi.z).abs() * fresnel
+        } else {
+            let denominator = (Vector3::dot(&wi, &wm) + Vector3::dot(&wo, &wm) / etap).powi(2) * wi.z * wo.z;
+            d * g * (Vector3::dot(&wi, &wm) * Vector3::dot(&wo, &wm) / denominator).abs() * (Color::fromv(1.0) - fresnel)
+        };
+
+        let through = if matches!(self.dispersion, Dispersion::None) {Color::fromv(1.0)} else {spectrum::terminate_secondary()};
+        f * wi.z.abs() * through
+    }
+
+    fn scattering_pdf(&self, r_in: &Ray, rec: &HitRecord, direction: &Vector3) -> f32 {
+        let (_, wo, wi) = shading_frame(r_in, rec, direction);
+        let (wm, etap) = match self.half_vector(&wo, &wi, self.relative_index(rec)) {
+            Some(half) => half,
+            None => return 0.0,
+        };
+
+        let reflectance = average(&self.fresnel(Vector3::dot(&wo, &wm), rec));
+
+        if wo.z * wi.z > 0.0 {
+            self.distribution.visible_d(&wo, &wm) / (4.0 * Vector3::dot(&wo, &wm).abs()) * reflectance
+        } else {
+            let dwm_dwi = Vector3::dot(&wi, &wm).abs() / (Vector3::dot(&wi, &wm) + Vector3::dot(&wo, &wm) / etap).powi(2);
+            self.distribution.visible_d(&wo, &wm) * dwm_dwi * (1.0 - reflectance)
+        }
     }
+}
+
 
-    fn get_attenuation(&self) -> Color {Color::fromv(1.0)}
+impl Dielectric {
 
-    fn get_scatter_ray(&self, r_in: &Ray, rec: &HitRecord) -> Ray {
-        let refraction_ratio: f32 = if rec.front_face {1.0 / self.ir} else {self.ir};
+    // a mirror reflection or a refraction, picked by reflect_probability
+    fn sample_smooth(&self, r_in: &Ray, rec: &HitRecord) -> Ray {
+        let ir = self.index();
+        let refraction_ratio: f32 = if rec.front_face {1.0 / ir} else {ir};
         let unit_direction: Vector3 = r_in.direction.normalized();
 
         let cos_theta: f32 =  Vector3::dot(&-unit_direction, &rec.normal).min(1.0);
         let sin_theta: f32 = (1.0 - cos_theta * cos_theta).sqrt();
         let cannot_refract: bool = refraction_ratio * sin_theta > 1.0;
-        let mut rng = thread_rng();
 
         let direction: Vector3 = 
-            if cannot_refract || (Dielectric::reflectance(cos_theta, refraction_ratio) > rng.gen::<f32>()) {
+            if cannot_refract || (self.reflect_probability(cos_theta, rec) > random_f32()) {
                 Vector3::reflect(&unit_direction, &rec.normal)
             } else {
                 Vector3::refract(&unit_direction, &rec.normal, refraction_ratio)
@@ -127,4 +498,89 @@ impl Material for Dielectric {
 
         Ray::new(rec.p, direction, r_in.time)
     }
+
+    // picks a visible microfacet, then reflects off or refracts through it as its fresnel term says.
+    // the direction alone, for materials that weight it themselves
+    pub fn sample_rough(&self, r_in: &Ray, rec: &HitRecord) -> Ray {
+        let (uvw, wo, _) = shading_frame(r_in, rec, &rec.normal);
+        let eta = self.relative_index(rec);
+        let wm = self.distribution.sample_visible_normal(&wo, random_f32(), random_f32());
+        let cos_theta_o = Vector3::dot(&wo, &wm);
+
+        let wi = if random_f32() < average(&self.fresnel(cos_theta_o, rec)) {
+            Vector3::reflect(&-wo, &wm)
+        } else {
+            // snell's law through wm, which wo is in front of
+            let (etap, wm) = if cos_theta_o < 0.0 {(1.0 / eta, -wm)} else {(eta, wm)};
+            let cos_theta_o = cos_theta_o.abs();
+            let sin2_theta_t = (1.0 - cos_theta_o * cos_theta_o) / (etap * etap);
+            if sin2_theta_t >= 1.0 {
+                return degenerate(r_in, rec);
+            }
+            -wo / etap + (cos_theta_o / etap - (1.0 - sin2_theta_t).sqrt()) * wm
+        };
+
+        // reflections have to stay on wo's side & refractions have to cross
+        let reflected = wo.z * wi.z > 0.0;
+        if reflected != (Vector3::dot(&wo, &wm) * Vector3::dot(&wi, &wm) > 0.0) || wi.z == 0.0 {
+            return degenerate(r_in, rec);
+        }
+
+        Ray::new(rec.p, uvw.local(wi.x, wi.y, wi.z), r_in.time)
+    }
+
+    // the microfacet normal that takes wo to wi, facing up, with the index ratio across it.
+    // None for the pairs no microfacet connects
+    fn half_vector(&self, wo: &Vector3, wi: &Vector3, eta: f32) -> Option<(Vector3, f32)> {
+        if self.distribution.is_smooth() || wo.z == 0.0 || wi.z == 0.0 {
+            return None;
+        }
+
+        let reflected = wo.z * wi.z > 0.0;
+        let etap = match (reflected, wo.z > 0.0) {
+            (true, _) => 1.0,
+            (false, true) => eta,
+            (false, false) => 1.0 / eta,
+        };
+
+        let wm = *wi * etap + *wo;
+        if wm.magnitude_squared() == 0.0 {
+            return None;
+        }
+        let wm = if wm.z < 0.0 {-wm.normalized()} else {wm.normalized()};
+
+        // microfacets seen from behind can't take part
+        if Vector3::dot(&wm, wi) * wi.z < 0.0 || Vector3::dot(&wm, wo) * wo.z < 0.0 {
+            return None;
+        }
+
+        Some((wm, etap))
+    }
+
+}
+
+
+
+// DiffuseLight
+pub struct DiffuseLight {
+    pub emit: Color,
+}
+
+
+impl DiffuseLight {
+    pub fn new(emit: Color) -> DiffuseLight {
+        DiffuseLight {emit}
+    }
+}
+
+
+impl Material for DiffuseLight {
+    fn scatter(&self, _r_in: &Ray, _rec: &HitRecord) -> Option<(Ray, Color)> {
+        None
+    }
+
+    // lights only shine out of their front face
+    fn emitted(&self, rec: &HitRecord) -> Color {
+        if rec.front_face {spectrum::sampled(&self.emit)} else {Color::zeros()}
+    }
 }
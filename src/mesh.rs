@@ -0,0 +1,592 @@
+use crate::{
+            vectors::*,
+            rays::Ray,
+            hittable::*,
+            materials::Material,
+            texture::Texture,
+};
+use std::{collections::HashMap, fs, io, sync::Arc, f32::consts::PI};
+
+
+// triangle meshes, their vertices shared between the triangles around them. each vertex has a
+// normal & tangents averaged from the triangles that meet there, interpolated across every
+// triangle, so the mesh shades smoothly & bump & normal maps follow its uvs from one triangle to
+// the next. displacement happens once, when the mesh is built: the triangles are split until
+// they're small enough to carry the detail, & their vertices moved along the normal, so unlike a
+// bump map it changes silhouettes & shadows too
+
+
+// leaves hold at most this many triangles
+const LEAF_SIZE: usize = 4;
+
+
+pub struct Mesh {
+    pub positions: Vec<Point3>,
+    pub uvs: Vec<(f32, f32)>,
+    pub triangles: Vec<[usize; 3]>, // counter clockwise seen from outside
+    pub normals: Vec<Vector3>,
+    pub tangents: Vec<Vector3>, // dpdu at each vertex, across the normal. zero where the uvs don't say
+    pub bitangents: Vec<Vector3>, // dpdv
+    pub mat: Arc<dyn Material>,
+    nodes: Vec<Node>, // bounding volume hierarchy over the triangles, the root first
+}
+
+
+// a box around some triangles. leaves have count > 0 & hold triangles start..start + count, inner
+// nodes have their first child right after them & their second at start
+struct Node {
+    min: Point3,
+    max: Point3,
+    start: usize,
+    count: usize,
+}
+
+
+impl Mesh {
+
+    pub fn new(positions: Vec<Point3>, uvs: Vec<(f32, f32)>, triangles: Vec<[usize; 3]>, mat: Arc<dyn Material>) -> Mesh {
+        let mut mesh = Mesh {
+            normals: Vec::new(),
+            tangents: Vec::new(),
+            bitangents: Vec::new(),
+            nodes: Vec::new(),
+            positions, uvs, triangles, mat,
+        };
+
+        mesh.normals = mesh.vertex_normals();
+        mesh.compute_tangents();
+        mesh.build_hierarchy();
+        mesh
+    }
+
+    // a sphere of rings by segments quads, with the sphere primitive's uvs. the seam at u = 0 & 1
+    // has its vertices twice, once for each u
+    pub fn uv_sphere(center: Point3, radius: f32, rings: usize, segments: usize, mat: Arc<dyn Material>) -> Mesh {
+        let (mut positions, mut uvs, mut triangles) = (Vec::new(), Vec::new(), Vec::new());
+
+        for j in 0..=rings {
+            for i in 0..=segments {
+                let (u, v) = (i as f32 / segments as f32, j as f32 / rings as f32);
+                // the seam & the poles exactly where their twins are, so their normals are shared
+                let phi = 2.0 * PI * (i % segments) as f32 / segments as f32;
+                let theta = PI * v;
+                let sin_theta = if j == 0 || j == rings {0.0} else {theta.sin()};
+                let direction = Vector3::new(-phi.cos() * sin_theta, -theta.cos(), phi.sin() * sin_theta);
+                positions.push(center + radius * direction);
+                uvs.push((u, v));
+            }
+        }
+        for j in 0..rings {
+            for i in 0..segments {
+                let at = j * (segments + 1) + i;
+                let above = at + segments + 1;
+                if j > 0 {
+                    triangles.push([at, at + 1, above]);
+                }
+                if j + 1 < rings {
+                    triangles.push([at + 1, above + 1, above]);
+                }
+            }
+        }
+
+        Mesh::new(positions, uvs, triangles, mat)
+    }
+
+    // a wavefront obj file's vertices, texture coordinates & faces. polygons are split into fans of
+    // triangles, & everything else in the file is ignored
+    pub fn load_obj(path: &str, mat: Arc<dyn Material>) -> io::Result<Mesh> {
+        let text = fs::read_to_string(path)?;
+        let invalid = |number: usize, message: &str| io::Error::new(io::ErrorKind::InvalidData, format!("{}: line {}: {}", path, number + 1, message));
+
+        let (mut points, mut coordinates) = (Vec::new(), Vec::new());
+        let (mut positions, mut uvs, mut triangles) = (Vec::new(), Vec::new(), Vec::new());
+        let mut vertices = HashMap::new(); // a vertex for each pair of position & uv used
+
+        for (number, line) in text.lines().enumerate() {
+            let mut words = line.split('#').next().unwrap_or("").split_whitespace();
+            let numbers = |words: std::str::SplitWhitespace, count: usize| -> io::Result<Vec<f32>> {
+                let numbers: Vec<f32> = words.take(count).map(|word| word.parse()).collect::<Result<_, _>>().map_err(|_| invalid(number, "bad number"))?;
+                if numbers.len() < count {Err(invalid(number, "too few numbers"))} else {Ok(numbers)}
+            };
+
+            match words.next() {
+                Some("v") => {
+                    let p = numbers(words, 3)?;
+                    points.push(Point3::new(p[0], p[1], p[2]));
+                }
+                Some("vt") => {
+                    let uv = numbers(words, 2)?;
+                    coordinates.push((uv[0], uv[1]));
+                }
+                Some("f") => {
+                    let mut face = Vec::new();
+                    for word in words {
+                        // v, v/vt, v//vn or v/vt/vn, counting from 1, or back from the end if negative
+                        let mut indices = word.split('/');
+                        let mut index = |count: usize| -> io::Result<Option<usize>> {
+                            match indices.next() {
+                                None | Some("") => Ok(None),
+                                Some(index) => {
+                                    let index: i64 = index.parse().map_err(|_| invalid(number, "bad index"))?;
+                                    let index = if index < 0 {count as i64 + index} else {index - 1};
+                                    if index < 0 || index >= count as i64 {Err(invalid(number, "index out of range"))} else {Ok(Some(index as usize))}
+                                }
+                            }
+                        };
+
+                        let point = index(points.len())?.ok_or_else(|| invalid(number, "face without a vertex"))?;
+                        let coordinate = index(coordinates.len())?;
+                        let vertex = *vertices.entry((point, coordinate)).or_insert_with(|| {
+                            positions.push(points[point]);
+                            uvs.push(coordinate.map_or((0.0, 0.0), |c| coordinates[c]));
+                            positions.len() - 1
+                        });
+                        face.push(vertex);
+                    }
+
+                    if face.len() < 3 {
+                        return Err(invalid(number, "face with fewer than three vertices"));
+                    }
+                    for i in 1..face.len() - 1 {
+                        triangles.push([face[0], face[i], face[i + 1]]);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(Mesh::new(positions, uvs, triangles, mat))
+    }
+
+    // the mesh split subdivisions times, each triangle into four, & every vertex then moved scale *
+    // height along its normal. new vertices take the normals interpolated from the coarse mesh, so a
+    // faceted sphere is displaced as a round one. vertices the mesh has twice, along a uv seam, move
+    // apart unless height agrees on both sides of it
+    pub fn displaced(&self, height: &dyn Texture, scale: f32, subdivisions: u32) -> Mesh {
+        let mut positions = self.positions.clone();
+        let mut uvs = self.uvs.clone();
+        let mut normals = self.normals.clone();
+        let mut triangles = self.triangles.clone();
+
+        for _ in 0..subdivisions {
+            let mut midpoints = HashMap::new(); // shared by the two triangles along each edge
+            let mut midpoint = |a: usize, b: usize| -> usize {
+                *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                    positions.push(0.5 * (positions[a] + positions[b]));
+                    uvs.push((0.5 * (uvs[a].0 + uvs[b].0), 0.5 * (uvs[a].1 + uvs[b].1)));
+                    normals.push(normals[a] + normals[b]);
+                    positions.len() - 1
+                })
+            };
+
+            triangles = triangles.iter().flat_map(|&[a, b, c]| {
+                let (ab, bc, ca) = (midpoint(a, b), midpoint(b, c), midpoint(c, a));
+                vec![[a, ab, ca], [ab, b, bc], [ca, bc, c], [ab, bc, ca]]
+            }).collect();
+        }
+
+        for (i, p) in positions.iter_mut().enumerate() {
+            let (u, v) = uvs[i];
+            *p += scale * height.scalar(u, v, p) * normals[i].normalized();
+        }
+
+        Mesh::new(positions, uvs, triangles, self.mat.clone())
+    }
+
+    fn corners(&self, triangle: usize) -> (Point3, Point3, Point3) {
+        let [a, b, c] = self.triangles[triangle];
+        (self.positions[a], self.positions[b], self.positions[c])
+    }
+
+    // area weighted averages of the triangles' normals. vertices in the same place, along a seam,
+    // get the same one so the seam doesn't show
+    fn vertex_normals(&self) -> Vec<Vector3> {
+        let key = |p: &Point3| ((p.x + 0.0).to_bits(), (p.y + 0.0).to_bits(), (p.z + 0.0).to_bits()); // -0 & 0 alike
+        let mut sums: HashMap<_, Vector3> = HashMap::new();
+
+        for triangle in 0..self.triangles.len() {
+            let (p0, p1, p2) = self.corners(triangle);
+            let area_normal = Vector3::cross(&(p1 - p0), &(p2 - p0)); // twice the area long
+            for p in [p0, p1, p2].iter() {
+                *sums.entry(key(p)).or_insert_with(Vector3::zeros) += area_normal;
+            }
+        }
+
+        self.positions.iter().map(|p| {
+            let sum = sums.get(&key(p)).copied().unwrap_or_else(Vector3::zeros);
+            if sum.near_zero() {Vector3::zeros()} else {sum.normalized()}
+        }).collect()
+    }
+
+    // each triangle's dpdu & dpdv from its uvs, averaged over the triangles around a vertex the same
+    // way as the normals, & made to lie across the vertex normal
+    fn compute_tangents(&mut self) {
+        let mut tangents = vec![Vector3::zeros(); self.positions.len()];
+        let mut bitangents = vec![Vector3::zeros(); self.positions.len()];
+
+        for (triangle, &[a, b, c]) in self.triangles.iter().enumerate() {
+            let (p0, p1, p2) = self.corners(triangle);
+            let (e1, e2) = (p1 - p0, p2 - p0);
+            let (du1, dv1) = (self.uvs[b].0 - self.uvs[a].0, self.uvs[b].1 - self.uvs[a].1);
+            let (du2, dv2) = (self.uvs[c].0 - self.uvs[a].0, self.uvs[c].1 - self.uvs[a].1);
+
+            let determinant = du1 * dv2 - dv1 * du2;
+            if determinant.abs() < 1e-12 {
+                continue;
+            }
+            let area = Vector3::cross(&e1, &e2).magnitude();
+            let dpdu = (dv2 * e1 - dv1 * e2) / determinant;
+            let dpdv = (du1 * e2 - du2 * e1) / determinant;
+
+            for &vertex in [a, b, c].iter() {
+                tangents[vertex] += area * dpdu;
+                bitangents[vertex] += area * dpdv;
+            }
+        }
+
+        let across = |v: Vector3, n: &Vector3| v - Vector3::dot(&v, n) * *n;
+        let areas = self.area_around_vertices();
+        self.tangents = tangents.iter().zip(&self.normals).zip(&areas).map(|((t, n), area)| {
+            if *area > 0.0 {across(*t / *area, n)} else {Vector3::zeros()}
+        }).collect();
+        self.bitangents = bitangents.iter().zip(&self.normals).zip(&areas).map(|((t, n), area)| {
+            if *area > 0.0 {across(*t / *area, n)} else {Vector3::zeros()}
+        }).collect();
+    }
+
+    // the area of the triangles with uvs that touch each vertex, twice over like the sums it divides
+    fn area_around_vertices(&self) -> Vec<f32> {
+        let mut areas = vec![0.0; self.positions.len()];
+
+        for (triangle, &[a, b, c]) in self.triangles.iter().enumerate() {
+            let (du1, dv1) = (self.uvs[b].0 - self.uvs[a].0, self.uvs[b].1 - self.uvs[a].1);
+            let (du2, dv2) = (self.uvs[c].0 - self.uvs[a].0, self.uvs[c].1 - self.uvs[a].1);
+            if (du1 * dv2 - dv1 * du2).abs() < 1e-12 {
+                continue;
+            }
+
+            let (p0, p1, p2) = self.corners(triangle);
+            let area = Vector3::cross(&(p1 - p0), &(p2 - p0)).magnitude();
+            for &vertex in [a, b, c].iter() {
+                areas[vertex] += area;
+            }
+        }
+
+        areas
+    }
+
+    // splits the triangles in half along the longest axis of their centers until few are left in each
+    // box, reordering them so every node's triangles are next to each other
+    fn build_hierarchy(&mut self) {
+        let centers: Vec<Point3> = (0..self.triangles.len()).map(|t| {
+            let (p0, p1, p2) = self.corners(t);
+            (p0 + p1 + p2) / 3.0
+        }).collect();
+        let mut order: Vec<usize> = (0..self.triangles.len()).collect();
+
+        self.nodes.clear();
+        if !order.is_empty() {
+            self.build_node(&mut order, 0, &centers);
+        }
+        self.triangles = order.iter().map(|&t| self.triangles[t]).collect();
+    }
+
+    fn build_node(&mut self, order: &mut [usize], start: usize, centers: &[Point3]) {
+        let (mut min, mut max) = (Point3::fromv(f32::INFINITY), Point3::fromv(f32::NEG_INFINITY));
+        for &t in order.iter() {
+            let (p0, p1, p2) = self.corners(t);
+            for p in [p0, p1, p2].iter() {
+                min = component_min(&min, p);
+                max = component_max(&max, p);
+            }
+        }
+
+        let node = self.nodes.len();
+        self.nodes.push(Node { min, max, start, count: order.len() });
+        if order.len() <= LEAF_SIZE {
+            return;
+        }
+
+        let (mut low, mut high) = (Point3::fromv(f32::INFINITY), Point3::fromv(f32::NEG_INFINITY));
+        for &t in order.iter() {
+            low = component_min(&low, &centers[t]);
+            high = component_max(&high, &centers[t]);
+        }
+        let extent = high - low;
+        let axis = if extent.x >= extent.y && extent.x >= extent.z {0} else if extent.y >= extent.z {1} else {2};
+
+        order.sort_unstable_by(|&a, &b| component(&centers[a], axis).partial_cmp(&component(&centers[b], axis)).unwrap_or(std::cmp::Ordering::Equal));
+        let half = order.len() / 2;
+        let (first, second) = order.split_at_mut(half);
+
+        self.build_node(first, start, centers);
+        let second_node = self.nodes.len();
+        self.build_node(second, start + half, centers);
+        self.nodes[node].start = second_node;
+        self.nodes[node].count = 0;
+    }
+
+    // möller & trumbore: the ray's t & the barycentric coordinates of p1 & p2 where it crosses the triangle
+    fn intersect(&self, triangle: usize, r: &Ray, t_min: f32, t_max: f32) -> Option<(f32, f32, f32)> {
+        let (p0, p1, p2) = self.corners(triangle);
+        let (e1, e2) = (p1 - p0, p2 - p0);
+
+        let pvec = Vector3::cross(&r.direction, &e2);
+        let determinant = Vector3::dot(&e1, &pvec);
+        if determinant.abs() < 1e-12 {
+            return None;
+        }
+
+        let tvec = r.origin - p0;
+        let b1 = Vector3::dot(&tvec, &pvec) / determinant;
+        if !(0.0..=1.0).contains(&b1) {
+            return None;
+        }
+        let qvec = Vector3::cross(&tvec, &e1);
+        let b2 = Vector3::dot(&r.direction, &qvec) / determinant;
+        if b2 < 0.0 || b1 + b2 > 1.0 {
+            return None;
+        }
+
+        let t = Vector3::dot(&e2, &qvec) / determinant;
+        if t < t_min || t > t_max {None} else {Some((t, b1, b2))}
+    }
+
+}
+
+
+fn component(v: &Vector3, axis: usize) -> f32 {
+    match axis {0 => v.x, 1 => v.y, _ => v.z}
+}
+
+
+fn component_min(a: &Vector3, b: &Vector3) -> Vector3 {
+    Vector3::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z))
+}
+
+
+fn component_max(a: &Vector3, b: &Vector3) -> Vector3 {
+    Vector3::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z))
+}
+
+
+// whether the ray passes through the box anywhere between t_min & t_max
+fn hits_box(min: &Point3, max: &Point3, r: &Ray, t_min: f32, t_max: f32) -> bool {
+    let (mut t0, mut t1) = (t_min, t_max);
+
+    for axis in 0..3 {
+        let inverse = 1.0 / component(&r.direction, axis);
+        let origin = component(&r.origin, axis);
+        let mut near = (component(min, axis) - origin) * inverse;
+        let mut far = (component(max, axis) - origin) * inverse;
+        if inverse < 0.0 {
+            std::mem::swap(&mut near, &mut far);
+        }
+
+        // nan, from a ray in the plane of a face, leaves the range as it was
+        t0 = if near > t0 {near} else {t0};
+        t1 = if far < t1 {far} else {t1};
+        if t1 < t0 {
+            return false;
+        }
+    }
+
+    true
+}
+
+
+impl Hittable for Mesh {
+
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32, rec: &mut HitRecord) -> bool {
+        let mut closest: Option<(usize, f32, f32, f32)> = None;
+        let mut stack = Vec::with_capacity(32);
+        if !self.nodes.is_empty() {
+            stack.push(0);
+        }
+
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            let t_max = closest.map_or(t_max, |(_, t, _, _)| t);
+            if !hits_box(&node.min, &node.max, r, t_min, t_max) {
+                continue;
+            }
+
+            if node.count == 0 {
+                stack.push(index + 1);
+                stack.push(node.start);
+                continue;
+            }
+            for triangle in node.start..node.start + node.count {
+                let t_max = closest.map_or(t_max, |(_, t, _, _)| t);
+                if let Some((t, b1, b2)) = self.intersect(triangle, r, t_min, t_max) {
+                    closest = Some((triangle, t, b1, b2));
+                }
+            }
+        }
+
+        let (triangle, t, b1, b2) = match closest {
+            Some(closest) => closest,
+            None => return false,
+        };
+        let [a, b, c] = self.triangles[triangle];
+        let b0 = 1.0 - b1 - b2;
+        let interpolate = |values: &[Vector3]| b0 * values[a] + b1 * values[b] + b2 * values[c];
+
+        let (p0, p1, p2) = self.corners(triangle);
+        rec.t = t;
+        rec.p = r.at(t);
+        rec.u = b0 * self.uvs[a].0 + b1 * self.uvs[b].0 + b2 * self.uvs[c].0;
+        rec.v = b0 * self.uvs[a].1 + b1 * self.uvs[b].1 + b2 * self.uvs[c].1;
+        rec.dpdu = interpolate(&self.tangents);
+        rec.dpdv = interpolate(&self.bitangents);
+        rec.mat = self.mat.clone();
+        rec.velocity = Vector3::zeros();
+
+        // which side was hit goes by the flat triangle. the smooth normal is used where it agrees
+        rec.set_face_normal(r, Vector3::cross(&(p1 - p0), &(p2 - p0)).normalized());
+        let smooth = interpolate(&self.normals);
+        if !smooth.near_zero() {
+            let smooth = if rec.front_face {smooth.normalized()} else {-smooth.normalized()};
+            if Vector3::dot(&smooth, &rec.normal) > 0.0 {
+                rec.normal = smooth;
+            }
+        }
+
+        true
+    }
+
+    fn materials(&self) -> Vec<Arc<dyn Material>> {
+        vec![self.mat.clone()]
+    }
+
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{materials::Lambertian, texture::Procedural};
+
+    fn gray() -> Arc<dyn Material> {
+        Arc::new(Lambertian::new(Color::fromv(0.5)))
+    }
+
+    // a flat rectangle from corner along edges u & v, in n by n squares of two triangles
+    fn grid(corner: Point3, u: Vector3, v: Vector3, n: usize, mat: Arc<dyn Material>) -> Mesh {
+        let (mut positions, mut uvs, mut triangles) = (Vec::new(), Vec::new(), Vec::new());
+
+        for j in 0..=n {
+            for i in 0..=n {
+                let (s, t) = (i as f32 / n as f32, j as f32 / n as f32);
+                positions.push(corner + s * u + t * v);
+                uvs.push((s, t));
+            }
+        }
+        for j in 0..n {
+            for i in 0..n {
+                let at = j * (n + 1) + i;
+                triangles.push([at, at + 1, at + n + 2]);
+                triangles.push([at, at + n + 2, at + n + 1]);
+            }
+        }
+
+        Mesh::new(positions, uvs, triangles, mat)
+    }
+
+    fn hit(mesh: &Mesh, origin: Point3, direction: Vector3) -> Option<HitRecord> {
+        let mut rec = HitRecord::new();
+        if mesh.hit(&Ray::new(origin, direction, 0.0), 0.001, f32::INFINITY, &mut rec) {Some(rec)} else {None}
+    }
+
+    #[test]
+    fn hits_are_found_through_the_hierarchy() {
+        let mesh = grid(Point3::newi(-1, 0, 1), Vector3::newi(2, 0, 0), Vector3::newi(0, 0, -2), 16, gray());
+        assert_eq!(mesh.triangles.len(), 2 * 16 * 16);
+
+        // every ray through the square hits it at the right place, from either side
+        for i in 0..20 {
+            let (x, z) = (-0.95 + 0.0947 * i as f32, 0.9 - 0.09 * i as f32);
+            let rec = hit(&mesh, Point3::new(x, 2.0, z), Vector3::newi(0, -1, 0)).unwrap();
+            assert!((rec.t - 2.0).abs() < 1e-4 && rec.front_face);
+            assert!((rec.normal - Vector3::newi(0, 1, 0)).magnitude() < 1e-4);
+            assert!((rec.u - (x + 1.0) / 2.0).abs() < 1e-4 && (rec.v - (1.0 - z) / 2.0).abs() < 1e-4);
+
+            let rec = hit(&mesh, Point3::new(x, -1.0, z), Vector3::newi(0, 1, 0)).unwrap();
+            assert!(!rec.front_face && (rec.normal - Vector3::newi(0, -1, 0)).magnitude() < 1e-4);
+        }
+        assert!(hit(&mesh, Point3::new(1.1, 2.0, 0.0), Vector3::newi(0, -1, 0)).is_none());
+    }
+
+    #[test]
+    fn tangents_follow_the_uvs() {
+        let sphere = Mesh::uv_sphere(Point3::zeros(), 1.0, 32, 64, gray());
+
+        for direction in [Vector3::new(1.0, 0.3, 0.2), Vector3::new(-0.4, -0.5, 1.0), Vector3::new(0.1, 0.8, -0.6)].iter() {
+            let rec = hit(&sphere, 3.0 * direction.normalized(), -*direction).unwrap();
+            let (dpdu, dpdv) = crate::sphere::get_sphere_partials(&rec.p.normalized(), 1.0);
+
+            // the same uvs & derivatives as the sphere the mesh approximates, near enough
+            let (u, v) = crate::sphere::get_sphere_uv(&rec.p.normalized());
+            assert!((rec.u - u).abs() < 0.01 && (rec.v - v).abs() < 0.01);
+            assert!(Vector3::dot(&rec.dpdu.normalized(), &dpdu.normalized()) > 0.99);
+            assert!(Vector3::dot(&rec.dpdv.normalized(), &dpdv.normalized()) > 0.99);
+            assert!((rec.dpdu.magnitude() / dpdu.magnitude() - 1.0).abs() < 0.02);
+            assert!(Vector3::dot(&rec.normal, &direction.normalized()) > 0.999);
+        }
+    }
+
+    #[test]
+    fn displacement_moves_the_surface_itself() {
+        let square = grid(Point3::newi(0, 0, 1), Vector3::newi(1, 0, 0), Vector3::newi(0, 0, -1), 1, gray());
+        let ridge = Procedural::new(|u, _, _| Color::fromv((PI * u).sin()));
+        let displaced = square.displaced(&ridge, 0.25, 4);
+
+        // each subdivision splits each triangle into four, the edges' midpoints shared between them
+        assert_eq!(displaced.triangles.len(), 2 * 4usize.pow(4));
+        assert_eq!(displaced.positions.len(), 17 * 17);
+
+        // rays straight down meet the ridge, not the plane it was
+        for x in [0.1, 0.3, 0.5, 0.8].iter() {
+            let rec = hit(&displaced, Point3::new(*x, 2.0, 0.5), Vector3::newi(0, -1, 0)).unwrap();
+            assert!((rec.p.y - 0.25 * (PI * x).sin()).abs() < 0.01);
+
+            // & the normal leans away from the top of the ridge
+            let slope = 0.25 * PI * (PI * x).cos();
+            let normal = Vector3::new(-slope, 1.0, 0.0).normalized();
+            assert!(Vector3::dot(&rec.normal, &normal) > 0.999);
+        }
+
+        // a ray just skimming the flat square is blocked
+        assert!(hit(&square, Point3::new(-1.0, 0.1, 0.5), Vector3::newi(1, 0, 0)).is_none());
+        assert!(hit(&displaced, Point3::new(-1.0, 0.1, 0.5), Vector3::newi(1, 0, 0)).is_some());
+    }
+
+    #[test]
+    fn obj_faces_share_their_vertices() {
+        let path = std::env::temp_dir().join(format!("mesh_test_{}.obj", std::process::id()));
+        fs::write(&path, "
+            # a unit square as one quad
+            v 0 0 0
+            v 1 0 0
+            v 1 1 0
+            v 0 1 0
+            vt 0 0
+            vt 1 0
+            vt 1 1
+            vt 0 1
+            f 1/1 2/2 3/3 -1/-1
+        ").unwrap();
+        let mesh = Mesh::load_obj(&path.to_string_lossy(), gray()).unwrap();
+
+        assert_eq!((mesh.positions.len(), mesh.triangles.len()), (4, 2));
+        let rec = hit(&mesh, Point3::new(0.25, 0.75, 1.0), Vector3::newi(0, 0, -1)).unwrap();
+        assert!((rec.u - 0.25).abs() < 1e-5 && (rec.v - 0.75).abs() < 1e-5 && rec.front_face);
+        assert!((rec.dpdu - Vector3::newi(1, 0, 0)).magnitude() < 1e-5);
+
+        fs::write(&path, "v 0 0 0\nf 1 2 3\n").unwrap();
+        let error = Mesh::load_obj(&path.to_string_lossy(), gray()).err().unwrap();
+        assert!(error.to_string().ends_with("line 2: index out of range"));
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,374 @@
+use crate::vectors::*;
+use std::f32::consts::PI;
+
+
+// below this roughness a surface is treated as a perfect mirror / window, since the
+// distribution gets too sharp to evaluate or sample reliably
+const SMOOTH_ALPHA: f32 = 1e-3;
+
+
+// trowbridge-reitz (ggx) microfacet distribution, with a separate roughness along the tangent
+// (alpha_x) & the bitangent (alpha_y). works in a local frame with the normal along +z
+#[derive(Copy, Clone)]
+pub struct TrowbridgeReitz {
+    pub alpha_x: f32,
+    pub alpha_y: f32,
+}
+
+
+impl TrowbridgeReitz {
+
+    pub fn new(alpha_x: f32, alpha_y: f32) -> TrowbridgeReitz {
+        TrowbridgeReitz { alpha_x, alpha_y }
+    }
+
+    pub fn is_smooth(&self) -> bool {
+        self.alpha_x.max(self.alpha_y) < SMOOTH_ALPHA
+    }
+
+    // density of microfacet normals wm, per unit projected area
+    pub fn d(&self, wm: &Vector3) -> f32 {
+        if wm.z <= 0.0 {
+            return 0.0;
+        }
+
+        let (x, y) = (wm.x / self.alpha_x, wm.y / self.alpha_y);
+        let denominator = x * x + y * y + wm.z * wm.z;
+
+        1.0 / (PI * self.alpha_x * self.alpha_y * denominator * denominator)
+    }
+
+    // smith's auxiliary function, the masked microfacet area per visible area seen from w
+    fn lambda(&self, w: &Vector3) -> f32 {
+        if w.z == 0.0 {
+            return f32::INFINITY;
+        }
+
+        let (x, y) = (self.alpha_x * w.x, self.alpha_y * w.y);
+        let tan2_alpha2 = (x * x + y * y) / (w.z * w.z);
+
+        0.5 * ((1.0 + tan2_alpha2).sqrt() - 1.0)
+    }
+
+    // fraction of the microfacets visible from w
+    pub fn g1(&self, w: &Vector3) -> f32 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    // fraction visible from both wo & wi, height correlated
+    pub fn g(&self, wo: &Vector3, wi: &Vector3) -> f32 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    // density of wm among the normals visible from w
+    pub fn visible_d(&self, w: &Vector3, wm: &Vector3) -> f32 {
+        if w.z == 0.0 {
+            return 0.0;
+        }
+
+        self.g1(w) / w.z.abs() * self.d(wm) * Vector3::dot(w, wm).abs()
+    }
+
+    // a microfacet normal visible from w, picked with pdf visible_d (heitz, "sampling the ggx
+    // distribution of visible normals"). w may be on either side, wm always faces +z
+    pub fn sample_visible_normal(&self, w: &Vector3, u1: f32, u2: f32) -> Vector3 {
+        let w = if w.z < 0.0 {-*w} else {*w};
+
+        // stretch to the hemisphere configuration, where the distribution is a unit half sphere
+        let wh = Vector3::new(self.alpha_x * w.x, self.alpha_y * w.y, w.z).normalized();
+        let length_squared = wh.x * wh.x + wh.y * wh.y;
+        let t1 = if length_squared > 0.0 {Vector3::new(-wh.y, wh.x, 0.0) / length_squared.sqrt()} else {Vector3::newi(1, 0, 0)};
+        let t2 = Vector3::cross(&wh, &t1);
+
+        // a point on the disk, squeezed onto the part of it visible from wh
+        let r = u1.sqrt();
+        let phi = 2.0 * PI * u2;
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + wh.z);
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).max(0.0).sqrt() + s * r * phi.sin();
+
+        let nh = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * wh;
+
+        Vector3::new(self.alpha_x * nh.x, self.alpha_y * nh.y, nh.z.max(1e-6)).normalized()
+    }
+
+}
+
+
+
+// unpolarized fresnel reflectance at a boundary with relative index eta (transmitted side over
+// incident side). cos_theta_i is negative for light arriving from the other side
+pub fn fresnel_dielectric(cos_theta_i: f32, eta: f32) -> f32 {
+    let (cos_theta_i, eta) = if cos_theta_i < 0.0 {(-cos_theta_i, 1.0 / eta)} else {(cos_theta_i, eta)};
+    let cos_theta_i = cos_theta_i.min(1.0);
+
+    let sin2_theta_t = (1.0 - cos_theta_i * cos_theta_i) / (eta * eta);
+    if sin2_theta_t >= 1.0 {
+        return 1.0; // total internal reflection
+    }
+    let cos_theta_t = (1.0 - sin2_theta_t).sqrt();
+
+    let r_parallel = (eta * cos_theta_i - cos_theta_t) / (eta * cos_theta_i + cos_theta_t);
+    let r_perpendicular = (cos_theta_i - eta * cos_theta_t) / (cos_theta_i + eta * cos_theta_t);
+
+    0.5 * (r_parallel * r_parallel + r_perpendicular * r_perpendicular)
+}
+
+
+// fresnel reflectance of a conductor with complex index eta + ik
+fn fresnel_complex(cos_theta_i: f32, eta: f32, k: f32) -> f32 {
+    let cos2 = cos_theta_i.clamp(0.0, 1.0).powi(2);
+    let sin2 = 1.0 - cos2;
+
+    let t0 = eta * eta - k * k - sin2;
+    let a2_plus_b2 = (t0 * t0 + 4.0 * eta * eta * k * k).sqrt();
+    let t1 = a2_plus_b2 + cos2;
+    let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+    let t2 = 2.0 * cos_theta_i.abs() * a;
+    let r_s = (t1 - t2) / (t1 + t2);
+
+    let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let r_p = r_s * (t3 - t4) / (t3 + t4);
+
+    0.5 * (r_p + r_s)
+}
+
+
+// per channel (or per wavelength) fresnel_complex
+pub fn fresnel_conductor(cos_theta_i: f32, eta: &Color, k: &Color) -> Color {
+    Color::new(
+        fresnel_complex(cos_theta_i, eta.x, k.x),
+        fresnel_complex(cos_theta_i, eta.y, k.y),
+        fresnel_complex(cos_theta_i, eta.z, k.z),
+    )
+}
+
+
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::{random::random_f32, rays::Ray, hittable::*, materials::*, scene::Scene, film::Film, camera::Camera,
+                hittable_list::HittableList, sphere::Sphere, sky::GradientSky, integrator::*, color_space::*};
+    use std::sync::Arc;
+
+    // a hit on the plane z = 0 by a ray coming in at theta degrees, from above or below
+    pub fn hit_at(theta: f32, from_above: bool) -> (Ray, HitRecord) {
+        let (sin, cos) = theta.to_radians().sin_cos();
+        let origin = Point3::new(sin, 0.3 * sin, if from_above {cos} else {-cos});
+        let r = Ray::new(origin, -origin, 0.0);
+
+        let mut rec = HitRecord::new();
+        rec.set_face_normal(&r, Vector3::newi(0, 0, 1));
+        (r, rec)
+    }
+
+    // mean weight of the rays scatter picks, i.e. how much light the surface sends on
+    pub fn sampled_albedo(material: &dyn Material, r: &Ray, rec: &HitRecord, samples: u32) -> Color {
+        let mut albedo = Color::zeros();
+        for _ in 0..samples {
+            if let Some((_, weight)) = material.scatter(r, rec) {
+                albedo += weight;
+            }
+        }
+        albedo / samples as f32
+    }
+
+    // the same from eval, integrated over the sphere with the midpoint rule. the lobes are narrow,
+    // so a uniform monte carlo estimate would be far too noisy
+    pub fn integrated_albedo(material: &dyn Material, r: &Ray, rec: &HitRecord) -> Color {
+        let (n_theta, n_phi) = (600, 200);
+        let (d_theta, d_phi) = (PI / n_theta as f32, 2.0 * PI / n_phi as f32);
+        let mut albedo = Color::zeros();
+
+        for i in 0..n_theta {
+            let theta = (i as f32 + 0.5) * d_theta;
+            for j in 0..n_phi {
+                let phi = (j as f32 + 0.5) * d_phi;
+                let direction = Vector3::new(theta.sin() * phi.cos(), theta.sin() * phi.sin(), theta.cos());
+                albedo += theta.sin() * d_theta * d_phi * material.eval(r, rec, &direction);
+            }
+        }
+        albedo
+    }
+
+    pub fn assert_sampling_matches_eval(material: &dyn Material, r: &Ray, rec: &HitRecord) {
+        let sampled = sampled_albedo(material, r, rec, 200_000);
+        let integrated = integrated_albedo(material, r, rec);
+
+        assert!((sampled - integrated).magnitude() < 0.02 * integrated.magnitude().max(0.3),
+                "sampled {} {} {} but eval integrates to {} {} {}", sampled.x, sampled.y, sampled.z, integrated.x, integrated.y, integrated.z);
+    }
+
+    #[test]
+    fn rough_conductors_sample_what_they_evaluate() {
+        for theta in [0.0, 45.0, 80.0].iter() {
+            let (r, rec) = hit_at(*theta, true);
+            assert_sampling_matches_eval(&Conductor::gold().with_roughness(0.3, 0.3), &r, &rec);
+            assert_sampling_matches_eval(&Conductor::copper().with_roughness(0.5, 0.05), &r, &rec);
+        }
+    }
+
+    #[test]
+    fn rough_dielectrics_sample_what_they_evaluate() {
+        let glass = Dielectric::new(1.5).with_roughness(0.3, 0.1);
+
+        for theta in [0.0, 30.0, 60.0].iter() {
+            let (r, rec) = hit_at(*theta, true);
+            assert_sampling_matches_eval(&glass, &r, &rec);
+
+            // from inside, eval blows up next to the critical angle & can't be integrated like that.
+            // just check that no energy is made up, & that masking doesn't lose too much
+            let (r, rec) = hit_at(*theta, false);
+            let albedo = sampled_albedo(&glass, &r, &rec, 100_000);
+            assert!(albedo.x < 1.01 && albedo.x > 0.75, "albedo {} at {} degrees", albedo.x, theta);
+        }
+    }
+
+    #[test]
+    fn light_sampling_matches_naive_path_tracing_on_glossy_surfaces() {
+        let mut world = HittableList::new();
+        let mut lights = HittableList::new();
+        world.add(Box::new(Sphere::new(Point3::new(0.0, -100.5, -1.0), 100.0, Arc::new(Conductor::aluminium().with_roughness(0.4, 0.4)))));
+        world.add(Box::new(Sphere::new(Point3::newi(0, 0, -1), 0.5, Arc::new(Conductor::gold().with_roughness(0.3, 0.1)))));
+        world.add(Box::new(Sphere::new(Point3::newi(-1, 0, -1), 0.5, Arc::new(Dielectric::new(1.5).with_roughness(0.2, 0.2)))));
+        let lamp = Arc::new(DiffuseLight::new(Color::fromv(4.0)));
+        world.add(Box::new(Sphere::new(Point3::new(0.0, 2.0, -1.0), 0.5, lamp.clone())));
+        lights.add(Box::new(Sphere::new(Point3::new(0.0, 2.0, -1.0), 0.5, lamp)));
+
+        let sky = GradientSky::new(Color::fromv(1.0), Color::new(0.5, 0.7, 1.0));
+        let cam = Camera::new(Point3::zeros(), Point3::newi(0, 0, -1), Vector3::newi(0, 1, 0), 90.0, 1.0, 0.0, 1.0, 0.0, 0.0);
+        let scene = Scene::new(world, lights, Some(Box::new(sky)), cam);
+
+        let naive = PathTracer::new(50, 3);
+        let mis = MisPathTracer::new(50, 3);
+        let mut film = Film::new(2, 2);
+
+        for target in [Point3::newi(0, 0, -1), Point3::new(-1.0, 0.1, -1.0), Point3::new(0.5, -0.45, -1.0)].iter() {
+            let r = Ray::new(Point3::zeros(), *target, 0.0);
+            let mut difference = Color::zeros();
+            for _ in 0..40000 {
+                difference += naive.li(&r, &scene, &mut film) - mis.li(&r, &scene, &mut film);
+            }
+
+            let difference = difference / 40000;
+            assert!(difference.magnitude() < 0.03, "mean radiance differs by {} {} {}", difference.x, difference.y, difference.z);
+        }
+    }
+
+    #[test]
+    fn visible_normals_are_sampled_with_their_density() {
+        let distribution = TrowbridgeReitz::new(0.3, 0.08);
+        let w = Vector3::new(0.5, -0.2, 0.6).normalized();
+
+        // a histogram over the hemisphere against the density, per unit solid angle
+        let (bins, samples) = (8, 400_000);
+        let mut counts = vec![0u32; bins * bins];
+        for _ in 0..samples {
+            let wm = distribution.sample_visible_normal(&w, random_f32(), random_f32());
+            let (u, v) = (1.0 - wm.z, 0.5 * (wm.y.atan2(wm.x) / PI + 1.0));
+            counts[(u * bins as f32).min(bins as f32 - 1.0) as usize * bins + (v * bins as f32).min(bins as f32 - 1.0) as usize] += 1;
+        }
+
+        // integrate the density over each bin with a fine midpoint rule, z = 1 - u & phi = 2 pi v - pi
+        let n = 24;
+        for i in 0..bins {
+            for j in 0..bins {
+                let mut expected = 0.0;
+                for a in 0..n {
+                    for b in 0..n {
+                        let z = 1.0 - (i as f32 + (a as f32 + 0.5) / n as f32) / bins as f32;
+                        let phi = 2.0 * PI * (j as f32 + (b as f32 + 0.5) / n as f32) / bins as f32 - PI;
+                        let sin_theta = (1.0 - z * z).sqrt();
+                        let wm = Vector3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), z);
+                        expected += distribution.visible_d(&w, &wm) * (2.0 * PI / bins as f32) * (1.0 / bins as f32) / (n * n) as f32;
+                    }
+                }
+
+                let actual = counts[i * bins + j] as f32 / samples as f32;
+                assert!((actual - expected).abs() < 0.005 + 0.1 * expected, "bin {} {}: sampled {} against a density of {}", i, j, actual, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn smooth_dielectrics_reflect_as_much_as_nearly_smooth_ones() {
+        let smooth = Dielectric::new(1.5);
+        let nearly_smooth = Dielectric::new(1.5).with_roughness(0.005, 0.005);
+
+        let reflected = |material: &Dielectric, r: &Ray, rec: &HitRecord| {
+            let samples = 100_000;
+            let count = (0..samples).filter(|_| match material.scatter(r, rec) {
+                Some((scattered, _)) => Vector3::dot(&scattered.direction, &rec.normal) > 0.0,
+                None => false,
+            }).count();
+            count as f32 / samples as f32
+        };
+
+        for theta in [0.0, 45.0, 70.0, 85.0].iter() {
+            for from_above in [true, false].iter() {
+                let (r, rec) = hit_at(*theta, *from_above);
+                let (a, b) = (reflected(&smooth, &r, &rec), reflected(&nearly_smooth, &r, &rec));
+                assert!((a - b).abs() < 0.01, "smooth reflects {} & nearly smooth {} at {} degrees", a, b, theta);
+            }
+        }
+    }
+
+    #[test]
+    fn anisotropy_follows_the_surface_tangent() {
+        let metal = Conductor::gold().with_roughness(0.5, 0.05);
+        let (r, mut rec) = hit_at(40.0, true);
+        let direction = Vector3::new(-0.3, 0.5, 0.8);
+
+        // the same hit turned a quarter around the normal, tangent & all
+        let turn = |v: &Vector3| Vector3::new(-v.y, v.x, v.z);
+        let turned_r = Ray::new(turn(&r.origin), turn(&r.direction), 0.0);
+        let mut turned_rec = HitRecord::new();
+        turned_rec.set_face_normal(&turned_r, Vector3::newi(0, 0, 1));
+
+        rec.dpdu = Vector3::new(2.0, 0.0, 0.7); // the part along the normal doesn't count
+        turned_rec.dpdu = Vector3::newi(0, 3, 0);
+        let f = metal.eval(&r, &rec, &direction);
+        let turned_f = metal.eval(&turned_r, &turned_rec, &turn(&direction));
+        assert!((f - turned_f).magnitude() < 1e-4 * f.magnitude());
+
+        // & it does matter which way it runs
+        rec.dpdu = Vector3::newi(0, 1, 0);
+        assert!((f - metal.eval(&r, &rec, &direction)).magnitude() > 0.1 * f.magnitude());
+    }
+
+    #[test]
+    fn dielectric_fresnel_is_symmetric_and_total_internal_reflection_reflects_everything() {
+        assert!((fresnel_dielectric(1.0, 1.5) - 0.04).abs() < 1e-4);
+        assert!((fresnel_dielectric(0.7, 1.5) - fresnel_dielectric(-0.7, 1.0 / 1.5)).abs() < 1e-6);
+        assert!(fresnel_dielectric(-0.3, 1.5) == 1.0);
+    }
+
+    #[test]
+    fn conductor_fresnel_goes_to_one_at_grazing_angles() {
+        let (eta, k) = (Color::new(0.143, 0.374, 1.442), Color::new(3.983, 2.385, 1.603));
+        let head_on = fresnel_conductor(1.0, &eta, &k);
+        let grazing = fresnel_conductor(1e-4, &eta, &k);
+
+        // normal incidence has a closed form, ((n - 1)^2 + k^2) / ((n + 1)^2 + k^2)
+        let expected = ((eta.x - 1.0).powi(2) + k.x * k.x) / ((eta.x + 1.0).powi(2) + k.x * k.x);
+        assert!((head_on.x - expected).abs() < 1e-4);
+        assert!(grazing.x > 0.99 && grazing.y > 0.99 && grazing.z > 0.99);
+    }
+
+    #[test]
+    fn conductors_convert_their_reflectance_not_their_indices() {
+        let gold = Conductor::gold();
+        let (r, rec) = hit_at(0.0, true);
+        let reflectance = fresnel_conductor(1.0, &gold.eta, &gold.k);
+
+        // eta & k go into fresnel as measured, & only what it gives back is a color
+        with_working_space(ColorSpace::AcesCg, || {
+            let (_, weight) = gold.scatter(&r, &rec).unwrap();
+            assert!((weight - to_working(&reflectance)).magnitude() < 1e-5);
+        });
+        let (_, weight) = gold.scatter(&r, &rec).unwrap();
+        assert!((weight - reflectance).magnitude() < 1e-5);
+    }
+}
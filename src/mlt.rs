@@ -0,0 +1,304 @@
+use crate::{
+            vectors::*,
+            scene::Scene,
+            film::Film,
+            rays::Ray,
+            colors::luminance,
+            integrator::*,
+            random::*,
+            sampler::Sampler,
+};
+use rand::{prelude::*, rngs::StdRng};
+use std::{rc::Rc, cell::RefCell, sync::Arc, f32::consts::PI};
+
+
+// primary sample space metropolis (kelemen et al.) layered on the mis path tracer. a path is
+// just the list of random numbers the path tracer drew for it, so mutating those numbers
+// mutates the path without the tracer knowing. large steps draw fresh numbers, small steps
+// nudge the current ones, which keeps chains exploring whatever narrow set of paths carries
+// the light, e.g. through a small opening. a bootstrap pass of plain path tracing estimates
+// the overall brightness the chains can't see & picks their starting paths
+pub struct Mlt {
+    tracer: MisPathTracer,
+    bootstrap_samples: u32,
+    chains: u32,
+    sigma: f32, // small step size, in primary sample space
+    large_step_probability: f32,
+}
+
+
+#[derive(Clone, Copy, Default)]
+struct PrimarySample {
+    value: f32,
+    modified: u64, // iteration value was last changed in
+    backup_value: f32,
+    backup_modified: u64,
+}
+
+
+// the random numbers behind the current path, mutated lazily: a number only catches up on
+// the steps it missed when the path tracer asks for it
+struct PrimarySamples {
+    rng: StdRng,
+    sigma: f32,
+    large_step_probability: f32,
+    samples: Vec<PrimarySample>,
+    iteration: u64,
+    last_large_step: u64,
+    large_step: bool,
+    index: usize, // next sample the path tracer gets
+}
+
+
+impl PrimarySamples {
+
+    // starts out on a large step, so the first path is a plain path tracer sample
+    fn new(seed: u64, sigma: f32, large_step_probability: f32) -> PrimarySamples {
+        PrimarySamples {
+            rng: StdRng::seed_from_u64(seed),
+            sigma,
+            large_step_probability,
+            samples: Vec::new(),
+            iteration: 0,
+            last_large_step: 0,
+            large_step: true,
+            index: 0,
+        }
+    }
+
+    fn start_iteration(&mut self) {
+        self.iteration += 1;
+        self.large_step = self.rng.gen::<f32>() < self.large_step_probability;
+        self.index = 0;
+    }
+
+    fn accept(&mut self) {
+        if self.large_step {
+            self.last_large_step = self.iteration;
+        }
+    }
+
+    fn reject(&mut self) {
+        let iteration = self.iteration;
+        for s in self.samples.iter_mut().filter(|s| s.modified == iteration) {
+            s.value = s.backup_value;
+            s.modified = s.backup_modified;
+        }
+        self.iteration -= 1;
+    }
+
+    fn sample(&mut self) -> f32 {
+        // a number the path never asked for before starts out uniform, as if the last large step drew it
+        if self.index == self.samples.len() {
+            let value = self.rng.gen::<f32>();
+            self.samples.push(PrimarySample { value, modified: self.last_large_step, ..PrimarySample::default() });
+        }
+        let s = &mut self.samples[self.index];
+        let rng = &mut self.rng;
+        self.index += 1;
+
+        // a large step accepted since this number was last used replaced it too
+        if s.modified < self.last_large_step {
+            s.value = rng.gen::<f32>();
+            s.modified = self.last_large_step;
+        }
+
+        s.backup_value = s.value;
+        s.backup_modified = s.modified;
+
+        if self.large_step {
+            s.value = rng.gen::<f32>();
+        } else {
+            // one gaussian step per iteration missed, wrapped around the unit interval
+            let steps = (self.iteration - s.modified) as f32;
+            let normal = (-2.0 * (1.0 - rng.gen::<f32>()).ln()).sqrt() * (2.0 * PI * rng.gen::<f32>()).cos();
+            s.value += normal * self.sigma * steps.sqrt();
+            s.value -= s.value.floor();
+        }
+        s.modified = self.iteration;
+
+        s.value
+    }
+
+}
+
+
+// shared with the path tracer through random::with_source while a path is traced
+impl RandomSource for Rc<RefCell<PrimarySamples>> {
+    fn next(&mut self) -> f32 {
+        self.borrow_mut().sample()
+    }
+}
+
+
+impl Mlt {
+
+    pub fn new(max_depth: u32, rr_min_bounces: u32, bootstrap_samples: u32, chains: u32, sigma: f32, large_step_probability: f32) -> Mlt {
+        Mlt { tracer: MisPathTracer::new(max_depth, rr_min_bounces), bootstrap_samples, chains, sigma, large_step_probability }
+    }
+
+    // runs the path tracer on samples, the first two numbers picking the spot on the image.
+    // returns the radiance & the index of the pixel it lands in
+    fn trace(&self, scene: &Scene, film: &mut Film, samples: &Rc<RefCell<PrimarySamples>>) -> (Color, usize) {
+        let (width, height) = (film.width, film.height);
+
+        with_source(Box::new(samples.clone()), || {
+            let x = random_f32() * width as f32;
+            let y = random_f32() * height as f32;
+            let r: Ray = scene.camera.get_ray(x / (width - 1) as f32, y / (height - 1) as f32);
+            let index = (y as u32).min(height - 1) * width + (x as u32).min(width - 1);
+
+            (self.tracer.li(&r, scene, film), index as usize)
+        })
+    }
+
+}
+
+
+impl Integrator for Mlt {
+
+    fn li(&self, r: &Ray, scene: &Scene, film: &mut Film) -> Color {
+        self.tracer.li(r, scene, film)
+    }
+
+    // samples_per_pixel counts mutations, spread evenly over the chains
+    fn render(&self, scene: &Scene, film: &mut Film, _sampler: &Arc<dyn Sampler>, samples_per_pixel: u32) {
+        let mut rng = thread_rng();
+        let pixels = (film.width * film.height) as usize;
+
+        // bootstrap, seeded so a chain can start off from any of these paths by replaying it
+        let first_seed = rng.gen::<u64>();
+        let weights: Vec<f32> = (0..self.bootstrap_samples as u64)
+            .map(|i| {
+                let samples = Rc::new(RefCell::new(PrimarySamples::new(first_seed.wrapping_add(i), self.sigma, self.large_step_probability)));
+                luminance(&self.trace(scene, film, &samples).0).max(0.0)
+            })
+            .collect();
+        let b = weights.iter().sum::<f32>() / self.bootstrap_samples.max(1) as f32;
+
+        let mut image = vec![Color::zeros(); pixels];
+        let chains = self.chains.max(1) as u64;
+        let mutations_per_chain = samples_per_pixel as u64 * pixels as u64 / chains;
+
+        if b > 0.0 {
+            let cdf: Vec<f32> = weights.iter().scan(0.0, |sum, w| { *sum += w; Some(*sum) }).collect();
+
+            for chain in 0..chains {
+                eprintln!("\rChains remaining - {}", chains - chain);
+
+                // start from a bootstrap path, picked in proportion to its brightness
+                let target = rng.gen::<f32>() * cdf[cdf.len() - 1];
+                let start = cdf.partition_point(|c| *c <= target).min(cdf.len() - 1);
+                let samples = Rc::new(RefCell::new(PrimarySamples::new(first_seed.wrapping_add(start as u64), self.sigma, self.large_step_probability)));
+                let (mut current, mut current_pixel) = self.trace(scene, film, &samples);
+
+                for _ in 0..mutations_per_chain {
+                    samples.borrow_mut().start_iteration();
+                    let (proposed, proposed_pixel) = self.trace(scene, film, &samples);
+
+                    let (y_current, y_proposed) = (luminance(&current), luminance(&proposed));
+                    let accept = if y_current > 0.0 {(y_proposed / y_current).clamp(0.0, 1.0)} else {1.0};
+
+                    // both paths get splatted, weighted by how likely the chain is to move
+                    if y_proposed > 0.0 {
+                        image[proposed_pixel] += accept / y_proposed * proposed;
+                    }
+                    if y_current > 0.0 {
+                        image[current_pixel] += (1.0 - accept) / y_current * current;
+                    }
+
+                    if rng.gen::<f32>() < accept {
+                        current = proposed;
+                        current_pixel = proposed_pixel;
+                        samples.borrow_mut().accept();
+                    } else {
+                        samples.borrow_mut().reject();
+                    }
+                }
+            }
+        }
+
+        // every mutation carries b / (mutations per pixel) of brightness
+        let total_mutations = (chains * mutations_per_chain).max(1);
+        let scale = b * pixels as f32 / total_mutations as f32;
+
+        for j in 0..film.height {
+            for i in 0..film.width {
+                film.add_sample(i, j, scale * image[(j * film.width + i) as usize]);
+            }
+        }
+    }
+
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{hittable_list::HittableList, sphere::Sphere, aarect::*, materials::*, camera::Camera, render::render, sampler::Independent};
+
+    // a diffuse box lit through a gap in its roof by a lamp above it
+    fn skylight_scene() -> Scene {
+        let mut world = HittableList::new();
+        let mut lights = HittableList::new();
+
+        let white = Arc::new(Lambertian::new(Color::fromv(0.7)));
+        world.add(Box::new(XzRect::new(-2.0, 2.0, -2.0, 2.0, 0.0, white.clone())));
+        world.add(Box::new(XyRect::new(-2.0, 2.0, 0.0, 2.0, -2.0, white.clone())));
+        world.add(Box::new(YzRect::new(0.0, 2.0, -2.0, 2.0, -2.0, Arc::new(Lambertian::new(Color::new(0.7, 0.2, 0.2))))));
+        world.add(Box::new(YzRect::new(0.0, 2.0, -2.0, 2.0, 2.0, Arc::new(Lambertian::new(Color::new(0.2, 0.7, 0.2))))));
+        world.add(Box::new(XzRect::new(-2.0, 2.0, -2.0, 0.3, 2.0, white.clone())));
+        world.add(Box::new(XzRect::new(-2.0, 2.0, 0.8, 2.0, 2.0, white.clone())));
+        world.add(Box::new(Sphere::new(Point3::new(0.5, 0.5, -0.5), 0.5, white)));
+
+        let lamp = Arc::new(DiffuseLight::new(Color::fromv(20.0)));
+        world.add(Box::new(Sphere::new(Point3::new(0.0, 4.0, 0.5), 0.5, lamp.clone())));
+        lights.add(Box::new(Sphere::new(Point3::new(0.0, 4.0, 0.5), 0.5, lamp)));
+
+        let cam = Camera::new(Point3::new(0.0, 1.0, 5.0), Point3::new(0.0, 1.0, 0.0), Vector3::newi(0, 1, 0), 45.0, 1.5, 0.0, 5.0, 0.0, 0.0);
+        Scene::new(world, lights, None, cam)
+    }
+
+    #[test]
+    fn rejected_mutations_restore_the_path() {
+        let samples = Rc::new(RefCell::new(PrimarySamples::new(7, 0.01, 0.3)));
+        let first: Vec<f32> = (0..16).map(|_| samples.borrow_mut().sample()).collect();
+
+        for _ in 0..20 {
+            samples.borrow_mut().start_iteration();
+            for _ in 0..16 {
+                samples.borrow_mut().sample();
+            }
+            samples.borrow_mut().reject();
+        }
+
+        samples.borrow_mut().index = 0;
+        let samples = &mut samples.borrow_mut().samples;
+        assert!(samples.iter().zip(first.iter()).all(|(s, f)| s.value == *f));
+    }
+
+    #[test]
+    fn matches_path_tracing_through_a_small_opening() {
+        let scene = skylight_scene();
+
+        // the metropolis side's counts are what keep it within the tolerance. over 60 runs, each
+        // quadrant's difference over what the tolerance scales had an rms of 0.032 with 20000
+        // bootstrap paths, 64 chains & 512 mutations a pixel, 2 runs failing, & 0.020 with these,
+        // which puts 0.08 four of them out
+        let mut reference = Film::new(12, 8);
+        let sampler: Arc<dyn Sampler> = Arc::new(Independent);
+        render(&scene, &MisPathTracer::new(5, 5), &mut reference, &sampler, 512);
+        let mut metropolis = Film::new(12, 8);
+        Mlt::new(5, 5, 100_000, 256, 0.01, 0.3).render(&scene, &mut metropolis, &sampler, 1024);
+
+        let expected = reference.quadrant_means();
+        let actual = metropolis.quadrant_means();
+        let image_mean = expected.iter().fold(Color::zeros(), |sum, q| sum + *q) / 4;
+
+        for (e, a) in expected.iter().zip(actual.iter()) {
+            let difference = *e - *a;
+            assert!(difference.magnitude() < 0.08 * (e.magnitude() + image_mean.magnitude()),
+                    "quadrant means differ: {} {} {} vs {} {} {}", e.x, e.y, e.z, a.x, a.y, a.z);
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{hittable::*, vectors::*, rays::*, materials::*};
+use crate::{hittable::*, vectors::*, rays::*, materials::*, sphere::{get_sphere_uv, get_sphere_partials}};
 use std::sync::Arc;
 
 
@@ -52,10 +52,21 @@ impl Hittable for MovingSphere {
         rec.p = r.at(rec.t);
         let outward_normal = (rec.p - self.center(r.time)) / self.radius;
         rec.set_face_normal(r, outward_normal);
+        let (u, v) = get_sphere_uv(&outward_normal);
+        rec.u = u;
+        rec.v = v;
+        let (dpdu, dpdv) = get_sphere_partials(&outward_normal, self.radius);
+        rec.dpdu = dpdu;
+        rec.dpdv = dpdv;
         rec.mat = self.mat.clone();
+        rec.velocity = (self.center1 - self.center0) / (self.time1 - self.time0);
 
-        return true;
+        true
 
     }
 
+    fn materials(&self) -> Vec<Arc<dyn Material>> {
+        vec![self.mat.clone()]
+    }
+
 }
\ No newline at end of file
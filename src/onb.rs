@@ -0,0 +1,57 @@
+use crate::vectors::Vector3;
+use crate::random::random_f32;
+use std::f32::consts::PI;
+
+
+// orthonormal basis around w, used to map locally sampled directions into world space
+pub struct Onb {
+    pub u: Vector3,
+    pub v: Vector3,
+    pub w: Vector3,
+}
+
+
+impl Onb {
+
+    pub fn build_from_w(n: &Vector3) -> Onb {
+        let w = n.normalized();
+        let a = if w.x.abs() > 0.9 {Vector3::newi(0, 1, 0)} else {Vector3::newi(1, 0, 0)};
+        let v = Vector3::cross(&w, &a).normalized();
+        let u = Vector3::cross(&w, &v);
+
+        Onb { u, v, w }
+    }
+
+    // around n with u along the surface's tangent, dpdu with its part along n taken out. where that
+    // leaves nothing, e.g. at a sphere's poles or when the shape gives no dpdu, any frame will do
+    pub fn build_from_w_along(n: &Vector3, dpdu: &Vector3) -> Onb {
+        let w = n.normalized();
+        let along_u = *dpdu - Vector3::dot(dpdu, &w) * w;
+        if along_u.near_zero() {
+            return Onb::build_from_w(&w);
+        }
+
+        let u = along_u.normalized();
+        Onb { u, v: Vector3::cross(&w, &u), w }
+    }
+
+    pub fn local(&self, a: f32, b: f32, c: f32) -> Vector3 {
+        a * self.u + b * self.v + c * self.w
+    }
+
+    // the inverse of local, world space d in this basis' coordinates
+    pub fn to_local(&self, d: &Vector3) -> Vector3 {
+        Vector3::new(Vector3::dot(d, &self.u), Vector3::dot(d, &self.v), Vector3::dot(d, &self.w))
+    }
+
+}
+
+
+// cosine weighted direction around +z, meant for Onb::local
+pub fn random_cosine_direction() -> Vector3 {
+    let r1 = random_f32();
+    let r2 = random_f32();
+    let phi = 2.0 * PI * r1;
+
+    Vector3::new(phi.cos() * r2.sqrt(), phi.sin() * r2.sqrt(), (1.0 - r2).sqrt())
+}
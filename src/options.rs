@@ -0,0 +1,526 @@
+use crate::{integrator::DebugMode, aov::*, tonemap::ToneOperator, color_space::*};
+use std::{env, process, str::FromStr, time::Duration};
+
+
+#[derive(Debug)]
+pub enum SkyChoice {
+    Gradient,
+    Physical,
+}
+
+
+#[derive(Debug)]
+pub enum SceneChoice {
+    Random,
+    Caustics,
+    Window,
+    Dispersion,
+    Rough,
+    Principled,
+    Layered,
+    Iridescent,
+    Subsurface,
+    Bumps,
+    Cutouts,
+    Sheets,
+    Colors,
+    File(String), // described in a scene file
+}
+
+
+#[derive(Debug)]
+pub enum SamplerChoice {
+    Independent,
+    Stratified,
+    Halton,
+    Sobol,
+    PaddedSobol,
+}
+
+
+#[derive(Debug)]
+pub enum FilterChoice {
+    Box,
+    Tent,
+    Gaussian,
+    Mitchell,
+    Lanczos,
+}
+
+
+#[derive(Debug)]
+pub enum IntegratorChoice {
+    Path,
+    Mis,
+    Bidirectional,
+    PhotonMapping,
+    Metropolis,
+    Direct,
+    AmbientOcclusion,
+    Debug(DebugMode),
+}
+
+
+pub struct Options {
+    pub scene: SceneChoice,
+    pub seed: Option<u64>, // None picks one, or takes the checkpoint's when resuming
+    pub integrator: IntegratorChoice,
+    pub sampler: SamplerChoice,
+    pub target_error: Option<f32>, // adaptive sampling stops at this relative error
+    pub heat_map: Option<String>, // file for the samples each pixel took
+    pub filter: FilterChoice,
+    pub filter_radius: Option<f32>, // None takes the filter's own default
+    pub filter_importance: bool,
+    pub denoise: Option<u32>, // à-trous iterations, None leaves the image noisy
+    pub aovs: Vec<Aov>,
+    pub aov_prefix: String, // aov files are named prefix.name.pfm
+    pub exposure: f32, // stops
+    pub white_balance: Option<f32>, // kelvin
+    pub tonemap: ToneOperator,
+    pub hdr: Option<String>, // file for the linear image
+    pub progressive: Option<String>, // file the image is written to, & rewritten as it renders
+    pub snapshot_passes: Option<u32>,
+    pub snapshot_seconds: Option<f32>,
+    pub time_budget: Option<Duration>, // render passes for this long instead of a set number
+    pub checkpoint: Option<String>, // file the render's state is saved to, to resume from
+    pub checkpoint_seconds: f32,
+    pub resume: bool,
+    pub working_space: ColorSpace,
+    pub display: ColorSpace,
+    pub texture: Option<String>, // an 8 bit ppm for the colors scene
+    pub texture_space: ColorSpace,
+    pub texture_transfer: Transfer,
+    pub ao_samples: u32, // occlusion rays per camera sample
+    pub ao_distance: f32,
+    pub sky: Option<SkyChoice>, // None keeps the scene's own
+    pub sun_elevation: f32, // degrees above the horizon
+    pub sun_azimuth: f32, // degrees, clockwise from +z
+    pub turbidity: f32,
+    pub max_depth: u32,
+    pub rr_min_bounces: u32, // bounces before russian roulette may end a path
+    pub passes: u32, // photon mapping passes
+    pub photons: u32, // photons shot per pass
+    pub photon_memory: usize, // MiB one pass's photons may take up
+    pub photon_radius: f32, // initial gather radius
+    pub bootstrap: u32, // metropolis bootstrap paths
+    pub chains: u32, // metropolis chains
+    pub mutation_size: f32, // metropolis small step size
+    pub large_step: f32, // metropolis large step probability
+    pub spectral: bool,
+}
+
+
+const USAGE: &str = "usage: ray_tracing [options] > image.ppm
+
+  --scene <name>              built-in scene: random (default), caustics, window, dispersion,
+                              rough, principled, layered, iridescent, subsurface,
+                              bumps, cutouts, sheets, colors
+  --scene-file <file>         read the scene from file instead, see scenes/principled.txt
+  --seed <n>                  where the random & cutouts scenes put things, the same every time
+                              for the same seed. picked at random unless given, & saved in
+                              the checkpoint so --resume builds the scene it was started with
+  --integrator <name>         path (default), mis, bdpt, sppm, mlt, direct, ao,
+                              or the debug views normals, depth, uv, material
+  --sampler <name>            where the pixel loop's random numbers come from: independent
+                              (default), stratified, halton, sobol or padded-sobol.
+                              sppm & mlt pick their own
+  --target-error <e>          sample adaptively, until pixels' relative errors get down to e
+                              or the same total as without is spent
+  --heat-map <file>           write how many samples each pixel took to file, as a ppm
+  --filter <name>             pixel reconstruction filter: box (default), tent, gaussian,
+                              mitchell or lanczos
+  --filter-radius <r>         in pixels, defaults to 0.5, 1, 1.5, 2 & 3 for those
+  --filter-importance         place samples by the filter instead of weighting them into the
+                              pixels around them
+  --denoise <iterations>      filter the noise out of the finished image, guided by the albedo,
+                              normal & depth each pixel's samples saw first. 5 iterations
+                              is a good start, more blur wider. mlt doesn't give the
+                              denoiser those, so it goes by the image alone there
+  --aovs <names>              also write these passes, comma separated, or all of them:
+                              depth, position, normal, albedo, object_id, material_id,
+                              direct_diffuse, indirect_diffuse, direct_specular,
+                              indirect_specular, emission & motion. each is a pfm of
+                              floats. only path & mis split the light into its passes,
+                              the others but mlt give the rest. material ids are numbered
+                              in the order the scene adds its materials
+  --aov-prefix <path>         aov files are written to <path>.<name>.pfm, default aov
+  --exposure <ev>             brighten the image by this many stops, default 0
+  --white-balance <kelvin>    make light of this color temperature come out white
+  --tonemap <name>            tone curve: clamp (default), reinhard, hable or aces
+  --hdr <file>                also write the linear image, before exposure & tone mapping,
+                              as a pfm
+  --working-space <name>      linear rgb to render in, srgb (default, rec. 709 primaries) or
+                              acescg. the hdr image & aovs are written in it
+  --display <name>            what the ppm is encoded for: srgb (default), display-p3 or
+                              rec2020
+  --texture <file>            an 8 bit ppm to wrap the colors scene's globe in
+  --texture-space <name>      the texture's primaries: srgb (default), display-p3, rec2020
+                              or acescg
+  --texture-linear            the texture stores linear values instead of srgb encoded ones
+  --progressive <file>        render a sample per pixel over the whole image at a time, writing
+                              the image to file instead of stdout & rewriting it as it goes,
+                              so it can be looked at or stopped whenever. the samples it's
+                              made of are noted in its header. path, mis, bdpt, direct & ao
+  --snapshot-passes <n>       rewrite the progressive image every n passes, default 10
+  --snapshot-seconds <s>      or every s seconds, instead unless both are given
+  --time <duration>           render whole passes of a sample per pixel for as long as this,
+                              in seconds or with s, m or h after it, e.g. 10m, instead of a
+                              set number. path, mis, bdpt, direct & ao. a resumed render
+                              counts the time its checkpoint had spent against it, so it
+                              stops when the two add up to it
+  --checkpoint <file>         save what the render's accumulated to file every so often, so
+                              it can be resumed if it's stopped. path, mis, bdpt, direct & ao
+  --checkpoint-seconds <s>    how often, default 600
+  --resume                    carry on from the checkpoint, with the options it was started
+                              with. the image comes out as if it had never stopped. ones
+                              that would change it, like another sampler, other aovs or
+                              --progressive or --time on a render that was in tiles, are
+                              refused
+  --ao-samples <n>            occlusion rays the ao integrator casts per camera sample, default 1
+  --ao-distance <d>           occlusion range of the ao integrator, default 1
+  --sky <gradient|physical>   background, defaults to the scene's
+  --sun-elevation <degrees>   physical sky only, default 45
+  --sun-azimuth <degrees>     physical sky only, default 0
+  --turbidity <t>             physical sky haziness, 2 (clear) to 10 (hazy), default 3
+  --max-depth <n>             bounce limit per path, default 50
+  --rr-min-bounces <n>        bounces before russian roulette kicks in, default 3
+  --passes <n>                sppm passes, default 64
+  --photons <n>               sppm photons shot per pass, default 200000
+  --photon-memory <MiB>       sppm cap on the photons kept per pass, default 256
+  --photon-radius <r>         sppm initial gather radius, default 0.1
+  --bootstrap <n>             mlt paths traced to normalise & seed the chains, default 100000
+  --chains <n>                mlt markov chains, default 1000
+  --mutation-size <s>         mlt small step size, default 0.01
+  --large-step <p>            mlt large step probability, default 0.3
+  --spectral                  trace wavelengths instead of rgb, so glass can disperse light.
+                              works with path, mis, bdpt, direct & ao";
+
+
+fn fail(message: &str) -> ! {
+    eprintln!("{}\n\n{}", message, USAGE);
+    process::exit(1);
+}
+
+
+fn parse_value<T: FromStr>(flag: &str, value: Option<String>) -> T {
+    match value {
+        Some(v) => v.parse().unwrap_or_else(|_| fail(&format!("invalid value '{}' for {}", v, flag))),
+        None => fail(&format!("missing value for {}", flag)),
+    }
+}
+
+
+// seconds, minutes or hours, e.g. 90, 90s, 10m or 1.5h
+fn parse_duration(value: &str) -> Option<Duration> {
+    let (number, unit) = match value.char_indices().last()? {
+        (at, 's') => (&value[..at], 1.0),
+        (at, 'm') => (&value[..at], 60.0),
+        (at, 'h') => (&value[..at], 3600.0),
+        _ => (value, 1.0),
+    };
+
+    number.parse::<f64>().ok().filter(|n| n.is_finite() && *n > 0.0).map(|n| Duration::from_secs_f64(n * unit))
+}
+
+
+impl Options {
+
+    pub fn from_args() -> Options {
+        let mut options = Options {
+            scene: SceneChoice::Random,
+            seed: None,
+            integrator: IntegratorChoice::Path,
+            sampler: SamplerChoice::Independent,
+            target_error: None,
+            heat_map: None,
+            filter: FilterChoice::Box,
+            filter_radius: None,
+            filter_importance: false,
+            denoise: None,
+            aovs: Vec::new(),
+            aov_prefix: String::from("aov"),
+            exposure: 0.0,
+            white_balance: None,
+            tonemap: ToneOperator::Clamp,
+            hdr: None,
+            progressive: None,
+            snapshot_passes: None,
+            snapshot_seconds: None,
+            time_budget: None,
+            checkpoint: None,
+            checkpoint_seconds: 600.0,
+            resume: false,
+            working_space: ColorSpace::Srgb,
+            display: ColorSpace::Srgb,
+            texture: None,
+            texture_space: ColorSpace::Srgb,
+            texture_transfer: Transfer::Srgb,
+            ao_samples: 1,
+            ao_distance: 1.0,
+            sky: None,
+            sun_elevation: 45.0,
+            sun_azimuth: 0.0,
+            turbidity: 3.0,
+            max_depth: 50,
+            rr_min_bounces: 3,
+            passes: 64,
+            photons: 200_000,
+            photon_memory: 256,
+            photon_radius: 0.1,
+            bootstrap: 100_000,
+            chains: 1000,
+            mutation_size: 0.01,
+            large_step: 0.3,
+            spectral: false,
+        };
+
+        let mut args = env::args().skip(1);
+
+        while let Some(flag) = args.next() {
+            match flag.as_str() {
+                "--scene" => {
+                    options.scene = match parse_value::<String>(&flag, args.next()).as_str() {
+                        "random" => SceneChoice::Random,
+                        "caustics" => SceneChoice::Caustics,
+                        "window" => SceneChoice::Window,
+                        "dispersion" => SceneChoice::Dispersion,
+                        "rough" => SceneChoice::Rough,
+                        "principled" => SceneChoice::Principled,
+                        "layered" => SceneChoice::Layered,
+                        "iridescent" => SceneChoice::Iridescent,
+                        "subsurface" => SceneChoice::Subsurface,
+                        "bumps" => SceneChoice::Bumps,
+                        "cutouts" => SceneChoice::Cutouts,
+                        "sheets" => SceneChoice::Sheets,
+                        "colors" => SceneChoice::Colors,
+                        other => fail(&format!("unknown scene '{}'", other)),
+                    }
+                }
+                "--scene-file" => options.scene = SceneChoice::File(parse_value(&flag, args.next())),
+                "--seed" => options.seed = Some(parse_value(&flag, args.next())),
+                "--integrator" => {
+                    options.integrator = match parse_value::<String>(&flag, args.next()).as_str() {
+                        "path" => IntegratorChoice::Path,
+                        "mis" => IntegratorChoice::Mis,
+                        "bdpt" => IntegratorChoice::Bidirectional,
+                        "sppm" => IntegratorChoice::PhotonMapping,
+                        "mlt" => IntegratorChoice::Metropolis,
+                        "direct" => IntegratorChoice::Direct,
+                        "ao" => IntegratorChoice::AmbientOcclusion,
+                        "normals" => IntegratorChoice::Debug(DebugMode::Normals),
+                        "depth" => IntegratorChoice::Debug(DebugMode::Depth),
+                        "uv" => IntegratorChoice::Debug(DebugMode::Uvs),
+                        "material" => IntegratorChoice::Debug(DebugMode::MaterialIds),
+                        other => fail(&format!("unknown integrator '{}'", other)),
+                    }
+                }
+                "--sampler" => {
+                    options.sampler = match parse_value::<String>(&flag, args.next()).as_str() {
+                        "independent" => SamplerChoice::Independent,
+                        "stratified" => SamplerChoice::Stratified,
+                        "halton" => SamplerChoice::Halton,
+                        "sobol" => SamplerChoice::Sobol,
+                        "padded-sobol" => SamplerChoice::PaddedSobol,
+                        other => fail(&format!("unknown sampler '{}'", other)),
+                    }
+                }
+                "--target-error" => options.target_error = Some(parse_value(&flag, args.next())),
+                "--heat-map" => options.heat_map = Some(parse_value(&flag, args.next())),
+                "--filter" => {
+                    options.filter = match parse_value::<String>(&flag, args.next()).as_str() {
+                        "box" => FilterChoice::Box,
+                        "tent" => FilterChoice::Tent,
+                        "gaussian" => FilterChoice::Gaussian,
+                        "mitchell" => FilterChoice::Mitchell,
+                        "lanczos" => FilterChoice::Lanczos,
+                        other => fail(&format!("unknown filter '{}'", other)),
+                    }
+                }
+                "--filter-radius" => options.filter_radius = Some(parse_value(&flag, args.next())),
+                "--filter-importance" => options.filter_importance = true,
+                "--denoise" => options.denoise = Some(parse_value(&flag, args.next())),
+                "--aovs" => {
+                    let names = parse_value::<String>(&flag, args.next());
+                    options.aovs = if names == "all" {
+                        ALL_AOVS.to_vec()
+                    } else {
+                        names.split(',').map(|name| Aov::from_name(name).unwrap_or_else(|| fail(&format!("unknown aov '{}'", name)))).collect()
+                    };
+                }
+                "--aov-prefix" => options.aov_prefix = parse_value(&flag, args.next()),
+                "--exposure" => options.exposure = parse_value(&flag, args.next()),
+                "--white-balance" => options.white_balance = Some(parse_value(&flag, args.next())),
+                "--tonemap" => {
+                    options.tonemap = match parse_value::<String>(&flag, args.next()).as_str() {
+                        "clamp" => ToneOperator::Clamp,
+                        "reinhard" => ToneOperator::Reinhard,
+                        "hable" => ToneOperator::Hable,
+                        "aces" => ToneOperator::Aces,
+                        other => fail(&format!("unknown tone operator '{}'", other)),
+                    }
+                }
+                "--hdr" => options.hdr = Some(parse_value(&flag, args.next())),
+                "--working-space" => {
+                    options.working_space = match ColorSpace::from_name(&parse_value::<String>(&flag, args.next())) {
+                        Some(space @ (ColorSpace::Srgb | ColorSpace::AcesCg)) => space,
+                        _ => fail("the working space is srgb or acescg"),
+                    }
+                }
+                "--display" => {
+                    options.display = match ColorSpace::from_name(&parse_value::<String>(&flag, args.next())) {
+                        Some(ColorSpace::AcesCg) | None => fail("the display is srgb, display-p3 or rec2020"),
+                        Some(space) => space,
+                    }
+                }
+                "--texture" => options.texture = Some(parse_value(&flag, args.next())),
+                "--texture-space" => {
+                    let name = parse_value::<String>(&flag, args.next());
+                    options.texture_space = ColorSpace::from_name(&name).unwrap_or_else(|| fail(&format!("unknown color space '{}'", name)));
+                }
+                "--texture-linear" => options.texture_transfer = Transfer::Linear,
+                "--progressive" => options.progressive = Some(parse_value(&flag, args.next())),
+                "--snapshot-passes" => options.snapshot_passes = Some(parse_value(&flag, args.next())),
+                "--snapshot-seconds" => options.snapshot_seconds = Some(parse_value(&flag, args.next())),
+                "--time" => {
+                    let value = parse_value::<String>(&flag, args.next());
+                    options.time_budget = Some(parse_duration(&value).unwrap_or_else(|| fail(&format!("invalid duration '{}' for {}", value, flag))));
+                }
+                "--checkpoint" => options.checkpoint = Some(parse_value(&flag, args.next())),
+                "--checkpoint-seconds" => options.checkpoint_seconds = parse_value(&flag, args.next()),
+                "--resume" => options.resume = true,
+                "--ao-samples" => options.ao_samples = parse_value(&flag, args.next()),
+                "--ao-distance" => options.ao_distance = parse_value(&flag, args.next()),
+                "--sky" => {
+                    options.sky = match parse_value::<String>(&flag, args.next()).as_str() {
+                        "gradient" => Some(SkyChoice::Gradient),
+                        "physical" => Some(SkyChoice::Physical),
+                        other => fail(&format!("unknown sky '{}'", other)),
+                    }
+                }
+                "--sun-elevation" => options.sun_elevation = parse_value(&flag, args.next()),
+                "--sun-azimuth" => options.sun_azimuth = parse_value(&flag, args.next()),
+                "--turbidity" => options.turbidity = parse_value(&flag, args.next()),
+                "--max-depth" => options.max_depth = parse_value(&flag, args.next()),
+                "--rr-min-bounces" => options.rr_min_bounces = parse_value(&flag, args.next()),
+                "--passes" => options.passes = parse_value(&flag, args.next()),
+                "--photons" => options.photons = parse_value(&flag, args.next()),
+                "--photon-memory" => options.photon_memory = parse_value(&flag, args.next()),
+                "--photon-radius" => options.photon_radius = parse_value(&flag, args.next()),
+                "--bootstrap" => options.bootstrap = parse_value(&flag, args.next()),
+                "--chains" => options.chains = parse_value(&flag, args.next()),
+                "--mutation-size" => options.mutation_size = parse_value(&flag, args.next()),
+                "--large-step" => options.large_step = parse_value(&flag, args.next()),
+                "--spectral" => options.spectral = true,
+                "-h" | "--help" => {
+                    println!("{}", USAGE);
+                    process::exit(0);
+                }
+                other => fail(&format!("unknown option '{}'", other)),
+            }
+        }
+
+        if matches!(options.target_error, Some(e) if e <= 0.0) {
+            fail("target error must be positive");
+        }
+
+        if matches!(options.filter_radius, Some(r) if r <= 0.0) {
+            fail("filter radius must be positive");
+        }
+
+        if matches!(options.white_balance, Some(k) if k < 1000.0) {
+            fail("white balance must be at least 1000 kelvin");
+        }
+
+        if matches!(options.snapshot_passes, Some(0)) || matches!(options.snapshot_seconds, Some(s) if s <= 0.0) {
+            fail("snapshots must be a positive number of passes or seconds apart");
+        }
+
+        if options.progressive.is_none() && (options.snapshot_passes.is_some() || options.snapshot_seconds.is_some()) {
+            fail("--snapshot-passes & --snapshot-seconds go with --progressive");
+        }
+
+        // sppm & mlt don't render a pass per pixel at a time, adaptive sampling doesn't go in passes
+        if options.progressive.is_some() {
+            if matches!(options.integrator, IntegratorChoice::PhotonMapping | IntegratorChoice::Metropolis) {
+                fail("--progressive works with the path, mis, bdpt, direct & ao integrators");
+            }
+            if options.target_error.is_some() {
+                fail("--progressive doesn't go with --target-error");
+            }
+        }
+
+        if options.checkpoint_seconds < 0.0 {
+            fail("checkpoints can't be a negative number of seconds apart");
+        }
+
+        if options.resume && options.checkpoint.is_none() {
+            fail("--resume needs the --checkpoint to resume from");
+        }
+
+        // sppm & mlt don't render in passes, adaptive sampling spends its own budget
+        if options.time_budget.is_some() {
+            if matches!(options.integrator, IntegratorChoice::PhotonMapping | IntegratorChoice::Metropolis) {
+                fail("--time works with the path, mis, bdpt, direct & ao integrators");
+            }
+            if options.target_error.is_some() {
+                fail("--time doesn't go with --target-error");
+            }
+        }
+
+        // sppm & mlt keep state of their own besides the film
+        if options.checkpoint.is_some() && matches!(options.integrator, IntegratorChoice::PhotonMapping | IntegratorChoice::Metropolis) {
+            fail("--checkpoint works with the path, mis, bdpt, direct & ao integrators");
+        }
+
+        if options.ao_samples == 0 {
+            fail("ao samples must be at least 1");
+        }
+
+        if options.turbidity < 1.0 {
+            fail("turbidity must be at least 1");
+        }
+
+        if options.photon_radius <= 0.0 {
+            fail("photon radius must be positive");
+        }
+
+        if !(0.0..=1.0).contains(&options.large_step) {
+            fail("large step probability must be between 0 and 1");
+        }
+
+        // sppm & mlt trace their paths outside of li, the debug views aren't radiance
+        if options.spectral {
+            match options.integrator {
+                IntegratorChoice::PhotonMapping | IntegratorChoice::Metropolis | IntegratorChoice::Debug(_) => {
+                    fail("--spectral works with the path, mis, bdpt, direct & ao integrators")
+                }
+                _ => {}
+            }
+        }
+
+        options
+    }
+
+    // the options that decide what a render's samples come out as, a line each, for a checkpoint to
+    // be resumed under only the same ones. the film checks its own aovs, features, filter importance
+    // & whether it renders in tiles or passes
+    pub fn render_settings(&self, samples_per_pixel: u32) -> String {
+        let sky = self.sky.as_ref().map(|sky| format!("{:?}, sun at {} & {}, turbidity {}", sky, self.sun_elevation, self.sun_azimuth, self.turbidity));
+        [
+            format!("scene: {:?}", self.scene),
+            format!("texture: {:?} in {:?}, {:?}", self.texture, self.texture_space, self.texture_transfer),
+            format!("sky: {:?}", sky),
+            format!("integrator: {:?}", self.integrator),
+            format!("max depth: {}", self.max_depth),
+            format!("rr min bounces: {}", self.rr_min_bounces),
+            format!("ao: {} samples, {} far", self.ao_samples, self.ao_distance),
+            format!("sampler: {:?}", self.sampler),
+            format!("samples per pixel: {}", samples_per_pixel),
+            format!("target error: {:?}", self.target_error),
+            format!("filter: {:?}, radius {:?}", self.filter, self.filter_radius),
+            format!("working space: {:?}", self.working_space),
+            format!("spectral: {}", self.spectral),
+        ].iter().map(|line| format!("{}\n", line)).collect()
+    }
+
+}
@@ -0,0 +1,353 @@
+use crate::{
+            vectors::*,
+            rays::Ray,
+            hittable::HitRecord,
+            materials::*,
+            microfacet::*,
+            onb::*,
+            texture::*,
+            colors::luminance,
+            random::random_f32,
+            spectrum,
+};
+use std::{sync::Arc, f32::consts::PI};
+
+
+// the roughest the specular & transmission lobes get to be, smoother than this they'd turn specular
+const MIN_ALPHA: f32 = 0.001;
+
+
+// disney's principled bsdf (burley, "physically based shading at disney" & its 2015 follow up
+// with transmission). one material for most things, described by the parameters artists think
+// in, each of them a texture. the lobes are a burley diffuse with sheen, an anisotropic ggx
+// specular, a gtr1 clearcoat & a rough dielectric for transmission. one lobe is sampled per
+// bounce, picked by how much it's expected to contribute, and weighted against all of them
+pub struct Principled {
+    pub base_color: Arc<dyn Texture>,
+    pub metallic: Arc<dyn Texture>,
+    pub roughness: Arc<dyn Texture>,
+    pub anisotropic: Arc<dyn Texture>, // 0 is isotropic, 1 stretches highlights along the tangent
+    pub specular: Arc<dyn Texture>, // 0.5 is a 4% reflectance, typical of dielectrics
+    pub specular_tint: Arc<dyn Texture>, // how much dielectric highlights take on the base color
+    pub sheen: Arc<dyn Texture>, // grazing retro-reflection of cloth
+    pub sheen_tint: Arc<dyn Texture>,
+    pub clearcoat: Arc<dyn Texture>,
+    pub clearcoat_gloss: Arc<dyn Texture>,
+    pub transmission: Arc<dyn Texture>,
+    pub ior: f32, // of the transmission lobe
+}
+
+
+// the parameters at one hit
+struct Parameters {
+    base: Color,
+    tint: Color, // the base color's hue at unit luminance
+    metallic: f32,
+    roughness: f32,
+    anisotropic: f32,
+    specular: f32,
+    specular_tint: f32,
+    sheen: f32,
+    sheen_tint: f32,
+    clearcoat: f32,
+    clearcoat_gloss: f32,
+    transmission: f32,
+}
+
+
+fn mix(a: Color, b: Color, t: f32) -> Color {
+    (1.0 - t) * a + t * b
+}
+
+
+fn schlick_weight(cos_theta: f32) -> f32 {
+    (1.0 - cos_theta).clamp(0.0, 1.0).powi(5)
+}
+
+
+fn schlick(f0: Color, cos_theta: f32) -> Color {
+    f0 + schlick_weight(cos_theta) * (Color::fromv(1.0) - f0)
+}
+
+
+// the clearcoat's "generalized trowbridge-reitz" distribution with gamma 1, which has a longer tail than ggx
+fn gtr1(cos_theta_h: f32, alpha: f32) -> f32 {
+    if alpha >= 1.0 {
+        return 1.0 / PI;
+    }
+
+    let a2 = alpha * alpha;
+    (a2 - 1.0) / (PI * a2.ln() * (1.0 + (a2 - 1.0) * cos_theta_h * cos_theta_h))
+}
+
+
+fn sample_gtr1(alpha: f32, u1: f32, u2: f32) -> Vector3 {
+    let a2 = alpha * alpha;
+    let cos_theta = ((1.0 - a2.powf(1.0 - u1)) / (1.0 - a2)).max(0.0).sqrt();
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * PI * u2;
+
+    Vector3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)
+}
+
+
+impl Parameters {
+
+    fn specular_distribution(&self) -> TrowbridgeReitz {
+        let aspect = (1.0 - 0.9 * self.anisotropic).sqrt();
+        let alpha = self.roughness * self.roughness;
+
+        TrowbridgeReitz::new((alpha / aspect).max(MIN_ALPHA), (alpha * aspect).max(MIN_ALPHA))
+    }
+
+    // reflectance at normal incidence, from a dielectric's few percent up to the base color of metals
+    fn specular_f0(&self) -> Color {
+        let dielectric = 0.08 * self.specular * mix(Color::fromv(1.0), self.tint, self.specular_tint);
+        mix(dielectric, self.base, self.metallic)
+    }
+
+    fn clearcoat_alpha(&self) -> f32 {
+        0.1 + (0.001 - 0.1) * self.clearcoat_gloss
+    }
+
+    fn transmission_lobe(&self, ior: f32) -> Dielectric {
+        let distribution = self.specular_distribution();
+        Dielectric::new(ior).with_roughness(distribution.alpha_x, distribution.alpha_y)
+    }
+
+    // chances of sampling the diffuse, specular, clearcoat & transmission lobes
+    fn lobe_probabilities(&self) -> [f32; 4] {
+        let weights = [
+            (1.0 - self.metallic) * (1.0 - self.transmission),
+            1.0,
+            0.25 * self.clearcoat,
+            (1.0 - self.metallic) * self.transmission,
+        ];
+        let total: f32 = weights.iter().sum();
+
+        [weights[0] / total, weights[1] / total, weights[2] / total, weights[3] / total]
+    }
+
+}
+
+
+impl Principled {
+
+    // a rough dielectric in base_color, the other parameters at disney's defaults
+    pub fn new(base_color: Arc<dyn Texture>) -> Principled {
+        Principled {
+            base_color,
+            metallic: constant(0.0),
+            roughness: constant(0.5),
+            anisotropic: constant(0.0),
+            specular: constant(0.5),
+            specular_tint: constant(0.0),
+            sheen: constant(0.0),
+            sheen_tint: constant(0.5),
+            clearcoat: constant(0.0),
+            clearcoat_gloss: constant(1.0),
+            transmission: constant(0.0),
+            ior: 1.5,
+        }
+    }
+
+    fn parameters(&self, rec: &HitRecord) -> Parameters {
+        let (u, v, p) = (rec.u, rec.v, &rec.p);
+        let scalar = |t: &Arc<dyn Texture>| t.scalar(u, v, p).clamp(0.0, 1.0);
+
+        let base = self.base_color.value(u, v, p);
+        let tint = if luminance(&base) > 0.0 {base / luminance(&base)} else {Color::fromv(1.0)};
+
+        Parameters {
+            base: spectrum::sampled(&base),
+            tint: spectrum::sampled(&tint),
+            metallic: scalar(&self.metallic),
+            roughness: scalar(&self.roughness),
+            anisotropic: scalar(&self.anisotropic),
+            specular: scalar(&self.specular),
+            specular_tint: scalar(&self.specular_tint),
+            sheen: scalar(&self.sheen),
+            sheen_tint: scalar(&self.sheen_tint),
+            clearcoat: scalar(&self.clearcoat),
+            clearcoat_gloss: scalar(&self.clearcoat_gloss),
+            transmission: scalar(&self.transmission),
+        }
+    }
+
+    // the reflection lobes, bsdf only, for wo & wi both above the surface
+    fn reflection(&self, parameters: &Parameters, wo: &Vector3, wi: &Vector3) -> Color {
+        let (diffuse, glossy) = self.reflection_lobes(parameters, wo, wi);
+        diffuse + glossy
+    }
+
+    // the same split into diffuse & sheen, & the specular & clearcoat lobes
+    fn reflection_lobes(&self, parameters: &Parameters, wo: &Vector3, wi: &Vector3) -> (Color, Color) {
+        let wh = (*wo + *wi).normalized();
+        let cos_d = Vector3::dot(wi, &wh);
+
+        // burley diffuse, with its retro-reflection at grazing angles on rough surfaces
+        let (fl, fv) = (schlick_weight(wi.z), schlick_weight(wo.z));
+        let rr = 2.0 * parameters.roughness * cos_d * cos_d;
+        let diffuse = parameters.base / PI * ((1.0 - 0.5 * fl) * (1.0 - 0.5 * fv) + rr * (fl + fv + fl * fv * (rr - 1.0)));
+        let sheen = parameters.sheen * schlick_weight(cos_d) * mix(Color::fromv(1.0), parameters.tint, parameters.sheen_tint);
+
+        let distribution = parameters.specular_distribution();
+        let specular = distribution.d(&wh) * distribution.g(wo, wi) / (4.0 * wo.z * wi.z) * schlick(parameters.specular_f0(), cos_d);
+
+        let clearcoat = 0.25 * parameters.clearcoat * gtr1(wh.z, parameters.clearcoat_alpha())
+            * TrowbridgeReitz::new(0.25, 0.25).g(wo, wi) * schlick(Color::fromv(0.04), cos_d).x / (4.0 * wo.z * wi.z);
+
+        ((1.0 - parameters.metallic) * ((1.0 - parameters.transmission) * diffuse + sheen), specular + Color::fromv(clearcoat))
+    }
+
+}
+
+
+impl Material for Principled {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Ray, Color)> {
+        let parameters = self.parameters(rec);
+        let transmission = parameters.transmission_lobe(self.ior);
+
+        // inside, only the boundary of the transmissive body is left
+        if !rec.front_face {
+            return sampled(self, r_in, rec, transmission.sample_rough(r_in, rec));
+        }
+
+        let (uvw, wo, _) = shading_frame(r_in, rec, &rec.normal);
+        let [p_diffuse, p_specular, p_clearcoat, _] = parameters.lobe_probabilities();
+        let u = random_f32();
+
+        let wi = if u < p_diffuse {
+            random_cosine_direction()
+        } else if u < p_diffuse + p_specular {
+            let wm = parameters.specular_distribution().sample_visible_normal(&wo, random_f32(), random_f32());
+            Vector3::reflect(&-wo, &wm)
+        } else if u < p_diffuse + p_specular + p_clearcoat {
+            let wh = sample_gtr1(parameters.clearcoat_alpha(), random_f32(), random_f32());
+            Vector3::reflect(&-wo, &wh)
+        } else {
+            return sampled(self, r_in, rec, transmission.sample_rough(r_in, rec));
+        };
+
+        // reflections that went below the surface don't count
+        if wi.z <= 0.0 {
+            return None;
+        }
+
+        sampled(self, r_in, rec, Ray::new(rec.p, uvw.local(wi.x, wi.y, wi.z), r_in.time))
+    }
+
+    fn is_specular(&self) -> bool {
+        false
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: &Vector3) -> Color {
+        let parameters = self.parameters(rec);
+        let transmission = parameters.transmission_lobe(self.ior);
+
+        if !rec.front_face {
+            return transmission.eval(r_in, rec, direction);
+        }
+
+        let (_, wo, wi) = shading_frame(r_in, rec, direction);
+        if wo.z <= 0.0 {
+            return Color::zeros();
+        }
+
+        if wi.z > 0.0 {
+            wi.z * self.reflection(&parameters, &wo, &wi)
+        } else {
+            let tint = Color::new(parameters.base.x.sqrt(), parameters.base.y.sqrt(), parameters.base.z.sqrt());
+            (1.0 - parameters.metallic) * parameters.transmission * tint * transmission.eval(r_in, rec, direction)
+        }
+    }
+
+    fn scattering_pdf(&self, r_in: &Ray, rec: &HitRecord, direction: &Vector3) -> f32 {
+        let parameters = self.parameters(rec);
+        let transmission = parameters.transmission_lobe(self.ior);
+
+        if !rec.front_face {
+            return transmission.scattering_pdf(r_in, rec, direction);
+        }
+
+        let (_, wo, wi) = shading_frame(r_in, rec, direction);
+        if wo.z <= 0.0 {
+            return 0.0;
+        }
+
+        let [p_diffuse, p_specular, p_clearcoat, p_transmission] = parameters.lobe_probabilities();
+        let mut pdf = p_transmission * transmission.scattering_pdf(r_in, rec, direction);
+
+        if wi.z > 0.0 {
+            let wh = (wo + wi).normalized();
+            let cos_o_h = Vector3::dot(&wo, &wh);
+
+            pdf += p_diffuse * wi.z / PI;
+            pdf += p_specular * parameters.specular_distribution().visible_d(&wo, &wh) / (4.0 * cos_o_h);
+            pdf += p_clearcoat * gtr1(wh.z, parameters.clearcoat_alpha()) * wh.z / (4.0 * cos_o_h);
+        }
+
+        pdf
+    }
+
+    // transmission counts as specular
+    fn diffuse_fraction(&self, r_in: &Ray, rec: &HitRecord, direction: &Vector3) -> f32 {
+        let (_, wo, wi) = shading_frame(r_in, rec, direction);
+        if !rec.front_face || wo.z <= 0.0 || wi.z <= 0.0 {
+            return 0.0;
+        }
+
+        let (diffuse, glossy) = self.reflection_lobes(&self.parameters(rec), &wo, &wi);
+        let total = luminance(&(diffuse + glossy));
+        if total > 0.0 {luminance(&diffuse) / total} else {0.0}
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::microfacet::tests::*;
+
+    fn principled(base: Color) -> Principled {
+        Principled::new(solid(base))
+    }
+
+    #[test]
+    fn every_lobe_samples_what_it_evaluates() {
+        let materials = [
+            principled(Color::new(0.8, 0.3, 0.2)),
+            Principled { roughness: constant(0.9), sheen: constant(1.0), ..principled(Color::new(0.2, 0.3, 0.8)) },
+            Principled { metallic: constant(1.0), roughness: constant(0.3), anisotropic: constant(0.8), ..principled(Color::new(0.9, 0.7, 0.3)) },
+            Principled { clearcoat: constant(1.0), clearcoat_gloss: constant(0.5), specular_tint: constant(1.0), ..principled(Color::new(0.1, 0.2, 0.6)) },
+            Principled { transmission: constant(1.0), roughness: constant(0.5), ..principled(Color::new(0.9, 0.9, 0.8)) },
+        ];
+
+        for material in materials.iter() {
+            for theta in [0.0, 40.0, 75.0].iter() {
+                let (r, rec) = hit_at(*theta, true);
+                assert_sampling_matches_eval(material, &r, &rec);
+            }
+        }
+    }
+
+    #[test]
+    fn textures_drive_the_parameters() {
+        // metal on one checker cell, plastic on the next
+        let metallic = Arc::new(Checker::new(constant(1.0), constant(0.0), 1.0));
+        let material = Principled { metallic, roughness: constant(0.2), ..principled(Color::new(0.9, 0.1, 0.1)) };
+        let (r, mut rec) = hit_at(0.0, true);
+        let direction = Vector3::new(0.1, 0.0, 1.0);
+
+        rec.p = Point3::new(0.5, 0.5, 0.5);
+        let metal = material.eval(&r, &rec, &direction);
+        rec.p = Point3::new(1.5, 0.5, 0.5);
+        let plastic = material.eval(&r, &rec, &direction);
+
+        // a red metal reflects red at normal incidence, red plastic has a white highlight on red diffuse
+        assert!(metal.y < 0.2 * metal.x);
+        assert!(plastic.y > 0.2 * metal.y);
+        assert!((metal - plastic).magnitude() > 0.1 * metal.magnitude());
+    }
+}
@@ -0,0 +1,68 @@
+use rand::{prelude::*, rngs::StdRng};
+use std::cell::RefCell;
+
+
+// where the random numbers rendering consumes come from. normally that's the thread's rng,
+// but an integrator can put its own source in place to replay or perturb whole paths
+pub trait RandomSource {
+    fn next(&mut self) -> f32; // in [0, 1)
+
+    // a path is about to draw its numbers for another bounce, so sources that hand out a dimension
+    // per number can line bounces up with theirs
+    fn start_bounce(&mut self, _bounce: u32) {}
+}
+
+
+thread_local! {
+    static SOURCE: RefCell<Option<Box<dyn RandomSource>>> = RefCell::new(None);
+}
+
+
+// runs f with every random number drawn from source
+pub fn with_source<R, F: FnOnce() -> R>(source: Box<dyn RandomSource>, f: F) -> R {
+    let previous = SOURCE.with(|s| s.borrow_mut().replace(source));
+    let result = f();
+    SOURCE.with(|s| *s.borrow_mut() = previous);
+
+    result
+}
+
+
+// runs f with every random number drawn from an rng seeded with seed, so whatever f builds comes
+// out the same every time it's given the same seed
+pub fn with_seed<R, F: FnOnce() -> R>(seed: u64, f: F) -> R {
+    with_source(Box::new(StdRng::seed_from_u64(seed)), f)
+}
+
+
+impl RandomSource for StdRng {
+    fn next(&mut self) -> f32 {
+        self.gen::<f32>()
+    }
+}
+
+
+pub fn random_f32() -> f32 {
+    SOURCE.with(|s| match s.borrow_mut().as_mut() {
+        Some(source) => source.next(),
+        None => thread_rng().gen::<f32>(),
+    })
+}
+
+
+pub fn start_bounce(bounce: u32) {
+    SOURCE.with(|s| if let Some(source) = s.borrow_mut().as_mut() {
+        source.start_bounce(bounce);
+    })
+}
+
+
+pub fn random_range(min: f32, max: f32) -> f32 {
+    min + (max - min) * random_f32()
+}
+
+
+// uniform in 0..n
+pub fn random_index(n: usize) -> usize {
+    ((random_f32() * n as f32) as usize).min(n - 1)
+}
@@ -1,6 +1,7 @@
 use crate::vectors::{Vector3, Point3};
 
 
+#[derive(Copy, Clone)]
 pub struct Ray {
     pub origin: Point3,
     pub direction: Vector3,
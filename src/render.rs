@@ -0,0 +1,398 @@
+use crate::{rays::Ray, scene::Scene, integrator::Integrator, film::*};
+use crate::{random::{random_f32, with_source}, sampler::*, denoise::Features, aov::*};
+use std::{sync::Arc, time::{Duration, Instant}};
+
+
+// samples every pixel takes before its error is looked at, & then takes per round while it's too high
+const ADAPTIVE_BATCH: u32 = 16;
+
+// no pixel takes more than this many times the average
+const MAX_SAMPLES_FACTOR: u32 = 16;
+
+// pixels are sampled a square tile of them at a time
+const TILE_SIZE: u32 = 16;
+
+// where the independent numbers the features & aovs draw start, far past any path's
+const FEATURE_DIMENSION: u32 = 1 << 24;
+
+
+// the per pixel loop most integrators render with, every sample's random numbers from sampler.
+// with a target error on the film, samples_per_pixel is the average a pixel gets instead, & with
+// a time budget it's ignored, pixels get as many as there's time for
+pub fn render<I: Integrator + ?Sized>(scene: &Scene, integrator: &I, film: &mut Film, sampler: &Arc<dyn Sampler>, samples_per_pixel: u32) {
+    if film.snapshots.is_some() || film.time_budget.is_some() {
+        render_progressive(scene, integrator, film, sampler, samples_per_pixel);
+        return;
+    }
+
+    let mut checkpointed = Instant::now();
+    let target_error = match film.target_error {
+        Some(target_error) => target_error,
+        None => {
+            let tiles = tiles(film);
+            for (k, tile) in tiles.iter().enumerate() {
+                eprintln!("\rTiles remaining - {}", tiles.len() - k);
+                // tiles are taken whole, so a resumed render's are either done or not started
+                let pixels: Vec<(u32, u32)> = tile_pixels(tile).collect();
+                let taken = pixels.iter().map(|(i, j)| film.samples(*i, *j)).min().unwrap_or(0);
+                if taken < samples_per_pixel {
+                    sample_tile(scene, integrator, film, sampler, tile, &pixels, samples_per_pixel - taken);
+                    checkpoint(film, &mut checkpointed);
+                }
+            }
+            return;
+        }
+    };
+
+    // rounds over the pixels still too noisy, until none are or the budget's spent. what's spent
+    // is what the film's taken, so a resumed render carries on with what's left
+    let mut budget = (samples_per_pixel as u64 * (film.width * film.height) as u64).saturating_sub(film.sample_count());
+    let max_samples = samples_per_pixel.saturating_mul(MAX_SAMPLES_FACTOR).max(ADAPTIVE_BATCH);
+
+    loop {
+        let is_noisy = |film: &Film, (i, j): &(u32, u32)| film.samples(*i, *j) < max_samples && film.relative_error(*i, *j) > target_error;
+        let noisy: Vec<Vec<(u32, u32)>> = tiles(film).iter().map(|tile| tile_pixels(tile).filter(|p| is_noisy(film, p)).collect()).collect();
+        let count = noisy.iter().map(|pixels| pixels.len()).sum::<usize>();
+
+        eprintln!("\rNoisy pixels remaining - {}", count);
+        if count == 0 || budget == 0 {
+            break;
+        }
+
+        let batch = (ADAPTIVE_BATCH as u64).min((budget / count as u64).max(1));
+        for (tile, pixels) in tiles(film).iter().zip(noisy.iter()) {
+            let pixels: Vec<(u32, u32)> = pixels.iter().take((budget / batch) as usize).cloned().collect();
+            if pixels.is_empty() {
+                continue;
+            }
+            sample_tile(scene, integrator, film, sampler, tile, &pixels, batch as u32);
+            budget -= batch * pixels.len() as u64;
+        }
+        // rounds are where the noisy pixels are picked again, from nothing but the film
+        checkpoint(film, &mut checkpointed);
+    }
+}
+
+
+// a pass of one sample per pixel over the whole image at a time, so there's all of it to look at
+// whenever the render's stopped, written out every so many passes or seconds if there are
+// snapshots. the last pass is left for the caller to write, with whatever else it does to the
+// finished image. with a time budget passes go on while the next looks like it'll fit in it, all of
+// them whole so every pixel ends up with as many samples
+fn render_progressive<I: Integrator + ?Sized>(scene: &Scene, integrator: &I, film: &mut Film, sampler: &Arc<dyn Sampler>, samples_per_pixel: u32) {
+    let started = Instant::now();
+    render_passes(scene, integrator, film, sampler, samples_per_pixel, &|| started.elapsed());
+}
+
+
+// render_progressive's passes, with the time budget spent by clock, the time since they started
+fn render_passes<I: Integrator + ?Sized>(scene: &Scene, integrator: &I, film: &mut Film, sampler: &Arc<dyn Sampler>, samples_per_pixel: u32, clock: &dyn Fn() -> Duration) {
+    let (snapshots, time_budget) = (film.snapshots.clone(), film.time_budget);
+    let tiles = tiles(film);
+    // a resumed render starts after the last pass it finished, with the time it took spent already
+    let done = tile_pixels(&(0, film.width, 0, film.height)).map(|(i, j)| film.samples(i, j)).min().unwrap_or(0);
+    let (mut last_pass, mut last_time, mut checkpointed, spent) = (done, Instant::now(), Instant::now(), film.render_time);
+    let finished = match time_budget {
+        Some(budget) => done > 0 && !next_pass_fits(budget, spent, done),
+        None => done >= samples_per_pixel,
+    };
+    if finished {
+        return;
+    }
+
+    let mut pass = done;
+    loop {
+        pass += 1;
+        match time_budget {
+            Some(budget) => eprintln!("\rPass {} - {:.0}s left", pass, budget.saturating_sub(film.render_time).as_secs_f32()),
+            None => eprintln!("\rPasses remaining - {}", samples_per_pixel - pass + 1),
+        }
+        for tile in tiles.iter() {
+            let pixels: Vec<(u32, u32)> = tile_pixels(tile).collect();
+            sample_tile(scene, integrator, film, sampler, tile, &pixels, 1);
+        }
+        film.render_time = spent + clock();
+
+        let last = match time_budget {
+            Some(budget) => !next_pass_fits(budget, film.render_time, pass),
+            None => pass >= samples_per_pixel,
+        };
+
+        if let Some(snapshots) = &snapshots {
+            let due = snapshots.every_passes.is_some_and(|n| pass - last_pass >= n)
+                || snapshots.every_seconds.is_some_and(|s| last_time.elapsed().as_secs_f32() >= s);
+            if due && !last {
+                if let Err(error) = film.save_ppm(&snapshots.path, &snapshots.transform) {
+                    eprintln!("couldn't write the snapshot to {}: {}", snapshots.path, error);
+                }
+                last_pass = pass;
+                last_time = Instant::now();
+            }
+        }
+        checkpoint(film, &mut checkpointed);
+
+        if last {
+            break;
+        }
+    }
+}
+
+
+// whether another pass, as long as the ones so far took on average, still fits in the budget
+fn next_pass_fits(budget: Duration, spent: Duration, passes: u32) -> bool {
+    spent + spent / passes <= budget
+}
+
+
+// saves the film to resume from if it's been long enough since it last was. only called between
+// the steps a render can pick up again from with nothing but the film
+fn checkpoint(film: &Film, last: &mut Instant) {
+    if let Some(checkpoints) = &film.checkpoints {
+        if last.elapsed().as_secs_f32() >= checkpoints.every_seconds {
+            if let Err(error) = film.save_checkpoint(&checkpoints.path) {
+                eprintln!("couldn't write the checkpoint to {}: {}", checkpoints.path, error);
+            }
+            *last = Instant::now();
+        }
+    }
+}
+
+
+// the tiles covering the image, top row first like the lines of the image, as x0, x1, y0, y1
+fn tiles(film: &Film) -> Vec<(u32, u32, u32, u32)> {
+    let (columns, rows) = (film.width.div_ceil(TILE_SIZE), film.height.div_ceil(TILE_SIZE));
+
+    (0..rows).rev()
+        .flat_map(|row| (0..columns).map(move |column| (column, row)))
+        .map(|(column, row)| (column * TILE_SIZE, ((column + 1) * TILE_SIZE).min(film.width), row * TILE_SIZE, ((row + 1) * TILE_SIZE).min(film.height)))
+        .collect()
+}
+
+
+fn tile_pixels(&(x0, x1, y0, y1): &(u32, u32, u32, u32)) -> impl Iterator<Item = (u32, u32)> {
+    (y0..y1).rev().flat_map(move |j| (x0..x1).map(move |i| (i, j)))
+}
+
+
+// count more samples for each of pixels, all within tile, carrying on from the sampler's index where
+// each left off. they're gathered in a tile of the film & merged into it once they're all taken
+fn sample_tile<I: Integrator + ?Sized>(scene: &Scene, integrator: &I, film: &mut Film, sampler: &Arc<dyn Sampler>,
+                                       &(x0, x1, y0, y1): &(u32, u32, u32, u32), pixels: &[(u32, u32)], count: u32) {
+    let mut tile = film.tile(x0, x1, y0, y1);
+    let filter_sampler = film.filter_sampler.clone();
+    let light_passes = !film.aovs.is_empty();
+    let independent: Arc<dyn Sampler> = Arc::new(Independent);
+
+    for &(i, j) in pixels {
+        let first = film.samples(i, j);
+
+        for index in first..first + count {
+            let samples = PixelSamples::new(sampler.clone(), (i, j), index);
+            let trace = || with_source(Box::new(samples), || {
+                // anywhere in the pixel, or around its center as the filter's importance sampled
+                let (dx, dy, weight) = match &filter_sampler {
+                    Some(filter_sampler) => filter_sampler.sample(),
+                    None => (random_f32() - 0.5, random_f32() - 0.5, 1.0),
+                };
+                let u = (i as f32 + 0.5 + dx) / ((film.width - 1) as f32);
+                let v = (j as f32 + 0.5 + dy) / ((film.height - 1) as f32);
+
+                let r: Ray = scene.camera.get_ray(u, v);
+                (dx, dy, weight, r, integrator.li(&r, scene, film))
+            });
+            // the light passes only cost anything while they're recorded
+            let ((dx, dy, weight, r, color), light_paths) = if light_passes {with_light_paths(trace)} else {(trace(), LightPaths::zeros())};
+            tile.add_sample(i, j, dx, dy, color, weight);
+
+            // off the sampler's numbers, so they're not spent on features, but just as repeatable
+            with_source(Box::new(PixelSamples::new(independent.clone(), (i, j), index).starting_at(FEATURE_DIMENSION)), || {
+                if film.gather_features {
+                    tile.add_features(i, j, Features::of_first_hit(scene, &r));
+                }
+                if light_passes {
+                    tile.add_aovs(i, j, &sample_aovs(&film.aovs, scene, &r, &light_paths, film.width, film.height));
+                }
+            });
+        }
+    }
+
+    film.merge_tile(&tile);
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{vectors::*, hittable_list::HittableList, aarect::XyRect, materials::Lambertian, sky::GradientSky,
+                camera::Camera, integrator::*, tonemap::*};
+    use std::cell::Cell;
+
+    // sky on the left half of the image, a diffuse card on the right, lit by it brighter from below
+    fn half_card_scene() -> Scene {
+        let mut world = HittableList::new();
+        world.add(Box::new(XyRect::new(0.0, 10.0, -10.0, 10.0, 0.0, Arc::new(Lambertian::new(Color::fromv(0.7))))));
+
+        let camera = Camera::new(Point3::new(0.0, 0.0, 5.0), Point3::zeros(), Vector3::newi(0, 1, 0), 40.0, 1.0, 0.0, 5.0, 0.0, 0.0);
+        Scene::new(world, HittableList::new(), Some(Box::new(GradientSky::new(Color::fromv(1.0), Color::zeros()))), camera)
+    }
+
+    #[test]
+    fn relative_error_follows_the_spread_of_the_samples() {
+        let mut film = Film::new(2, 1);
+        for k in 0..10_000 {
+            film.add_sample(0, 0, Color::fromv(1.0));
+            film.add_sample(1, 0, Color::fromv(if k % 2 == 0 {0.5} else {1.5}));
+        }
+
+        assert_eq!(film.relative_error(0, 0), 0.0);
+        // a standard deviation of 0.5 about a mean of 1, over 10000 samples
+        assert!((film.relative_error(1, 0) - 0.005).abs() < 1e-4);
+    }
+
+    #[test]
+    fn noisy_pixels_get_more_samples() {
+        let scene = half_card_scene();
+        let sampler: Arc<dyn Sampler> = Arc::new(Independent);
+        let mut film = Film::new(8, 8).with_target_error(0.02);
+        render(&scene, &PathTracer::new(5, 5), &mut film, &sampler, 64);
+
+        let (mut sky, mut card, mut total) = (0, 0, 0);
+        for j in 0..8 {
+            for i in 0..8 {
+                let n = film.samples(i, j);
+                total += n;
+                if i < 3 {sky += n} else if i > 4 {card += n}
+            }
+        }
+
+        // the sky converges after the first batch, & the card takes what it leaves
+        assert_eq!(sky, 3 * 8 * ADAPTIVE_BATCH);
+        assert!(card > 3 * 8 * 64);
+        assert!(total <= 8 * 8 * 64);
+    }
+
+    #[test]
+    fn progressive_renders_snapshot_whole_passes() {
+        let scene = half_card_scene();
+        let sampler: Arc<dyn Sampler> = Arc::new(Independent);
+        let path = std::env::temp_dir().join(format!("progressive_{}.ppm", std::process::id())).to_string_lossy().into_owned();
+        let snapshots = Snapshots { path: path.clone(), transform: OutputTransform::new(0.0, None, ToneOperator::Clamp), every_passes: Some(2), every_seconds: None };
+        let mut film = Film::new(20, 18).with_snapshots(snapshots);
+        render(&scene, &PathTracer::new(5, 5), &mut film, &sampler, 5);
+
+        // every pixel's had every pass, & the snapshot after the fourth is the last one written
+        assert!((0..18).all(|j| (0..20).all(|i| film.samples(i, j) == 5)));
+        let written = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(written.starts_with("P3\n# samples per pixel: 4\n20 18\n255\n"));
+        assert_eq!(written.lines().count(), 4 + 20 * 18);
+    }
+
+    #[test]
+    fn resumed_renders_match_uninterrupted_ones() {
+        let scene = half_card_scene();
+        let sampler: Arc<dyn Sampler> = Arc::new(Independent);
+        let integrator = MisPathTracer::new(5, 2);
+        let path = std::env::temp_dir().join(format!("checkpoint_{}", std::process::id())).to_string_lossy().into_owned();
+        let film = || Film::new(20, 18).with_features().with_aovs(vec![Aov::Albedo, Aov::DirectDiffuse]);
+        let equal = |a: Color, b: Color| (a.x, a.y, a.z) == (b.x, b.y, b.z);
+        let same = |a: &Film, b: &Film| (0..18).all(|j| (0..20).all(|i| {
+            equal(a.pixel(i, j), b.pixel(i, j)) && a.samples(i, j) == b.samples(i, j)
+                && equal(a.features(i, j).unwrap().albedo, b.features(i, j).unwrap().albedo) && equal(a.aov(0, i, j), b.aov(0, i, j))
+        }));
+
+        let mut whole = film();
+        render(&scene, &integrator, &mut whole, &sampler, 3);
+
+        // stopped after the first tile
+        let mut stopped = film();
+        let first = tiles(&stopped)[0];
+        let pixels: Vec<(u32, u32)> = tile_pixels(&first).collect();
+        sample_tile(&scene, &integrator, &mut stopped, &sampler, &first, &pixels, 3);
+        stopped.save_checkpoint(&path).unwrap();
+
+        let mut resumed = film();
+        resumed.load_checkpoint(&path).unwrap();
+        render(&scene, &integrator, &mut resumed, &sampler, 3);
+        assert!(same(&whole, &resumed));
+
+        // progressively, stopped after the second pass
+        let snapshots = Snapshots { path: format!("{}.ppm", path), transform: OutputTransform::new(0.0, None, ToneOperator::Clamp), every_passes: None, every_seconds: None };
+        let mut whole = film().with_snapshots(snapshots.clone());
+        render(&scene, &integrator, &mut whole, &sampler, 4);
+
+        let mut stopped = film().with_snapshots(snapshots.clone()).with_checkpoints(Checkpoints { path: path.clone(), every_seconds: 0.0, seed: 0, settings: String::new() });
+        render(&scene, &integrator, &mut stopped, &sampler, 2);
+        let mut resumed = film().with_snapshots(snapshots);
+        resumed.load_checkpoint(&path).unwrap();
+        render(&scene, &integrator, &mut resumed, &sampler, 4);
+        assert!(same(&whole, &resumed));
+
+        // checkpoints only fit a film made the same way
+        assert!(Film::new(20, 18).load_checkpoint(&path).is_err());
+        assert!(film().with_checkpoints(Checkpoints { path: path.clone(), every_seconds: 0.0, seed: 1, settings: String::new() }).load_checkpoint(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn tile_checkpoints_dont_resume_in_passes() {
+        let scene = half_card_scene();
+        let sampler: Arc<dyn Sampler> = Arc::new(Independent);
+        let path = std::env::temp_dir().join(format!("tile_checkpoint_{}", std::process::id())).to_string_lossy().into_owned();
+
+        // stopped after the first tile, whose pixels have all their samples & the rest none
+        let mut stopped = Film::new(20, 18);
+        let first = tiles(&stopped)[0];
+        let pixels: Vec<(u32, u32)> = tile_pixels(&first).collect();
+        sample_tile(&scene, &PathTracer::new(5, 5), &mut stopped, &sampler, &first, &pixels, 3);
+        stopped.save_checkpoint(&path).unwrap();
+
+        let snapshots = Snapshots { path: format!("{}.ppm", path), transform: OutputTransform::new(0.0, None, ToneOperator::Clamp), every_passes: None, every_seconds: None };
+        let progressive = Film::new(20, 18).with_snapshots(snapshots).load_checkpoint(&path).map_err(|error| error.to_string());
+        assert!(progressive.unwrap_err().ends_with("made with 'rendered in: tiles' where this render has 'rendered in: passes'"));
+        assert!(Film::new(20, 18).with_time_budget(Duration::from_secs(1)).load_checkpoint(&path).is_err());
+        assert!(Film::new(20, 18).load_checkpoint(&path).is_ok());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn time_budgets_render_whole_passes_until_they_run_out() {
+        let scene = half_card_scene();
+        let sampler: Arc<dyn Sampler> = Arc::new(Independent);
+        let integrator = PathTracer::new(5, 5);
+        let path = std::env::temp_dir().join(format!("budget_{}", std::process::id())).to_string_lossy().into_owned();
+        let film = |budget: u64| Film::new(20, 18).with_time_budget(Duration::from_millis(budget));
+        // every pass takes 10ms
+        let ticks = Cell::new(0);
+        let clock = || {
+            ticks.set(ticks.get() + 1);
+            Duration::from_millis(10 * ticks.get())
+        };
+        let passes = |film: &Film| {
+            let n = film.samples(0, 0);
+            assert!((0..18).all(|j| (0..20).all(|i| film.samples(i, j) == n)));
+            n
+        };
+
+        // past the samples per pixel it was given, until another pass would go over
+        let mut whole = film(100);
+        render_passes(&scene, &integrator, &mut whole, &sampler, 1, &clock);
+        assert_eq!((passes(&whole), whole.render_time), (10, Duration::from_millis(100)));
+        whole.save_checkpoint(&path).unwrap();
+
+        // resumed, the time the checkpoint spent counts against the budget
+        let mut resumed = film(100);
+        resumed.load_checkpoint(&path).unwrap();
+        ticks.set(0);
+        render_passes(&scene, &integrator, &mut resumed, &sampler, 1, &clock);
+        assert_eq!(passes(&resumed), 10);
+
+        let mut resumed = film(150);
+        resumed.load_checkpoint(&path).unwrap();
+        ticks.set(0);
+        render_passes(&scene, &integrator, &mut resumed, &sampler, 1, &clock);
+        assert_eq!((passes(&resumed), resumed.render_time), (15, Duration::from_millis(150)));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,468 @@
+use crate::random::RandomSource;
+use std::sync::Arc;
+
+
+// where the pixel loop's random numbers come from. a sample is a point in a high dimensional unit
+// cube, one coordinate per random number the path draws, & a sampler picks those points so the
+// samples in a pixel cover the cube more evenly than independent ones would. every sampler is
+// seeded per pixel, so neighbouring pixels don't share their patterns
+pub trait Sampler: Send + Sync {
+    // coordinate dimension of the index-th sample in pixel, in [0, 1)
+    fn get(&self, pixel: (u32, u32), index: u32, dimension: u32) -> f32;
+}
+
+
+// the dimensions the camera ray & spectral rendering use: the position in the pixel, the point on
+// the lens, the shutter time & the wavelengths, in that order
+pub const CAMERA_DIMENSIONS: u32 = 6;
+
+// the dimensions each bounce starts at are this far apart, so the same draw at the same bounce always
+// lands on the same dimension. a bounce that draws more spills into the next one's
+pub const BOUNCE_DIMENSIONS: u32 = 8;
+
+
+// the largest f32 below 1
+const ONE_MINUS_EPSILON: f32 = 0.999_999_94;
+
+
+
+// splitmix64's finalizer, which scatters the bits of v all over
+fn mix_bits(v: u64) -> u64 {
+    let mut h = v;
+    h = (h ^ (h >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    h = (h ^ (h >> 27)).wrapping_mul(0x94d049bb133111eb);
+    h ^ (h >> 31)
+}
+
+
+fn hash(values: &[u64]) -> u64 {
+    values.iter().fold(0x9e3779b97f4a7c15, |h, v| mix_bits(h ^ v).wrapping_add(0x9e3779b97f4a7c15))
+}
+
+
+fn pixel_hash(pixel: (u32, u32), values: &[u64]) -> u64 {
+    hash(&[&[pixel.0 as u64, pixel.1 as u64], values].concat())
+}
+
+
+fn to_unit(bits: u64) -> f32 {
+    (bits >> 40) as f32 / (1u64 << 24) as f32
+}
+
+
+// element i of a random permutation of 0..n picked by seed, without building it (kensler,
+// "correlated multi-jittered sampling")
+fn permutation_element(i: u32, n: u32, seed: u32) -> u32 {
+    let mut w = n - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+
+    let (mut i, p) = (i, seed);
+    loop {
+        i ^= p;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= p >> 16;
+        i ^= (i & w) >> 4;
+        i ^= p >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= p >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | p >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+
+        if i < n {
+            return i.wrapping_add(p) % n;
+        }
+    }
+}
+
+
+// owen scrambling of the bits of x, most significant first (burley, "practical hash-based owen
+// scrambling", after laine & karras)
+fn owen_scramble(x: u32, seed: u32) -> u32 {
+    let mut x = x.reverse_bits();
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+    x.reverse_bits()
+}
+
+
+
+// plain random numbers, what rendering had before samplers. hashed from the pixel, sample &
+// dimension, so any of them can be asked for in any order
+pub struct Independent;
+
+
+impl Sampler for Independent {
+    fn get(&self, pixel: (u32, u32), index: u32, dimension: u32) -> f32 {
+        to_unit(pixel_hash(pixel, &[index as u64, dimension as u64]))
+    }
+}
+
+
+
+// jittered strata: each pair of dimensions is cut into a grid of samples_per_pixel cells, as
+// square as it'll go, & each sample gets a cell of its own, in a different random order for every
+// pair. past samples_per_pixel it starts over with new orders
+pub struct Stratified {
+    pub samples_per_pixel: u32,
+    columns: u32,
+}
+
+
+impl Stratified {
+    pub fn new(samples_per_pixel: u32) -> Stratified {
+        let samples_per_pixel = samples_per_pixel.max(1);
+        let columns = (1..=samples_per_pixel).filter(|c| samples_per_pixel.is_multiple_of(*c) && c * c <= samples_per_pixel).max().unwrap_or(1);
+
+        Stratified { samples_per_pixel, columns }
+    }
+}
+
+
+impl Sampler for Stratified {
+    fn get(&self, pixel: (u32, u32), index: u32, dimension: u32) -> f32 {
+        let (n, pair) = (self.samples_per_pixel, dimension / 2);
+        let (round, i) = (index / n, index % n);
+        let cell = permutation_element(i, n, pixel_hash(pixel, &[pair as u64, round as u64]) as u32);
+
+        let jitter = to_unit(pixel_hash(pixel, &[index as u64, dimension as u64, 1]));
+        let (stratum, strata) = if dimension.is_multiple_of(2) {(cell % self.columns, self.columns)} else {(cell / self.columns, n / self.columns)};
+
+        ((stratum as f32 + jitter) / strata as f32).min(ONE_MINUS_EPSILON)
+    }
+}
+
+
+
+// the halton sequence, a radical inverse in the dimension's own prime base. owen scrambled, digit
+// by digit, with a seed per pixel & dimension. there are primes for the first HALTON_PRIMES.len()
+// dimensions, the rest get independent numbers
+pub struct Halton;
+
+
+const HALTON_PRIMES: [u32; 64] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53,
+    59, 61, 67, 71, 73, 79, 83, 89, 97, 101, 103, 107, 109, 113, 127, 131,
+    137, 139, 149, 151, 157, 163, 167, 173, 179, 181, 191, 193, 197, 199, 211, 223,
+    227, 229, 233, 239, 241, 251, 257, 263, 269, 271, 277, 281, 283, 293, 307, 311,
+];
+
+
+// the digits of a in base, mirrored around the point, each one permuted by a hash of those before
+// it. the digits past a's own are zeros, but get permuted too, which fills in below its precision
+fn owen_radical_inverse(a: u32, base: u32, seed: u64) -> f32 {
+    let digits = (24.0 / (base as f32).log2()).ceil() as u32;
+    let (mut a, mut reversed, mut inverse_base_m) = (a as u64, 0u64, 1.0f64);
+
+    for _ in 0..digits {
+        let digit = (a % base as u64) as u32;
+        a /= base as u64;
+
+        let digit = permutation_element(digit, base, mix_bits(seed ^ reversed) as u32);
+        reversed = reversed * base as u64 + digit as u64;
+        inverse_base_m /= base as f64;
+    }
+
+    ((reversed as f64 * inverse_base_m) as f32).min(ONE_MINUS_EPSILON)
+}
+
+
+impl Sampler for Halton {
+    fn get(&self, pixel: (u32, u32), index: u32, dimension: u32) -> f32 {
+        match HALTON_PRIMES.get(dimension as usize) {
+            Some(base) => owen_radical_inverse(index, *base, pixel_hash(pixel, &[dimension as u64])),
+            None => Independent.get(pixel, index, dimension),
+        }
+    }
+}
+
+
+
+// sobol's sequence, owen scrambled with a seed per pixel & dimension, & the order of the samples
+// shuffled per pixel too. the first dimension is van der corput's sequence, the rest come from joe &
+// kuo's primitive polynomials & initial direction numbers (degree, coefficients, m_1 .. m_degree).
+// dimensions past them are padded, as PaddedSobol does, so deep bounces stay stratified in pairs
+pub struct Sobol {
+    directions: Vec<[u32; 32]>,
+    padded: PaddedSobol,
+}
+
+
+const SOBOL_POLYNOMIALS: [(u32, u32, &[u32]); 20] = [
+    (1, 0, &[1]),
+    (2, 1, &[1, 3]),
+    (3, 1, &[1, 3, 1]),
+    (3, 2, &[1, 1, 1]),
+    (4, 1, &[1, 1, 3, 3]),
+    (4, 4, &[1, 3, 5, 13]),
+    (5, 2, &[1, 1, 5, 5, 17]),
+    (5, 4, &[1, 1, 5, 5, 5]),
+    (5, 7, &[1, 1, 7, 11, 19]),
+    (5, 11, &[1, 1, 5, 1, 1]),
+    (5, 13, &[1, 1, 1, 3, 11]),
+    (5, 14, &[1, 3, 5, 5, 31]),
+    (6, 1, &[1, 3, 3, 9, 7, 49]),
+    (6, 13, &[1, 1, 1, 15, 21, 21]),
+    (6, 16, &[1, 3, 1, 13, 27, 49]),
+    (6, 19, &[1, 1, 1, 15, 7, 5]),
+    (6, 22, &[1, 3, 1, 15, 13, 25]),
+    (6, 25, &[1, 1, 5, 5, 19, 61]),
+    (7, 1, &[1, 3, 7, 11, 23, 15, 103]),
+    (7, 4, &[1, 3, 7, 13, 13, 15, 69]),
+];
+
+
+// the direction numbers of one dimension, bit 31 being the first
+fn sobol_directions(degree: u32, coefficients: u32, m: &[u32]) -> [u32; 32] {
+    let mut v = [0u32; 32];
+    let s = degree as usize;
+
+    for i in 0..32 {
+        v[i] = if i < s {
+            m[i] << (31 - i)
+        } else {
+            let mut d = v[i - s] ^ (v[i - s] >> s);
+            for k in 1..s {
+                if (coefficients >> (s - 1 - k)) & 1 == 1 {
+                    d ^= v[i - k];
+                }
+            }
+            d
+        };
+    }
+    v
+}
+
+
+fn van_der_corput_directions() -> [u32; 32] {
+    let mut v = [0u32; 32];
+    for (i, d) in v.iter_mut().enumerate() {
+        *d = 1 << (31 - i);
+    }
+    v
+}
+
+
+// the unscrambled point, the xor of the directions of index's set bits
+fn sobol_bits(directions: &[u32; 32], index: u32) -> u32 {
+    (0..32).filter(|bit| (index >> bit) & 1 == 1).fold(0, |x, bit| x ^ directions[bit])
+}
+
+
+fn bits_to_unit(bits: u32) -> f32 {
+    (bits >> 8) as f32 / (1u32 << 24) as f32
+}
+
+
+impl Sobol {
+    pub fn new() -> Sobol {
+        let mut directions = vec![van_der_corput_directions()];
+        directions.extend(SOBOL_POLYNOMIALS.iter().map(|(degree, coefficients, m)| sobol_directions(*degree, *coefficients, m)));
+
+        Sobol { directions, padded: PaddedSobol::new() }
+    }
+}
+
+
+impl Sampler for Sobol {
+    fn get(&self, pixel: (u32, u32), index: u32, dimension: u32) -> f32 {
+        let directions = match self.directions.get(dimension as usize) {
+            Some(directions) => directions,
+            None => return self.padded.get(pixel, index, dimension),
+        };
+
+        let shuffled = owen_scramble(index, pixel_hash(pixel, &[]) as u32);
+        let bits = sobol_bits(directions, shuffled);
+        bits_to_unit(owen_scramble(bits, pixel_hash(pixel, &[dimension as u64 + 1]) as u32))
+    }
+}
+
+
+
+// the first two dimensions of sobol's sequence over & over, one copy for each pair of dimensions,
+// every copy with its own scrambling & its own shuffled order of the samples (burley's shuffled,
+// scrambled sobol). pairs are well stratified & there's no end to the dimensions, at the price of
+// the pairs not being stratified against each other
+pub struct PaddedSobol {
+    directions: [[u32; 32]; 2],
+}
+
+
+impl PaddedSobol {
+    pub fn new() -> PaddedSobol {
+        let (degree, coefficients, m) = SOBOL_POLYNOMIALS[0];
+        PaddedSobol { directions: [van_der_corput_directions(), sobol_directions(degree, coefficients, m)] }
+    }
+}
+
+
+impl Sampler for PaddedSobol {
+    fn get(&self, pixel: (u32, u32), index: u32, dimension: u32) -> f32 {
+        let pair = dimension / 2;
+        let shuffled = owen_scramble(index, pixel_hash(pixel, &[pair as u64]) as u32);
+        let bits = sobol_bits(&self.directions[(dimension % 2) as usize], shuffled);
+        bits_to_unit(owen_scramble(bits, pixel_hash(pixel, &[pair as u64, dimension as u64 + 1]) as u32))
+    }
+}
+
+
+
+// one sample's worth of a sampler, as the random numbers rendering draws. each draw takes the next
+// dimension, & bounces jump ahead to their own block of them. it never goes back, so no two draws
+// in a sample can share a dimension
+pub struct PixelSamples {
+    pub sampler: Arc<dyn Sampler>,
+    pub pixel: (u32, u32),
+    pub index: u32,
+    dimension: u32,
+}
+
+
+impl PixelSamples {
+    pub fn new(sampler: Arc<dyn Sampler>, pixel: (u32, u32), index: u32) -> PixelSamples {
+        PixelSamples { sampler, pixel, index, dimension: 0 }
+    }
+
+    // draws from dimension on, for numbers that mustn't overlap the ones a path draws
+    pub fn starting_at(mut self, dimension: u32) -> PixelSamples {
+        self.dimension = dimension;
+        self
+    }
+}
+
+
+impl RandomSource for PixelSamples {
+    fn next(&mut self) -> f32 {
+        let u = self.sampler.get(self.pixel, self.index, self.dimension);
+        self.dimension += 1;
+        u
+    }
+
+    fn start_bounce(&mut self, bounce: u32) {
+        self.dimension = self.dimension.max(CAMERA_DIMENSIONS + bounce * BOUNCE_DIMENSIONS);
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{random::{with_source, random_f32, start_bounce}, camera::Camera, vectors::*};
+
+    fn samplers() -> Vec<(&'static str, Box<dyn Sampler>)> {
+        vec![
+            ("independent", Box::new(Independent)),
+            ("stratified", Box::new(Stratified::new(64))),
+            ("halton", Box::new(Halton)),
+            ("sobol", Box::new(Sobol::new())),
+            ("padded sobol", Box::new(PaddedSobol::new())),
+        ]
+    }
+
+    // how many of the first n samples land in each cell of a columns by rows grid over two dimensions
+    fn cell_counts(sampler: &dyn Sampler, pixel: (u32, u32), dimensions: (u32, u32), n: u32, columns: u32, rows: u32) -> Vec<u32> {
+        let mut counts = vec![0; (columns * rows) as usize];
+        for index in 0..n {
+            let x = (sampler.get(pixel, index, dimensions.0) * columns as f32) as u32;
+            let y = (sampler.get(pixel, index, dimensions.1) * rows as f32) as u32;
+            counts[(y * columns + x) as usize] += 1;
+        }
+        counts
+    }
+
+    #[test]
+    fn samples_are_uniform() {
+        for (name, sampler) in samplers().iter() {
+            for dimension in [0, 1, 5, 17, 40, 100].iter() {
+                let mut sum = 0.0;
+                for index in 0..256 {
+                    let u = sampler.get((3, 7), index, *dimension);
+                    assert!((0.0..1.0).contains(&u), "{} gave {}", name, u);
+                    sum += u;
+                }
+                assert!((sum / 256.0 - 0.5).abs() < 0.05, "{} has mean {} in dimension {}", name, sum / 256.0, dimension);
+            }
+        }
+    }
+
+    #[test]
+    fn low_discrepancy_samples_are_stratified() {
+        // every elementary interval of area 1 / 64 holds exactly one of the first 64 points
+        let sobol = Sobol::new();
+        for pixel in [(0, 0), (5, 9)].iter() {
+            for log_columns in 0..=6 {
+                let (columns, rows) = (1 << log_columns, 1 << (6 - log_columns));
+                assert!(cell_counts(&sobol, *pixel, (0, 1), 64, columns, rows).iter().all(|c| *c == 1));
+                assert!(cell_counts(&PaddedSobol::new(), *pixel, (6, 7), 64, columns, rows).iter().all(|c| *c == 1));
+            }
+
+            // & the halton points in a 2^3 by 3^2 grid
+            assert!(cell_counts(&Halton, *pixel, (0, 1), 72, 8, 9).iter().all(|c| *c == 1));
+
+            // stratified ones fill the grid they were made for
+            assert!(cell_counts(&Stratified::new(64), *pixel, (2, 3), 64, 8, 8).iter().all(|c| *c == 1));
+            assert!(cell_counts(&Stratified::new(12), *pixel, (0, 1), 12, 3, 4).iter().all(|c| *c == 1));
+        }
+
+        // every dimension of sobol's sequence is stratified on its own
+        for dimension in 0..sobol.directions.len() as u32 {
+            assert!(cell_counts(&sobol, (1, 2), (dimension, dimension), 1024, 1024, 1).iter().all(|c| *c == 1), "dimension {}", dimension);
+        }
+
+        // & past them it carries on in stratified pairs
+        let bounce = CAMERA_DIMENSIONS + 3 * BOUNCE_DIMENSIONS;
+        assert!(bounce as usize > sobol.directions.len());
+        assert!(cell_counts(&sobol, (1, 2), (bounce, bounce + 1), 64, 8, 8).iter().all(|c| *c == 1));
+    }
+
+    #[test]
+    fn bounces_start_on_their_own_dimensions() {
+        let sampler: Arc<dyn Sampler> = Arc::new(Sobol::new());
+        let expected = |dimension| sampler.get((2, 3), 5, dimension);
+
+        let drawn = with_source(Box::new(PixelSamples::new(sampler.clone(), (2, 3), 5)), || {
+            let camera = [random_f32(), random_f32()];
+            start_bounce(0);
+            let first = random_f32();
+            start_bounce(1);
+            let second = random_f32();
+            (camera, first, second)
+        });
+
+        assert_eq!(drawn, ([expected(0), expected(1)], expected(CAMERA_DIMENSIONS), expected(CAMERA_DIMENSIONS + BOUNCE_DIMENSIONS)));
+    }
+
+    #[test]
+    fn camera_rays_take_the_same_dimensions_with_the_shutter_open_or_not() {
+        let sampler: Arc<dyn Sampler> = Arc::new(Sobol::new());
+        let wavelength = sampler.get((2, 3), 5, CAMERA_DIMENSIONS - 1);
+
+        for (aperture, time1) in [(0.0, 0.0), (0.1, 0.0), (0.0, 1.0), (0.1, 1.0)].iter() {
+            let camera = Camera::new(Point3::zeros(), Point3::newi(0, 0, -1), Vector3::newi(0, 1, 0), 40.0, 1.0, *aperture, 1.0, 0.0, *time1);
+
+            // the pixel position, then the ray, then whatever comes after the camera's dimensions
+            let drawn = with_source(Box::new(PixelSamples::new(sampler.clone(), (2, 3), 5)), || {
+                let (s, t) = (random_f32(), random_f32());
+                camera.get_ray(s, t);
+                random_f32()
+            });
+            assert_eq!(drawn, wavelength);
+        }
+    }
+}
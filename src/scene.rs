@@ -0,0 +1,719 @@
+use crate::{
+            vectors::*,
+            rays::Ray,
+            hittable::*,
+            hittable_list::HittableList,
+            sphere::Sphere,
+            aarect::*,
+            moving_sphere::MovingSphere,
+            materials::*,
+            camera::Camera,
+            sky::*,
+            random::*,
+            spectrum,
+            principled::Principled,
+            texture::*,
+            layered::*,
+            subsurface::Subsurface,
+            bump::*,
+            mesh::Mesh,
+            cutout::Cutout,
+            thin::*,
+            color_space::*,
+};
+use std::{collections::HashMap, sync::Arc, f32::consts::PI};
+
+
+pub struct Scene {
+    pub world: HittableList,
+    pub lights: HittableList, // stand-ins for the emitters in world, only used to pick directions towards them
+    pub sky: Option<Box<dyn Sky>>, // None is a black background
+    pub camera: Camera,
+    material_ids: HashMap<usize, u32>, // by the address of each material in world
+}
+
+
+fn material_address(material: &Arc<dyn Material>) -> usize {
+    Arc::as_ptr(material) as *const () as usize
+}
+
+
+impl Scene {
+
+    pub fn new(world: HittableList, lights: HittableList, sky: Option<Box<dyn Sky>>, camera: Camera) -> Scene {
+        let mut material_ids = HashMap::new();
+        for material in world.materials() {
+            let next = material_ids.len() as u32 + 1;
+            material_ids.entry(material_address(&material)).or_insert(next);
+        }
+
+        Scene { world, lights, sky, camera, material_ids }
+    }
+
+    // the materials in world numbered from 1, in the order they were added, so the numbers are the
+    // same every time the scene is built. 0 for materials added to world after the scene was made
+    pub fn material_id(&self, material: &Arc<dyn Material>) -> u32 {
+        self.material_ids.get(&material_address(material)).copied().unwrap_or(0)
+    }
+
+    pub fn background(&self, direction: &Vector3) -> Color {
+        match &self.sky {
+            Some(sky) => spectrum::sampled(&sky.radiance(direction)),
+            None => Color::zeros(),
+        }
+    }
+
+    // emitted radiance arriving back along r, zero when something that doesn't glow is in the way
+    pub fn incoming_radiance(&self, r: &Ray) -> Color {
+        let mut rec = HitRecord::new();
+
+        if self.world.hit(r, 0.001, f32::INFINITY, &mut rec) {
+            rec.mat.emitted(&rec)
+        } else {
+            self.background(&r.direction)
+        }
+    }
+
+    fn sky_sample_probability(&self) -> f32 {
+        match (&self.sky, self.lights.is_empty()) {
+            (None, _) => 0.0,
+            (Some(_), true) => 1.0,
+            (Some(_), false) => 0.5,
+        }
+    }
+
+    // a direction from origin towards the sky or one of the lights
+    pub fn sample_light_direction(&self, origin: &Point3) -> Option<Vector3> {
+        let p_sky = self.sky_sample_probability();
+
+        match &self.sky {
+            Some(sky) if random_f32() < p_sky => Some(sky.sample_direction().0),
+            _ if !self.lights.is_empty() => Some(self.lights.random(origin)),
+            _ => None,
+        }
+    }
+
+    // solid angle pdf of sample_light_direction picking direction
+    pub fn light_pdf(&self, origin: &Point3, direction: &Vector3) -> f32 {
+        let p_sky = self.sky_sample_probability();
+        let sky_pdf = match &self.sky {
+            Some(sky) => sky.pdf(direction),
+            None => 0.0,
+        };
+
+        p_sky * sky_pdf + (1.0 - p_sky) * self.lights.pdf_value(origin, direction)
+    }
+
+}
+
+
+
+pub fn random_scene(aspect_ratio: f32, seed: u64) -> Scene {
+    let mut world = HittableList::new();
+
+    let ground = Arc::new(Lambertian::new(Color::fromv(0.5)));
+    world.add(Box::new(Sphere::new(Point3::new( 0.0, -1000.0, 0.0), 1000.0, ground)));
+
+    // let mut sphere_material: Arc<dyn Material>;
+
+    // the small spheres, scattered & colored the same way every time for the same seed
+    with_seed(seed, || {
+        for i in -11..11 {
+            for j in -11..11 {
+                let choose_mat = random_f32();
+                let center = Point3::new(i as f32 + 0.9 * random_f32(), 0.2, j as f32 + 0.9 * random_f32());
+
+                if (center - Point3::new(4.0, 0.2, 0.0)).magnitude() > 0.9 {
+
+                    if choose_mat < 0.8 {
+                        // diffuse
+                        let albedo = Color::random() * Color::random();
+                        let sphere_material = Arc::new(Lambertian::new(albedo));
+                        let center2 = center + Vector3::new(0.0, random_range(0.0, 0.5), 0.0);
+                        world.add(Box::new(MovingSphere::new(center, center2, 0.0, 0.1, 0.2, sphere_material)));
+
+                    } else if choose_mat < 0.95 {
+                        // metal
+                        let albedo = Color::random_by_range(0.5, 1.0);
+                        let fuzz = random_range(0.0, 0.5);
+
+                        let sphere_material = Arc::new(Metal::new(albedo, fuzz));
+                        world.add(Box::new(Sphere::new(center, 0.2, sphere_material)));
+
+                    } else {
+                        // glass
+                        let sphere_material = Arc::new(Dielectric::new(1.5));
+                        world.add(Box::new(Sphere::new(center, 0.2, sphere_material)));
+                    }
+
+                    // world.add(Box::new(Sphere::new(center, 0.2, sphere_material)));
+                }
+
+            }
+        }
+    });
+
+
+    let m1 = Arc::new(Dielectric::new(1.5));
+    world.add(Box::new(Sphere::new(Point3::newi(0, 1, 0), 1.0, m1)));
+
+    let m2 = Arc::new(Lambertian::new(Color::new(0.4, 0.2, 0.1)));
+    world.add(Box::new(Sphere::new(Point3::newi(-4, 1, 0), 1.0, m2)));
+
+    let m3 = Arc::new(Metal::new(Color::new(0.7, 0.6, 0.5), 0.0));
+    world.add(Box::new(Sphere::new(Point3::newi(4, 1, 0), 1.0, m3)));
+
+
+    // camera
+    let look_from = Point3::newi(13, 2, 3);
+    let look_at = Point3::zeros();
+    let vup = Vector3::newi(0, 1, 0);
+    let vfov = 20.0;
+    let aperture = 0.1;
+    let focus_dist = 10.0; //(look_from - look_at).magnitude();
+
+    let cam = Camera::new(look_from, look_at, vup, vfov, aspect_ratio, aperture, focus_dist, 0.0, 0.1);
+
+    let sky = GradientSky::new(Color::fromv(1.0), Color::new(0.5, 0.7, 1.0));
+
+    Scene::new(world, HittableList::new(), Some(Box::new(sky)), cam)
+}
+
+
+
+// glass & diffuse spheres on a diffuse floor under one small lamp, everything else dark
+pub fn caustics_scene(aspect_ratio: f32) -> Scene {
+    let mut world = HittableList::new();
+    let mut lights = HittableList::new();
+
+    let ground = Arc::new(Lambertian::new(Color::fromv(0.6)));
+    world.add(Box::new(Sphere::new(Point3::new(0.0, -1000.0, 0.0), 1000.0, ground)));
+
+    let glass = Arc::new(Dielectric::new(1.5));
+    world.add(Box::new(Sphere::new(Point3::newi(0, 1, 0), 1.0, glass.clone())));
+    world.add(Box::new(Sphere::new(Point3::new(1.6, 0.4, 1.5), 0.4, glass)));
+
+    let diffuse = Arc::new(Lambertian::new(Color::new(0.7, 0.2, 0.2)));
+    world.add(Box::new(Sphere::new(Point3::new(-2.2, 0.7, 0.8), 0.7, diffuse)));
+
+    let metal = Arc::new(Metal::new(Color::new(0.8, 0.8, 0.9), 0.05));
+    world.add(Box::new(Sphere::new(Point3::new(2.2, 0.7, -0.8), 0.7, metal)));
+
+    let lamp = Arc::new(DiffuseLight::new(Color::fromv(150.0)));
+    let (lamp_center, lamp_radius) = (Point3::new(-1.0, 5.0, 1.0), 0.4);
+    world.add(Box::new(Sphere::new(lamp_center, lamp_radius, lamp.clone())));
+    lights.add(Box::new(Sphere::new(lamp_center, lamp_radius, lamp)));
+
+    let look_from = Point3::newi(0, 3, 9);
+    let look_at = Point3::new(0.0, 0.8, 0.0);
+    let focus_dist = (look_from - look_at).magnitude();
+    let cam = Camera::new(look_from, look_at, Vector3::newi(0, 1, 0), 35.0, aspect_ratio, 0.0, focus_dist, 0.0, 0.0);
+
+    Scene::new(world, lights, None, cam)
+}
+
+
+
+// a closed room whose only light is a lamp outside, shining in through a small window
+pub fn window_scene(aspect_ratio: f32) -> Scene {
+    let mut world = HittableList::new();
+    let mut lights = HittableList::new();
+
+    let white = Arc::new(Lambertian::new(Color::fromv(0.73)));
+    world.add(Box::new(XzRect::new(-3.0, 3.0, -3.0, 3.0, 0.0, white.clone()))); // floor
+    world.add(Box::new(XzRect::new(-3.0, 3.0, -3.0, 3.0, 3.0, white.clone()))); // ceiling
+    world.add(Box::new(XyRect::new(-3.0, 3.0, 0.0, 3.0, -3.0, white.clone()))); // back
+    world.add(Box::new(XyRect::new(-3.0, 3.0, 0.0, 3.0, 3.0, white.clone()))); // front, behind the camera
+    world.add(Box::new(YzRect::new(0.0, 3.0, -3.0, 3.0, -3.0, Arc::new(Lambertian::new(Color::new(0.65, 0.05, 0.05))))));
+
+    // the wall with the window, in four pieces around the opening
+    let (y0, y1, z0, z1) = (1.6, 2.0, -0.3, 0.3);
+    world.add(Box::new(YzRect::new(0.0, y0, -3.0, 3.0, 3.0, white.clone())));
+    world.add(Box::new(YzRect::new(y1, 3.0, -3.0, 3.0, 3.0, white.clone())));
+    world.add(Box::new(YzRect::new(y0, y1, -3.0, z0, 3.0, white.clone())));
+    world.add(Box::new(YzRect::new(y0, y1, z1, 3.0, 3.0, white)));
+
+    world.add(Box::new(Sphere::new(Point3::new(-1.0, 0.7, -1.2), 0.7, Arc::new(Dielectric::new(1.5)))));
+    world.add(Box::new(Sphere::new(Point3::new(0.8, 0.5, -0.5), 0.5, Arc::new(Lambertian::new(Color::new(0.12, 0.45, 0.15))))));
+
+    let lamp = Arc::new(DiffuseLight::new(Color::fromv(400.0)));
+    let (lamp_center, lamp_radius) = (Point3::new(6.0, 4.0, 0.0), 0.5);
+    world.add(Box::new(Sphere::new(lamp_center, lamp_radius, lamp.clone())));
+    lights.add(Box::new(Sphere::new(lamp_center, lamp_radius, lamp)));
+
+    let look_from = Point3::new(-0.5, 1.6, 2.8);
+    let look_at = Point3::new(0.5, 0.8, -1.0);
+    let focus_dist = (look_from - look_at).magnitude();
+    let cam = Camera::new(look_from, look_at, Vector3::newi(0, 1, 0), 70.0, aspect_ratio, 0.0, focus_dist, 0.0, 0.0);
+
+    Scene::new(world, lights, None, cam)
+}
+
+
+
+// diamond, crown & flint glass balls under a small, bright lamp, the caustics they throw on the
+// floor split into rainbows when rendered with --spectral
+pub fn dispersion_scene(aspect_ratio: f32) -> Scene {
+    let mut world = HittableList::new();
+    let mut lights = HittableList::new();
+
+    let ground = Arc::new(Lambertian::new(Color::fromv(0.8)));
+    world.add(Box::new(Sphere::new(Point3::new(0.0, -1000.0, 0.0), 1000.0, ground)));
+
+    let diamond = Arc::new(Dielectric::sellmeier(DIAMOND_SELLMEIER_B, DIAMOND_SELLMEIER_C));
+    world.add(Box::new(Sphere::new(Point3::new(-1.2, 1.0, 0.0), 1.0, diamond)));
+
+    let crown_glass = Arc::new(Dielectric::sellmeier(BK7_SELLMEIER_B, BK7_SELLMEIER_C));
+    world.add(Box::new(Sphere::new(Point3::new(1.2, 1.0, 0.0), 1.0, crown_glass)));
+
+    // cauchy fit of a dense flint
+    let flint_glass = Arc::new(Dielectric::cauchy(1.7474, 0.0130));
+    world.add(Box::new(Sphere::new(Point3::new(0.0, 0.4, 1.8), 0.4, flint_glass)));
+
+    let lamp = Arc::new(DiffuseLight::new(Color::fromv(2000.0)));
+    let (lamp_center, lamp_radius) = (Point3::new(-3.0, 6.0, -4.0), 0.15);
+    world.add(Box::new(Sphere::new(lamp_center, lamp_radius, lamp.clone())));
+    lights.add(Box::new(Sphere::new(lamp_center, lamp_radius, lamp)));
+
+    let look_from = Point3::newi(0, 4, 8);
+    let look_at = Point3::new(0.0, 0.6, 0.0);
+    let focus_dist = (look_from - look_at).magnitude();
+    let cam = Camera::new(look_from, look_at, Vector3::newi(0, 1, 0), 35.0, aspect_ratio, 0.0, focus_dist, 0.0, 0.0);
+
+    Scene::new(world, lights, None, cam)
+}
+
+
+
+// rough & anisotropic metals next to frosted glass, lit by a lamp & the old gradient sky
+pub fn rough_scene(aspect_ratio: f32) -> Scene {
+    let mut world = HittableList::new();
+    let mut lights = HittableList::new();
+
+    let ground = Arc::new(Lambertian::new(Color::new(0.4, 0.4, 0.45)));
+    world.add(Box::new(Sphere::new(Point3::new(0.0, -1000.0, 0.0), 1000.0, ground)));
+
+    let gold = Arc::new(Conductor::gold().with_roughness(0.2, 0.2));
+    world.add(Box::new(Sphere::new(Point3::new(-3.3, 1.0, 0.0), 1.0, gold)));
+
+    // brushed, much rougher across the brush strokes than along them
+    let copper = Arc::new(Conductor::copper().with_roughness(0.4, 0.05));
+    world.add(Box::new(Sphere::new(Point3::new(-1.1, 1.0, 0.0), 1.0, copper)));
+
+    let aluminium = Arc::new(Conductor::aluminium());
+    world.add(Box::new(Sphere::new(Point3::new(1.1, 1.0, 0.0), 1.0, aluminium)));
+
+    let frosted = Arc::new(Dielectric::new(1.5).with_roughness(0.3, 0.3));
+    world.add(Box::new(Sphere::new(Point3::new(3.3, 1.0, 0.0), 1.0, frosted)));
+
+    let lamp = Arc::new(DiffuseLight::new(Color::fromv(40.0)));
+    let (lamp_center, lamp_radius) = (Point3::new(0.0, 6.0, 4.0), 0.8);
+    world.add(Box::new(Sphere::new(lamp_center, lamp_radius, lamp.clone())));
+    lights.add(Box::new(Sphere::new(lamp_center, lamp_radius, lamp)));
+
+    let look_from = Point3::new(0.0, 2.5, 9.0);
+    let look_at = Point3::new(0.0, 0.9, 0.0);
+    let focus_dist = (look_from - look_at).magnitude();
+    let cam = Camera::new(look_from, look_at, Vector3::newi(0, 1, 0), 40.0, aspect_ratio, 0.0, focus_dist, 0.0, 0.0);
+
+    let sky = GradientSky::new(Color::fromv(0.3), Color::new(0.15, 0.21, 0.3));
+    Scene::new(world, lights, Some(Box::new(sky)), cam)
+}
+
+
+
+// a row of principled materials: plastic, brushed metal, velvet, lacquered paint & frosted glass,
+// on a checkered floor that alternates between matte & polished
+pub fn principled_scene(aspect_ratio: f32) -> Scene {
+    let mut world = HittableList::new();
+    let mut lights = HittableList::new();
+
+    let floor = Principled {
+        roughness: Arc::new(Checker::new(constant(0.9), constant(0.15), 1.0)),
+        ..Principled::new(Arc::new(Checker::new(solid(Color::new(0.6, 0.6, 0.6)), solid(Color::new(0.2, 0.2, 0.25)), 1.0)))
+    };
+    world.add(Box::new(Sphere::new(Point3::new(0.0, -1000.0, 0.0), 1000.0, Arc::new(floor))));
+
+    let plastic = Principled { roughness: constant(0.3), ..Principled::new(solid(Color::new(0.8, 0.1, 0.1))) };
+    let brushed = Principled {
+        metallic: constant(1.0),
+        roughness: constant(0.4),
+        anisotropic: constant(0.9),
+        ..Principled::new(solid(Color::new(0.95, 0.75, 0.4)))
+    };
+    let velvet = Principled {
+        roughness: constant(1.0),
+        sheen: constant(1.0),
+        sheen_tint: constant(0.8),
+        specular: constant(0.0),
+        ..Principled::new(solid(Color::new(0.3, 0.05, 0.4)))
+    };
+    let paint = Principled {
+        clearcoat: constant(1.0),
+        clearcoat_gloss: constant(0.9),
+        metallic: constant(0.6),
+        roughness: constant(0.5),
+        ..Principled::new(solid(Color::new(0.05, 0.2, 0.6)))
+    };
+    let glass = Principled { transmission: constant(1.0), roughness: constant(0.25), ..Principled::new(solid(Color::new(0.8, 1.0, 0.9))) };
+
+    let materials: Vec<Arc<dyn Material>> = vec![Arc::new(plastic), Arc::new(brushed), Arc::new(velvet), Arc::new(paint), Arc::new(glass)];
+    for (i, material) in materials.into_iter().enumerate() {
+        world.add(Box::new(Sphere::new(Point3::new(-4.4 + 2.2 * i as f32, 1.0, 0.0), 1.0, material)));
+    }
+
+    let lamp = Arc::new(DiffuseLight::new(Color::fromv(40.0)));
+    let (lamp_center, lamp_radius) = (Point3::new(0.0, 6.0, 4.0), 0.8);
+    world.add(Box::new(Sphere::new(lamp_center, lamp_radius, lamp.clone())));
+    lights.add(Box::new(Sphere::new(lamp_center, lamp_radius, lamp)));
+
+    let look_from = Point3::new(0.0, 2.5, 11.0);
+    let look_at = Point3::new(0.0, 0.9, 0.0);
+    let focus_dist = (look_from - look_at).magnitude();
+    let cam = Camera::new(look_from, look_at, Vector3::newi(0, 1, 0), 40.0, aspect_ratio, 0.0, focus_dist, 0.0, 0.0);
+
+    let sky = GradientSky::new(Color::fromv(0.3), Color::new(0.15, 0.21, 0.3));
+    Scene::new(world, lights, Some(Box::new(sky)), cam)
+}
+
+
+
+// coats & blends of the existing materials: varnished planks, car paint over a metallic flake
+// base, a tarnished coin where gold gives way to diffuse grime, & a clear coat over glossy plastic
+pub fn layered_scene(aspect_ratio: f32) -> Scene {
+    let mut world = HittableList::new();
+    let mut lights = HittableList::new();
+
+    let planks = Arc::new(Checker::new(solid(Color::new(0.45, 0.25, 0.1)), solid(Color::new(0.35, 0.18, 0.07)), 2.0));
+    let varnished = Coated::new(Arc::new(Principled { roughness: constant(0.8), ..Principled::new(planks) }), 1.5)
+        .with_tint(Color::new(0.95, 0.85, 0.6));
+    world.add(Box::new(Sphere::new(Point3::new(0.0, -1000.0, 0.0), 1000.0, Arc::new(varnished))));
+
+    let flakes = Principled { metallic: constant(0.8), roughness: constant(0.45), ..Principled::new(solid(Color::new(0.6, 0.05, 0.05))) };
+    let car_paint = Coated::new(Arc::new(flakes), 1.5).with_roughness(0.01);
+    world.add(Box::new(Sphere::new(Point3::new(-3.3, 1.0, 0.0), 1.0, Arc::new(car_paint))));
+
+    let grime = Arc::new(Lambertian::new(Color::new(0.25, 0.22, 0.18)));
+    let tarnished = Mix::new(Arc::new(Conductor::gold().with_roughness(0.15, 0.15)), grime, Arc::new(Checker::new(constant(0.1), constant(0.7), 6.0)));
+    world.add(Box::new(Sphere::new(Point3::new(-1.1, 1.0, 0.0), 1.0, Arc::new(tarnished))));
+
+    let plastic = Principled { roughness: constant(0.6), ..Principled::new(solid(Color::new(0.1, 0.4, 0.15))) };
+    let lacquered = Coated::new(Arc::new(plastic), 1.5).with_roughness(0.05);
+    world.add(Box::new(Sphere::new(Point3::new(1.1, 1.0, 0.0), 1.0, Arc::new(lacquered))));
+
+    let half_and_half = Mix::new(Arc::new(Lambertian::new(Color::fromv(0.8))), Arc::new(Conductor::aluminium().with_roughness(0.3, 0.3)), constant(0.5));
+    world.add(Box::new(Sphere::new(Point3::new(3.3, 1.0, 0.0), 1.0, Arc::new(half_and_half))));
+
+    let lamp = Arc::new(DiffuseLight::new(Color::fromv(40.0)));
+    let (lamp_center, lamp_radius) = (Point3::new(0.0, 6.0, 4.0), 0.8);
+    world.add(Box::new(Sphere::new(lamp_center, lamp_radius, lamp.clone())));
+    lights.add(Box::new(Sphere::new(lamp_center, lamp_radius, lamp)));
+
+    let look_from = Point3::new(0.0, 2.5, 9.0);
+    let look_at = Point3::new(0.0, 0.9, 0.0);
+    let focus_dist = (look_from - look_at).magnitude();
+    let cam = Camera::new(look_from, look_at, Vector3::newi(0, 1, 0), 40.0, aspect_ratio, 0.0, focus_dist, 0.0, 0.0);
+
+    let sky = GradientSky::new(Color::fromv(0.3), Color::new(0.15, 0.21, 0.3));
+    Scene::new(world, lights, Some(Box::new(sky)), cam)
+}
+
+
+
+// thin film interference: soap bubbles of a few thicknesses, an oil slick on a puddle, heat tinted
+// steel & a foil balloon. best with --spectral, which resolves the colors per wavelength
+pub fn iridescent_scene(aspect_ratio: f32) -> Scene {
+    let mut world = HittableList::new();
+    let mut lights = HittableList::new();
+
+    let ground = Arc::new(Lambertian::new(Color::new(0.08, 0.08, 0.09)));
+    world.add(Box::new(Sphere::new(Point3::new(0.0, -1000.0, 0.0), 1000.0, ground)));
+
+    // a water puddle in front with a film of oil on it
+    let oil_on_water = Arc::new(Dielectric::new(1.33).with_thin_film(450.0, 1.47));
+    world.add(Box::new(XzRect::new(-4.0, 4.0, 0.5, 3.5, 0.001, oil_on_water)));
+
+    // soap bubbles, water films with air on both sides
+    for (i, thickness) in [250.0, 400.0, 600.0].iter().enumerate() {
+        let bubble = Arc::new(Dielectric::new(1.0).with_thin_film(*thickness, 1.33));
+        world.add(Box::new(Sphere::new(Point3::new(-3.0 + 1.5 * i as f32, 1.6 + 0.3 * i as f32, -0.5), 0.6, bubble)));
+    }
+
+    // steel with a layer of oxide grown by heat
+    let tempered = Arc::new(Conductor::new(Color::new(2.9, 2.9, 2.8), Color::new(3.1, 3.0, 2.9)).with_roughness(0.05, 0.05).with_thin_film(120.0, 2.2));
+    world.add(Box::new(Sphere::new(Point3::new(1.8, 1.0, 0.0), 1.0, tempered)));
+
+    let foil = Arc::new(Metal::new(Color::fromv(0.9), 0.1).with_thin_film(320.0, 1.6));
+    world.add(Box::new(Sphere::new(Point3::new(3.9, 1.0, -0.5), 1.0, foil)));
+
+    let lamp = Arc::new(DiffuseLight::new(Color::fromv(40.0)));
+    let (lamp_center, lamp_radius) = (Point3::new(0.0, 6.0, 4.0), 0.8);
+    world.add(Box::new(Sphere::new(lamp_center, lamp_radius, lamp.clone())));
+    lights.add(Box::new(Sphere::new(lamp_center, lamp_radius, lamp)));
+
+    let look_from = Point3::new(0.0, 2.5, 9.0);
+    let look_at = Point3::new(0.0, 0.9, 0.0);
+    let focus_dist = (look_from - look_at).magnitude();
+    let cam = Camera::new(look_from, look_at, Vector3::newi(0, 1, 0), 40.0, aspect_ratio, 0.0, focus_dist, 0.0, 0.0);
+
+    let sky = GradientSky::new(Color::fromv(0.6), Color::new(0.3, 0.45, 0.7));
+    Scene::new(world, lights, Some(Box::new(sky)), cam)
+}
+
+
+
+// translucent materials lit from behind & above: wax, marble, skin under a rough oily surface & jade
+pub fn subsurface_scene(aspect_ratio: f32) -> Scene {
+    let mut world = HittableList::new();
+    let mut lights = HittableList::new();
+
+    let ground = Arc::new(Lambertian::new(Color::new(0.4, 0.4, 0.45)));
+    world.add(Box::new(Sphere::new(Point3::new(0.0, -1000.0, 0.0), 1000.0, ground)));
+
+    let wax = (Color::new(0.99, 0.95, 0.8), Color::new(0.3, 0.2, 0.1), Dielectric::new(1.45));
+    let marble = (Color::new(0.999, 0.998, 0.995), Color::new(0.08, 0.07, 0.06), Dielectric::new(1.5));
+    let skin = (Color::new(0.99, 0.85, 0.75), Color::new(0.25, 0.09, 0.05), Dielectric::new(1.4).with_roughness(0.35, 0.35));
+    let jade = (Color::new(0.7, 0.98, 0.8), Color::new(0.4, 0.6, 0.4), Dielectric::new(1.6).with_roughness(0.05, 0.05));
+
+    for (i, (albedo, mean_free_path, interface)) in vec![wax, marble, skin, jade].into_iter().enumerate() {
+        let center = Point3::new(-3.3 + 2.2 * i as f32, 1.0, 0.0);
+
+        // the walk needs its own copy of the shape to find its way back out, its material is never looked at
+        let boundary = Arc::new(Sphere::new(center, 1.0, Arc::new(Lambertian::new(Color::zeros()))));
+        let material = Subsurface::new(boundary, albedo, mean_free_path, interface.ir).with_interface(interface);
+        world.add(Box::new(Sphere::new(center, 1.0, Arc::new(material))));
+    }
+
+    let lamp = Arc::new(DiffuseLight::new(Color::fromv(40.0)));
+    let (lamp_center, lamp_radius) = (Point3::new(0.0, 5.0, -4.0), 0.8);
+    world.add(Box::new(Sphere::new(lamp_center, lamp_radius, lamp.clone())));
+    lights.add(Box::new(Sphere::new(lamp_center, lamp_radius, lamp)));
+
+    let look_from = Point3::new(0.0, 2.5, 9.0);
+    let look_at = Point3::new(0.0, 0.9, 0.0);
+    let focus_dist = (look_from - look_at).magnitude();
+    let cam = Camera::new(look_from, look_at, Vector3::newi(0, 1, 0), 40.0, aspect_ratio, 0.0, focus_dist, 0.0, 0.0);
+
+    let sky = GradientSky::new(Color::fromv(0.2), Color::new(0.1, 0.14, 0.2));
+    Scene::new(world, lights, Some(Box::new(sky)), cam)
+}
+
+
+
+// detail from normals alone: a tiled floor with grouted gaps, an orange peel, a golf ball like
+// dimpled metal & ridges from a normal map on glass. behind them a lumpy stone, displaced for real
+// so its outline & shadow are lumpy too
+pub fn bumps_scene(aspect_ratio: f32) -> Scene {
+    let mut world = HittableList::new();
+    let mut lights = HittableList::new();
+
+    let ground = Arc::new(Lambertian::new(Color::new(0.4, 0.4, 0.45)));
+    world.add(Box::new(Sphere::new(Point3::new(0.0, -1000.0, 0.0), 1000.0, ground)));
+
+    // twelve by six tiles, the grout sunk between them
+    let grout = Arc::new(Procedural::new(|u, v, _| {
+        let edge = |x: f32| (0.5 - (x.fract() - 0.5).abs()).min(0.05) / 0.05;
+        Color::fromv(edge(12.0 * u).min(edge(6.0 * v)))
+    }));
+    let tiles = Arc::new(Lambertian::new(Color::new(0.75, 0.7, 0.6)));
+    world.add(Box::new(BumpMap::new(Box::new(XzRect::new(-6.0, 6.0, -4.0, 2.0, 0.001, tiles)), grout, 0.02)));
+
+    let orange_peel = Arc::new(Procedural::new(|_, _, p: &Point3| {
+        Color::fromv((40.0 * p.x).sin() * (40.0 * p.y).sin() * (40.0 * p.z).sin())
+    }));
+    let orange = Arc::new(Principled { roughness: constant(0.4), ..Principled::new(solid(Color::new(0.9, 0.35, 0.05))) });
+    world.add(Box::new(BumpMap::new(Box::new(Sphere::new(Point3::new(-2.2, 1.0, 0.0), 1.0, orange)), orange_peel, 0.004)));
+
+    let dimples = Arc::new(Procedural::new(|u, v, _| {
+        let (x, y) = ((24.0 * u).fract() - 0.5, (12.0 * v).fract() - 0.5);
+        Color::fromv(-(0.16 - (x * x + y * y)).max(0.0))
+    }));
+    let steel = Arc::new(Conductor::aluminium().with_roughness(0.15, 0.15));
+    world.add(Box::new(BumpMap::new(Box::new(Sphere::new(Point3::new(0.0, 1.0, 0.0), 1.0, steel)), dimples, 0.05)));
+
+    // ridges running around the sphere, as a tangent space normal map
+    let ridges = Arc::new(Procedural::new(|u, _, _| {
+        let normal = Vector3::new(0.5 * (2.0 * PI * 30.0 * u).cos(), 0.0, 1.0).normalized();
+        0.5 * (normal + Color::fromv(1.0))
+    }));
+    let glass = Arc::new(Dielectric::new(1.5));
+    world.add(Box::new(NormalMap::new(Box::new(Sphere::new(Point3::new(2.2, 1.0, 0.0), 1.0, glass)), ridges)));
+
+    let lumps = Procedural::new(|_, _, p: &Point3| {
+        Color::fromv((3.0 * p.x).sin() * (2.0 * p.y + 1.0).sin() + 0.5 * (7.0 * p.z).sin() * (5.0 * p.x).cos())
+    });
+    let stone = Arc::new(Lambertian::new(Color::new(0.45, 0.42, 0.38)));
+    world.add(Box::new(Mesh::uv_sphere(Point3::new(-1.1, 1.1, -2.6), 1.0, 16, 32, stone).displaced(&lumps, 0.15, 3)));
+
+    let lamp = Arc::new(DiffuseLight::new(Color::fromv(40.0)));
+    let (lamp_center, lamp_radius) = (Point3::new(-3.0, 6.0, 4.0), 0.8);
+    world.add(Box::new(Sphere::new(lamp_center, lamp_radius, lamp.clone())));
+    lights.add(Box::new(Sphere::new(lamp_center, lamp_radius, lamp)));
+
+    let look_from = Point3::new(0.0, 2.5, 8.0);
+    let look_at = Point3::new(0.0, 0.9, 0.0);
+    let focus_dist = (look_from - look_at).magnitude();
+    let cam = Camera::new(look_from, look_at, Vector3::newi(0, 1, 0), 40.0, aspect_ratio, 0.0, focus_dist, 0.0, 0.0);
+
+    let sky = GradientSky::new(Color::fromv(0.3), Color::new(0.15, 0.21, 0.3));
+    Scene::new(world, lights, Some(Box::new(sky)), cam)
+}
+
+
+// a picket fence & a few leaves, each cut out of a single flat card by an alpha mask. the leaves
+// are strewn by seed
+pub fn cutouts_scene(aspect_ratio: f32, seed: u64) -> Scene {
+    let mut world = HittableList::new();
+    let mut lights = HittableList::new();
+
+    let grass = Arc::new(Lambertian::new(Color::new(0.3, 0.45, 0.2)));
+    world.add(Box::new(Sphere::new(Point3::new(0.0, -1000.0, 0.0), 1000.0, grass)));
+
+    // pointed pickets with gaps between them, & two rails across, all one card
+    let pickets = Arc::new(Procedural::new(|u, v, _| {
+        let x = (16.0 * u).fract();
+        let picket = (x - 0.5).abs() < 0.3 && v < 0.85 + 0.15 * (1.0 - (x - 0.5).abs() / 0.3);
+        let rail = (v - 0.25).abs() < 0.04 || (v - 0.7).abs() < 0.04;
+        Color::fromv(if picket || rail {1.0} else {0.0})
+    }));
+    let paint = Arc::new(Lambertian::new(Color::new(0.85, 0.85, 0.8)));
+    world.add(Box::new(XyRect::new(-4.0, 4.0, 0.0, 1.5, -1.0, Arc::new(Cutout::new(paint, pickets).with_threshold(0.5)))));
+
+    // leaves, pointed ovals on cards leaning every which way, their edges softened stochastically
+    let leaf = Arc::new(Procedural::new(|u, v, _| {
+        let (x, y) = (2.0 * u - 1.0, 2.0 * v - 1.0);
+        let half_width = 0.5 * (1.0 - y * y);
+        Color::fromv(((half_width - x.abs()) / 0.05 + 0.5).clamp(0.0, 1.0))
+    }));
+    let green = Arc::new(Lambertian::new(Color::new(0.2, 0.5, 0.1)));
+    with_seed(seed, || for _ in 0..40 {
+        let (x, y, z) = (random_range(-2.5, 2.5), random_range(0.05, 0.6), random_range(0.0, 2.0));
+        let card: Box<dyn Hittable> = if random_f32() < 0.5 {
+            Box::new(XzRect::new(x, x + 0.3, z, z + 0.5, y, Arc::new(Cutout::new(green.clone(), leaf.clone()))))
+        } else {
+            Box::new(YzRect::new(y, y + 0.5, z, z + 0.3, x, Arc::new(Cutout::new(green.clone(), leaf.clone()))))
+        };
+        world.add(card);
+    });
+
+    let lamp = Arc::new(DiffuseLight::new(Color::fromv(30.0)));
+    let (lamp_center, lamp_radius) = (Point3::new(3.0, 5.0, 5.0), 0.8);
+    world.add(Box::new(Sphere::new(lamp_center, lamp_radius, lamp.clone())));
+    lights.add(Box::new(Sphere::new(lamp_center, lamp_radius, lamp)));
+
+    let look_from = Point3::new(0.0, 1.8, 6.0);
+    let look_at = Point3::new(0.0, 0.5, 0.0);
+    let focus_dist = (look_from - look_at).magnitude();
+    let cam = Camera::new(look_from, look_at, Vector3::newi(0, 1, 0), 40.0, aspect_ratio, 0.0, focus_dist, 0.0, 0.0);
+
+    let sky = GradientSky::new(Color::fromv(1.0), Color::new(0.5, 0.7, 1.0));
+    Scene::new(world, lights, Some(Box::new(sky)), cam)
+}
+
+
+// thin things: a paper lampshade glowing with the bulb inside it, a pane of glass leaning in front
+// of it, & a card that's red on one side & blue on the other
+pub fn sheets_scene(aspect_ratio: f32) -> Scene {
+    let mut world = HittableList::new();
+    let mut lights = HittableList::new();
+
+    let floor = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+    world.add(Box::new(Sphere::new(Point3::new(0.0, -1000.0, 0.0), 1000.0, floor)));
+
+    // a square shade open at the top & bottom, around a small bright bulb
+    let paper = Arc::new(DiffuseTransmission::new(Color::new(0.6, 0.55, 0.45), Color::new(0.35, 0.3, 0.2)));
+    let (half, bottom, top) = (0.5, 0.6, 1.6);
+    world.add(Box::new(XyRect::new(-half, half, bottom, top, -half, paper.clone())));
+    world.add(Box::new(XyRect::new(-half, half, bottom, top, half, paper.clone())));
+    world.add(Box::new(YzRect::new(bottom, top, -half, half, -half, paper.clone())));
+    world.add(Box::new(YzRect::new(bottom, top, -half, half, half, paper)));
+
+    let bulb = Arc::new(DiffuseLight::new(Color::fromv(60.0)));
+    let (bulb_center, bulb_radius) = (Point3::new(0.0, 1.1, 0.0), 0.15);
+    world.add(Box::new(Sphere::new(bulb_center, bulb_radius, bulb.clone())));
+    lights.add(Box::new(Sphere::new(bulb_center, bulb_radius, bulb)));
+
+    let pane = Arc::new(ThinDielectric::new(1.5));
+    world.add(Box::new(XyRect::new(-1.6, -0.2, 0.0, 1.4, 1.2, pane)));
+
+    let card = Arc::new(TwoSided::new(Arc::new(Lambertian::new(Color::new(0.8, 0.1, 0.1))), Arc::new(Lambertian::new(Color::new(0.1, 0.2, 0.8)))));
+    world.add(Box::new(YzRect::new(0.0, 1.2, -0.5, 0.7, 1.6, card)));
+
+    let look_from = Point3::new(1.0, 2.0, 6.0);
+    let look_at = Point3::new(0.0, 0.8, 0.0);
+    let focus_dist = (look_from - look_at).magnitude();
+    let cam = Camera::new(look_from, look_at, Vector3::newi(0, 1, 0), 40.0, aspect_ratio, 0.0, focus_dist, 0.0, 0.0);
+
+    let sky = GradientSky::new(Color::fromv(0.05), Color::new(0.02, 0.03, 0.06));
+    Scene::new(world, lights, Some(Box::new(sky)), cam)
+}
+
+
+// the same saturated red declared in srgb, display p3 & acescg, which aren't the same red, beside a
+// globe wrapped in an 8 bit srgb image: image if one's given, else a chart of its own
+pub fn colors_scene(aspect_ratio: f32, image: Option<ImageTexture>) -> Scene {
+    let mut world = HittableList::new();
+    let mut lights = HittableList::new();
+
+    let ground = Arc::new(Lambertian::new(Color::fromv(0.5)));
+    world.add(Box::new(Sphere::new(Point3::new(0.0, -1000.0, 0.0), 1000.0, ground)));
+
+    let red = Color::new(0.8, 0.05, 0.05);
+    for (k, space) in [ColorSpace::Srgb, ColorSpace::DisplayP3, ColorSpace::AcesCg].iter().enumerate() {
+        let paint = Arc::new(Principled { roughness: constant(0.5), ..Principled::new(solid_in(red, *space)) });
+        world.add(Box::new(Sphere::new(Point3::new(-2.4 + 1.2 * k as f32, 0.5, 1.2), 0.5, paint)));
+    }
+
+    // eight hues around & four values down, stored as an image file would
+    let image = image.unwrap_or_else(|| {
+        let (width, height) = (8, 4);
+        let data: Vec<u8> = (0..height).flat_map(|j| (0..width).map(move |i| (i, j))).flat_map(|(i, j)| {
+            let hue = i as f32 / width as f32 * 2.0 * PI;
+            let value = 1.0 - j as f32 / height as f32 * 0.75;
+            [0.0, 2.0, 4.0].iter().map(move |phase| (255.0 * value * (0.5 + 0.5 * (hue - phase * PI / 3.0).cos())) as u8).collect::<Vec<u8>>()
+        }).collect();
+        ImageTexture::new(width, height, &data, ColorSpace::Srgb, Transfer::Srgb)
+    });
+    let globe = Arc::new(Principled { roughness: constant(0.6), ..Principled::new(Arc::new(image)) });
+    world.add(Box::new(Sphere::new(Point3::new(1.4, 1.0, 0.0), 1.0, globe)));
+
+    let lamp = Arc::new(DiffuseLight::new(Color::fromv(30.0)));
+    let (lamp_center, lamp_radius) = (Point3::new(-2.0, 6.0, 5.0), 0.8);
+    world.add(Box::new(Sphere::new(lamp_center, lamp_radius, lamp.clone())));
+    lights.add(Box::new(Sphere::new(lamp_center, lamp_radius, lamp)));
+
+    let look_from = Point3::new(0.0, 2.0, 7.0);
+    let look_at = Point3::new(0.0, 0.7, 0.0);
+    let focus_dist = (look_from - look_at).magnitude();
+    let cam = Camera::new(look_from, look_at, Vector3::newi(0, 1, 0), 40.0, aspect_ratio, 0.0, focus_dist, 0.0, 0.0);
+
+    let sky = GradientSky::new(Color::fromv(0.3), Color::new(0.15, 0.21, 0.3));
+    Scene::new(world, lights, Some(Box::new(sky)), cam)
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // where rays dropped straight down over the small spheres first hit
+    fn heights(scene: &Scene) -> Vec<f32> {
+        let mut heights = Vec::new();
+        for i in -44..44 {
+            for j in -44..44 {
+                let r = Ray::new(Point3::new(i as f32 / 4.0, 5.0, j as f32 / 4.0), Vector3::new(0.0, -1.0, 0.0), 0.0);
+                let mut rec = HitRecord::new();
+                scene.world.hit(&r, 0.001, f32::INFINITY, &mut rec);
+                heights.push(rec.p.y);
+            }
+        }
+        heights
+    }
+
+    #[test]
+    fn seeds_build_the_same_scene_every_time() {
+        assert_eq!(heights(&random_scene(1.0, 7)), heights(&random_scene(1.0, 7)));
+        assert_ne!(heights(&random_scene(1.0, 7)), heights(&random_scene(1.0, 8)));
+    }
+}
@@ -0,0 +1,359 @@
+use crate::{
+            vectors::*,
+            hittable_list::HittableList,
+            sphere::Sphere,
+            mesh::Mesh,
+            materials::*,
+            camera::Camera,
+            sky::*,
+            principled::Principled,
+            texture::*,
+            color_space::*,
+            scene::Scene,
+};
+use std::{collections::HashMap, fs, io, path::Path, sync::Arc};
+
+
+// scenes described in a text file instead of in code. one statement a line, words separated by
+// whitespace, # starts a comment:
+//
+//   camera <from x y z> <at x y z> <vfov> [aperture <a>] [focus <distance>]
+//   sky gradient <bottom r g b> <top r g b>
+//   sky physical <sun elevation> <sun azimuth> <turbidity>
+//   texture <name> color <r g b>
+//   texture <name> checker <even> <odd> <scale>
+//   texture <name> image <file.ppm>
+//   material <name> lambertian <r g b>
+//   material <name> metal <r g b> <fuzz>
+//   material <name> dielectric <ior>
+//   material <name> light <r g b>
+//   material <name> principled [<parameter> <value>]...
+//   sphere <center x y z> <radius> <material>
+//   mesh <file.obj> <material> [displace <height texture> <scale> <subdivisions>]
+//
+// colors are linear srgb & images srgb encoded, found next to the scene file. the principled
+// parameters are base_color, metallic, roughness, anisotropic, specular, specular_tint, sheen,
+// sheen_tint, clearcoat, clearcoat_gloss, transmission & ior. each but ior takes a number, three
+// for base_color, or the name of a texture, so any of them can vary over the surface. meshes are
+// found next to the scene file too, & displaced when they're loaded. spheres of light material
+// are sampled as lights
+pub fn load_scene(path: &str, aspect_ratio: f32) -> io::Result<Scene> {
+    let text = fs::read_to_string(path)?;
+    let directory = Path::new(path).parent().unwrap_or_else(|| Path::new(""));
+
+    parse_scene(&text, directory, aspect_ratio).map_err(|message| io::Error::new(io::ErrorKind::InvalidData, message))
+}
+
+
+pub fn parse_scene(text: &str, directory: &Path, aspect_ratio: f32) -> Result<Scene, String> {
+    let mut parser = Parser {
+        directory,
+        aspect_ratio,
+        textures: HashMap::new(),
+        materials: HashMap::new(),
+        world: HittableList::new(),
+        lights: HittableList::new(),
+        camera: None,
+        sky: None,
+    };
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("");
+        let mut words = Words { words: line.split_whitespace().collect(), next: 0 };
+        if words.words.is_empty() {
+            continue;
+        }
+
+        parser.statement(&mut words)
+            .and_then(|_| words.end())
+            .map_err(|message| format!("line {}: {}", number + 1, message))?;
+    }
+
+    let camera = parser.camera.ok_or("no camera")?;
+    Ok(Scene::new(parser.world, parser.lights, parser.sky, camera))
+}
+
+
+
+// the words of one statement, taken in order
+struct Words<'a> {
+    words: Vec<&'a str>,
+    next: usize,
+}
+
+
+impl<'a> Words<'a> {
+
+    fn word(&mut self, what: &str) -> Result<&'a str, String> {
+        let word = self.words.get(self.next).ok_or(format!("missing {}", what))?;
+        self.next += 1;
+        Ok(word)
+    }
+
+    fn peek(&self) -> Option<&'a str> {
+        self.words.get(self.next).copied()
+    }
+
+    fn number(&mut self, what: &str) -> Result<f32, String> {
+        let word = self.word(what)?;
+        word.parse().map_err(|_| format!("expected a number for {}, not '{}'", what, word))
+    }
+
+    fn vector(&mut self, what: &str) -> Result<Vector3, String> {
+        Ok(Vector3::new(self.number(what)?, self.number(what)?, self.number(what)?))
+    }
+
+    fn end(&self) -> Result<(), String> {
+        match self.peek() {
+            Some(word) => Err(format!("unexpected '{}'", word)),
+            None => Ok(()),
+        }
+    }
+
+}
+
+
+
+struct Parser<'a> {
+    directory: &'a Path,
+    aspect_ratio: f32,
+    textures: HashMap<String, Arc<dyn Texture>>,
+    materials: HashMap<String, (Arc<dyn Material>, bool)>, // & whether it emits
+    world: HittableList,
+    lights: HittableList,
+    camera: Option<Camera>,
+    sky: Option<Box<dyn Sky>>,
+}
+
+
+impl<'a> Parser<'a> {
+
+    fn statement(&mut self, words: &mut Words) -> Result<(), String> {
+        match words.word("statement")? {
+            "camera" => self.camera(words),
+            "sky" => self.sky(words),
+            "texture" => self.texture(words),
+            "material" => self.material(words),
+            "sphere" => self.sphere(words),
+            "mesh" => self.mesh(words),
+            other => Err(format!("unknown statement '{}'", other)),
+        }
+    }
+
+    fn camera(&mut self, words: &mut Words) -> Result<(), String> {
+        let look_from = words.vector("camera position")?;
+        let look_at = words.vector("camera target")?;
+        let vfov = words.number("field of view")?;
+        let mut aperture = 0.0;
+        let mut focus_dist = (look_from - look_at).magnitude();
+
+        while let Some(word) = words.peek() {
+            match word {
+                "aperture" => {words.next += 1; aperture = words.number("aperture")?}
+                "focus" => {words.next += 1; focus_dist = words.number("focus distance")?}
+                _ => break,
+            }
+        }
+
+        self.camera = Some(Camera::new(look_from, look_at, Vector3::newi(0, 1, 0), vfov, self.aspect_ratio, aperture, focus_dist, 0.0, 0.0));
+        Ok(())
+    }
+
+    fn sky(&mut self, words: &mut Words) -> Result<(), String> {
+        self.sky = match words.word("sky")? {
+            "gradient" => Some(Box::new(GradientSky::new(words.vector("bottom color")?, words.vector("top color")?))),
+            "physical" => {
+                let sun_direction = direction_from_angles(words.number("sun elevation")?, words.number("sun azimuth")?);
+                Some(Box::new(PhysicalSky::new(sun_direction, words.number("turbidity")?)))
+            }
+            other => return Err(format!("unknown sky '{}'", other)),
+        };
+        Ok(())
+    }
+
+    fn texture(&mut self, words: &mut Words) -> Result<(), String> {
+        let name = words.word("texture name")?;
+        let texture: Arc<dyn Texture> = match words.word("texture kind")? {
+            "color" => solid(words.vector("color")?),
+            "checker" => Arc::new(Checker::new(self.named_texture(words)?, self.named_texture(words)?, words.number("checker scale")?)),
+            "image" => {
+                let path = self.directory.join(words.word("image file")?);
+                let image = ImageTexture::load_ppm(&path.to_string_lossy(), ColorSpace::Srgb, Transfer::Srgb).map_err(|error| error.to_string())?;
+                Arc::new(image)
+            }
+            other => return Err(format!("unknown texture '{}'", other)),
+        };
+
+        self.textures.insert(name.to_string(), texture);
+        Ok(())
+    }
+
+    fn named_texture(&self, words: &mut Words) -> Result<Arc<dyn Texture>, String> {
+        let name = words.word("texture")?;
+        self.textures.get(name).cloned().ok_or(format!("no texture named '{}'", name))
+    }
+
+    // a number, three for a color, or a texture's name
+    fn texture_value(&self, words: &mut Words, color: bool) -> Result<Arc<dyn Texture>, String> {
+        match words.peek() {
+            Some(word) if word.parse::<f32>().is_ok() => {
+                if color {Ok(solid(words.vector("color")?))} else {Ok(constant(words.number("value")?))}
+            }
+            _ => self.named_texture(words),
+        }
+    }
+
+    fn material(&mut self, words: &mut Words) -> Result<(), String> {
+        let name = words.word("material name")?;
+        let (material, emits): (Arc<dyn Material>, bool) = match words.word("material kind")? {
+            "lambertian" => (Arc::new(Lambertian::new(words.vector("albedo")?)), false),
+            "metal" => (Arc::new(Metal::new(words.vector("albedo")?, words.number("fuzz")?)), false),
+            "dielectric" => (Arc::new(Dielectric::new(words.number("index of refraction")?)), false),
+            "light" => (Arc::new(DiffuseLight::new(words.vector("emitted color")?)), true),
+            "principled" => (Arc::new(self.principled(words)?), false),
+            other => return Err(format!("unknown material '{}'", other)),
+        };
+
+        self.materials.insert(name.to_string(), (material, emits));
+        Ok(())
+    }
+
+    fn principled(&self, words: &mut Words) -> Result<Principled, String> {
+        let mut material = Principled::new(solid(Color::fromv(0.8)));
+
+        while let Some(parameter) = words.peek() {
+            words.next += 1;
+            match parameter {
+                "base_color" => material.base_color = self.texture_value(words, true)?,
+                "metallic" => material.metallic = self.texture_value(words, false)?,
+                "roughness" => material.roughness = self.texture_value(words, false)?,
+                "anisotropic" => material.anisotropic = self.texture_value(words, false)?,
+                "specular" => material.specular = self.texture_value(words, false)?,
+                "specular_tint" => material.specular_tint = self.texture_value(words, false)?,
+                "sheen" => material.sheen = self.texture_value(words, false)?,
+                "sheen_tint" => material.sheen_tint = self.texture_value(words, false)?,
+                "clearcoat" => material.clearcoat = self.texture_value(words, false)?,
+                "clearcoat_gloss" => material.clearcoat_gloss = self.texture_value(words, false)?,
+                "transmission" => material.transmission = self.texture_value(words, false)?,
+                "ior" => material.ior = words.number("ior")?,
+                other => return Err(format!("unknown principled parameter '{}'", other)),
+            }
+        }
+
+        Ok(material)
+    }
+
+    fn sphere(&mut self, words: &mut Words) -> Result<(), String> {
+        let center = words.vector("center")?;
+        let radius = words.number("radius")?;
+        let name = words.word("material")?;
+        let (material, emits) = self.materials.get(name).cloned().ok_or(format!("no material named '{}'", name))?;
+
+        self.world.add(Box::new(Sphere::new(center, radius, material.clone())));
+        if emits {
+            self.lights.add(Box::new(Sphere::new(center, radius, material)));
+        }
+        Ok(())
+    }
+
+    fn mesh(&mut self, words: &mut Words) -> Result<(), String> {
+        let path = self.directory.join(words.word("mesh file")?);
+        let name = words.word("material")?;
+        let (material, _) = self.materials.get(name).cloned().ok_or(format!("no material named '{}'", name))?;
+        let mut mesh = Mesh::load_obj(&path.to_string_lossy(), material).map_err(|error| error.to_string())?;
+
+        if words.peek() == Some("displace") {
+            words.next += 1;
+            let height = self.named_texture(words)?;
+            let scale = words.number("displacement scale")?;
+            let subdivisions = words.number("subdivisions")?;
+            mesh = mesh.displaced(height.as_ref(), scale, subdivisions.max(0.0) as u32);
+        }
+
+        self.world.add(Box::new(mesh));
+        Ok(())
+    }
+
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{rays::Ray, hittable::*};
+
+    fn parse(text: &str) -> Result<Scene, String> {
+        parse_scene(text, Path::new(""), 1.5)
+    }
+
+    #[test]
+    fn principled_parameters_take_numbers_colors_or_textures() {
+        let scene = parse("
+            camera 0 0 5  0 0 0  40
+            texture rough checker smooth matte 10   # used before it's declared
+        ");
+        assert_eq!(scene.err().unwrap(), "line 3: no texture named 'smooth'");
+
+        let scene = parse("
+            camera 0 0 5  0 0 0  40  aperture 0.1
+            sky gradient 1 1 1  0.5 0.7 1
+            texture smooth color 0.1 0.1 0.1
+            texture matte color 0.9 0.9 0.9
+            texture rough checker smooth matte 10
+            material paint principled base_color 0.8 0.1 0.1 metallic 0.5 roughness rough clearcoat 1 ior 1.4
+            material lamp light 4 4 4
+            sphere 0 0 0 1 paint
+            sphere 0 3 0 0.5 lamp
+        ").unwrap();
+
+        assert!(scene.sky.is_some());
+        let mut rec = HitRecord::new();
+        assert!(scene.world.hit(&Ray::new(Point3::newi(0, 0, 5), Vector3::newi(0, 0, -1), 0.0), 0.001, f32::INFINITY, &mut rec));
+        assert!(scene.lights.hit(&Ray::new(Point3::zeros(), Vector3::newi(0, 1, 0), 0.0), 0.001, f32::INFINITY, &mut rec));
+        assert!(!scene.lights.hit(&Ray::new(Point3::newi(0, 0, 5), Vector3::newi(0, 0, -1), 0.0), 0.001, f32::INFINITY, &mut rec));
+    }
+
+    #[test]
+    fn mistakes_are_reported_with_their_line() {
+        let errors = [
+            ("sphere 0 0 0 1 paint", "line 1: no material named 'paint'"),
+            ("camera 0 0 5  0 0 0", "line 1: missing field of view"),
+            ("material m principled sheen", "line 1: missing texture"),
+            ("material m principled shine 1", "line 1: unknown principled parameter 'shine'"),
+            ("material m lambertian 0.5 0.5 0.5 0.5", "line 1: unexpected '0.5'"),
+            ("material m lambertian 0.5 0.5 0.5\nmesh missing.obj m", "line 2: No such file or directory (os error 2)"),
+            ("# only a comment", "no camera"),
+        ];
+
+        for (text, expected) in errors.iter() {
+            assert_eq!(parse(text).err().unwrap(), *expected);
+        }
+    }
+
+    #[test]
+    fn meshes_are_found_next_to_the_scene_file() {
+        let directory = std::env::temp_dir().join(format!("scene_file_test_{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(directory.join("square.obj"), "v -1 -1 0\nv 1 -1 0\nv 1 1 0\nv -1 1 0\nvt 0 0\nvt 1 0\nvt 1 1\nvt 0 1\nf 1/1 2/2 3/3 4/4\n").unwrap();
+
+        // an even height lifts the whole square off its plane, toward the camera
+        let scene = parse_scene("
+            camera 0 0 5  0 0 0  40
+            texture bulge color 1 1 1
+            material matte lambertian 0.5 0.5 0.5
+            mesh square.obj matte displace bulge 0.5 3
+        ", &directory, 1.5);
+        fs::remove_dir_all(&directory).unwrap();
+
+        let mut rec = HitRecord::new();
+        assert!(scene.unwrap().world.hit(&Ray::new(Point3::newi(0, 0, 5), Vector3::newi(0, 0, -1), 0.0), 0.001, f32::INFINITY, &mut rec));
+        assert!((rec.p.z - 0.5).abs() < 1e-4);
+    }
+
+    #[test]
+    fn the_example_scene_parses() {
+        let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes");
+        parse_scene(include_str!("../scenes/principled.txt"), &directory, 1.5).unwrap();
+    }
+}
@@ -0,0 +1,304 @@
+use crate::{vectors::*, onb::Onb, random::random_f32};
+use std::f32::consts::PI;
+
+
+// angular radius of the sun as seen from earth, in radians
+pub const SUN_ANGULAR_RADIUS: f32 = 0.00465;
+
+// preetham luminances are in kcd/m^2, this brings a midday zenith to roughly 1.0
+const SKY_SCALE: f32 = 0.1;
+
+// luminance of the sun disk before atmospheric extinction, in kcd/m^2
+const SUN_LUMINANCE: f32 = 2.0e6;
+
+// of the physical sky's samples that don't go to the sun, those spread over the whole sphere
+const SPHERE_SAMPLE_SHARE: f32 = 0.25;
+
+
+// what a ray sees when it leaves the scene. sample_direction & pdf let the sky act as a light
+pub trait Sky {
+    fn radiance(&self, direction: &Vector3) -> Color;
+    // (direction, solid angle pdf)
+    fn sample_direction(&self) -> (Vector3, f32);
+    fn pdf(&self, direction: &Vector3) -> f32;
+}
+
+
+pub fn direction_from_angles(elevation: f32, azimuth: f32) -> Vector3 {
+    let (elevation, azimuth) = (elevation.to_radians(), azimuth.to_radians());
+
+    Vector3::new(elevation.cos() * azimuth.sin(), elevation.sin(), elevation.cos() * azimuth.cos())
+}
+
+
+fn random_on_unit_sphere() -> (Vector3, f32) {
+    (Vector3::random_unit_vector(), 1.0 / (4.0 * PI))
+}
+
+
+
+// the old white to blue gradient background
+pub struct GradientSky {
+    pub bottom: Color,
+    pub top: Color,
+}
+
+
+impl GradientSky {
+    pub fn new(bottom: Color, top: Color) -> GradientSky {
+        GradientSky { bottom, top }
+    }
+}
+
+
+impl Sky for GradientSky {
+    fn radiance(&self, direction: &Vector3) -> Color {
+        let t: f32 = (direction.normalized().y + 1.0) * 0.5;
+        (1.0 - t) * self.bottom + t * self.top
+    }
+
+    fn sample_direction(&self) -> (Vector3, f32) {
+        random_on_unit_sphere()
+    }
+
+    fn pdf(&self, _direction: &Vector3) -> f32 {
+        1.0 / (4.0 * PI)
+    }
+}
+
+
+
+// the sun as a small cone of constant radiance
+pub struct SunDisk {
+    pub direction: Vector3,
+    pub angular_radius: f32,
+    pub radiance: Color,
+}
+
+
+impl SunDisk {
+    pub fn new(direction: Vector3, angular_radius: f32, radiance: Color) -> SunDisk {
+        SunDisk { direction: direction.normalized(), angular_radius, radiance }
+    }
+
+    // 1 - cos(angular_radius), written so it doesn't cancel out for tiny disks
+    fn one_minus_cos_max(&self) -> f32 {
+        2.0 * (self.angular_radius / 2.0).sin().powi(2)
+    }
+
+    pub fn solid_angle(&self) -> f32 {
+        2.0 * PI * self.one_minus_cos_max()
+    }
+
+    pub fn contains(&self, direction: &Vector3) -> bool {
+        1.0 - Vector3::dot(&direction.normalized(), &self.direction) <= self.one_minus_cos_max()
+    }
+
+    pub fn sample_direction(&self) -> (Vector3, f32) {
+        let cos_theta = 1.0 - random_f32() * self.one_minus_cos_max();
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * random_f32();
+
+        let uvw = Onb::build_from_w(&self.direction);
+        (uvw.local(phi.cos() * sin_theta, phi.sin() * sin_theta, cos_theta), 1.0 / self.solid_angle())
+    }
+
+    pub fn pdf(&self, direction: &Vector3) -> f32 {
+        if self.contains(direction) {1.0 / self.solid_angle()} else {0.0}
+    }
+}
+
+
+
+// Preetham, Shirley & Smits, "A Practical Analytic Model for Daylight" (1999)
+pub struct PhysicalSky {
+    pub sun: SunDisk,
+    perez_luminance: [f32; 5],
+    perez_x: [f32; 5],
+    perez_y: [f32; 5],
+    zenith: Vector3, // (Y, x, y) at the zenith, each already divided by its perez term at the zenith
+}
+
+
+impl PhysicalSky {
+    pub fn new(sun_direction: Vector3, turbidity: f32) -> PhysicalSky {
+        let sun_direction = sun_direction.normalized();
+        let t = turbidity;
+        let theta_s = sun_direction.y.clamp(-1.0, 1.0).acos();
+
+        let perez_luminance = [ 0.1787 * t - 1.4630, -0.3554 * t + 0.4275, -0.0227 * t + 5.3251,  0.1206 * t - 2.5771, -0.0670 * t + 0.3703];
+        let perez_x =         [-0.0193 * t - 0.2592, -0.0665 * t + 0.0008, -0.0004 * t + 0.2125, -0.0641 * t - 0.8989, -0.0033 * t + 0.0452];
+        let perez_y =         [-0.0167 * t - 0.2608, -0.0950 * t + 0.0092, -0.0079 * t + 0.2102, -0.0441 * t - 1.6537, -0.0109 * t + 0.0529];
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+        let zenith_luminance = ((4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192).max(0.0);
+
+        let cubic = |c: [f32; 4]| ((c[0] * theta_s + c[1]) * theta_s + c[2]) * theta_s + c[3];
+        let zenith_x = t * t * cubic([0.00166, -0.00375, 0.00209, 0.0])
+                     + t * cubic([-0.02903, 0.06377, -0.03202, 0.00394])
+                     + cubic([0.11693, -0.21196, 0.06052, 0.25886]);
+        let zenith_y = t * t * cubic([0.00275, -0.00610, 0.00317, 0.0])
+                     + t * cubic([-0.04214, 0.08970, -0.04153, 0.00516])
+                     + cubic([0.15346, -0.26756, 0.06670, 0.26688]);
+
+        let zenith = Vector3::new(
+            zenith_luminance / PhysicalSky::perez(&perez_luminance, 1.0, theta_s),
+            zenith_x / PhysicalSky::perez(&perez_x, 1.0, theta_s),
+            zenith_y / PhysicalSky::perez(&perez_y, 1.0, theta_s),
+        );
+
+        let sun_radiance = SKY_SCALE * SUN_LUMINANCE * PhysicalSky::transmittance(theta_s, t);
+        let sun = SunDisk::new(sun_direction, SUN_ANGULAR_RADIUS, sun_radiance);
+
+        PhysicalSky { sun, perez_luminance, perez_x, perez_y, zenith }
+    }
+
+    // F(theta, gamma), the perez distribution over view zenith angle & angle to the sun
+    fn perez(c: &[f32; 5], cos_theta: f32, gamma: f32) -> f32 {
+        (1.0 + c[0] * (c[1] / cos_theta).exp()) * (1.0 + c[2] * (c[3] * gamma).exp() + c[4] * gamma.cos().powi(2))
+    }
+
+    // extinction of direct sunlight by rayleigh & aerosol scattering, at 680, 550 & 440nm
+    fn transmittance(theta_s: f32, turbidity: f32) -> Color {
+        let theta_degrees = theta_s.to_degrees();
+        if theta_degrees >= 90.0 {
+            return Color::zeros();
+        }
+
+        let relative_optical_mass = 1.0 / (theta_s.cos() + 0.15 * (93.885 - theta_degrees).powf(-1.253));
+        let beta = 0.04608 * turbidity - 0.04586;
+        let extinction = |lambda: f32| {
+            let rayleigh = 0.008735 * lambda.powf(-4.08);
+            let aerosol = beta * lambda.powf(-1.3);
+            (-(rayleigh + aerosol) * relative_optical_mass).exp()
+        };
+
+        Color::new(extinction(0.680), extinction(0.550), extinction(0.440))
+    }
+
+    // sky dome only, without the sun disk
+    pub fn sky_radiance(&self, direction: &Vector3) -> Color {
+        let direction = direction.normalized();
+        // below the horizon we just keep seeing the horizon
+        let cos_theta = direction.y.max(0.001);
+        let gamma = Vector3::dot(&direction, &self.sun.direction).clamp(-1.0, 1.0).acos();
+
+        let luminance = self.zenith.x * PhysicalSky::perez(&self.perez_luminance, cos_theta, gamma);
+        let x = self.zenith.y * PhysicalSky::perez(&self.perez_x, cos_theta, gamma);
+        let y = self.zenith.z * PhysicalSky::perez(&self.perez_y, cos_theta, gamma);
+
+        SKY_SCALE * xyy_to_rgb(x, y, luminance)
+    }
+
+    fn sun_sample_probability(&self) -> f32 {
+        if self.sun.radiance.magnitude_squared() > 0.0 {0.5} else {0.0}
+    }
+}
+
+
+impl Sky for PhysicalSky {
+    fn radiance(&self, direction: &Vector3) -> Color {
+        let sky = self.sky_radiance(direction);
+        if self.sun.contains(direction) {sky + self.sun.radiance} else {sky}
+    }
+
+    // half the samples go to the sun, the rest mostly cosine weighted around the zenith. below the
+    // horizon the sky goes on looking like the horizon, so a share of them covers the whole sphere
+    // & there's nowhere with radiance the pdf says can't be sampled
+    fn sample_direction(&self) -> (Vector3, f32) {
+        let u = random_f32();
+        let p_sun = self.sun_sample_probability();
+
+        let direction = if u < p_sun {
+            self.sun.sample_direction().0
+        } else if u < p_sun + (1.0 - p_sun) * SPHERE_SAMPLE_SHARE {
+            random_on_unit_sphere().0
+        } else {
+            let r1 = random_f32();
+            let r2 = random_f32();
+            let phi = 2.0 * PI * r1;
+            Vector3::new(phi.cos() * r2.sqrt(), (1.0 - r2).sqrt(), phi.sin() * r2.sqrt())
+        };
+
+        (direction, self.pdf(&direction))
+    }
+
+    fn pdf(&self, direction: &Vector3) -> f32 {
+        let p_sun = self.sun_sample_probability();
+        let cosine_pdf = direction.normalized().y.max(0.0) / PI;
+        let dome_pdf = SPHERE_SAMPLE_SHARE / (4.0 * PI) + (1.0 - SPHERE_SAMPLE_SHARE) * cosine_pdf;
+
+        p_sun * self.sun.pdf(direction) + (1.0 - p_sun) * dome_pdf
+    }
+}
+
+
+// CIE xyY to linear sRGB
+pub fn xyy_to_rgb(x: f32, y: f32, luminance: f32) -> Color {
+    if y <= 0.0 {
+        return Color::zeros();
+    }
+
+    let cx = x * luminance / y;
+    let cy = luminance;
+    let cz = (1.0 - x - y) * luminance / y;
+
+    Color::new(
+        ( 3.2406 * cx - 1.5372 * cy - 0.4986 * cz).max(0.0),
+        (-0.9689 * cx + 1.8758 * cy + 0.0415 * cz).max(0.0),
+        ( 0.0557 * cx - 0.2040 * cy + 1.0570 * cz).max(0.0),
+    )
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color_space::ColorSpace;
+
+    #[test]
+    fn sampling_covers_everywhere_the_sky_shines() {
+        let sky = PhysicalSky::new(direction_from_angles(45.0, 0.0), 3.0);
+        let n = 200_000;
+
+        // the pdf sampled directions come with is the pdf, & 1 / pdf integrates to the whole sphere
+        let (mut area, mut importance, mut uniform) = (0.0, Color::zeros(), Color::zeros());
+        for _ in 0..n {
+            let (direction, pdf) = sky.sample_direction();
+            assert!((pdf - sky.pdf(&direction)).abs() <= 1e-4 * pdf);
+            area += 1.0 / pdf;
+            importance += sky.sky_radiance(&direction) / pdf;
+
+            let (direction, pdf) = random_on_unit_sphere();
+            uniform += sky.sky_radiance(&direction) / pdf;
+        }
+
+        assert!((area / n as f32 / (4.0 * PI) - 1.0).abs() < 0.02);
+        let (importance, uniform) = (importance / n as f32, uniform / n as f32);
+        assert!((importance - uniform).magnitude() < 0.02 * uniform.magnitude(), "{} vs {}", importance.y, uniform.y);
+    }
+
+    #[test]
+    fn zenith_and_horizon_match_preetham() {
+        // turbidity 3 with the sun 45 degrees up, worked out from the paper's formulas: luminance in
+        // kcd/m^2 & chromaticity at the zenith, & on the horizon facing away from & towards the sun
+        let sky = PhysicalSky::new(direction_from_angles(45.0, 0.0), 3.0);
+        let expected = [
+            (Vector3::newi(0, 1, 0), 7.3204, 0.2457, 0.2515),
+            (Vector3::newi(0, 0, -1), 7.9491, 0.3099, 0.3157),
+            (Vector3::newi(0, 0, 1), 14.336, 0.3320, 0.3291),
+        ];
+
+        for (direction, luminance, x, y) in expected.iter() {
+            let xyz = ColorSpace::Srgb.to_xyz(&sky.radiance(direction));
+            let sum = xyz.x + xyz.y + xyz.z;
+            assert!((xyz.y / SKY_SCALE / luminance - 1.0).abs() < 0.01, "{} vs {}", xyz.y / SKY_SCALE, luminance);
+            assert!((xyz.x / sum - x).abs() < 2e-3 && (xyz.y / sum - y).abs() < 2e-3);
+        }
+
+        // below the horizon still shines, & can be sampled
+        let below = Vector3::new(0.0, -0.5, -1.0);
+        assert!(sky.radiance(&below).y > 0.0 && sky.pdf(&below) > 0.0);
+    }
+}
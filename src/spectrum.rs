@@ -0,0 +1,357 @@
+use crate::{vectors::*, rays::Ray, scene::Scene, film::Film, integrator::Integrator, random::random_f32, color_space::to_working};
+use std::cell::Cell;
+
+
+// spectral rendering with hero wavelength sampling (wilkie et al.). every path carries three
+// wavelengths, the hero picked uniformly & the other two spread evenly around the range from
+// it, and a Color along the path holds its value at each of them instead of rgb. materials &
+// lights stay rgb, they get upsampled to spectra (smits) wherever a path looks at them, so the
+// integrators don't have to know which mode they run in. the film gets xyz -> the working space
+
+
+pub const LAMBDA_MIN: f32 = 380.0; // nm
+pub const LAMBDA_MAX: f32 = 720.0;
+const LAMBDA_RANGE: f32 = LAMBDA_MAX - LAMBDA_MIN;
+
+// wavelengths every path carries, so that a Color can hold one value per wavelength
+pub const SAMPLED_WAVELENGTHS: usize = 3;
+
+
+#[derive(Copy, Clone)]
+pub struct Wavelengths {
+    pub lambda: [f32; SAMPLED_WAVELENGTHS], // the hero first
+}
+
+
+impl Wavelengths {
+
+    pub fn sample(u: f32) -> Wavelengths {
+        let mut lambda = [0.0; SAMPLED_WAVELENGTHS];
+        for (i, l) in lambda.iter_mut().enumerate() {
+            let offset = u * LAMBDA_RANGE + i as f32 * LAMBDA_RANGE / SAMPLED_WAVELENGTHS as f32;
+            *l = LAMBDA_MIN + offset % LAMBDA_RANGE;
+        }
+
+        Wavelengths { lambda }
+    }
+
+    pub fn hero(&self) -> f32 {
+        self.lambda[0]
+    }
+
+    // every wavelength is as likely as the hero, uniform over the range
+    pub fn pdf(&self) -> f32 {
+        1.0 / LAMBDA_RANGE
+    }
+
+    // what an rgb color looks like at these wavelengths
+    pub fn sample_rgb(&self, c: &Color) -> Color {
+        Color::new(rgb_to_spectrum(c, self.lambda[0]), rgb_to_spectrum(c, self.lambda[1]), rgb_to_spectrum(c, self.lambda[2]))
+    }
+
+    // the working space rgb of a spectral sample, white balanced so a constant spectrum of 1 comes out (1, 1, 1)
+    pub fn to_rgb(self, values: &Color) -> Color {
+        let values = [values.x, values.y, values.z];
+        let mut xyz = Color::zeros();
+        for (l, v) in self.lambda.iter().zip(values.iter()) {
+            xyz += *v / (self.pdf() * SAMPLED_WAVELENGTHS as f32) * cie_xyz(*l);
+        }
+
+        to_working(&(xyz_to_srgb(&xyz) / white_rgb()))
+    }
+
+}
+
+
+
+thread_local! {
+    static WAVELENGTHS: Cell<Option<Wavelengths>> = const { Cell::new(None) };
+}
+
+
+// runs f with the path traced for these wavelengths, every color picked up along it is spectral
+pub fn with_wavelengths<R, F: FnOnce() -> R>(wavelengths: Wavelengths, f: F) -> R {
+    let previous = WAVELENGTHS.with(|w| w.replace(Some(wavelengths)));
+    let result = f();
+    WAVELENGTHS.with(|w| w.set(previous));
+
+    result
+}
+
+
+// None when rendering rgb
+pub fn current_wavelengths() -> Option<Wavelengths> {
+    WAVELENGTHS.with(|w| w.get())
+}
+
+
+// an rgb albedo or emission as the current path sees it. colors from wider gamuts than the
+// working space's clip to it
+pub fn sampled(c: &Color) -> Color {
+    match current_wavelengths() {
+        Some(wavelengths) => wavelengths.sample_rgb(&Color::new(c.x.max(0.0), c.y.max(0.0), c.z.max(0.0))),
+        None => {
+            let c = to_working(c);
+            Color::new(c.x.max(0.0), c.y.max(0.0), c.z.max(0.0))
+        }
+    }
+}
+
+
+// a physical quantity given at the red, green & blue wavelengths, e.g. an index of refraction or a
+// distance, as the current path sees it. it isn't a color, so it's neither clamped nor converted to
+// the working space: at the path's wavelengths, or as it is
+pub fn sampled_quantity(c: &Color) -> Color {
+    match current_wavelengths() {
+        Some(wavelengths) => wavelengths.sample_rgb(c),
+        None => *c,
+    }
+}
+
+
+// a reflectance worked out from quantities sampled_quantity gave, e.g. fresnel's, as a color: at the
+// path's wavelengths already, or linear srgb still to be converted to the working space
+pub fn computed_color(c: &Color) -> Color {
+    match current_wavelengths() {
+        Some(_) => *c,
+        None => to_working(c),
+    }
+}
+
+
+// throughput factor for a surface that sends each wavelength its own way, e.g. dispersive glass.
+// only the hero can follow the path on, so the others drop out & it stands in for all of them
+pub fn terminate_secondary() -> Color {
+    match current_wavelengths() {
+        Some(_) => Color::new(SAMPLED_WAVELENGTHS as f32, 0.0, 0.0),
+        None => Color::fromv(1.0),
+    }
+}
+
+
+// a quantity known as a function of wavelength, e.g. thin film interference, as the current path
+// sees it: at its wavelengths, or averaged into rgb through the color matching functions
+pub fn spectral<F: Fn(f32) -> f32>(f: F) -> Color {
+    const RGB_STEP: f32 = 10.0; // nm
+
+    match current_wavelengths() {
+        Some(wavelengths) => Color::new(f(wavelengths.lambda[0]), f(wavelengths.lambda[1]), f(wavelengths.lambda[2])),
+        None => {
+            let steps = (LAMBDA_RANGE / RGB_STEP) as u32;
+            let xyz = (0..steps).fold(Color::zeros(), |sum, i| {
+                let lambda = LAMBDA_MIN + (i as f32 + 0.5) * RGB_STEP;
+                sum + RGB_STEP * f(lambda) * cie_xyz(lambda)
+            });
+
+            to_working(&(xyz_to_srgb(&xyz) / white_rgb()))
+        }
+    }
+}
+
+
+// turns whatever reaches the film from the current path back into rgb
+pub fn film_rgb(c: &Color) -> Color {
+    match current_wavelengths() {
+        Some(wavelengths) => wavelengths.to_rgb(c),
+        None => *c,
+    }
+}
+
+
+
+// smits' "an rgb to spectrum conversion for reflectances": ten bins spread evenly over the range
+const SMITS_WHITE: [f32; 10] = [1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000, 1.0000];
+const SMITS_CYAN: [f32; 10] = [0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000, 0.0000];
+const SMITS_MAGENTA: [f32; 10] = [1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000, 0.9959];
+const SMITS_YELLOW: [f32; 10] = [0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685, 0.9840];
+const SMITS_RED: [f32; 10] = [0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149, 1.0149];
+const SMITS_GREEN: [f32; 10] = [0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000, 0.0025];
+const SMITS_BLUE: [f32; 10] = [1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483, 0.0496];
+
+
+fn smits_bin(table: &[f32; 10], lambda: f32) -> f32 {
+    let x = ((lambda - LAMBDA_MIN) / LAMBDA_RANGE * 9.0).clamp(0.0, 9.0);
+    let i = (x as usize).min(8);
+    let t = x - i as f32;
+
+    (1.0 - t) * table[i] + t * table[i + 1]
+}
+
+
+// white for the smallest component, then the primary & the secondary that make up the rest.
+// linear in c, so it does emission as well as reflectance
+pub fn rgb_to_spectrum(c: &Color, lambda: f32) -> f32 {
+    let (r, g, b) = (c.x, c.y, c.z);
+    let bin = |table| smits_bin(table, lambda);
+
+    if r <= g && r <= b {
+        r * bin(&SMITS_WHITE) + if g <= b {
+            (g - r) * bin(&SMITS_CYAN) + (b - g) * bin(&SMITS_BLUE)
+        } else {
+            (b - r) * bin(&SMITS_CYAN) + (g - b) * bin(&SMITS_GREEN)
+        }
+    } else if g <= r && g <= b {
+        g * bin(&SMITS_WHITE) + if r <= b {
+            (r - g) * bin(&SMITS_MAGENTA) + (b - r) * bin(&SMITS_BLUE)
+        } else {
+            (b - g) * bin(&SMITS_MAGENTA) + (r - b) * bin(&SMITS_RED)
+        }
+    } else {
+        b * bin(&SMITS_WHITE) + if r <= g {
+            (r - b) * bin(&SMITS_YELLOW) + (g - r) * bin(&SMITS_GREEN)
+        } else {
+            (g - b) * bin(&SMITS_YELLOW) + (r - g) * bin(&SMITS_RED)
+        }
+    }
+}
+
+
+
+// piecewise gaussian with different widths left & right of the peak
+fn lobe(lambda: f32, mu: f32, sigma_left: f32, sigma_right: f32) -> f32 {
+    let t = (lambda - mu) / if lambda < mu {sigma_left} else {sigma_right};
+    (-0.5 * t * t).exp()
+}
+
+
+// cie 1931 2 degree color matching functions, the multi-lobe fit of wyman, sloan & shirley
+pub fn cie_xyz(lambda: f32) -> Color {
+    Color::new(
+        1.056 * lobe(lambda, 599.8, 37.9, 31.0) + 0.362 * lobe(lambda, 442.0, 16.0, 26.7) - 0.065 * lobe(lambda, 501.1, 20.4, 26.2),
+        0.821 * lobe(lambda, 568.8, 46.9, 40.5) + 0.286 * lobe(lambda, 530.9, 16.3, 31.1),
+        1.217 * lobe(lambda, 437.0, 11.8, 36.0) + 0.681 * lobe(lambda, 459.0, 26.0, 13.8),
+    )
+}
+
+
+pub fn xyz_to_srgb(xyz: &Color) -> Color {
+    Color::new(
+         3.2406 * xyz.x - 1.5372 * xyz.y - 0.4986 * xyz.z,
+        -0.9689 * xyz.x + 1.8758 * xyz.y + 0.0415 * xyz.z,
+         0.0557 * xyz.x - 0.2040 * xyz.y + 1.0570 * xyz.z,
+    )
+}
+
+
+// linear srgb of a constant spectrum of 1 over the range, before white balancing
+fn white_rgb() -> Color {
+    thread_local! {
+        static WHITE: Color = {
+            let steps = LAMBDA_RANGE as u32;
+            let xyz = (0..steps).fold(Color::zeros(), |sum, i| sum + cie_xyz(LAMBDA_MIN + i as f32 + 0.5));
+            xyz_to_srgb(&xyz)
+        };
+    }
+
+    WHITE.with(|w| *w)
+}
+
+
+
+// renders with any integrator that traces its paths through li, a fresh set of wavelengths
+// per camera sample
+pub struct Spectral {
+    pub inner: Box<dyn Integrator>,
+}
+
+
+impl Spectral {
+    pub fn new(inner: Box<dyn Integrator>) -> Spectral {
+        Spectral { inner }
+    }
+}
+
+
+impl Integrator for Spectral {
+    fn li(&self, r: &Ray, scene: &Scene, film: &mut Film) -> Color {
+        let wavelengths = Wavelengths::sample(random_f32());
+        let radiance = with_wavelengths(wavelengths, || self.inner.li(r, scene, film));
+
+        wavelengths.to_rgb(&radiance)
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{hittable::HitRecord, hittable_list::HittableList, sphere::Sphere, materials::*, sky::GradientSky, camera::Camera, integrator::PathTracer};
+    use std::sync::Arc;
+
+    // the mean over many wavelength samples, i.e. what the film ends up with
+    fn round_trip(c: &Color, samples: u32) -> Color {
+        let mut sum = Color::zeros();
+        for i in 0..samples {
+            let wavelengths = Wavelengths::sample((i as f32 + 0.5) / samples as f32);
+            sum += wavelengths.to_rgb(&wavelengths.sample_rgb(c));
+        }
+        sum / samples as f32
+    }
+
+    #[test]
+    fn upsampled_colors_come_back_out() {
+        let colors = [Color::fromv(1.0), Color::fromv(0.5), Color::new(0.7, 0.3, 0.3), Color::new(0.2, 0.4, 0.8), Color::new(0.8, 0.6, 0.2)];
+
+        for c in colors.iter() {
+            let rgb = round_trip(c, 1000);
+            assert!((rgb - *c).magnitude() < 0.06, "{} {} {} came back as {} {} {}", c.x, c.y, c.z, rgb.x, rgb.y, rgb.z);
+        }
+    }
+
+    #[test]
+    fn hero_wavelengths_are_spread_over_the_range() {
+        let wavelengths = Wavelengths::sample(0.9);
+        let mut lambda = wavelengths.lambda;
+        lambda.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        assert!((wavelengths.hero() - (LAMBDA_MIN + 0.9 * LAMBDA_RANGE)).abs() < 1e-3);
+        assert!(lambda.iter().all(|l| (LAMBDA_MIN..LAMBDA_MAX).contains(l)));
+        for pair in lambda.windows(2) {
+            assert!((pair[1] - pair[0] - LAMBDA_RANGE / 3.0).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn spectral_path_tracing_matches_rgb_on_a_gray_scene() {
+        let mut world = HittableList::new();
+        world.add(Box::new(Sphere::new(Point3::new(0.0, -100.5, -1.0), 100.0, Arc::new(Lambertian::new(Color::fromv(0.5))))));
+        world.add(Box::new(Sphere::new(Point3::newi(0, 0, -1), 0.5, Arc::new(Lambertian::new(Color::fromv(0.7))))));
+        world.add(Box::new(Sphere::new(Point3::newi(-1, 0, -1), 0.5, Arc::new(Dielectric::new(1.5)))));
+        let sky = GradientSky::new(Color::fromv(1.0), Color::fromv(0.6));
+        let cam = Camera::new(Point3::zeros(), Point3::newi(0, 0, -1), Vector3::newi(0, 1, 0), 90.0, 1.0, 0.0, 1.0, 0.0, 0.0);
+        let scene = Scene::new(world, HittableList::new(), Some(Box::new(sky)), cam);
+
+        let rgb = PathTracer::new(50, 3);
+        let spectral = Spectral::new(Box::new(PathTracer::new(50, 3)));
+        let mut film = Film::new(2, 2);
+
+        for target in [Point3::newi(0, 0, -1), Point3::new(-1.0, 0.1, -1.0), Point3::new(0.0, -0.45, -1.0)].iter() {
+            let r = Ray::new(Point3::zeros(), *target, 0.0);
+            let mut difference = Color::zeros();
+
+            for _ in 0..20000 {
+                difference += rgb.li(&r, &scene, &mut film) - spectral.li(&r, &scene, &mut film);
+            }
+
+            let difference = difference / 20000;
+            assert!(difference.magnitude() < 0.02, "mean radiance differs by {} {} {}", difference.x, difference.y, difference.z);
+        }
+    }
+
+    #[test]
+    fn dispersive_glass_bends_blue_more_than_red() {
+        let glass = Dielectric::sellmeier(BK7_SELLMEIER_B, BK7_SELLMEIER_C);
+
+        assert!((glass.ir - 1.5168).abs() < 1e-3); // bk7's catalogue n_d
+        assert!(glass.index_at(450.0) > glass.index_at(650.0));
+        assert!(Dielectric::new(1.5).index_at(450.0) == 1.5);
+
+        // only the hero makes it through
+        let r = Ray::new(Point3::newi(0, 0, 2), Vector3::newi(0, 0, -1), 0.0);
+        let mut rec = HitRecord::new();
+        rec.set_face_normal(&r, Vector3::newi(0, 0, 1));
+        let through = with_wavelengths(Wavelengths::sample(0.3), || glass.scatter(&r, &rec).unwrap().1);
+        assert!(through.x == SAMPLED_WAVELENGTHS as f32 && through.y == 0.0 && through.z == 0.0);
+    }
+}
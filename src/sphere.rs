@@ -1,5 +1,6 @@
-use crate::{hittable::*, vectors::*, rays::*, materials::*};
-use std::sync::Arc;
+use crate::{hittable::*, vectors::*, rays::*, materials::*, onb::Onb};
+use crate::random::random_f32;
+use std::{sync::Arc, f32::consts::PI};
 
 
 pub struct Sphere {
@@ -18,6 +19,31 @@ impl Sphere {
 }
 
 
+// p is a point on the unit sphere. u goes around the y axis from x=-1, v from y=-1 to y=+1
+pub fn get_sphere_uv(p: &Point3) -> (f32, f32) {
+    let theta = (-p.y).clamp(-1.0, 1.0).acos();
+    let phi = (-p.z).atan2(p.x) + PI;
+
+    (phi / (2.0 * PI), theta / PI)
+}
+
+
+// derivatives of the point at outward_normal on a sphere of radius, with respect to get_sphere_uv's
+// u & v. dpdv is zero at the poles, where u isn't defined
+pub fn get_sphere_partials(outward_normal: &Point3, radius: f32) -> (Vector3, Vector3) {
+    let (x, y, z) = (outward_normal.x, outward_normal.y, outward_normal.z);
+    let dpdu = 2.0 * PI * radius * Vector3::new(z, 0.0, -x);
+
+    let sin_theta = (x * x + z * z).sqrt();
+    if sin_theta < 1e-6 {
+        return (dpdu, Vector3::zeros());
+    }
+    let dpdv = PI * radius * Vector3::new(-x * y / sin_theta, sin_theta, -y * z / sin_theta);
+
+    (dpdu, dpdv)
+}
+
+
 impl Hittable for Sphere {
 
     fn hit(&self, r: &Ray, t_min: f32, t_max: f32, rec: &mut HitRecord) -> bool {
@@ -44,10 +70,85 @@ impl Hittable for Sphere {
         rec.p = r.at(rec.t);
         let outward_normal = (rec.p - self.center) / self.radius;
         rec.set_face_normal(r, outward_normal);
+        let (u, v) = get_sphere_uv(&outward_normal);
+        rec.u = u;
+        rec.v = v;
+        let (dpdu, dpdv) = get_sphere_partials(&outward_normal, self.radius);
+        rec.dpdu = dpdu;
+        rec.dpdv = dpdv;
         rec.mat = self.mat.clone();
 
-        return true;
+        true
+
+    }
+
+
+    // the cone of directions subtended by the sphere, as seen from origin
+    fn pdf_value(&self, origin: &Point3, direction: &Vector3) -> f32 {
+        let mut rec = HitRecord::new();
+        if !self.hit(&Ray::new(*origin, *direction, 0.0), 0.001, f32::INFINITY, &mut rec) {
+            return 0.0;
+        }
+
+        let distance_squared = (self.center - *origin).magnitude_squared();
+        let cos_theta_max = (1.0 - self.radius * self.radius / distance_squared).max(0.0).sqrt();
+        let solid_angle = 2.0 * PI * (1.0 - cos_theta_max);
 
+        1.0 / solid_angle
     }
 
-}
\ No newline at end of file
+
+    fn random(&self, origin: &Point3) -> Vector3 {
+        let direction = self.center - *origin;
+        let distance_squared = direction.magnitude_squared();
+        let uvw = Onb::build_from_w(&direction);
+
+        let r1 = random_f32();
+        let r2 = random_f32();
+        let cos_theta_max = (1.0 - self.radius * self.radius / distance_squared).max(0.0).sqrt();
+        let z = 1.0 + r2 * (cos_theta_max - 1.0);
+        let phi = 2.0 * PI * r1;
+        let sin_theta = (1.0 - z * z).max(0.0).sqrt();
+
+        uvw.local(phi.cos() * sin_theta, phi.sin() * sin_theta, z)
+    }
+
+
+    fn sample_surface(&self) -> Option<(HitRecord, f32)> {
+        let outward_normal = Vector3::random_unit_vector();
+        let (u, v) = get_sphere_uv(&outward_normal);
+        let (dpdu, dpdv) = get_sphere_partials(&outward_normal, self.radius);
+
+        let rec = HitRecord {
+            p: self.center + self.radius * outward_normal,
+            normal: outward_normal,
+            t: 0.0,
+            u,
+            v,
+            dpdu,
+            dpdv,
+            front_face: true,
+            mat: self.mat.clone(),
+            velocity: Vector3::zeros(),
+            object: 0,
+        };
+
+        let pdf = self.surface_pdf(&rec.p);
+        Some((rec, pdf))
+    }
+
+
+    fn surface_pdf(&self, p: &Point3) -> f32 {
+        let distance = (*p - self.center).magnitude();
+        if (distance - self.radius).abs() > 1e-3 * self.radius {
+            return 0.0;
+        }
+
+        1.0 / (4.0 * PI * self.radius * self.radius)
+    }
+
+    fn materials(&self) -> Vec<Arc<dyn Material>> {
+        vec![self.mat.clone()]
+    }
+
+}
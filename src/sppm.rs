@@ -0,0 +1,388 @@
+use crate::{
+            vectors::*,
+            rays::Ray,
+            hittable::*,
+            scene::Scene,
+            film::Film,
+            onb::*,
+            integrator::*,
+            random::*,
+            sampler::Sampler,
+            denoise::Features,
+            aov::*,
+};
+use std::{f32::consts::PI, mem, sync::Arc};
+
+
+// how much of the photon count a pixel keeps each pass, trading blur for noise (2/3 as in the paper)
+const ALPHA: f32 = 2.0 / 3.0;
+
+
+// stochastic progressive photon mapping (hachisuka & jensen). every pass follows one camera
+// path per pixel to its first diffuse hit, shoots a batch of photons from the lights into a
+// hash grid & gathers the ones that land near each pixel's hit. the gather radius shrinks
+// from pass to pass, so the blur fades out & the estimate converges, caustics included.
+// the sky only lights things directly here, photons only come from the scene's lights
+pub struct Sppm {
+    passes: u32,
+    photons_per_pass: u32,
+    max_stored: usize, // photons one pass may keep, which bounds the memory used
+    initial_radius: f32,
+    max_depth: u32,
+}
+
+
+struct Photon {
+    p: Point3,
+    direction: Vector3, // the way it was travelling
+    power: Color,
+}
+
+
+// the diffuse hit a pixel's camera path ended on this pass
+struct VisiblePoint {
+    rec: HitRecord,
+    r_in: Ray,
+    beta: Color, // camera path throughput up to rec
+}
+
+
+// what a pixel has collected over all passes
+struct PixelStats {
+    direct: Color, // summed over the passes
+    radius: f32,
+    photons: f32, // photon count n in the paper, shrunk along with the radius
+    tau: Color, // flux gathered inside the current radius
+}
+
+
+impl PixelStats {
+
+    // folds in the m photons carrying flux phi found during a pass, then shrinks the radius
+    // so the density stays consistent
+    fn update(&mut self, phi: Color, m: u32) {
+        if m == 0 {
+            return;
+        }
+
+        let photons = self.photons + ALPHA * m as f32;
+        let radius = self.radius * (photons / (self.photons + m as f32)).sqrt();
+
+        self.tau = (self.tau + phi) * (radius * radius) / (self.radius * self.radius);
+        self.photons = photons;
+        self.radius = radius;
+    }
+
+}
+
+
+// photons bucketed by the grid cell they're in. cells are at least as big as any gather
+// radius, so a lookup only needs to check the cells next to it
+struct PhotonGrid {
+    cell_size: f32,
+    starts: Vec<usize>, // bucket b holds photons[starts[b]..starts[b+1]]
+    photons: Vec<Photon>,
+}
+
+
+impl PhotonGrid {
+
+    fn new(mut photons: Vec<Photon>, cell_size: f32) -> PhotonGrid {
+        let mut grid = PhotonGrid { cell_size, starts: vec![0; photons.len().max(1) + 1], photons: Vec::new() };
+
+        photons.sort_by_cached_key(|photon| grid.bucket(grid.cell(&photon.p)));
+        for photon in photons.iter() {
+            let b = grid.bucket(grid.cell(&photon.p));
+            grid.starts[b + 1] += 1;
+        }
+        for b in 1..grid.starts.len() {
+            grid.starts[b] += grid.starts[b - 1];
+        }
+
+        grid.photons = photons;
+        grid
+    }
+
+    fn cell(&self, p: &Point3) -> (i32, i32, i32) {
+        ((p.x / self.cell_size).floor() as i32, (p.y / self.cell_size).floor() as i32, (p.z / self.cell_size).floor() as i32)
+    }
+
+    fn bucket(&self, (x, y, z): (i32, i32, i32)) -> usize {
+        let hash = (x as u32).wrapping_mul(73856093) ^ (y as u32).wrapping_mul(19349663) ^ (z as u32).wrapping_mul(83492791);
+        hash as usize % (self.starts.len() - 1)
+    }
+
+    // calls found for every photon within radius of p
+    fn gather<F: FnMut(&Photon)>(&self, p: &Point3, radius: f32, mut found: F) {
+        let (x0, y0, z0) = self.cell(&(*p - Vector3::fromv(radius)));
+        let (x1, y1, z1) = self.cell(&(*p + Vector3::fromv(radius)));
+
+        // neighbouring cells can share a bucket, each bucket must only be looked through once
+        let mut buckets = Vec::new();
+        for x in x0..=x1 {
+            for y in y0..=y1 {
+                for z in z0..=z1 {
+                    buckets.push(self.bucket((x, y, z)));
+                }
+            }
+        }
+        buckets.sort_unstable();
+        buckets.dedup();
+
+        for b in buckets {
+            for photon in self.photons[self.starts[b]..self.starts[b + 1]].iter() {
+                if (photon.p - *p).magnitude_squared() <= radius * radius {
+                    found(photon);
+                }
+            }
+        }
+    }
+
+}
+
+
+impl Sppm {
+
+    // memory is in bytes, for the photons of one pass
+    pub fn new(passes: u32, photons_per_pass: u32, memory: usize, initial_radius: f32, max_depth: u32) -> Sppm {
+        Sppm { passes, photons_per_pass, max_stored: memory / mem::size_of::<Photon>(), initial_radius, max_depth }
+    }
+
+    // follows r through specular bounces to the first diffuse hit. returns the light picked up
+    // on the way plus what reaches that hit directly, & the hit itself for the photons to land near
+    fn visible_point(&self, r: &Ray, scene: &Scene) -> (Color, Option<VisiblePoint>) {
+        let mut l = Color::zeros();
+        let mut beta = Color::fromv(1.0);
+        let mut ray = *r;
+
+        for _ in 0..self.max_depth {
+            let mut rec = HitRecord::new();
+
+            if !scene.world.hit(&ray, 0.001, f32::INFINITY, &mut rec) {
+                return (l + beta * scene.background(&ray.direction), None);
+            }
+
+            l += beta * rec.mat.emitted(&rec);
+
+            if !rec.mat.is_specular() {
+                l += beta * estimate_direct(&ray, &rec, scene);
+                return (l, Some(VisiblePoint { rec, r_in: ray, beta }));
+            }
+
+            let (scatter_ray, attenuation) = match rec.mat.scatter(&ray, &rec) {
+                Some(scattered) => scattered,
+                None => break,
+            };
+
+            beta *= attenuation;
+            ray = scatter_ray;
+        }
+
+        (l, None)
+    }
+
+    // traces photon paths until the pass's count or its memory runs out, returns how many were emitted.
+    // photons are kept from the second bounce on, direct light is already handled by visible_point
+    fn trace_photons(&self, scene: &Scene, photons: &mut Vec<Photon>) -> u32 {
+        let mut emitted = 0;
+
+        // a path that might not fit is never started, so no path gets cut short
+        while emitted < self.photons_per_pass && photons.len() + self.max_depth as usize <= self.max_stored {
+            emitted += 1;
+
+            let (rec, pdf_pos) = match scene.lights.sample_surface() {
+                Some(sample) => sample,
+                None => return 0,
+            };
+            if pdf_pos <= 0.0 {
+                continue;
+            }
+
+            // cosine weighted emission, its cosine & pdf cancel to pi
+            let d = random_cosine_direction();
+            let direction = Onb::build_from_w(&rec.normal).local(d.x, d.y, d.z);
+            let mut beta = PI / pdf_pos * rec.mat.emitted(&rec);
+            let mut ray = Ray::new(rec.p, direction, scene.camera.shutter_time());
+
+            for depth in 0..self.max_depth {
+                let mut rec = HitRecord::new();
+                if !scene.world.hit(&ray, 0.001, f32::INFINITY, &mut rec) {
+                    break;
+                }
+
+                if depth > 0 && !rec.mat.is_specular() {
+                    photons.push(Photon { p: rec.p, direction: ray.direction, power: beta });
+                }
+
+                let (scatter_ray, attenuation) = match rec.mat.scatter(&ray, &rec) {
+                    Some(scattered) => scattered,
+                    None => break,
+                };
+
+                beta *= attenuation;
+                if depth >= 3 && !russian_roulette(&mut beta) {
+                    break;
+                }
+                ray = scatter_ray;
+            }
+        }
+
+        emitted
+    }
+
+    // flux the photons near vp carry towards the camera, & how many there were
+    fn gather(&self, grid: &PhotonGrid, vp: &VisiblePoint, radius: f32) -> (Color, u32) {
+        let mut phi = Color::zeros();
+        let mut m = 0;
+
+        grid.gather(&vp.rec.p, radius, |photon| {
+            // eval includes the cosine at the visible point, photon density estimates don't
+            let wi = -photon.direction.normalized();
+            let cosine = Vector3::dot(&vp.rec.normal, &wi);
+            if cosine <= 0.0 {
+                return;
+            }
+
+            phi += vp.rec.mat.eval(&vp.r_in, &vp.rec, &wi) / cosine * photon.power;
+            m += 1;
+        });
+
+        (vp.beta * phi, m)
+    }
+
+}
+
+
+impl Integrator for Sppm {
+
+    // only what the camera path sees by itself, the photons come in through render
+    fn li(&self, r: &Ray, scene: &Scene, _film: &mut Film) -> Color {
+        self.visible_point(r, scene).0
+    }
+
+    // one camera sample per pixel per pass, so passes takes the place of samples_per_pixel
+    fn render(&self, scene: &Scene, film: &mut Film, _sampler: &Arc<dyn Sampler>, _samples_per_pixel: u32) {
+        let mut stats: Vec<PixelStats> = (0..film.width * film.height)
+            .map(|_| PixelStats { direct: Color::zeros(), radius: self.initial_radius, photons: 0.0, tau: Color::zeros() })
+            .collect();
+        let mut emitted: u64 = 0;
+
+        for pass in 0..self.passes {
+            eprintln!("\rPasses remaining - {}", self.passes - pass);
+
+            let mut points = Vec::with_capacity(stats.len());
+            for j in 0..film.height {
+                for i in 0..film.width {
+                    let u = (i as f32 + random_f32()) / ((film.width - 1) as f32);
+                    let v = (j as f32 + random_f32()) / ((film.height - 1) as f32);
+
+                    let r = scene.camera.get_ray(u, v);
+                    let (l, vp) = self.visible_point(&r, scene);
+                    stats[(j * film.width + i) as usize].direct += l;
+                    if film.gather_features {
+                        film.add_features(i, j, Features::of_first_hit(scene, &r));
+                    }
+                    if !film.aovs.is_empty() {
+                        let values = sample_aovs(&film.aovs, scene, &r, &LightPaths::zeros(), film.width, film.height);
+                        film.add_aovs(i, j, &values);
+                    }
+                    points.push(vp);
+                }
+            }
+
+            let mut photons = Vec::new();
+            emitted += self.trace_photons(scene, &mut photons) as u64;
+
+            let max_radius = stats.iter().fold(0.0, |r: f32, s| r.max(s.radius));
+            let grid = PhotonGrid::new(photons, max_radius);
+
+            for (s, vp) in stats.iter_mut().zip(points.iter()) {
+                if let Some(vp) = vp {
+                    let (phi, m) = self.gather(&grid, vp, s.radius);
+                    s.update(phi, m);
+                }
+            }
+        }
+
+        for j in 0..film.height {
+            for i in 0..film.width {
+                let s = &stats[(j * film.width + i) as usize];
+                let mut l = s.direct / self.passes.max(1) as f32;
+                if emitted > 0 {
+                    l += s.tau / (emitted as f32 * PI * s.radius * s.radius);
+                }
+                film.add_sample(i, j, l);
+            }
+        }
+    }
+
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{hittable_list::HittableList, sphere::Sphere, materials::*, camera::Camera, bdpt::Bdpt, render::render, sampler::Independent};
+
+    // a glass ball focusing an out of view lamp into its own shadow, seen from above
+    fn caustic_scene() -> Scene {
+        let mut world = HittableList::new();
+        let mut lights = HittableList::new();
+
+        world.add(Box::new(Sphere::new(Point3::new(0.0, -1000.0, 0.0), 1000.0, Arc::new(Lambertian::new(Color::fromv(0.7))))));
+        world.add(Box::new(Sphere::new(Point3::newi(0, 1, 0), 1.0, Arc::new(Dielectric::new(1.5)))));
+
+        let lamp = Arc::new(DiffuseLight::new(Color::fromv(15.0)));
+        world.add(Box::new(Sphere::new(Point3::new(0.0, 6.0, -4.0), 1.0, lamp.clone())));
+        lights.add(Box::new(Sphere::new(Point3::new(0.0, 6.0, -4.0), 1.0, lamp)));
+
+        let cam = Camera::new(Point3::newi(0, 4, 4), Point3::newi(0, 0, 1), Vector3::newi(0, 1, 0), 30.0, 1.5, 0.0, 5.0, 0.0, 0.0);
+        Scene::new(world, lights, None, cam)
+    }
+
+    fn image_mean(film: &Film) -> Color {
+        let mut mean = Color::zeros();
+        for j in 0..film.height {
+            for i in 0..film.width {
+                mean += film.pixel(i, j) / (film.width * film.height) as f32;
+            }
+        }
+        mean
+    }
+
+    #[test]
+    fn grid_finds_exactly_the_photons_in_range() {
+        let points: Vec<Point3> = (0..2000).map(|_| Point3::random_by_range(-1.0, 1.0)).collect();
+        let photons = points.iter().map(|p| Photon { p: *p, direction: Vector3::newi(0, -1, 0), power: Color::fromv(1.0) }).collect();
+        let grid = PhotonGrid::new(photons, 0.2);
+
+        for _ in 0..100 {
+            let center = Point3::random_by_range(-1.0, 1.0);
+            let radius = random_range(0.01, 0.2);
+
+            let mut found = 0;
+            grid.gather(&center, radius, |_| found += 1);
+
+            let expected = points.iter().filter(|p| (**p - center).magnitude_squared() <= radius * radius).count();
+            assert_eq!(found, expected);
+        }
+    }
+
+    #[test]
+    fn converges_to_bidirectional_path_tracing_on_caustics() {
+        let scene = caustic_scene();
+
+        let mut reference = Film::new(12, 8);
+        let sampler: Arc<dyn Sampler> = Arc::new(Independent);
+        render(&scene, &Bdpt::new(8), &mut reference, &sampler, 512);
+        let mut photon_mapped = Film::new(12, 8);
+        Sppm::new(256, 5000, 1 << 24, 0.2, 8).render(&scene, &mut photon_mapped, &sampler, 256);
+
+        let expected = image_mean(&reference);
+        let actual = image_mean(&photon_mapped);
+        let difference = expected - actual;
+
+        assert!(difference.magnitude() < 0.08 * expected.magnitude(),
+                "means differ: {} {} {} vs {} {} {}", expected.x, expected.y, expected.z, actual.x, actual.y, actual.z);
+    }
+}
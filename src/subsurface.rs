@@ -0,0 +1,234 @@
+use crate::{
+            vectors::*,
+            rays::Ray,
+            hittable::*,
+            materials::*,
+            colors::average,
+            random::random_f32,
+            spectrum,
+};
+use std::sync::Arc;
+
+
+// light that gets into the object wanders around inside it before coming back out somewhere
+// else (skin, wax, marble). a random walk through a homogeneous, isotropically scattering medium
+// filling boundary, a closed copy of the object's own shape, like the copies scene.lights keeps.
+// the surface itself is a dielectric interface, smooth or rough, that light crosses on the way in
+// & out or reflects off. the walk picks a channel to sample each distance from, in proportion to
+// its throughput, & weights all of them by how likely any channel was to get there (spectral mis),
+// so chromatic mean free paths don't need a walk per channel
+pub struct Subsurface {
+    pub boundary: Arc<dyn Hittable>,
+    pub albedo: Color, // single scattering albedo
+    pub mean_free_path: Color, // in scene units
+    pub interface: Dielectric,
+    pub max_steps: u32,
+}
+
+
+fn exp(c: &Color) -> Color {
+    Color::new(c.x.exp(), c.y.exp(), c.z.exp())
+}
+
+
+fn channel(c: &Color, i: usize) -> f32 {
+    [c.x, c.y, c.z][i]
+}
+
+
+fn sum(c: &Color) -> f32 {
+    c.x + c.y + c.z
+}
+
+
+fn pick_channel(probabilities: &Color, u: f32) -> usize {
+    if u < probabilities.x {
+        0
+    } else if u < probabilities.x + probabilities.y {
+        1
+    } else {
+        2
+    }
+}
+
+
+impl Subsurface {
+
+    pub fn new(boundary: Arc<dyn Hittable>, albedo: Color, mean_free_path: Color, ior: f32) -> Subsurface {
+        Subsurface { boundary, albedo, mean_free_path, interface: Dielectric::new(ior), max_steps: 1024 }
+    }
+
+    pub fn with_interface(mut self, interface: Dielectric) -> Subsurface {
+        self.interface = interface;
+        self
+    }
+
+    // extinction per unit distance. a mean free path is a distance in each channel, not a color, so it
+    // doesn't go through the working space
+    fn sigma_t(&self) -> Color {
+        Color::fromv(1.0) / spectrum::sampled_quantity(&self.mean_free_path)
+    }
+
+    // crosses the interface, walks inside for as long as it takes to get back out, & returns the
+    // ray leaving the surface with its throughput. None if the light never makes it out
+    fn walk(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Ray, Color)> {
+        let (mut ray, mut throughput) = self.interface.scatter(r_in, rec)?;
+
+        // reflected off the surface, or hit from inside, in which case there's nothing to walk through
+        if Vector3::dot(&ray.direction, &rec.normal) > 0.0 || !rec.front_face {
+            return Some((ray, throughput));
+        }
+
+        let sigma_t = self.sigma_t();
+        let albedo = spectrum::sampled(&self.albedo);
+        let mut on_surface = true;
+
+        for _ in 0..self.max_steps {
+            // channels are picked by how much they still carry, so the weights stay tame
+            let probabilities = throughput / (3.0 * average(&throughput));
+            let c = pick_channel(&probabilities, random_f32());
+            let distance = -(1.0 - random_f32()).ln() / channel(&sigma_t, c);
+
+            // scattering can happen right next to the surface, only rays leaving it need to skip it
+            let t_min = if on_surface {0.001} else {0.0};
+            let mut exit = HitRecord::new();
+            if self.boundary.hit(&ray, t_min, distance, &mut exit) {
+                // got to the surface: the chance of getting this far, averaged over the channels
+                let transmittance = exp(&(-exit.t * sigma_t));
+                throughput *= transmittance / sum(&(probabilities * transmittance));
+
+                let (out, weight) = self.interface.scatter(&ray, &exit)?;
+                throughput *= weight;
+
+                // out through the surface, or reflected back in to keep walking
+                if Vector3::dot(&out.direction, &exit.normal) < 0.0 {
+                    return Some((out, throughput));
+                }
+                ray = out;
+                on_surface = true;
+            } else {
+                // scattered inside, in a new direction
+                let transmittance = exp(&(-distance * sigma_t));
+                throughput *= albedo * sigma_t * transmittance / sum(&(probabilities * sigma_t * transmittance));
+                ray = Ray::new(ray.at(distance), Vector3::random_unit_vector(), ray.time);
+                on_surface = false;
+            }
+
+            if average(&throughput) <= 0.0 {
+                return None;
+            }
+        }
+
+        None
+    }
+
+}
+
+
+impl Material for Subsurface {
+    // the ray leaves from wherever the walk came out, not from rec.p
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Ray, Color)> {
+        self.walk(r_in, rec)
+    }
+
+    // there's no bsdf to evaluate towards a light, paths only find them by leaving the object
+    fn is_specular(&self) -> bool {
+        true
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{sphere::Sphere, layered::Mix, texture::constant, color_space::*};
+
+    fn hit_sphere(material: &Subsurface) -> (Ray, HitRecord) {
+        let r = Ray::new(Point3::new(0.0, 0.0, 5.0), Vector3::newi(0, 0, -1), 0.0);
+        let mut rec = HitRecord::new();
+        assert!(material.boundary.hit(&r, 0.001, f32::INFINITY, &mut rec));
+        (r, rec)
+    }
+
+    fn ball(albedo: Color, mean_free_path: Color, ior: f32) -> Subsurface {
+        let boundary = Arc::new(Sphere::new(Point3::zeros(), 1.0, Arc::new(Lambertian::new(Color::zeros()))));
+        Subsurface::new(boundary, albedo, mean_free_path, ior)
+    }
+
+    // mean throughput of the walks, & the mean cosine of where they come out against the incoming ray
+    fn walk_statistics(material: &Subsurface, samples: u32) -> (Color, f32) {
+        let (r, rec) = hit_sphere(material);
+        let (mut albedo, mut exit_cos) = (Color::zeros(), 0.0);
+
+        for _ in 0..samples {
+            if let Some((scattered, weight)) = material.scatter(&r, &rec) {
+                albedo += weight;
+                exit_cos += scattered.origin.z;
+            }
+        }
+        (albedo / samples as f32, exit_cos / samples as f32)
+    }
+
+    #[test]
+    fn nothing_is_lost_without_absorption() {
+        // index matched, so every walk comes out eventually, whatever the colors of the mean free path
+        let (albedo, _) = walk_statistics(&ball(Color::fromv(1.0), Color::new(0.05, 0.1, 0.3), 1.0), 20_000);
+        assert!((albedo - Color::fromv(1.0)).magnitude() < 0.03, "albedo {} {} {}", albedo.x, albedo.y, albedo.z);
+
+        // with a dielectric surface some light is trapped for longer, but still gets out
+        let (albedo, _) = walk_statistics(&ball(Color::fromv(1.0), Color::fromv(0.1), 1.4), 20_000);
+        assert!((albedo - Color::fromv(1.0)).magnitude() < 0.03, "albedo {} {} {}", albedo.x, albedo.y, albedo.z);
+    }
+
+    #[test]
+    fn walks_keep_their_weight_inside_other_materials() {
+        // the walk's weight comes back with its ray, wrapping it changes nothing & neither does
+        // another walk starting before the first one's weight is used
+        let ball = Arc::new(ball(Color::fromv(1.0), Color::new(0.05, 0.1, 0.3), 1.0));
+        let wrapped = Mix::new(ball.clone(), ball.clone(), constant(0.5));
+        let (r, rec) = hit_sphere(&ball);
+
+        let mut albedo = Color::zeros();
+        for _ in 0..20_000 {
+            let first = wrapped.scatter(&r, &rec);
+            let _second = ball.scatter(&r, &rec);
+            if let Some((_, weight)) = first {
+                albedo += weight / 20_000.0;
+            }
+        }
+        assert!((albedo - Color::fromv(1.0)).magnitude() < 0.03, "albedo {} {} {}", albedo.x, albedo.y, albedo.z);
+    }
+
+    #[test]
+    fn light_spreads_further_the_longer_the_mean_free_path() {
+        // a clear ball lets the light straight through to the far side
+        let (_, clear) = walk_statistics(&ball(Color::fromv(1.0), Color::fromv(100.0), 1.0), 2_000);
+        assert!(clear < -0.9, "mean exit z {}", clear);
+
+        // a dense one sends it back out near where it came in
+        let (_, dense) = walk_statistics(&ball(Color::fromv(1.0), Color::fromv(0.01), 1.0), 2_000);
+        assert!(dense > 0.9, "mean exit z {}", dense);
+    }
+
+    #[test]
+    fn absorption_is_per_channel() {
+        let (albedo, _) = walk_statistics(&ball(Color::new(0.99, 0.9, 0.5), Color::fromv(0.05), 1.0), 20_000);
+        assert!(albedo.x > albedo.y && albedo.y > albedo.z && albedo.z > 0.0);
+        assert!(albedo.x < 1.0);
+
+        // a semi infinite slab of single scattering albedo 0.9 reflects a little over 0.4, more than
+        // the 0.39 diffusion theory gives, as it's least accurate near the surface. the ball's radius is
+        // 20 mean free paths, near enough to a slab
+        assert!((albedo.y - 0.44).abs() < 0.04, "albedo {}", albedo.y);
+    }
+
+    #[test]
+    fn mean_free_paths_stay_out_of_the_working_space() {
+        let ball = ball(Color::fromv(0.9), Color::new(0.05, 0.5, 2.0), 1.0);
+
+        // a distance per channel, whatever space the colors are converted to
+        let sigma_t = with_working_space(ColorSpace::AcesCg, || ball.sigma_t());
+        assert!((sigma_t - Color::new(20.0, 2.0, 0.5)).magnitude() < 1e-4);
+    }
+}
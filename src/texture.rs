@@ -0,0 +1,218 @@
+use crate::{vectors::*, color_space::*};
+use std::{fs, io, sync::Arc};
+
+
+// a color that varies over a surface, looked up by the hit's surface coordinates & position.
+// scalar material parameters are textures too, read through their first channel. colors are linear
+// srgb like the rest of the scene's, textures declared in other spaces convert when they're made
+pub trait Texture: Send + Sync {
+    fn value(&self, u: f32, v: f32, p: &Point3) -> Color;
+
+    fn scalar(&self, u: f32, v: f32, p: &Point3) -> f32 {
+        self.value(u, v, p).x
+    }
+}
+
+
+
+pub struct SolidColor {
+    pub color: Color,
+}
+
+
+impl SolidColor {
+    pub fn new(color: Color) -> SolidColor {
+        SolidColor { color }
+    }
+}
+
+
+impl Texture for SolidColor {
+    fn value(&self, _u: f32, _v: f32, _p: &Point3) -> Color {
+        self.color
+    }
+}
+
+
+// shorthands for constant textures, the common case
+pub fn solid(color: Color) -> Arc<dyn Texture> {
+    Arc::new(SolidColor::new(color))
+}
+
+
+// a constant color given in another space, e.g. acescg values from a different package
+pub fn solid_in(color: Color, space: ColorSpace) -> Arc<dyn Texture> {
+    solid(space.to_srgb(&color))
+}
+
+
+pub fn constant(value: f32) -> Arc<dyn Texture> {
+    solid(Color::fromv(value))
+}
+
+
+
+pub type TextureFn = dyn Fn(f32, f32, &Point3) -> Color + Send + Sync;
+
+
+// any function of the surface coordinates & position, for patterns & height fields written in code
+pub struct Procedural {
+    pub f: Box<TextureFn>,
+}
+
+
+impl Procedural {
+    pub fn new<F: Fn(f32, f32, &Point3) -> Color + Send + Sync + 'static>(f: F) -> Procedural {
+        Procedural { f: Box::new(f) }
+    }
+}
+
+
+impl Texture for Procedural {
+    fn value(&self, u: f32, v: f32, p: &Point3) -> Color {
+        (self.f)(u, v, p)
+    }
+}
+
+
+
+// alternates between two textures in 3d, cells 1 / scale across
+pub struct Checker {
+    pub even: Arc<dyn Texture>,
+    pub odd: Arc<dyn Texture>,
+    pub scale: f32,
+}
+
+
+impl Checker {
+    pub fn new(even: Arc<dyn Texture>, odd: Arc<dyn Texture>, scale: f32) -> Checker {
+        Checker { even, odd, scale }
+    }
+}
+
+
+impl Texture for Checker {
+    fn value(&self, u: f32, v: f32, p: &Point3) -> Color {
+        let cell = (self.scale * p.x).floor() + (self.scale * p.y).floor() + (self.scale * p.z).floor();
+
+        if cell as i64 % 2 == 0 {self.even.value(u, v, p)} else {self.odd.value(u, v, p)}
+    }
+}
+
+
+
+// an 8 bit rgb image over u & v, decoded to linear light by its transfer & converted from its space
+// once, up front. rows run top to bottom like an image file's, so v = 1 is the first row
+pub struct ImageTexture {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<Color>,
+}
+
+
+impl ImageTexture {
+
+    // data is width * height rgb triples. color maps are srgb encoded, data like roughness or
+    // normal maps linear
+    pub fn new(width: u32, height: u32, data: &[u8], space: ColorSpace, transfer: Transfer) -> ImageTexture {
+        let decode = |b: u8| transfer.decode(b as f32 / 255.0);
+        let pixels = data.chunks_exact(3)
+            .map(|rgb| space.to_srgb(&Color::new(decode(rgb[0]), decode(rgb[1]), decode(rgb[2]))))
+            .collect();
+
+        ImageTexture { width, height, pixels }
+    }
+
+    // a binary (p6) or plain (p3) ppm with 8 bit samples
+    pub fn load_ppm(path: &str, space: ColorSpace, transfer: Transfer) -> io::Result<ImageTexture> {
+        let bytes = fs::read(path)?;
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path, message));
+
+        // the header's four fields, skipping whitespace & comments, & where the samples start
+        let (mut fields, mut at) = (Vec::new(), 0);
+        while fields.len() < 4 {
+            while at < bytes.len() && (bytes[at].is_ascii_whitespace() || bytes[at] == b'#') {
+                if bytes[at] == b'#' {
+                    while at < bytes.len() && bytes[at] != b'\n' {
+                        at += 1;
+                    }
+                } else {
+                    at += 1;
+                }
+            }
+            let start = at;
+            while at < bytes.len() && !bytes[at].is_ascii_whitespace() {
+                at += 1;
+            }
+            if start == at {
+                return Err(invalid("truncated header"));
+            }
+            fields.push(String::from_utf8_lossy(&bytes[start..at]).into_owned());
+        }
+
+        let number = |field: &str| field.parse::<u32>().map_err(|_| invalid("bad header"));
+        let (width, height, max) = (number(&fields[1])?, number(&fields[2])?, number(&fields[3])?);
+        if max != 255 {
+            return Err(invalid("only 8 bit samples are supported"));
+        }
+        let count = (width * height * 3) as usize;
+
+        let data: Vec<u8> = match fields[0].as_str() {
+            // a single whitespace byte ends the header
+            "P6" => bytes.get(at + 1..at + 1 + count).ok_or_else(|| invalid("truncated samples"))?.to_vec(),
+            "P3" => String::from_utf8_lossy(&bytes[at..]).split_whitespace().take(count)
+                .map(|v| v.parse::<u8>().map_err(|_| invalid("bad sample")))
+                .collect::<io::Result<Vec<u8>>>()?,
+            _ => return Err(invalid("not a ppm")),
+        };
+        if data.len() < count {
+            return Err(invalid("truncated samples"));
+        }
+
+        Ok(ImageTexture::new(width, height, &data, space, transfer))
+    }
+
+    fn texel(&self, i: i64, j: i64) -> Color {
+        // wraps around, so textures tile
+        let (i, j) = (i.rem_euclid(self.width as i64), j.rem_euclid(self.height as i64));
+        self.pixels[(j * self.width as i64 + i) as usize]
+    }
+
+}
+
+
+impl Texture for ImageTexture {
+    // bilinear between the four nearest texels
+    fn value(&self, u: f32, v: f32, _p: &Point3) -> Color {
+        let x = u * self.width as f32 - 0.5;
+        let y = (1.0 - v) * self.height as f32 - 0.5;
+        let (i, j) = (x.floor() as i64, y.floor() as i64);
+        let (fx, fy) = (x - x.floor(), y - y.floor());
+
+        (1.0 - fy) * ((1.0 - fx) * self.texel(i, j) + fx * self.texel(i + 1, j))
+            + fy * ((1.0 - fx) * self.texel(i, j + 1) + fx * self.texel(i + 1, j + 1))
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn images_decode_to_linear_srgb() {
+        // a 2 x 1 image, srgb middle gray & acescg red
+        let image = ImageTexture::new(2, 1, &[188, 188, 188, 255, 0, 0], ColorSpace::Srgb, Transfer::Srgb);
+        let gray = image.value(0.25, 0.5, &Point3::zeros());
+        assert!((gray - Color::fromv(0.5)).magnitude() < 1e-2);
+
+        let red = ImageTexture::new(1, 1, &[255, 0, 0], ColorSpace::AcesCg, Transfer::Linear).value(0.5, 0.5, &Point3::zeros());
+        assert!((red - ColorSpace::AcesCg.to_srgb(&Color::newi(1, 0, 0))).magnitude() < 1e-6);
+        assert!(red.x > 1.0 && red.y < 0.0);
+
+        // halfway between the texels, & wrapping round at the edge
+        assert!((image.value(0.5, 0.5, &Point3::zeros()).x - (gray.x + 1.0) / 2.0).abs() < 1e-5);
+        assert!((image.value(0.0, 0.5, &Point3::zeros()).x - (gray.x + 1.0) / 2.0).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,161 @@
+use crate::{
+            vectors::*,
+            rays::Ray,
+            hittable::HitRecord,
+            materials::*,
+            microfacet::fresnel_dielectric,
+            onb::random_cosine_direction,
+            colors::average,
+            random::random_f32,
+            spectrum,
+};
+use std::f32::consts::PI;
+
+
+// materials for sheets too thin to model as two surfaces, that light crosses in one hit & leaves
+// on the far side without being displaced
+
+
+
+// a pane of glass or a soap film, as a single surface: light reflects off it, or passes straight
+// through. the bounces between its two faces are summed into the reflectance, so a pane reflects
+// about twice what one face of it does
+pub struct ThinDielectric {
+    pub ir: f32,
+}
+
+
+impl ThinDielectric {
+
+    pub fn new(index_of_refraction: f32) -> ThinDielectric {
+        ThinDielectric { ir: index_of_refraction }
+    }
+
+    // both faces reflect r, & whatever they let through bounces between them r + t r t + t r r r t + ...
+    pub fn reflectance(&self, cos_theta: f32) -> f32 {
+        let r = fresnel_dielectric(cos_theta.abs().min(1.0), self.ir);
+        if r < 1.0 {r + (1.0 - r) * (1.0 - r) * r / (1.0 - r * r)} else {1.0}
+    }
+
+}
+
+
+impl Material for ThinDielectric {
+    // reflection & transmission are picked in proportion to how much each carries, so either has weight 1
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Ray, Color)> {
+        let unit_direction = r_in.direction.normalized();
+        let cos_theta = Vector3::dot(&-unit_direction, &rec.normal);
+
+        let direction = if random_f32() < self.reflectance(cos_theta) {
+            Vector3::reflect(&unit_direction, &rec.normal)
+        } else {
+            unit_direction
+        };
+
+        Some((Ray::new(rec.p, direction, r_in.time), Color::fromv(1.0)))
+    }
+}
+
+
+
+// paper, cloth, a lampshade: a lambertian reflection on the side the light comes from & another
+// out of the far side, each with its own color (pbrt's diffuse transmission)
+pub struct DiffuseTransmission {
+    pub reflectance: Color,
+    pub transmittance: Color,
+}
+
+
+impl DiffuseTransmission {
+
+    pub fn new(reflectance: Color, transmittance: Color) -> DiffuseTransmission {
+        DiffuseTransmission { reflectance, transmittance }
+    }
+
+    // chance of sampling the reflection over the transmission
+    fn reflect_probability(&self) -> f32 {
+        let (r, t) = (average(&self.reflectance), average(&self.transmittance));
+        if r + t > 0.0 {r / (r + t)} else {0.5}
+    }
+
+}
+
+
+impl Material for DiffuseTransmission {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Ray, Color)> {
+        let (uvw, _, _) = shading_frame(r_in, rec, &rec.normal);
+        let d = random_cosine_direction();
+        let side = if random_f32() < self.reflect_probability() {1.0} else {-1.0};
+
+        sampled(self, r_in, rec, Ray::new(rec.p, uvw.local(d.x, d.y, side * d.z), r_in.time))
+    }
+
+    fn is_specular(&self) -> bool {
+        false
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: &Vector3) -> Color {
+        let (_, wo, wi) = shading_frame(r_in, rec, direction);
+        let color = if wo.z * wi.z > 0.0 {&self.reflectance} else {&self.transmittance};
+
+        wi.z.abs() / PI * spectrum::sampled(color)
+    }
+
+    fn scattering_pdf(&self, r_in: &Ray, rec: &HitRecord, direction: &Vector3) -> f32 {
+        let (_, wo, wi) = shading_frame(r_in, rec, direction);
+        let p = if wo.z * wi.z > 0.0 {self.reflect_probability()} else {1.0 - self.reflect_probability()};
+
+        p * wi.z.abs() / PI
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::microfacet::tests::*;
+
+    #[test]
+    fn thin_glass_passes_light_straight_through() {
+        let pane = ThinDielectric::new(1.5);
+
+        // two faces of 4% each, & the light bouncing between them
+        assert!((pane.reflectance(1.0) - 2.0 * 0.04 / 1.04).abs() < 1e-4);
+        assert!(pane.reflectance(0.05) > pane.reflectance(0.5));
+
+        for theta in [0.0, 40.0, 85.0].iter() {
+            for from_above in [true, false].iter() {
+                let (r, rec) = hit_at(*theta, *from_above);
+                let (mut reflected, samples) = (0, 20_000);
+
+                for _ in 0..samples {
+                    let (scattered, _) = pane.scatter(&r, &rec).unwrap();
+                    if Vector3::dot(&scattered.direction, &rec.normal) > 0.0 {
+                        reflected += 1;
+                    } else {
+                        assert!((scattered.direction - r.direction.normalized()).magnitude() < 1e-5);
+                    }
+                }
+
+                let expected = pane.reflectance(Vector3::dot(&-r.direction.normalized(), &rec.normal));
+                assert!((reflected as f32 / samples as f32 - expected).abs() < 0.01);
+            }
+        }
+    }
+
+    #[test]
+    fn diffuse_transmission_samples_what_it_evaluates() {
+        let paper = DiffuseTransmission::new(Color::new(0.7, 0.6, 0.5), Color::new(0.2, 0.25, 0.1));
+
+        for theta in [0.0, 60.0].iter() {
+            for from_above in [true, false].iter() {
+                let (r, rec) = hit_at(*theta, *from_above);
+                assert_sampling_matches_eval(&paper, &r, &rec);
+
+                let integrated = integrated_albedo(&paper, &r, &rec);
+                assert!((integrated - (paper.reflectance + paper.transmittance)).magnitude() < 0.01);
+            }
+        }
+    }
+}
@@ -0,0 +1,196 @@
+use crate::{vectors::*, spectrum};
+use std::{ops::{Add, Sub, Mul, Div}, f32::consts::PI};
+
+
+// just enough of a complex number for the interference sums
+#[derive(Copy, Clone, Debug)]
+pub struct Complex {
+    pub re: f32,
+    pub im: f32,
+}
+
+
+impl Complex {
+
+    pub fn new(re: f32, im: f32) -> Complex {
+        Complex { re, im }
+    }
+
+    pub fn real(re: f32) -> Complex {
+        Complex { re, im: 0.0 }
+    }
+
+    pub fn norm_squared(&self) -> f32 {
+        self.re * self.re + self.im * self.im
+    }
+
+    // principal root, the one with a non negative real part
+    pub fn sqrt(&self) -> Complex {
+        let r = self.norm_squared().sqrt();
+        let re = (0.5 * (r + self.re)).max(0.0).sqrt();
+        let im = (0.5 * (r - self.re)).max(0.0).sqrt();
+
+        Complex::new(re, if self.im < 0.0 {-im} else {im})
+    }
+
+    // e^(i self)
+    pub fn exp_i(&self) -> Complex {
+        let decay = (-self.im).exp();
+        Complex::new(decay * self.re.cos(), decay * self.re.sin())
+    }
+
+}
+
+
+impl Add for Complex {
+    type Output = Complex;
+
+    fn add(self, other: Complex) -> Complex {
+        Complex::new(self.re + other.re, self.im + other.im)
+    }
+}
+
+
+impl Sub for Complex {
+    type Output = Complex;
+
+    fn sub(self, other: Complex) -> Complex {
+        Complex::new(self.re - other.re, self.im - other.im)
+    }
+}
+
+
+impl Mul for Complex {
+    type Output = Complex;
+
+    fn mul(self, other: Complex) -> Complex {
+        Complex::new(self.re * other.re - self.im * other.im, self.re * other.im + self.im * other.re)
+    }
+}
+
+
+impl Div for Complex {
+    type Output = Complex;
+
+    fn div(self, other: Complex) -> Complex {
+        let denominator = other.norm_squared();
+        Complex::new((self.re * other.re + self.im * other.im) / denominator, (self.im * other.re - self.re * other.im) / denominator)
+    }
+}
+
+
+
+// a clear film a few hundred nm thick over a surface: soap, oil on water, the oxide on heated metal.
+// light reflected off its top interferes with light reflected off the surface under it, so the
+// reflectance swings with wavelength & angle
+#[derive(Copy, Clone)]
+pub struct ThinFilm {
+    pub thickness: f32, // nm
+    pub ior: f32,
+}
+
+
+impl ThinFilm {
+
+    pub fn new(thickness: f32, ior: f32) -> ThinFilm {
+        ThinFilm { thickness, ior }
+    }
+
+    // reflectance at lambda nm for light arriving at cos_theta, from a medium of index outside, over
+    // a substrate of complex index (eta + ik for metals). airy's sum over the bounces inside the
+    // film, for both polarizations
+    pub fn reflectance_at(&self, cos_theta: f32, outside: f32, substrate: Complex, lambda: f32) -> f32 {
+        let cos_theta = cos_theta.clamp(0.0, 1.0);
+        let (n0, n1, n2) = (Complex::real(outside), Complex::real(self.ior), substrate);
+
+        // snell's law with complex cosines, which also covers total internal reflection & absorption
+        let sin2_outside = Complex::real((1.0 - cos_theta * cos_theta) * outside * outside);
+        let cos_in = |n: Complex| (Complex::real(1.0) - sin2_outside / (n * n)).sqrt();
+        let (cos0, cos1, cos2) = (Complex::real(cos_theta), cos_in(n1), cos_in(n2));
+
+        let r_s = |ni: Complex, ci: Complex, nj: Complex, cj: Complex| (ni * ci - nj * cj) / (ni * ci + nj * cj);
+        let r_p = |ni: Complex, ci: Complex, nj: Complex, cj: Complex| (nj * ci - ni * cj) / (nj * ci + ni * cj);
+
+        // the phase a round trip through the film adds
+        let phase = (Complex::real(4.0 * PI * self.thickness / lambda) * n1 * cos1).exp_i();
+        let airy = |r01: Complex, r12: Complex| {
+            ((r01 + r12 * phase) / (Complex::real(1.0) + r01 * r12 * phase)).norm_squared()
+        };
+
+        let s = airy(r_s(n0, cos0, n1, cos1), r_s(n1, cos1, n2, cos2));
+        let p = airy(r_p(n0, cos0, n1, cos1), r_p(n1, cos1, n2, cos2));
+
+        (0.5 * (s + p)).min(1.0)
+    }
+
+    // reflectance as the current path sees it, with the substrate's index given per wavelength
+    pub fn reflectance<F: Fn(f32) -> Complex>(&self, cos_theta: f32, outside: f32, substrate: F) -> Color {
+        spectrum::spectral(|lambda| self.reflectance_at(cos_theta, outside, substrate(lambda), lambda))
+    }
+
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{microfacet::{fresnel_dielectric, fresnel_conductor, tests::*}, materials::*};
+
+    #[test]
+    fn vanishing_films_leave_plain_fresnel() {
+        let none = ThinFilm::new(0.0, 1.33);
+        let invisible = ThinFilm::new(350.0, 1.5);
+
+        for cos_theta in [1.0, 0.7, 0.3, 0.05].iter() {
+            let glass = fresnel_dielectric(*cos_theta, 1.5);
+            assert!((none.reflectance_at(*cos_theta, 1.0, Complex::real(1.5), 550.0) - glass).abs() < 1e-4);
+            assert!((invisible.reflectance_at(*cos_theta, 1.0, Complex::real(1.5), 550.0) - glass).abs() < 1e-4);
+
+            // from inside the glass, past the critical angle too
+            let inside = fresnel_dielectric(*cos_theta, 1.0 / 1.5);
+            assert!((none.reflectance_at(*cos_theta, 1.5, Complex::real(1.0), 550.0) - inside).abs() < 1e-4);
+
+            let gold = fresnel_conductor(*cos_theta, &Color::fromv(0.143), &Color::fromv(3.983)).x;
+            assert!((none.reflectance_at(*cos_theta, 1.0, Complex::new(0.143, 3.983), 650.0) - gold).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn quarter_wave_films_cancel_reflections() {
+        // the textbook anti-reflection coating: index sqrt(1.5), a quarter wavelength thick inside it
+        let ior = 1.5f32.sqrt();
+        let coating = ThinFilm::new(550.0 / (4.0 * ior), ior);
+        assert!(coating.reflectance_at(1.0, 1.0, Complex::real(1.5), 550.0) < 1e-4);
+
+        // a soap film in air goes through bright & dark as the wavelength changes
+        let soap = ThinFilm::new(400.0, 1.33);
+        let reflectances: Vec<f32> = (400..700).step_by(10).map(|l| soap.reflectance_at(1.0, 1.0, Complex::real(1.0), l as f32)).collect();
+        let (min, max) = reflectances.iter().fold((1.0f32, 0.0f32), |(min, max), r| (min.min(*r), max.max(*r)));
+        assert!(min < 0.01 && max > 0.05 && max < 0.2);
+    }
+
+    #[test]
+    fn rgb_reflectance_averages_over_the_spectrum() {
+        let none = ThinFilm::new(0.0, 1.0);
+        let rgb = none.reflectance(1.0, 1.0, |_| Complex::real(1.5));
+
+        // a flat spectrum comes out grey
+        assert!((rgb - Color::fromv(0.04)).magnitude() < 1e-3);
+    }
+
+    #[test]
+    fn films_on_glass_only_move_light_between_reflection_and_refraction() {
+        let bubble = Dielectric::new(1.0).with_thin_film(400.0, 1.33);
+        let frosted = Dielectric::new(1.5).with_roughness(0.2, 0.2).with_thin_film(300.0, 1.38);
+
+        for theta in [0.0, 50.0, 80.0].iter() {
+            let (r, rec) = hit_at(*theta, true);
+            let albedo = sampled_albedo(&bubble, &r, &rec, 100_000);
+            assert!((albedo - Color::fromv(1.0)).magnitude() < 0.02, "albedo {} {} {} at {} degrees", albedo.x, albedo.y, albedo.z, theta);
+
+            // at fixed wavelengths, integrating rgb films over the sphere takes too long
+            spectrum::with_wavelengths(spectrum::Wavelengths::sample(0.3), || assert_sampling_matches_eval(&frosted, &r, &rec));
+        }
+    }
+}
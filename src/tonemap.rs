@@ -0,0 +1,203 @@
+use crate::{vectors::Color, colors::luminance, spectrum, color_space::*};
+
+
+// curves that squeeze the film's unbounded radiance into what a display shows
+#[derive(Copy, Clone)]
+pub enum ToneOperator {
+    Clamp, // none, everything over 1 clips
+    Reinhard,
+    Hable,
+    Aces,
+}
+
+
+// the output transform every display referred image goes through: exposure, white balance, the
+// conversion from the working space to the display's, a tone curve & the display's transfer
+// function. the film itself stays linear, hdr outputs skip all of this
+#[derive(Clone)]
+pub struct OutputTransform {
+    pub exposure: f32, // stops
+    pub white_balance: Option<f32>, // kelvin of the light that should come out white
+    pub operator: ToneOperator,
+    pub working: ColorSpace, // the film's
+    pub display: ColorSpace,
+    gains: Color, // von kries scaling of the white balance, in bradford cone space
+}
+
+
+impl OutputTransform {
+
+    pub fn new(exposure: f32, white_balance: Option<f32>, operator: ToneOperator) -> OutputTransform {
+        let gains = match white_balance {
+            Some(kelvin) => {
+                let white = blackbody_rgb(kelvin);
+                let srgb = ColorSpace::Srgb;
+                let (source, target) = (bradford(&srgb.to_xyz(&(white / luminance(&white)))), bradford(&srgb.to_xyz(&Color::fromv(1.0))));
+                target / source
+            }
+            None => Color::fromv(1.0),
+        };
+
+        OutputTransform { exposure, white_balance, operator, working: ColorSpace::Srgb, display: ColorSpace::Srgb, gains }
+    }
+
+    pub fn with_color_spaces(mut self, working: ColorSpace, display: ColorSpace) -> OutputTransform {
+        self.working = working;
+        self.display = display;
+        self
+    }
+
+    // linear scene radiance to display encoded values in [0, 1]
+    pub fn apply(&self, c: &Color) -> Color {
+        let c = self.exposure.exp2() * *c;
+        let c = if self.white_balance.is_some() {
+            self.display.xyz_in(&bradford_inverse(&(self.gains * bradford(&self.working.to_xyz(&c)))))
+        } else {
+            self.working.convert(&c, self.display)
+        };
+
+        let mapped = match self.operator {
+            ToneOperator::Clamp => c,
+            // on luminance, so colors keep their hue as they roll off
+            ToneOperator::Reinhard => c / (1.0 + luminance(&c).max(0.0)),
+            ToneOperator::Hable => Color::new(hable(c.x), hable(c.y), hable(c.z)) / hable(HABLE_WHITE),
+            ToneOperator::Aces => aces_fitted(&c),
+        };
+
+        let transfer = self.display.display_transfer();
+        Color::new(transfer.encode(mapped.x), transfer.encode(mapped.y), transfer.encode(mapped.z))
+    }
+
+    pub fn to_bytes(&self, c: &Color) -> [u8; 3] {
+        let c = self.apply(c);
+        [c.x, c.y, c.z].map(|v| (255.0 * v + 0.5) as u8)
+    }
+
+}
+
+
+// john hable's filmic curve from uncharted 2, with its shoulder, linear section & toe strengths
+fn hable(x: f32) -> f32 {
+    const A: f32 = 0.15;
+    const B: f32 = 0.50;
+    const C: f32 = 0.10;
+    const D: f32 = 0.20;
+    const E: f32 = 0.02;
+    const F: f32 = 0.30;
+
+    let x = x.max(0.0);
+    (x * (A * x + C * B) + D * E) / (x * (A * x + B) + D * F) - E / F
+}
+
+// linear white point of the hable curve, everything brighter clips
+const HABLE_WHITE: f32 = 11.2;
+
+
+// stephen hill's fit of the aces reference rendering & output transforms, through their primaries
+fn aces_fitted(c: &Color) -> Color {
+    let input = Color::new(
+        0.59719 * c.x + 0.35458 * c.y + 0.04823 * c.z,
+        0.07600 * c.x + 0.90834 * c.y + 0.01566 * c.z,
+        0.02840 * c.x + 0.13383 * c.y + 0.83777 * c.z,
+    );
+    let fit = |v: f32| (v * (v + 0.024_578_6) - 0.000_090_537) / (v * (0.983_729 * v + 0.432_951) + 0.238_081);
+    let v = Color::new(fit(input.x), fit(input.y), fit(input.z));
+
+    Color::new(
+         1.60475 * v.x - 0.53108 * v.y - 0.07367 * v.z,
+        -0.10208 * v.x + 1.10813 * v.y - 0.00605 * v.z,
+        -0.00327 * v.x - 0.07276 * v.y + 1.07602 * v.z,
+    )
+}
+
+
+// planck's law, the spectral radiance of a blackbody at lambda nm, up to a constant
+fn planck(lambda: f32, kelvin: f32) -> f32 {
+    let l = lambda * 1e-9;
+    1.0 / (l.powi(5) * ((1.438_777e-2 / (l * kelvin)).exp() - 1.0))
+}
+
+
+// a blackbody's color, in linear srgb where a flat spectrum is white
+pub fn blackbody_rgb(kelvin: f32) -> Color {
+    with_working_space(ColorSpace::Srgb, || spectrum::spectral(|lambda| planck(lambda, kelvin)))
+}
+
+
+// xyz to the cone responses chromatic adaptation scales
+fn bradford(c: &Color) -> Color {
+    Color::new(
+         0.8951 * c.x + 0.2664 * c.y - 0.1614 * c.z,
+        -0.7502 * c.x + 1.7135 * c.y + 0.0367 * c.z,
+         0.0389 * c.x - 0.0685 * c.y + 1.0296 * c.z,
+    )
+}
+
+
+fn bradford_inverse(c: &Color) -> Color {
+    Color::new(
+         0.986_993 * c.x - 0.147_054 * c.y + 0.159_963 * c.z,
+         0.432_305 * c.x + 0.518_360 * c.y + 0.049_291 * c.z,
+        -0.008_529 * c.x + 0.040_043 * c.y + 0.968_487 * c.z,
+    )
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn srgb_transfer_is_exact() {
+        assert_eq!(srgb_oetf(0.0), 0.0);
+        assert!((srgb_oetf(1.0) - 1.0).abs() < 1e-6);
+        assert!((srgb_oetf(0.5) - 0.735_357).abs() < 1e-5);
+        // the two pieces meet
+        assert!((12.92 * 0.003_130_8 - (1.055 * 0.003_130_8f32.powf(1.0 / 2.4) - 0.055)).abs() < 1e-5);
+
+        let transform = OutputTransform::new(0.0, None, ToneOperator::Clamp);
+        assert_eq!(transform.to_bytes(&Color::new(0.0, 0.5, 4.0)), [0, 188, 255]);
+        // a stop up doubles the light
+        assert_eq!(OutputTransform::new(1.0, None, ToneOperator::Clamp).to_bytes(&Color::fromv(0.25)), [188, 188, 188]);
+    }
+
+    #[test]
+    fn tone_curves_roll_highlights_off() {
+        for operator in [ToneOperator::Reinhard, ToneOperator::Hable, ToneOperator::Aces].iter() {
+            let transform = OutputTransform::new(0.0, None, *operator);
+            let mut previous = 0.0;
+
+            for k in 1..100 {
+                let value = transform.apply(&Color::fromv(0.1 * k as f32)).y;
+                assert!(value > previous && value < 1.0);
+                previous = value;
+            }
+        }
+    }
+
+    #[test]
+    fn white_balance_neutralises_its_light() {
+        let transform = OutputTransform::new(0.0, Some(3200.0), ToneOperator::Clamp);
+        let light = blackbody_rgb(3200.0);
+        let light = 0.5 * light / luminance(&light);
+        assert!(light.x > 1.5 * light.z);
+
+        let balanced = transform.apply(&light);
+        assert!((balanced.x - balanced.y).abs() < 0.01 && (balanced.z - balanced.y).abs() < 0.01, "{} {} {}", balanced.x, balanced.y, balanced.z);
+    }
+
+    #[test]
+    fn output_converts_the_working_space_to_the_display() {
+        let srgb = OutputTransform::new(0.0, None, ToneOperator::Clamp);
+        let acescg = OutputTransform::new(0.0, None, ToneOperator::Clamp).with_color_spaces(ColorSpace::AcesCg, ColorSpace::Srgb);
+        let c = Color::new(0.6, 0.3, 0.1);
+        assert_eq!(srgb.to_bytes(&c), acescg.to_bytes(&ColorSpace::AcesCg.srgb_in(&c)));
+
+        // grays look the same on any display, saturated colors are less saturated on a wider one's primaries
+        let p3 = OutputTransform::new(0.0, None, ToneOperator::Clamp).with_color_spaces(ColorSpace::Srgb, ColorSpace::DisplayP3);
+        assert_eq!(srgb.to_bytes(&Color::fromv(0.5)), p3.to_bytes(&Color::fromv(0.5)));
+        let red = p3.to_bytes(&Color::newi(1, 0, 0));
+        assert!(red[0] < 255 && red[1] > 0);
+    }
+}
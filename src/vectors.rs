@@ -1,5 +1,5 @@
 use std::ops::*;
-use rand::prelude::*;
+use crate::random::*;
 
 
 #[derive(Copy, Clone)]
@@ -101,12 +101,10 @@ impl Vector3 {
 
     #[inline(always)]
     pub fn random() -> Vector3 {
-        let mut rng = thread_rng();
-
         Vector3 {
-            x: rng.gen::<f32>(), 
-            y: rng.gen::<f32>(), 
-            z: rng.gen::<f32>(), 
+            x: random_f32(), 
+            y: random_f32(), 
+            z: random_f32(), 
         }
     }
 
@@ -114,29 +112,30 @@ impl Vector3 {
     #[inline(always)]
     pub fn random_by_range(min: f32, max: f32) -> Vector3 {
 
-        let mut rng = thread_rng();
         Vector3 {
-            x: rng.gen_range(min..=max), 
-            y: rng.gen_range(min..=max), 
-            z: rng.gen_range(min..=max), 
+            x: random_range(min, max), 
+            y: random_range(min, max), 
+            z: random_range(min, max), 
         }
     }
 
 
+    // these map a fixed number of random numbers to their points instead of rejecting samples,
+    // so samplers can hand each of them a dimension of its own
     #[inline(always)]
     pub fn random_in_unit_sphere() -> Vector3 {
-        loop {
-            let p: Vector3 = Vector3::random_by_range(-1.0, 1.0);
-            if p.magnitude_squared() < 1.0 {
-                return p;
-            }
-        }
+        let direction = Vector3::random_unit_vector();
+        random_f32().cbrt() * direction
     }
 
 
     #[inline(always)]
     pub fn random_unit_vector() -> Vector3 {
-        Vector3::random_in_unit_sphere().normalized()
+        let z = 1.0 - 2.0 * random_f32();
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * std::f32::consts::PI * random_f32();
+
+        Vector3 { x: r * phi.cos(), y: r * phi.sin(), z }
     }
 
 
@@ -155,13 +154,13 @@ impl Vector3 {
     #[inline(always)]
     pub fn near_zero(&self) -> bool {
         let s: f32 = 1e-8;
-        (self.x < s) && (self.y < s) && (self.z < s)
+        (self.x.abs() < s) && (self.y.abs() < s) && (self.z.abs() < s)
     }
 
 
     #[inline(always)]
     pub fn reflect(v: &Vector3, n: &Vector3) -> Vector3{
-        (*v) - 2.0 * Vector3::dot(&v, &n) * (*n)
+        (*v) - 2.0 * Vector3::dot(v, n) * (*n)
     }
 
 
@@ -173,29 +172,24 @@ impl Vector3 {
         let r_out_parallel: Vector3 = (1.0 - r_out_perp.magnitude_squared())
                                       .abs()
                                       .sqrt()
-                                      * (-1.0) 
-                                      * (*n);
+                                      * -(*n);
 
         r_out_perp + r_out_parallel
 
     }
 
 
+    // shirley & chiu's concentric mapping of the square onto the disk
     #[inline(always)]
     pub fn random_in_unit_disk() -> Vector3 {
-        let mut rng = thread_rng();
-
-        loop {
-            let p = Vector3 {
-                x: rng.gen_range(-1.0..1.0),
-                y: rng.gen_range(-1.0..1.0), 
-                z: 0.0
-            };
-
-            if p.magnitude_squared() < 1.0 {
-                return p;
-            }
+        let (a, b) = (random_range(-1.0, 1.0), random_range(-1.0, 1.0));
+        if a == 0.0 && b == 0.0 {
+            return Vector3::zeros();
         }
+
+        let quarter = std::f32::consts::FRAC_PI_4;
+        let (r, phi) = if a.abs() > b.abs() {(a, quarter * (b / a))} else {(b, 2.0 * quarter - quarter * (a / b))};
+        Vector3 { x: r * phi.cos(), y: r * phi.sin(), z: 0.0 }
     }
 }
 